@@ -1,27 +1,49 @@
 use url::Url;
 use crate::render::{FontCache};
 use crate::layout::{Dimensions, RenderBox};
-use crate::dom::{Document, strip_empty_nodes, expand_entities};
-use crate::net::{BrowserError, load_doc_from_net, relative_filepath_to_url, load_stylesheets_new};
+use crate::dom::{Document, NodeType, ElementState, expand_entities};
+use crate::net::{BrowserError, StylesheetSet, load_doc_from_net, relative_filepath_to_url, load_stylesheets_new, calculate_url_from_doc};
 use crate::style::{dom_tree_to_stylednodes};
+use crate::css::MediaContext;
 use crate::layout;
 use std::env;
+use std::collections::HashSet;
 use glium_glyph::glyph_brush::rusttype::Font;
 
-pub fn navigate_to_doc(url:&Url, font_cache:&mut FontCache, containing_block:Dimensions) -> Result<(Document, RenderBox),BrowserError> {
+pub fn navigate_to_doc(url:&Url, font_cache:&mut FontCache, containing_block:Dimensions, media:&MediaContext, visited:&HashSet<Url>) -> Result<(Document, StylesheetSet, RenderBox),BrowserError> {
     let mut doc = load_doc_from_net(&url)?;
-    strip_empty_nodes(&mut doc);
     expand_entities(&mut doc);
+    mark_visited_links(&mut doc, visited);
     // println!("doc is now {:#?}",doc);
-    let mut stylesheets = load_stylesheets_new(&doc, font_cache)?;
-    let stree = dom_tree_to_stylednodes(&doc.root_node, &stylesheets);
+    let stylesheets = load_stylesheets_new(&doc, font_cache)?;
+    let render_root = layout_doc(&doc, &stylesheets, font_cache, containing_block, media);
+    Ok((doc, stylesheets, render_root))
+}
+
+/// Styles and lays out an already loaded document, like after the shell
+/// changes the hovered or focused element or the window is resized. Media
+/// queries are evaluated against `media`.
+pub fn layout_doc(doc:&Document, stylesheets:&StylesheetSet, font_cache:&mut FontCache, containing_block:Dimensions, media:&MediaContext) -> RenderBox {
+    let stree = dom_tree_to_stylednodes(doc, stylesheets, media);
     // println!("styled tree is {:#?}", stree);
     // println!("font_cache looks like {:#?}",font_cache.families);
-    let mut bbox = layout::build_layout_tree(&stree.root.borrow(), &doc);
+    let mut bbox = layout::build_layout_tree(&stree.root.borrow(), doc);
     // println!("doing layout with bounds {:#?}", containing_block);
-    let render_root = bbox.layout(&mut containing_block.clone(), font_cache, &doc);
-    // println!("render root is {:#?}",render_root);
-    Ok((doc,render_root))
+    bbox.layout(&mut containing_block.clone(), font_cache, doc)
+}
+
+// links to pages in the history match :visited
+fn mark_visited_links(doc:&mut Document, visited:&HashSet<Url>) {
+    for id in doc.querySelectorAll("a[href], area[href]").unwrap_or_default() {
+        let href = match &doc.node(id).node_type {
+            NodeType::Element(ed) => ed.attributes.get("href").cloned(),
+            _ => None,
+        };
+        let url = href.and_then(|href| calculate_url_from_doc(doc, &href).ok());
+        if url.map(|url| visited.contains(&url)).unwrap_or(false) {
+            doc.set_element_state(id, ElementState::Visited, true);
+        }
+    }
 }
 
 pub fn install_standard_fonts(font_cache:&mut FontCache) -> Result<(),BrowserError> {
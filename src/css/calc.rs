@@ -0,0 +1,284 @@
+// https://drafts.csswg.org/css-values-4/#math
+//
+// calc(), min(), max() and clamp() are parsed into a tree of operations. The
+// units have to work together: lengths can be added to lengths and multiplied
+// or divided by numbers, and anything else makes the value invalid. The tree
+// is evaluated when the values are computed, unless it has a percentage in it,
+// in which case layout evaluates it once it knows what the percentage is of.
+
+use crate::css::{Unit, Value, Token};
+use crate::css::value::unit_from_name;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Calc {
+    Number(f32),
+    Length(f32, Unit),
+    Sum(Box<Calc>, Box<Calc>),
+    Difference(Box<Calc>, Box<Calc>),
+    Product(Box<Calc>, Box<Calc>),
+    Quotient(Box<Calc>, Box<Calc>),
+    Min(Vec<Calc>),
+    Max(Vec<Calc>),
+    /// the minimum, the preferred value and the maximum
+    Clamp(Box<Calc>, Box<Calc>, Box<Calc>),
+}
+
+impl Calc {
+    /// The result, with `length` giving each length in pixels. It is None if
+    /// a length can't be resolved yet, or for a division by zero.
+    pub fn eval(&self, length:&dyn Fn(f32, &Unit) -> Option<f32>) -> Option<f32> {
+        Some(match self {
+            Calc::Number(v) => *v,
+            Calc::Length(v, unit) => length(*v, unit)?,
+            Calc::Sum(a, b) => a.eval(length)? + b.eval(length)?,
+            Calc::Difference(a, b) => a.eval(length)? - b.eval(length)?,
+            Calc::Product(a, b) => a.eval(length)? * b.eval(length)?,
+            Calc::Quotient(a, b) => {
+                let divisor = b.eval(length)?;
+                if divisor == 0.0 {
+                    return None;
+                }
+                a.eval(length)? / divisor
+            }
+            Calc::Min(items) => items.iter().map(|item| item.eval(length)).collect::<Option<Vec<f32>>>()?
+                .into_iter().fold(f32::INFINITY, f32::min),
+            Calc::Max(items) => items.iter().map(|item| item.eval(length)).collect::<Option<Vec<f32>>>()?
+                .into_iter().fold(f32::NEG_INFINITY, f32::max),
+            // the minimum wins when it is more than the maximum
+            Calc::Clamp(min, value, max) => value.eval(length)?.min(max.eval(length)?).max(min.eval(length)?),
+        })
+    }
+
+    /// The same tree with each length replaced by what `length` gives for it.
+    pub fn map_lengths(&self, length:&dyn Fn(f32, &Unit) -> (f32, Unit)) -> Calc {
+        let map = |item:&Calc| Box::new(item.map_lengths(length));
+        match self {
+            Calc::Number(v) => Calc::Number(*v),
+            Calc::Length(v, unit) => {
+                let (v, unit) = length(*v, unit);
+                Calc::Length(v, unit)
+            }
+            Calc::Sum(a, b) => Calc::Sum(map(a), map(b)),
+            Calc::Difference(a, b) => Calc::Difference(map(a), map(b)),
+            Calc::Product(a, b) => Calc::Product(map(a), map(b)),
+            Calc::Quotient(a, b) => Calc::Quotient(map(a), map(b)),
+            Calc::Min(items) => Calc::Min(items.iter().map(|item| item.map_lengths(length)).collect()),
+            Calc::Max(items) => Calc::Max(items.iter().map(|item| item.map_lengths(length)).collect()),
+            Calc::Clamp(min, value, max) => Calc::Clamp(map(min), map(value), map(max)),
+        }
+    }
+
+    /// Whether the result is a length rather than a plain number, or None if
+    /// the units don't work together.
+    pub fn is_length(&self) -> Option<bool> {
+        let same = |items:&[&Calc]| -> Option<bool> {
+            let first = items[0].is_length()?;
+            for item in &items[1..] {
+                if item.is_length()? != first {
+                    return None;
+                }
+            }
+            Some(first)
+        };
+        match self {
+            Calc::Number(_) => Some(false),
+            Calc::Length(_, _) => Some(true),
+            Calc::Sum(a, b) | Calc::Difference(a, b) => same(&[a, b]),
+            Calc::Product(a, b) => match (a.is_length()?, b.is_length()?) {
+                (true, true) => None,
+                (a, b) => Some(a || b),
+            },
+            Calc::Quotient(a, b) => match b.is_length()? {
+                true => None,
+                false => a.is_length(),
+            },
+            Calc::Min(items) | Calc::Max(items) => same(&items.iter().collect::<Vec<&Calc>>()),
+            Calc::Clamp(min, value, max) => same(&[min, value, max]),
+        }
+    }
+}
+
+/// Whether a function is one of the math functions. Their names are
+/// case-insensitive.
+pub(crate) fn is_math_function(name:&str) -> bool {
+    ["calc", "min", "max", "clamp"].iter().any(|f| f.eq_ignore_ascii_case(name))
+}
+
+/// A math function as a value, from its tokens starting with the function
+/// token, if it parses and its units work together.
+pub(crate) fn math_value(tokens:&[Token]) -> Option<Value> {
+    let mut parser = MathParser { tokens, pos: 0 };
+    let calc = parser.term()?;
+    if parser.pos < tokens.len() {
+        return None;
+    }
+    calc.is_length()?;
+    Some(Value::Calc(calc))
+}
+
+struct MathParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> MathParser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+    fn skip_whitespace(&mut self) {
+        while self.peek() == Some(&Token::Whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    // the arguments up to the closing paren, or the end if it isn't closed
+    fn function(&mut self, name:&str) -> Option<Calc> {
+        let mut items = vec![];
+        loop {
+            self.skip_whitespace();
+            items.push(self.sum()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(Token::Comma) => self.pos += 1,
+                Some(Token::CloseParen) => {
+                    self.pos += 1;
+                    break;
+                }
+                None => break,
+                Some(_) => return None,
+            }
+        }
+        match name.to_ascii_lowercase().as_str() {
+            "calc" if items.len() == 1 => items.pop(),
+            "min" => Some(Calc::Min(items)),
+            "max" => Some(Calc::Max(items)),
+            "clamp" if items.len() == 3 => {
+                let max = items.pop()?;
+                let value = items.pop()?;
+                let min = items.pop()?;
+                Some(Calc::Clamp(Box::new(min), Box::new(value), Box::new(max)))
+            }
+            _ => None,
+        }
+    }
+
+    // + and - need whitespace on both sides, so they aren't taken as signs
+    fn sum(&mut self) -> Option<Calc> {
+        let mut left = self.product()?;
+        loop {
+            let before = self.pos;
+            self.skip_whitespace();
+            let operator = match self.peek() {
+                Some(Token::Delim(op)) if self.pos > before && (*op == '+' || *op == '-') => *op,
+                _ => {
+                    self.pos = before;
+                    return Some(left);
+                }
+            };
+            self.pos += 1;
+            if self.peek() != Some(&Token::Whitespace) {
+                return None;
+            }
+            self.skip_whitespace();
+            let right = Box::new(self.product()?);
+            left = match operator {
+                '+' => Calc::Sum(Box::new(left), right),
+                _ => Calc::Difference(Box::new(left), right),
+            };
+        }
+    }
+
+    fn product(&mut self) -> Option<Calc> {
+        let mut left = self.term()?;
+        loop {
+            let before = self.pos;
+            self.skip_whitespace();
+            let operator = match self.peek() {
+                Some(Token::Delim(op)) if *op == '*' || *op == '/' => *op,
+                _ => {
+                    self.pos = before;
+                    return Some(left);
+                }
+            };
+            self.pos += 1;
+            self.skip_whitespace();
+            let right = Box::new(self.term()?);
+            left = match operator {
+                '*' => Calc::Product(Box::new(left), right),
+                _ => Calc::Quotient(Box::new(left), right),
+            };
+        }
+    }
+
+    fn term(&mut self) -> Option<Calc> {
+        let token = self.peek()?;
+        self.pos += 1;
+        match token {
+            Token::Number { value, .. } => Some(Calc::Number(*value)),
+            Token::Percentage(value) => Some(Calc::Length(*value, Unit::Per)),
+            Token::Dimension { value, unit, .. } => Some(Calc::Length(*value, unit_from_name(unit)?)),
+            Token::OpenParen => {
+                self.skip_whitespace();
+                let sum = self.sum()?;
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(Token::CloseParen) => self.pos += 1,
+                    None => {}
+                    Some(_) => return None,
+                }
+                Some(sum)
+            }
+            Token::Function(name) if is_math_function(name) => self.function(name),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_math_functions() {
+    let parse = crate::css::value::parse_value_text;
+    let px = |v:f32| Box::new(Calc::Length(v, Unit::Px));
+    assert_eq!(parse("calc(100% - 20px)"), Some(Value::Calc(Calc::Difference(Box::new(Calc::Length(100.0, Unit::Per)), px(20.0)))));
+    assert_eq!(parse("calc(1px + 2px * 3)"), Some(Value::Calc(Calc::Sum(px(1.0),
+        Box::new(Calc::Product(px(2.0), Box::new(Calc::Number(3.0))))))));
+    assert_eq!(parse("min(10px,calc( 2em / 2 ))"), Some(Value::Calc(Calc::Min(vec![Calc::Length(10.0, Unit::Px),
+        Calc::Quotient(Box::new(Calc::Length(2.0, Unit::Em)), Box::new(Calc::Number(2.0)))]))));
+    assert!(parse("clamp(1rem, 2.5vw, 3rem)").is_some());
+    assert!(parse("calc((1px + 2px) * -2)").is_some());
+    // signs need the whitespace, units have to match, and clamp takes three
+    assert_eq!(parse("calc(100% -20px)"), None);
+    assert_eq!(parse("calc(1px + 2)"), None);
+    assert_eq!(parse("calc(1px * 2px)"), None);
+    assert_eq!(parse("calc(1px / 2px)"), None);
+    assert_eq!(parse("clamp(1px, 2px)"), None);
+    assert_eq!(parse("max()"), None);
+    assert_eq!(parse("min()"), None);
+    // the names are case-insensitive
+    assert_eq!(parse("CALC(1px)"), Some(Value::Calc(Calc::Length(1.0, Unit::Px))));
+    assert_eq!(parse("Min(1px, 2px)"), Some(Value::Calc(Calc::Min(vec![Calc::Length(1.0, Unit::Px), Calc::Length(2.0, Unit::Px)]))));
+
+    let resolve = |text:&str, percent_of:f32| match parse(text) {
+        Some(Value::Calc(calc)) => calc.eval(&|v, unit| match unit {
+            Unit::Per => Some(v / 100.0 * percent_of),
+            _ => Some(v),
+        }),
+        _ => None,
+    };
+    assert_eq!(resolve("calc(100% - 20px)", 200.0), Some(180.0));
+    assert_eq!(resolve("calc((1px + 2px) * -2)", 0.0), Some(-6.0));
+    assert_eq!(resolve("max(10px, 50%, 5px)", 100.0), Some(50.0));
+    assert_eq!(resolve("clamp(10px, 50%, 30px)", 100.0), Some(30.0));
+    assert_eq!(resolve("clamp(10px, 50%, 30px)", 10.0), Some(10.0));
+    assert_eq!(resolve("calc(1px / 0)", 0.0), None);
+}
+
+#[test]
+fn test_invalid_math_declarations() {
+    use crate::css::parse_declarations;
+    // a math function that doesn't parse makes the declaration invalid, so an
+    // earlier one stays
+    let (declarations, skipped) = parse_declarations("width: 10px; width: calc(100% -20px); height: min(); margin-left: clamp(1px, 2px)");
+    assert_eq!(declarations.iter().map(|d| (d.name.as_str(), &d.value)).collect::<Vec<(&str, &Value)>>(),
+               vec![("width", &Value::Length(10.0, Unit::Px))]);
+    assert_eq!(skipped.len(), 3);
+}
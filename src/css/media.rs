@@ -0,0 +1,625 @@
+// https://drafts.csswg.org/mediaqueries-4/
+//
+// Parses media query lists from the css tokens and evaluates them against a
+// MediaContext, which the shell makes from the size of its window. Like the
+// browsers, a query that doesn't parse becomes `not all` without taking the
+// rest of the list with it. Lengths are converted to px and resolutions to
+// dppx when parsed, so evaluating only has to compare numbers.
+
+use crate::css::tokenizer::{tokenize, Token, SpannedToken};
+use crate::style::INITIAL_FONT_SIZE;
+
+/// What media queries are evaluated against.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaContext {
+    pub media_type: MediaType,
+    /// the size of the viewport in css pixels
+    pub width: f32,
+    pub height: f32,
+    /// device pixels per css pixel
+    pub resolution: f32,
+    pub color_scheme: ColorScheme,
+    pub reduced_motion: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MediaType {
+    Screen,
+    Print,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl MediaContext {
+    /// A screen with a viewport of the given size.
+    pub fn new(width:f32, height:f32) -> Self {
+        MediaContext {
+            media_type: MediaType::Screen,
+            width,
+            height,
+            resolution: 1.0,
+            color_scheme: ColorScheme::Light,
+            reduced_motion: false,
+        }
+    }
+}
+
+impl Default for MediaContext {
+    fn default() -> Self {
+        MediaContext::new(800.0, 600.0)
+    }
+}
+
+/// The comma separated queries of an `@media` rule. It matches if any of
+/// them do, or if there are none.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct MediaQueryList {
+    pub queries: Vec<MediaQuery>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaQuery {
+    pub negated: bool,
+    /// `None` is the same as `all`
+    pub media_type: Option<String>,
+    pub condition: Option<MediaCondition>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+    /// something in parens that isn't understood. it is neither true nor
+    /// false, so `not` doesn't make it match.
+    Unknown,
+}
+
+/// A feature test. `(min-width: 10px)` and `(width >= 10px)` both come out
+/// as a `GreaterOrEqual` test of `width`, and `(color)` has no test.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaFeature {
+    pub name: String,
+    pub test: Option<(Comparison, MediaValue)>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MediaValue {
+    /// lengths in px, resolutions in dppx and ratios as their quotient
+    Number(f32),
+    Ident(String),
+}
+
+impl MediaQueryList {
+    pub fn matches(&self, context:&MediaContext) -> bool {
+        self.queries.is_empty() || self.queries.iter().any(|query| query.matches(context))
+    }
+}
+
+impl MediaQuery {
+    // what an invalid query turns into
+    fn not_all() -> Self {
+        MediaQuery { negated: true, media_type: None, condition: None }
+    }
+    pub fn matches(&self, context:&MediaContext) -> bool {
+        let type_matches = match self.media_type.as_deref() {
+            None | Some("all") => true,
+            Some("screen") => context.media_type == MediaType::Screen,
+            Some("print") => context.media_type == MediaType::Print,
+            Some(_) => false,
+        };
+        let condition_matches = match &self.condition {
+            Some(condition) => condition.matches(context),
+            None => Some(true),
+        };
+        // an unknown result only becomes false here, after any `not`
+        match and(vec![Some(type_matches), condition_matches]) {
+            Some(matches) => matches != self.negated,
+            None => false,
+        }
+    }
+}
+
+impl MediaCondition {
+    /// Whether the condition is true, or None if it's unknown.
+    pub fn matches(&self, context:&MediaContext) -> Option<bool> {
+        match self {
+            MediaCondition::Feature(feature) => feature.matches(context),
+            MediaCondition::Not(condition) => condition.matches(context).map(|matches| !matches),
+            MediaCondition::And(conditions) => and(conditions.iter().map(|c| c.matches(context)).collect()),
+            MediaCondition::Or(conditions) => or(conditions.iter().map(|c| c.matches(context)).collect()),
+            MediaCondition::Unknown => None,
+        }
+    }
+}
+
+// https://drafts.csswg.org/mediaqueries-4/#evaluating
+// false wins over unknown in `and`, and true wins over it in `or`
+fn and(results:Vec<Option<bool>>) -> Option<bool> {
+    if results.contains(&Some(false)) {
+        Some(false)
+    } else if results.contains(&None) {
+        None
+    } else {
+        Some(true)
+    }
+}
+
+fn or(results:Vec<Option<bool>>) -> Option<bool> {
+    if results.contains(&Some(true)) {
+        Some(true)
+    } else if results.contains(&None) {
+        None
+    } else {
+        Some(false)
+    }
+}
+
+impl MediaFeature {
+    /// Whether the feature test is true, or None if the feature or the value
+    /// it's tested against isn't one it can have.
+    pub fn matches(&self, context:&MediaContext) -> Option<bool> {
+        let actual = feature_value(&self.name, context)?;
+        if let Some((_, MediaValue::Ident(expected))) = &self.test {
+            if !feature_idents(&self.name).contains(&expected.as_str()) {
+                return None;
+            }
+        }
+        let matches = match (&self.test, actual) {
+            (None, MediaValue::Number(n)) => n != 0.0,
+            (None, MediaValue::Ident(ident)) => ident != "none" && ident != "no-preference",
+            (Some((op, MediaValue::Number(expected))), MediaValue::Number(n)) => match op {
+                Comparison::Less => n < *expected,
+                Comparison::LessOrEqual => n <= *expected,
+                Comparison::Equal => (n - expected).abs() < 0.001,
+                Comparison::GreaterOrEqual => n >= *expected,
+                Comparison::Greater => n > *expected,
+            },
+            (Some((Comparison::Equal, MediaValue::Ident(expected))), MediaValue::Ident(ident)) => *expected == ident,
+            _ => return None,
+        };
+        Some(matches)
+    }
+}
+
+// the value of a feature in the context, or None if it isn't one we know
+fn feature_value(name:&str, context:&MediaContext) -> Option<MediaValue> {
+    let ident = |s:&str| Some(MediaValue::Ident(String::from(s)));
+    match name {
+        "width" | "device-width" => Some(MediaValue::Number(context.width)),
+        "height" | "device-height" => Some(MediaValue::Number(context.height)),
+        "aspect-ratio" | "device-aspect-ratio" => Some(MediaValue::Number(context.width / context.height)),
+        "resolution" => Some(MediaValue::Number(context.resolution)),
+        "orientation" if context.height >= context.width => ident("portrait"),
+        "orientation" => ident("landscape"),
+        "prefers-color-scheme" => match context.color_scheme {
+            ColorScheme::Light => ident("light"),
+            ColorScheme::Dark => ident("dark"),
+        },
+        "prefers-reduced-motion" if context.reduced_motion => ident("reduce"),
+        "prefers-reduced-motion" => ident("no-preference"),
+        "color" => Some(MediaValue::Number(8.0)),
+        "monochrome" | "grid" => Some(MediaValue::Number(0.0)),
+        "hover" | "any-hover" => ident("hover"),
+        "pointer" | "any-pointer" => ident("fine"),
+        _ => None,
+    }
+}
+
+// the values of the features that have keywords
+fn feature_idents(name:&str) -> &'static [&'static str] {
+    match name {
+        "orientation" => &["portrait", "landscape"],
+        "prefers-color-scheme" => &["light", "dark"],
+        "prefers-reduced-motion" => &["no-preference", "reduce"],
+        "hover" | "any-hover" => &["none", "hover"],
+        "pointer" | "any-pointer" => &["none", "coarse", "fine"],
+        _ => &[],
+    }
+}
+
+// features that can't be compared with < and >, or take min- and max-
+fn is_discrete(name:&str) -> bool {
+    matches!(name, "orientation" | "prefers-color-scheme" | "prefers-reduced-motion" | "grid"
+        | "hover" | "any-hover" | "pointer" | "any-pointer")
+}
+
+// a name that can go in a range, like `width` in (width > 10px)
+fn is_range(name:&str) -> bool {
+    !is_discrete(name) && !name.starts_with("min-") && !name.starts_with("max-")
+}
+
+/// Parses the prelude of an `@media` rule. Queries that don't parse never
+/// match, but the others in the list still do.
+pub fn parse_media_query_list(text:&str) -> MediaQueryList {
+    let tokens = tokenize(text).into_iter()
+        .filter(|t| !matches!(t.token, Token::Comment(_)))
+        .collect();
+    let mut parser = MediaParser { tokens, pos: 0 };
+    let mut queries = vec![];
+    parser.skip_whitespace();
+    if *parser.peek() == Token::Eof {
+        return MediaQueryList { queries };
+    }
+    loop {
+        let start = parser.pos;
+        let query = parser.query();
+        queries.push(query.unwrap_or_else(|| {
+            parser.pos = start;
+            parser.skip_to_comma();
+            MediaQuery::not_all()
+        }));
+        match parser.peek() {
+            Token::Comma => parser.pos += 1,
+            _ => return MediaQueryList { queries },
+        }
+    }
+}
+
+struct MediaParser {
+    tokens: Vec<SpannedToken>,
+    pos: usize,
+}
+
+impl MediaParser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].token
+    }
+    fn skip_whitespace(&mut self) {
+        while *self.peek() == Token::Whitespace {
+            self.pos += 1;
+        }
+    }
+    // the first token that isn't whitespace, starting `offset` tokens ahead,
+    // without moving
+    fn lookahead(&self, offset:usize) -> &Token {
+        let mut i = self.pos + offset;
+        while self.tokens[i].token == Token::Whitespace {
+            i += 1;
+        }
+        &self.tokens[i].token
+    }
+    fn ident(&self) -> Option<String> {
+        match self.peek() {
+            Token::Ident(name) => Some(name.to_ascii_lowercase()),
+            _ => None,
+        }
+    }
+    fn expect(&mut self, token:Token) -> Option<()> {
+        self.skip_whitespace();
+        if *self.peek() == token {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+    // moves past the block or function that starts at pos
+    fn skip_block(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Token::Eof => return,
+                Token::OpenParen | Token::Function(_) | Token::OpenSquare | Token::OpenCurly => depth += 1,
+                Token::CloseParen | Token::CloseSquare | Token::CloseCurly => depth -= 1,
+                _ => {}
+            }
+            self.pos += 1;
+            if depth <= 0 {
+                return;
+            }
+        }
+    }
+    fn skip_to_comma(&mut self) {
+        loop {
+            match self.peek() {
+                Token::Eof | Token::Comma => return,
+                Token::OpenParen | Token::Function(_) | Token::OpenSquare | Token::OpenCurly => self.skip_block(),
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn query(&mut self) -> Option<MediaQuery> {
+        self.skip_whitespace();
+        let query = match self.ident() {
+            Some(word) if word == "not" && *self.lookahead(1) == Token::OpenParen => {
+                MediaQuery { negated: false, media_type: None, condition: Some(self.condition(true)?) }
+            }
+            Some(word) => {
+                self.pos += 1;
+                let negated = word == "not";
+                let media_type = if word == "not" || word == "only" {
+                    self.skip_whitespace();
+                    let media_type = self.ident()?;
+                    self.pos += 1;
+                    media_type
+                } else {
+                    word
+                };
+                if matches!(media_type.as_str(), "not" | "only" | "and" | "or" | "layer") {
+                    return None;
+                }
+                self.skip_whitespace();
+                let condition = match self.ident() {
+                    Some(word) if word == "and" => {
+                        self.pos += 1;
+                        Some(self.condition(false)?)
+                    }
+                    Some(_) => return None,
+                    None => None,
+                };
+                MediaQuery { negated, media_type: Some(media_type), condition }
+            }
+            None => MediaQuery { negated: false, media_type: None, condition: Some(self.condition(true)?) },
+        };
+        self.skip_whitespace();
+        match self.peek() {
+            Token::Comma | Token::Eof => Some(query),
+            _ => None,
+        }
+    }
+
+    // `not` a condition, or conditions joined by all `and` or all `or`
+    fn condition(&mut self, allow_or:bool) -> Option<MediaCondition> {
+        self.skip_whitespace();
+        if self.ident().as_deref() == Some("not") {
+            self.pos += 1;
+            return Some(MediaCondition::Not(Box::new(self.in_parens()?)));
+        }
+        let first = self.in_parens()?;
+        let mut rest = vec![];
+        let mut joiner:Option<String> = None;
+        while let Token::Ident(word) = self.lookahead(0) {
+            let word = word.to_ascii_lowercase();
+            let allowed = word == "and" || (word == "or" && allow_or);
+            if !allowed || matches!(&joiner, Some(j) if *j != word) {
+                break;
+            }
+            self.skip_whitespace();
+            self.pos += 1;
+            rest.push(self.in_parens()?);
+            joiner = Some(word);
+        }
+        if rest.is_empty() {
+            return Some(first);
+        }
+        rest.insert(0, first);
+        match joiner.as_deref() {
+            Some("or") => Some(MediaCondition::Or(rest)),
+            _ => Some(MediaCondition::And(rest)),
+        }
+    }
+
+    // a condition or a feature in parens, or anything else in parens or a
+    // function, which is unknown
+    fn in_parens(&mut self) -> Option<MediaCondition> {
+        self.skip_whitespace();
+        match self.peek() {
+            Token::OpenParen => {}
+            Token::Function(_) => {
+                self.skip_block();
+                return Some(MediaCondition::Unknown);
+            }
+            _ => return None,
+        }
+        let open = self.pos;
+        self.pos += 1;
+        self.skip_whitespace();
+        let nested = *self.peek() == Token::OpenParen || self.ident().as_deref() == Some("not");
+        let parsed = if nested {
+            self.condition(true)
+        } else {
+            self.feature()
+        };
+        match parsed {
+            Some(condition) if self.expect(Token::CloseParen).is_some() => Some(condition),
+            _ => {
+                self.pos = open;
+                self.skip_block();
+                Some(MediaCondition::Unknown)
+            }
+        }
+    }
+
+    // the inside of the parens of a feature
+    fn feature(&mut self) -> Option<MediaCondition> {
+        let feature = |name:&str, op, value| MediaCondition::Feature(MediaFeature { name: String::from(name), test: Some((op, value)) });
+        if let Some(name) = self.ident() {
+            self.pos += 1;
+            self.skip_whitespace();
+            match self.peek() {
+                Token::CloseParen => return Some(MediaCondition::Feature(MediaFeature { name, test: None })),
+                Token::Colon => {
+                    self.pos += 1;
+                    let value = self.value()?;
+                    let (name, op) = if let Some(name) = name.strip_prefix("min-") {
+                        (name, Comparison::GreaterOrEqual)
+                    } else if let Some(name) = name.strip_prefix("max-") {
+                        (name, Comparison::LessOrEqual)
+                    } else {
+                        (name.as_str(), Comparison::Equal)
+                    };
+                    if op != Comparison::Equal && is_discrete(name) {
+                        return None;
+                    }
+                    return Some(feature(name, op, value));
+                }
+                _ => {}
+            }
+            let op = self.comparison()?;
+            let value = self.value()?;
+            return if is_range(&name) { Some(feature(&name, op, value)) } else { None };
+        }
+        // a value first, like (400px < width) or (400px < width < 800px)
+        let low = self.value()?;
+        let low_op = flip(self.comparison()?);
+        self.skip_whitespace();
+        let name = self.ident().filter(|name| is_range(name))?;
+        self.pos += 1;
+        self.skip_whitespace();
+        if *self.peek() == Token::CloseParen {
+            return Some(feature(&name, low_op, low));
+        }
+        let high_op = self.comparison()?;
+        // both sides have to point the same way
+        let ascending = |op| matches!(op, Comparison::Less | Comparison::LessOrEqual);
+        if low_op == Comparison::Equal || high_op == Comparison::Equal || ascending(high_op) == ascending(low_op) {
+            return None;
+        }
+        let high = self.value()?;
+        Some(MediaCondition::And(vec![feature(&name, low_op, low), feature(&name, high_op, high)]))
+    }
+    fn comparison(&mut self) -> Option<Comparison> {
+        self.skip_whitespace();
+        let op = match self.peek() {
+            Token::Delim('<') => Comparison::Less,
+            Token::Delim('>') => Comparison::Greater,
+            Token::Delim('=') => Comparison::Equal,
+            _ => return None,
+        };
+        self.pos += 1;
+        if op != Comparison::Equal && *self.peek() == Token::Delim('=') {
+            self.pos += 1;
+            return Some(if op == Comparison::Less { Comparison::LessOrEqual } else { Comparison::GreaterOrEqual });
+        }
+        Some(op)
+    }
+    fn value(&mut self) -> Option<MediaValue> {
+        self.skip_whitespace();
+        let value = match self.peek().clone() {
+            Token::Ident(ident) => MediaValue::Ident(ident.to_ascii_lowercase()),
+            Token::Dimension { value, unit, .. } => MediaValue::Number(value * unit_scale(&unit.to_ascii_lowercase())?),
+            Token::Number { value, .. } => {
+                self.pos += 1;
+                // a ratio like 16/9
+                if *self.lookahead(0) == Token::Delim('/') {
+                    self.skip_whitespace();
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    if let Token::Number { value: denominator, .. } = *self.peek() {
+                        self.pos += 1;
+                        return Some(MediaValue::Number(value / denominator));
+                    }
+                    return None;
+                }
+                return Some(MediaValue::Number(value));
+            }
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(value)
+    }
+}
+
+// (10px < width) is the same as (width > 10px)
+fn flip(op:Comparison) -> Comparison {
+    match op {
+        Comparison::Less => Comparison::Greater,
+        Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+        Comparison::Equal => Comparison::Equal,
+        Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+        Comparison::Greater => Comparison::Less,
+    }
+}
+
+// lengths go to px and resolutions to dppx. em in a media query is always the
+// initial font size, the same one the root element starts with.
+fn unit_scale(unit:&str) -> Option<f32> {
+    match unit {
+        "px" | "dppx" | "x" => Some(1.0),
+        "em" | "rem" => Some(INITIAL_FONT_SIZE),
+        "in" => Some(96.0),
+        "cm" => Some(96.0 / 2.54),
+        "mm" => Some(96.0 / 25.4),
+        "q" => Some(96.0 / 101.6),
+        "pt" => Some(96.0 / 72.0),
+        "pc" => Some(16.0),
+        "dpi" => Some(1.0 / 96.0),
+        "dpcm" => Some(2.54 / 96.0),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_media_queries() {
+    let width = |op, px| MediaCondition::Feature(MediaFeature { name: String::from("width"), test: Some((op, MediaValue::Number(px))) });
+    let list = parse_media_query_list("screen and (min-width: 600px), not print, (orientation: portrait)");
+    assert_eq!(list.queries, vec![
+        MediaQuery { negated: false, media_type: Some(String::from("screen")), condition: Some(width(Comparison::GreaterOrEqual, 600.0)) },
+        MediaQuery { negated: true, media_type: Some(String::from("print")), condition: None },
+        MediaQuery { negated: false, media_type: None, condition: Some(MediaCondition::Feature(MediaFeature {
+            name: String::from("orientation"), test: Some((Comparison::Equal, MediaValue::Ident(String::from("portrait")))) })) },
+    ]);
+    assert_eq!(parse_media_query_list("(400px < width <= 50em)").queries[0].condition,
+               Some(MediaCondition::And(vec![width(Comparison::Greater, 400.0), width(Comparison::LessOrEqual, 900.0)])));
+    assert_eq!(parse_media_query_list("only screen and (width > 1in)").queries[0].condition, Some(width(Comparison::Greater, 96.0)));
+    // a bad query is `not all` and the rest still count
+    let list = parse_media_query_list("screen and, (min-orientation: portrait), all");
+    assert_eq!(list.queries.len(), 3);
+    assert_eq!(list.queries[0], MediaQuery::not_all());
+    assert_eq!(list.queries[1].condition, Some(MediaCondition::Unknown));
+    assert_eq!(list.queries[2], MediaQuery { negated: false, media_type: Some(String::from("all")), condition: None });
+    assert!(parse_media_query_list("").queries.is_empty());
+}
+
+#[test]
+fn test_evaluate_media_queries() {
+    let phone = MediaContext::new(375.0, 667.0);
+    let desktop = MediaContext::new(1280.0, 800.0);
+    let matches = |text:&str, context:&MediaContext| parse_media_query_list(text).matches(context);
+    assert!(matches("", &phone));
+    assert!(matches("all", &phone));
+    assert!(!matches("print", &phone));
+    assert!(matches("not print", &phone));
+    assert!(!matches("not screen", &phone));
+    assert!(!matches("tv", &phone));
+    assert!(matches("screen and (max-width: 600px)", &phone));
+    assert!(!matches("screen and (max-width: 600px)", &desktop));
+    assert!(matches("(600px < width < 1400px)", &desktop));
+    assert!(!matches("(600px < width < 1400px)", &phone));
+    assert!(matches("(orientation: portrait)", &phone));
+    assert!(matches("(orientation: landscape)", &desktop));
+    assert!(matches("(min-aspect-ratio: 16/10)", &desktop));
+    assert!(!matches("(min-aspect-ratio: 16/9)", &desktop));
+    assert!(matches("(min-resolution: 96dpi) and (max-resolution: 1dppx)", &phone));
+    assert!(matches("(prefers-color-scheme: light)", &phone));
+    assert!(!matches("(prefers-reduced-motion)", &phone));
+    assert!(matches("(prefers-reduced-motion: no-preference)", &phone));
+    assert!(matches("(color)", &phone));
+    assert!(matches("not (monochrome)", &phone));
+    assert!(matches("(width < 400px) or (height < 400px)", &phone));
+    assert!(!matches("(width < 400px) and (height < 400px)", &phone));
+    assert!(!matches("(unknown-feature)", &phone));
+    assert!(!matches("not (unknown-feature)", &phone));
+    assert!(!matches("not (hover: bogus)", &phone));
+    assert!(!matches("not (width: portrait)", &phone));
+    assert!(!matches("not ((unknown-feature) and (color))", &phone));
+    assert!(matches("not ((unknown-feature) and (monochrome))", &phone));
+    assert!(matches("(unknown-feature) or (color)", &phone));
+    assert!(!matches("not screen and (unknown-feature)", &phone));
+    assert!(matches("(hover: hover) and (pointer: fine)", &phone));
+    assert!(!matches("(min-width: 600px) and (max-width)", &phone));
+    assert!(matches("print, (max-width: 400px)", &phone));
+    let dark = MediaContext { color_scheme: ColorScheme::Dark, reduced_motion: true, ..MediaContext::default() };
+    assert!(matches("(prefers-color-scheme: dark) and (prefers-reduced-motion: reduce)", &dark));
+    assert!(matches("(prefers-reduced-motion)", &dark));
+    // em is the font size the root element has when nothing sets it
+    let em_wide = MediaContext::new(INITIAL_FONT_SIZE * 20.0, 600.0);
+    assert!(matches("(width: 20em) and (width: 20rem)", &em_wide));
+    assert!(!matches("(width: 20em)", &MediaContext::new(320.0, 600.0)));
+}
@@ -1,26 +1,60 @@
-extern crate pom;
-use pom::parser::{Parser,is_a,one_of,sym, none_of,seq};
-use pom::char_class::alpha;
-use std::str::{self, FromStr};
-use self::pom::char_class::{alphanum, digit};
+use std::str;
 use std::fs::File;
 use std::io::Read;
 use crate::net::BrowserError;
+#[cfg(test)]
+use crate::net::Diagnostic;
+use crate::net::encoding::{sniff_css_encoding, decode};
+use encoding_rs::{Encoding, UTF_8};
+#[cfg(test)]
 use crate::css::Value::{Length, Keyword,  StringLiteral, UnicodeRange, UnicodeCodepoint};
-use self::pom::parser::{list, call, take};
 use url::Url;
 use crate::css::RuleType::Comment;
-
+use crate::css::parser::parse_rules;
+pub use crate::css::parser::{SkippedSpan, parse_declarations};
+pub use crate::css::selector::parse_selector_list;
+pub use crate::css::media::{MediaContext, MediaType, ColorScheme, MediaQueryList, MediaQuery, MediaCondition, MediaFeature, Comparison, MediaValue, parse_media_query_list};
+pub use crate::css::supports::{SupportsCondition, parse_supports_condition};
+pub use crate::css::tokenizer::Token;
+pub use crate::css::calc::Calc;
+#[cfg(test)]
+use crate::css::value::{parse_value_text, unit_from_name};
+
+mod calc;
+mod media;
+mod parser;
+mod selector;
+mod serialize;
+mod supports;
+mod tokenizer;
+mod value;
 
 #[derive(Debug, PartialEq)]
 pub struct Stylesheet {
     pub(crate) rules: Vec<RuleType>,
     pub base_url: Url,
+    pub encoding: &'static Encoding,
+    pub origin: Origin,
+}
+
+/// Where a stylesheet comes from. Later origins win the cascade for normal
+/// declarations and earlier ones win it for `!important` declarations.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
 }
 #[derive(Debug, PartialEq)]
 pub enum RuleType {
     Rule(Rule),
     AtRule(AtRule),
+    Media(MediaRule),
+    Supports(SupportsRule),
+    Import(ImportRule),
+    Layer(LayerRule),
+    /// `@layer a, b;`, which only gives the order of the layers
+    LayerStatement(Vec<String>),
     Comment(String),
 }
 #[derive(Debug, PartialEq)]
@@ -29,11 +63,49 @@ pub struct Rule {
     pub declarations: Vec<Declaration>,
 }
 
+/// An `@media` rule. Its rules apply while the queries match.
+#[derive(Debug, PartialEq)]
+pub struct MediaRule {
+    pub queries: MediaQueryList,
+    pub rules: Vec<RuleType>,
+}
+
+/// An `@supports` rule. Its rules apply if the engine supports the condition.
+#[derive(Debug, PartialEq)]
+pub struct SupportsRule {
+    pub condition: SupportsCondition,
+    pub rules: Vec<RuleType>,
+}
+
+/// An `@import` rule. The loader fills in the sheet it points to, and its
+/// rules apply as if they were in the importing sheet when the conditions do.
+#[derive(Debug, PartialEq)]
+pub struct ImportRule {
+    /// as written, relative to the importing sheet
+    pub url: String,
+    /// the layer the rules go in. it is empty for `layer` without a name.
+    pub layer: Option<String>,
+    pub supports: Option<SupportsCondition>,
+    pub media: MediaQueryList,
+    pub stylesheet: Option<Box<Stylesheet>>,
+}
+
+/// An `@layer` block. A block without a name is a layer of its own that
+/// nothing else can add to.
+#[derive(Debug, PartialEq)]
+pub struct LayerRule {
+    pub name: Option<String>,
+    pub rules: Vec<RuleType>,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct AtRule {
     pub name:String,
     pub value:Option<Value>,
     pub rules: Vec<RuleType>,
+    /// the prelude and block of an at-rule the parser doesn't know, as tokens,
+    /// so it can be written back out
+    pub raw: Option<Vec<Token>>,
 }
 
 
@@ -47,19 +119,114 @@ pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
-    pub pseudo_class: Vec<String>,
+    pub pseudo_class: Vec<PseudoClass>,
+    pub attributes: Vec<AttributeSelector>,
+    /// only ever set on the rightmost compound of a selector
+    pub pseudo_element: Option<PseudoElement>,
 }
 
+/// https://drafts.csswg.org/selectors-4/#pseudo-classes
+#[derive(Debug, PartialEq)]
+pub enum PseudoClass {
+    Root,
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    OnlyOfType,
+    /// `:nth-child(an+b of S)`. the list is empty when there is no `of S`
+    NthChild(Nth, Vec<Selector>),
+    NthLastChild(Nth, Vec<Selector>),
+    NthOfType(Nth),
+    NthLastOfType(Nth),
+    Not(Vec<Selector>),
+    Is(Vec<Selector>),
+    Where(Vec<Selector>),
+    /// each selector starts from a `Scope` compound, so `:has(> img)` is
+    /// stored as `:scope > img`
+    Has(Vec<Selector>),
+    /// the element a `:has()` is being matched for. it adds no specificity
+    Scope,
+    // dynamic states, set on the document by the shell
+    Hover,
+    Active,
+    Focus,
+    Visited,
+    Link,
+    Checked,
+    Disabled,
+    Enabled,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PseudoElement {
+    Before,
+    After,
+}
+
+/// The `an+b` argument of the `:nth-*` pseudo-classes. Elements are counted
+/// from 1.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Nth {
+    pub a: i32,
+    pub b: i32,
+}
+
+impl Nth {
+    pub fn matches(&self, index:i32) -> bool {
+        if self.a == 0 {
+            index == self.b
+        } else {
+            (index - self.b) % self.a == 0 && (index - self.b) / self.a >= 0
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AttributeSelector {
+    /// always lowercase
+    pub name: String,
+    pub op: AttributeOp,
+    /// empty for `AttributeOp::Exists`
+    pub value: String,
+    /// set by the `i` flag, as in `[type="a" i]`
+    pub case_insensitive: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AttributeOp {
+    Exists,    // [attr]
+    Equals,    // [attr=v]
+    Includes,  // [attr~=v]
+    DashMatch, // [attr|=v]
+    Prefix,    // [attr^=v]
+    Suffix,    // [attr$=v]
+    Substring, // [attr*=v]
+}
+
+/// `ancestor` is everything to the left of the combinator and `child` is the
+/// compound selector on its right, so `a > b c` is `(a > b) c`.
 #[derive(Debug, PartialEq)]
 pub struct AncestorSelector {
     pub ancestor: Box<Selector>,
     pub child: Box<Selector>,
-    pub immediate: bool,
+    pub combinator: Combinator,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Combinator {
+    Descendant,        // a b
+    Child,             // a > b
+    NextSibling,       // a + b
+    SubsequentSibling, // a ~ b
 }
 #[derive(Debug, PartialEq, Clone)]
 pub struct Declaration {
-    pub(crate) name: String,
-    pub(crate) value: Value,
+    pub name: String,
+    pub value: Value,
+    pub important: bool,
 }
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
@@ -73,6 +240,8 @@ pub enum Value {
     UnicodeCodepoint(i32),
     UnicodeRange(i32,i32),
     Number(f32),
+    Unparsed(UnparsedValue),
+    Calc(Calc),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -81,6 +250,17 @@ pub enum Unit {
     Em,
     Per,
     Rem,
+    Ex,
+    Ch,
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+    Pt,
+    Pc,
+    In,
+    Cm,
+    Mm,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -91,509 +271,402 @@ pub struct Color {
     pub a:u8,
 }
 impl Color {
-    pub fn from_hex(str:&str) -> Self {
-        let n = i32::from_str_radix(&str[1..], 16).unwrap();
-        let r = (n >> 16) & 0xFF;
-        let g = (n >> 8) & 0xFF;
-        let b = (n/*>>0*/) & 0xFF;
-        Self {
-            r: r as u8,
-            g: g as u8,
-            b: b as u8,
-            a: 255
+    /// Parses `#rgb`, `#rgba`, `#rrggbb` and `#rrggbbaa`.
+    pub fn from_hex(str:&str) -> Option<Self> {
+        let digits = str.strip_prefix('#')?;
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
         }
+        let channels:Vec<u8> = match digits.len() {
+            3 | 4 => digits.chars().map(|c| c.to_digit(16).unwrap() as u8 * 17).collect(),
+            6 | 8 => (0..digits.len()).step_by(2)
+                .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+                .collect(),
+            _ => return None,
+        };
+        Some(Self {
+            r: channels[0],
+            g: channels[1],
+            b: channels[2],
+            a: channels.get(3).cloned().unwrap_or(255),
+        })
+    }
+    /// Evaluates `rgb()`, `rgba()`, `hsl()`, `hsla()` and `hwb()`, in both the
+    /// comma separated and the space separated `/ alpha` syntax.
+    pub fn from_function(name:&str, arguments:&[Value]) -> Option<Self> {
+        let slash = arguments.iter().position(|arg| *arg == Value::Keyword(String::from("/")));
+        let (channels, alpha) = match slash {
+            Some(i) if i + 2 == arguments.len() => (&arguments[..i], Some(&arguments[i + 1])),
+            Some(_) => return None,
+            None if arguments.len() == 4 => (&arguments[..3], Some(&arguments[3])),
+            None => (arguments, None),
+        };
+        if channels.len() != 3 {
+            return None;
+        }
+        let alpha = match alpha {
+            Some(Value::Number(n)) => n.clamp(0.0, 1.0),
+            Some(Value::Length(p, Unit::Per)) => (p / 100.0).clamp(0.0, 1.0),
+            Some(_) => return None,
+            None => 1.0,
+        };
+        let (r, g, b) = match name.to_lowercase().as_str() {
+            "rgb" | "rgba" => {
+                let channel = |value:&Value| match value {
+                    Value::Number(n) => Some(n / 255.0),
+                    Value::Length(p, Unit::Per) => Some(p / 100.0),
+                    _ => None,
+                };
+                (channel(&channels[0])?, channel(&channels[1])?, channel(&channels[2])?)
+            }
+            "hsl" | "hsla" => {
+                let (s, l) = (fraction(&channels[1])?, fraction(&channels[2])?);
+                hsl_to_rgb(hue(&channels[0])?, s, l)
+            }
+            "hwb" => {
+                let (white, black) = (fraction(&channels[1])?, fraction(&channels[2])?);
+                if white + black >= 1.0 {
+                    let gray = white / (white + black);
+                    (gray, gray, gray)
+                } else {
+                    let (r, g, b) = hsl_to_rgb(hue(&channels[0])?, 1.0, 0.5);
+                    let scale = |c:f32| c * (1.0 - white - black) + white;
+                    (scale(r), scale(g), scale(b))
+                }
+            }
+            _ => return None,
+        };
+        let to_u8 = |c:f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Some(Self { r: to_u8(r), g: to_u8(g), b: to_u8(b), a: to_u8(alpha) })
+    }
+    pub fn transparent() -> Self {
+        Self { r: 0, g: 0, b: 0, a: 0 }
     }
     pub fn to_array(&self) -> [f32;4]{
         [(self.r as f32)/255.0, (self.g as f32)/255.0, (self.b as f32)/255.0, (self.a as f32)/255.0]
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct FunCallValue {
-    pub (crate) name:String,
-    pub (crate) arguments: Vec<Value>,
+// hues are numbers of degrees, angles are converted to them when parsed
+fn hue(value:&Value) -> Option<f32> {
+    match value {
+        Value::Number(n) => Some(n.rem_euclid(360.0)),
+        _ => None,
+    }
 }
 
-pub type Specificity = (usize, usize, usize);
-
-impl Selector {
-    pub fn specificity(&self) -> Specificity {
-        if let Selector::Simple(ref simple) = *self {
-            let a = simple.id.iter().count();
-            let b = simple.class.len();
-            let c = simple.tag_name.iter().count();
-            return (a, b, c)
-        }
-        if let Selector::Ancestor(ref anc) = *self {
-            return anc.ancestor.specificity();
-        }
-        panic!("unknown selector type");
+// saturation, lightness, whiteness and blackness, as 0 to 1
+fn fraction(value:&Value) -> Option<f32> {
+    match value {
+        Value::Length(p, Unit::Per) => Some((p / 100.0).clamp(0.0, 1.0)),
+        Value::Number(n) => Some((n / 100.0).clamp(0.0, 1.0)),
+        _ => None,
     }
 }
 
-
-fn space<'a>() -> Parser<'a, u8, ()> {
-    one_of(b" \t\r\n").repeat(0..).discard()
-}
-fn space1<'a>() -> Parser<'a, u8, ()> {
-    one_of(b" \t\r\n").repeat(1..).discard()
+// https://drafts.csswg.org/css-color-4/#hsl-to-rgb
+fn hsl_to_rgb(hue:f32, saturation:f32, lightness:f32) -> (f32, f32, f32) {
+    let channel = |n:f32| {
+        let k = (n + hue / 30.0) % 12.0;
+        let a = saturation * lightness.min(1.0 - lightness);
+        lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    (channel(0.0), channel(8.0), channel(4.0))
 }
 
-fn number<'a>() -> Parser<'a, u8, f64> {
-    let integer = one_of(b"123456789") - one_of(b"0123456789").repeat(0..) | sym(b'0');
-    let frac = sym(b'.') + one_of(b"0123456789").repeat(1..);
-    // let exp = one_of(b"eE") + one_of(b"+-").opt() + one_of(b"0123456789").repeat(1..);
-    let number = sym(b'-').opt() + integer.opt() + frac.opt();// + exp.opt();
-    number.collect().convert(str::from_utf8).convert(|s|f64::from_str(&s))
+/// The tokens of a custom property, or of a value with `var()` in it, which is
+/// parsed once the variables are substituted.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UnparsedValue {
+    pub(crate) tokens: Vec<Token>,
+    /// the shorthand, on the longhands of a shorthand with `var()` in it. the
+    /// tokens are parsed as the shorthand and then expanded.
+    pub(crate) shorthand: Option<String>,
 }
 
-fn string<'a>() -> Parser<'a, u8, String> {
-    let special_char = sym(b'\\') | sym(b'/') | sym(b'"')
-        | sym(b'b').map(|_|b'\x08') | sym(b'f').map(|_|b'\x0C')
-        | sym(b'n').map(|_|b'\n') | sym(b'r').map(|_|b'\r') | sym(b't').map(|_|b'\t');
-    let escape_sequence = sym(b'\\') * special_char;
-    let string = sym(b'"') * (none_of(b"\\\"") | escape_sequence).repeat(0..) - sym(b'"');
-    string.convert(String::from_utf8)
-}
-fn single_quote_string<'a>() -> Parser<'a, u8, String> {
-    (space() * sym(b'\'') * none_of(b"'").repeat(0..) - sym(b'\'')).map(|v|v2s(&v))
-}
-fn double_quote_string<'a>() -> Parser<'a, u8, String> {
-    (space() * sym(b'\"') * none_of(b"\"").repeat(0..) - sym(b'\"')).map(|v|v2s(&v))
+impl UnparsedValue {
+    pub fn to_css(&self) -> String {
+        self.tokens.iter().map(|token| token.to_string()).collect()
+    }
 }
 
-fn v2s(v:&[u8]) -> String {
-    str::from_utf8(v).unwrap().to_string()
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunCallValue {
+    pub name:String,
+    pub arguments: Vec<Value>,
 }
 
-pub fn star(term:u8) -> bool {
-    term == b'*'
-}
+pub type Specificity = (usize, usize, usize);
 
-fn alphanum_string<'a>() -> Parser<'a, u8, String> {
-    is_a(alphanum).repeat(1..).map(|str|v2s(&str))
-}
-fn star_string<'a>() -> Parser<'a, u8, String> {
-    let r = sym(b'*');
-    r.map(|str|v2s(&[str]))
-}
-fn element_name_string<'a>() -> Parser<'a,u8,String> {
-    star_string() | alphanum_string()
-}
-fn dash(term:u8) -> bool {
-    term == b'-'
-}
-fn alphanumdash(term:u8) -> bool {
-    alpha(term) || digit(term) || dash(term)
-}
-fn class_string<'a>() -> Parser<'a,u8,String> {
-    (sym(b'.') + is_a(alphanumdash).repeat(1..)).map(|(_,str)| v2s(&str))
-}
-fn id_string<'a>() -> Parser<'a,u8,String> {
-    (sym(b'#') + is_a(alphanum).repeat(1..)).map(|(_,str)| v2s(&str))
-}
-fn pseudo_class_call<'a>() -> Parser<'a,u8, String> {
-    //:string(stuff)
-    (sym(b'(')
-        * call(simple_selector)
-    - sym(b')')).map(|a|String::from("some-call"))
-}
-fn pseudo_class_string<'a>() -> Parser<'a,u8,String> {
-    (sym(b':')
-        * is_a(alphanumdash).repeat(1..)
-        + pseudo_class_call().opt()
-    ).map(|(str,call)| v2s(&str))
-}
-fn child_combinator<'a>() -> Parser<'a, u8, AncestorSelector> {
-    let r = simple_selector() - space1() - sym(b'>') - space1() + call(selector);
-    r.map(|(a,b)| AncestorSelector{
-        ancestor: Box::new(a),
-        child: Box::new(b),
-        immediate:true,
-    })
-}
-fn descendant_combinator<'a>() -> Parser<'a, u8, AncestorSelector> {
-    let r = simple_selector() - space1() + call(selector);
-    r.map(|(a,b)| AncestorSelector{
-        ancestor: Box::new(a),
-        child: Box::new(b),
-        immediate:false,
-    })
-}
-fn ancestor<'a>() -> Parser<'a,u8,Selector> {
-    (child_combinator() | descendant_combinator()).map(|c|Selector::Ancestor(c))
+impl Selector {
+    /// The pseudo-element the selector styles, if it doesn't style elements.
+    pub fn pseudo_element(&self) -> Option<PseudoElement> {
+        match self {
+            Selector::Simple(simple) => simple.pseudo_element,
+            Selector::Ancestor(anc) => anc.child.pseudo_element(),
+        }
+    }
+    pub fn specificity(&self) -> Specificity {
+        match self {
+            Selector::Simple(simple) => simple.specificity(),
+            Selector::Ancestor(anc) => {
+                let (a1, b1, c1) = anc.ancestor.specificity();
+                let (a2, b2, c2) = anc.child.specificity();
+                (a1 + a2, b1 + b2, c1 + c2)
+            }
+        }
+    }
 }
 
+impl SimpleSelector {
+    pub fn specificity(&self) -> Specificity {
+        let a = self.id.iter().count();
+        let b = self.class.len() + self.attributes.len();
+        let c = self.tag_name.iter().filter(|name| *name != "*").count() + self.pseudo_element.iter().count();
+        self.pseudo_class.iter().fold((a, b, c), |(a, b, c), pc| {
+            let (a2, b2, c2) = pc.specificity();
+            (a + a2, b + b2, c + c2)
+        })
+    }
+}
 
-fn string_literal<'a>() -> Parser<'a, u8, Value> {
-    (single_quote_string() | double_quote_string()).map(StringLiteral)
+impl PseudoClass {
+    pub fn specificity(&self) -> Specificity {
+        let most_specific = |list:&Vec<Selector>| list.iter().map(|sel| sel.specificity()).max().unwrap_or((0, 0, 0));
+        match self {
+            PseudoClass::Where(_) | PseudoClass::Scope => (0, 0, 0),
+            PseudoClass::Not(list) | PseudoClass::Is(list) | PseudoClass::Has(list) => most_specific(list),
+            PseudoClass::NthChild(_, list) | PseudoClass::NthLastChild(_, list) => {
+                let (a, b, c) = most_specific(list);
+                (a, b + 1, c)
+            }
+            _ => (0, 1, 0),
+        }
+    }
 }
 
 #[test]
 fn test_string_literal() {
-    assert_eq!(string_literal().parse(br#""foo""#),
+    assert_eq!(value(br#""foo""#),
                Ok(Value::StringLiteral(String::from("foo"))));
 }
 
-fn simple_selector<'a>() -> Parser<'a, u8, Selector> {
-    let p = (
-        element_name_string().opt()
-        + id_string().opt()
-        + class_string().opt()
-        + pseudo_class_string().opt()
-        );
-    p.convert(|(((a,i),c),b)| {
-        // println!("simple selectors {:#?} {:#?} {:#?} {:#?}",a,i,c,b);
-        if a.is_none() && i.is_none() && c.is_none() && b.is_none() {
-            return Result::Err("warning, nothing matched")
-        }
-        let mut sel = SimpleSelector{
-            tag_name: None,
-            id: None,
-            class: vec![],
-            pseudo_class: vec![]
-        };
-        if let Some(element_name) = a {
-            sel.tag_name = Some(element_name);
-        }
-        if let Some(id_name) = i {
-            sel.id = Some(id_name);
-        }
-        if let Some(class_string) = c {
-            sel.class.push(class_string)
-        }
-        if let Some(pseudo_class_string) = b {
-            sel.pseudo_class.push(pseudo_class_string);
-        }
-        Result::Ok(Selector::Simple(sel))
-    }
-    )
-}
-fn selector<'a>() -> Parser<'a, u8, Selector>{
-    let r
-        = space()
-        + (ancestor() | simple_selector())
-        - space()
-    ;
-    r.map(|(_, selector)| selector)
-}
-
 #[test]
 fn test_selectors() {
-    assert_eq!(selector().parse(b"div"),
+    assert_eq!(selector(b"div"),
                Ok(Selector::Simple(SimpleSelector {
                    tag_name: Some("div".to_string()),
                    id: None,
                    class: vec![],
-                   pseudo_class: vec![]
+                   pseudo_class: vec![],
+                   attributes: vec![],
+                   pseudo_element: None,
                })));
-    assert_eq!(selector().parse(b"h3"),
+    assert_eq!(selector(b"h3"),
                Ok(Selector::Simple(SimpleSelector {
                    tag_name: Some("h3".to_string()),
                    id: None,
                    class: vec![],
-                   pseudo_class: vec![]
+                   pseudo_class: vec![],
+                   attributes: vec![],
+                   pseudo_element: None,
                })));
-    assert_eq!(selector().parse(b".cool"),
+    assert_eq!(selector(b".cool"),
                Ok(Selector::Simple(SimpleSelector {
                    tag_name: None,
                    id: None,
                    class: vec![String::from("cool")],
-                   pseudo_class: vec![]
+                   pseudo_class: vec![],
+                   attributes: vec![],
+                   pseudo_element: None,
                })));
-    assert_eq!(selector().parse(b"div.cool"),
+    assert_eq!(selector(b"div.cool"),
                Ok(Selector::Simple(SimpleSelector {
                    tag_name: Some(String::from("div")),
                    id: None,
                    class: vec![String::from("cool")],
-                   pseudo_class: vec![]
+                   pseudo_class: vec![],
+                   attributes: vec![],
+                   pseudo_element: None,
                })));
 }
+#[cfg(test)]
+fn type_selector(name:&str) -> Box<Selector> {
+    Box::new(Selector::Simple(SimpleSelector{
+        tag_name:Some(String::from(name)),
+        id: None,
+        class: vec![],
+        pseudo_class: vec![],
+        attributes: vec![],
+        pseudo_element: None,
+    }))
+}
 #[test]
 fn test_ancestor_selector() {
-    assert_eq!(selector().parse(b"a b"),
+    assert_eq!(selector(b"a b"),
                Ok(Selector::Ancestor(AncestorSelector{
-                   ancestor:Box::new(Selector::Simple(SimpleSelector{
-                       tag_name:Some(String::from("a")),
-                       id: None,
-                       class: vec![],
-                       pseudo_class: vec![]
-                   })),
-                   child:Box::new(Selector::Simple(SimpleSelector{
-                       tag_name:Some(String::from("b")),
-                       id: None,
-                       class: vec![],
-                       pseudo_class: vec![]
-                   })),
-                   immediate:false,
+                   ancestor:type_selector("a"),
+                   child:type_selector("b"),
+                   combinator:Combinator::Descendant,
                })));
-    assert_eq!(selector().parse(b"a > b"),
+    assert_eq!(selector(b"a > b"),
                Ok(Selector::Ancestor(AncestorSelector{
-                   ancestor:Box::new(Selector::Simple(SimpleSelector{
-                       tag_name:Some(String::from("a")),
-                       id: None,
-                       class: vec![],
-                       pseudo_class: vec![]
-                   })),
-                   child:Box::new(Selector::Simple(SimpleSelector{
-                       tag_name:Some(String::from("b")),
-                       id: None,
-                       class: vec![],
-                       pseudo_class: vec![]
-                   })),
-                   immediate:true,
+                   ancestor:type_selector("a"),
+                   child:type_selector("b"),
+                   combinator:Combinator::Child,
                })));
-    assert_eq!(selector().parse(b"div.epigraph > blockquote"),
+    assert_eq!(selector(b"div.epigraph > blockquote"),
                Ok(Selector::Ancestor(AncestorSelector{
                    ancestor:Box::new(Selector::Simple(SimpleSelector{
                        tag_name:Some(String::from("div")),
                        id: None,
                        class: vec![String::from("epigraph")],
-                       pseudo_class: vec![]
-                   })),
-                   child:Box::new(Selector::Simple(SimpleSelector{
-                       tag_name:Some(String::from("blockquote")),
-                       id: None,
-                       class: vec![],
-                       pseudo_class: vec![]
+                       pseudo_class: vec![],
+                       attributes: vec![],
+                       pseudo_element: None,
                    })),
-                   immediate:true,
+                   child:type_selector("blockquote"),
+                   combinator:Combinator::Child,
                })));
-    assert_eq!(selector().parse(b"a > b > c"),
+    assert_eq!(selector(b"a > b > c"),
                Ok(Selector::Ancestor(AncestorSelector{
-                   ancestor:Box::new(Selector::Simple(SimpleSelector{
-                       tag_name:Some(String::from("a")),
-                       id: None,
-                       class: vec![],
-                       pseudo_class: vec![]
+                   ancestor:Box::new(Selector::Ancestor(AncestorSelector{
+                       ancestor: type_selector("a"),
+                       child: type_selector("b"),
+                       combinator: Combinator::Child,
                    })),
-                   child:Box::new(Selector::Ancestor(AncestorSelector{
-                       ancestor: Box::new(Selector::Simple(SimpleSelector{
-                           tag_name:Some(String::from("b")),
-                           id: None,
-                           class: vec![],
-                           pseudo_class: vec![]
-                       })),
-                       child: Box::new(Selector::Simple(SimpleSelector{
-                           tag_name:Some(String::from("c")),
-                           id: None,
-                           class: vec![],
-                           pseudo_class: vec![]
-                       })),
-                       immediate: true
-                   })),
-                   immediate:true,
+                   child:type_selector("c"),
+                   combinator:Combinator::Child,
                })));
-    assert_eq!(selector().parse(b"a b c"),
+    assert_eq!(selector(b"a b+c"),
                Ok(Selector::Ancestor(AncestorSelector{
-                   ancestor:Box::new(Selector::Simple(SimpleSelector{
-                       tag_name:Some(String::from("a")),
-                       id: None,
-                       class: vec![],
-                       pseudo_class: vec![]
+                   ancestor:Box::new(Selector::Ancestor(AncestorSelector{
+                       ancestor: type_selector("a"),
+                       child: type_selector("b"),
+                       combinator: Combinator::Descendant,
                    })),
-                   child:Box::new(Selector::Ancestor(AncestorSelector{
-                       ancestor: Box::new(Selector::Simple(SimpleSelector{
-                           tag_name:Some(String::from("b")),
-                           id: None,
-                           class: vec![],
-                           pseudo_class: vec![]
-                       })),
-                       child: Box::new(Selector::Simple(SimpleSelector{
-                           tag_name:Some(String::from("c")),
-                           id: None,
-                           class: vec![],
-                           pseudo_class: vec![]
-                       })),
-                       immediate: false
-                   })),
-                   immediate:false,
+                   child:type_selector("c"),
+                   combinator:Combinator::NextSibling,
                })));
 }
 
 #[test]
 fn test_all_selector() {
     let input = br#"*"#;
-    let result = selector().parse(input);
+    let result = selector(input);
     println!("{:?}", result);
     assert_eq!(Selector::Simple(SimpleSelector{
         tag_name:Some("*".to_string()),
         id: None,
         class: vec![],
-        pseudo_class: vec![]
+        pseudo_class: vec![],
+        attributes: vec![],
+        pseudo_element: None,
     }), result.unwrap())
 }
 
 #[test]
 fn test_pseudo_selector() {
-    assert_eq!(selector().parse(b":link"), Ok(Selector::Simple(SimpleSelector {
+    assert_eq!(selector(b":link"), Ok(Selector::Simple(SimpleSelector {
         tag_name: None,
         id: None,
         class: vec![],
-        pseudo_class: vec![String::from("link")]
+        pseudo_class: vec![PseudoClass::Link],
+        attributes: vec![],
+        pseudo_element: None,
     })));
 
-    assert_eq!(rule().parse(b"a:link, a:visited { }"),
+    assert_eq!(rule(b"a:link, a:visited { }"),
                Ok(RuleType::Rule(Rule{
                    selectors: vec![
                        Selector::Simple(SimpleSelector{
                            tag_name: Some(String::from("a")),
                            id: None,
                            class: vec![],
-                           pseudo_class: vec![String::from("link")]
+                           pseudo_class: vec![PseudoClass::Link],
+                           attributes: vec![],
+                           pseudo_element: None,
                        }),
                        Selector::Simple(SimpleSelector{
                            tag_name: Some(String::from("a")),
                            id: None,
                            class: vec![],
-                           pseudo_class: vec![String::from("visited")]
+                           pseudo_class: vec![PseudoClass::Visited],
+                           attributes: vec![],
+                           pseudo_element: None,
                        })
                    ],
                    declarations: vec![]
                })
 
                ));
-    assert_eq!(selector().parse(b".no-tufte-underline:link"), Ok(Selector::Simple(SimpleSelector{
+    assert_eq!(selector(b".no-tufte-underline:link"), Ok(Selector::Simple(SimpleSelector{
         tag_name: None,
         id: None,
         class: vec![String::from("no-tufte-underline")],
-        pseudo_class: vec![String::from("link")]
+        pseudo_class: vec![PseudoClass::Link],
+        attributes: vec![],
+        pseudo_element: None,
     })));
 
-    assert_eq!(rule().parse(b"li:not(:first-child), b { }"),Ok(RuleType::Rule(Rule{
+    assert_eq!(rule(b"li:not(:first-child), b { }"),Ok(RuleType::Rule(Rule{
         selectors: vec![
             Selector::Simple(SimpleSelector {
                 tag_name: Some(String::from("li")),
                 id: None,
                 class: vec![],
-                pseudo_class: vec![String::from("not")]
+                pseudo_class: vec![PseudoClass::Not(vec![Selector::Simple(SimpleSelector {
+                    tag_name: None,
+                    id: None,
+                    class: vec![],
+                    pseudo_class: vec![PseudoClass::FirstChild],
+                    attributes: vec![],
+                    pseudo_element: None,
+                })])],
+                attributes: vec![],
+                pseudo_element: None,
             }),
             Selector::Simple(SimpleSelector {
                 tag_name: Some(String::from("b")),
                 id: None,
                 class: vec![],
-                pseudo_class: vec![]
+                pseudo_class: vec![],
+                attributes: vec![],
+                pseudo_element: None,
             }),
         ],
         declarations: vec![]
     })));
 }
 
-fn identifier<'a>() -> Parser<'a, u8, String> {
-    let r
-        = space()
-        * sym(b'-').opt()
-        + is_a(alpha)
-        + (is_a(alphanum) | sym(b'-')).repeat(0..)
-        ;
-    r.map(|((dash,uu),v)| {
-        let mut vv = vec![uu];
-        vv.extend(&v);
-        if let Some(dash) = dash {
-            vv.insert(0,dash)
-        }
-        v2s(&vv)
-    })
-}
 #[test]
 fn test_identifier() {
     let input = br"bar";
-    println!("{:?}",identifier().parse(input));
+    println!("{:?}",value(input));
 }
 
-fn unit_px<'a>() -> Parser<'a, u8, Unit> {
-    seq(b"px").map(|_| Unit::Px)
-}
-fn unit_per<'a>() -> Parser<'a, u8, Unit> {
-    seq(b"%").map(|_| Unit::Per)
-}
-fn unit_rem<'a>() -> Parser<'a, u8, Unit> {
-    seq(b"rem").map(|_| Unit::Rem)
-}
-fn unit_em<'a>() -> Parser<'a, u8, Unit> {
-    seq(b"em").map(|_| Unit::Em)
-}
-fn unit<'a>() -> Parser<'a, u8, Unit> {
-    unit_per() | unit_px() | unit_rem() | unit_em()
-}
 #[test]
 fn test_unit() {
-    assert_eq!(unit().parse(b"px"),Ok(Unit::Px));
-    assert_eq!(unit().parse(b"em"),Ok(Unit::Em));
+    assert_eq!(unit_from_name("px"),Some(Unit::Px));
+    assert_eq!(unit_from_name("em"),Some(Unit::Em));
+    assert_eq!(unit_from_name("vmax"),Some(Unit::Vmax));
+    assert_eq!(unit_from_name("mm"),Some(Unit::Mm));
 }
 
-fn length_unit<'a>() -> Parser<'a, u8, Value> {
-    let p = number() + unit();
-    p.map(|(v,unit)| {
-        Value::Length(v as f32,unit)
-    })
-}
 
 #[test]
 fn test_length_units() {
-    assert_eq!(length_unit().parse(br"3px"), Ok(Length(3.0,Unit::Px)));
-    assert_eq!(length_unit().parse(br"3em"), Ok(Length(3.0,Unit::Em)));
-    assert_eq!(length_unit().parse(br"0.3em"), Ok(Length(0.3,Unit::Em)));
-    assert_eq!(length_unit().parse(br".3em"), Ok(Length(0.3,Unit::Em)));
-}
-
-fn funarg<'a>() -> Parser<'a, u8, Value> {
-    string_literal() | hexcolor() | length_unit() | keyword()
-}
-
-fn normal_funcall<'a>() -> Parser<'a, u8, Value> {
-    let p
-        = space()
-        + identifier()
-        - space()
-        - sym(b'(')
-        - space()
-        + list(funarg(),space() - sym(b',') - space())
-        -space()
-        - sym(b')');
-    p.map(|((_,name), arguments)| Value::FunCall(FunCallValue{
-        name,
-        arguments
-    }))
-}
-//format('woff2')
-fn format_follower<'a>() -> Parser<'a, u8, Value> {
-    let p = seq(b"format")
-        - sym(b'(')
-        + (string_literal() | url())
-        - sym(b')');
-    p.map(|(_a,b)|Value::FunCall(FunCallValue{
-        name: "format".to_string(),
-        arguments: vec![b]
-    }))
-}
-fn url_funcall<'a>() -> Parser<'a, u8, Value> {
-    let p
-        = space()
-        - seq(b"url")
-        - space()
-        - sym(b'(')
-        + (string_literal() | url())
-        - sym(b')')
-        - space()
-        + format_follower().opt()
-        ;
-    p.map(|((_a,url),_format)| Value::FunCall(FunCallValue{
-        name: "url".to_string(),
-        arguments: vec![url]
-    }))
-
-}
-
-fn funcall<'a>() -> Parser<'a, u8, Value> {
-    url_funcall() | normal_funcall()
+    assert_eq!(value(br"3px"), Ok(Length(3.0,Unit::Px)));
+    assert_eq!(value(br"3em"), Ok(Length(3.0,Unit::Em)));
+    assert_eq!(value(br"0.3em"), Ok(Length(0.3,Unit::Em)));
+    assert_eq!(value(br".3em"), Ok(Length(0.3,Unit::Em)));
 }
 
 #[test]
 fn test_funcall_value() {
-    assert_eq!(funcall().parse(br"foo()"),
+    assert_eq!(value(br"foo()"),
                Ok(Value::FunCall(FunCallValue{ name: "foo".parse().unwrap(), arguments: vec![] })));
-    assert_eq!(funcall().parse(br"foo(keyword, keyword)"),
+    assert_eq!(value(br"foo(keyword, keyword)"),
                Ok(Value::FunCall(FunCallValue{
                    name: String::from("foo"),
                    arguments: vec![
@@ -601,7 +674,7 @@ fn test_funcall_value() {
                        Keyword(String::from("keyword")),
                    ] })
                ));
-    assert_eq!(funcall().parse(br"foo(#fffff8,#fffff8)"),
+    assert_eq!(value(br"foo(#fffff8,#fffff8)"),
                Ok(Value::FunCall(FunCallValue{
                    name: String::from("foo"),
                    arguments: vec![
@@ -609,7 +682,9 @@ fn test_funcall_value() {
                        Value::HexColor(String::from("#fffff8")),
                    ] })
                ));
-    assert_eq!(funcall().parse(br" foo ( #fffff8 , #fffff8 ) "),
+    // a function's name is right up against its paren
+    assert_eq!(value(br" foo ( #fffff8 , #fffff8 ) "), Err(()));
+    assert_eq!(value(br" foo( #fffff8 , #fffff8 ) "),
                Ok(Value::FunCall(FunCallValue{
                    name: String::from("foo"),
                    arguments: vec![
@@ -617,7 +692,7 @@ fn test_funcall_value() {
                        Value::HexColor(String::from("#fffff8")),
                    ] })
                ));
-    assert_eq!(funcall().parse(br" linear-gradient ( #fffff8 , #fffff8 ) "),
+    assert_eq!(value(br" linear-gradient( #fffff8 , #fffff8 ) "),
                Ok(Value::FunCall(FunCallValue{
                    name: String::from("linear-gradient"),
                    arguments: vec![
@@ -625,7 +700,7 @@ fn test_funcall_value() {
                        Value::HexColor(String::from("#fffff8")),
                    ] })
                ));
-    assert_eq!(declaration().parse(br"foo:linear-gradient(#fffff8,#fffff8);"),
+    assert_eq!(declaration(br"foo:linear-gradient(#fffff8,#fffff8);"),
                Ok(Declaration {
                    name: String::from("foo"),
                    value:Value::FunCall(FunCallValue{
@@ -634,11 +709,12 @@ fn test_funcall_value() {
                            Value::HexColor(String::from("#fffff8")),
                            Value::HexColor(String::from("#fffff8")),
                        ],
-                   })
+                   }),
+                   important: false
                }
                ));
     //check url with double quotes
-    assert_eq!(declaration().parse(br#"foo:url("https://www.google.com/");"#),
+    assert_eq!(declaration(br#"foo:url("https://www.google.com/");"#),
                Ok(Declaration {
                    name: String::from("foo"),
                    value:Value::FunCall(FunCallValue{
@@ -646,11 +722,12 @@ fn test_funcall_value() {
                        arguments: vec![
                            Value::StringLiteral(String::from("https://www.google.com/")),
                        ],
-                   })
+                   }),
+                   important: false
                }
                ));
     //check url with single quotes
-    assert_eq!(declaration().parse(br"foo:url('https://www.google.com/');"),
+    assert_eq!(declaration(br"foo:url('https://www.google.com/');"),
                Ok(Declaration {
                    name: String::from("foo"),
                    value:Value::FunCall(FunCallValue{
@@ -658,11 +735,12 @@ fn test_funcall_value() {
                        arguments: vec![
                            Value::StringLiteral(String::from("https://www.google.com/")),
                        ],
-                   })
+                   }),
+                   important: false
                }
                ));
     //check url with no quotes
-    assert_eq!(declaration().parse(br"foo:url(https://www.google.com/);"),
+    assert_eq!(declaration(br"foo:url(https://www.google.com/);"),
                Ok(Declaration {
                    name: String::from("foo"),
                    value:Value::FunCall(FunCallValue{
@@ -670,152 +748,114 @@ fn test_funcall_value() {
                        arguments: vec![
                            Value::StringLiteral(String::from("https://www.google.com/")),
                        ],
-                   })
+                   }),
+                   important: false
                }
                ));
 }
 
-fn simple_number<'a>() -> Parser<'a, u8, Value> {
-    let p = number();
-    p.map(|v| Value::Number(v as f32))
-}
-fn hexcolor<'a>() -> Parser<'a, u8, Value> {
-    let p = sym(b'#')
-        + (  one_of(b"0123456789ABCDEFabcdef").repeat(6..7)
-            | one_of(b"0123456789ABCDEFabcdef").repeat(3..4));
-    p.map(|(a,mut c)| {
-        c.insert(0,b'#');
-        Value::HexColor(v2s(&c).to_lowercase())
-    })
-}
 
 #[test]
 fn test_hexcolor() {
     let input = br"#4455fF";
-    let result = hexcolor().parse(input);
+    let result = value(input);
     println!("{:?}", result);
     assert_eq!( Value::HexColor("#4455FF".to_lowercase()), result.unwrap());
-    assert_eq!( Ok(Value::HexColor("#333".to_lowercase())), hexcolor().parse(br"#333"));
+    assert_eq!( Ok(Value::HexColor("#333".to_lowercase())), value(br"#333"));
+    assert_eq!( Ok(Value::HexColor("#3338".to_lowercase())), value(br"#3338"));
+    assert_eq!( Ok(Value::HexColor("#44556680".to_lowercase())), value(br"#44556680"));
 }
 
-
-fn keyword<'a>() -> Parser<'a, u8, Value> {
-    let r
-        = space()
-        + (is_a(|term:u8| {
-            (term >= 0x41 && term < 0x5A) || (term >= 0x61 && term <= 0x7A) || (term == '-' as u8)
-            })).repeat(1..)
-        ;
-    r.map(|(_,c)| {
-        Value::Keyword(String::from_utf8(c).unwrap())
-    })
+#[test]
+fn test_colors() {
+    let color = |r, g, b, a| Some(Color { r, g, b, a });
+    assert_eq!(Color::from_hex("#f00"), color(255, 0, 0, 255));
+    assert_eq!(Color::from_hex("#f008"), color(255, 0, 0, 136));
+    assert_eq!(Color::from_hex("#4455ff"), color(0x44, 0x55, 0xff, 255));
+    assert_eq!(Color::from_hex("#4455ff80"), color(0x44, 0x55, 0xff, 128));
+    assert_eq!(Color::from_hex("#4455f"), None);
+    assert_eq!(Color::from_hex("#ggg"), None);
+    assert_eq!(Color::from_hex("4455ff"), None);
+
+    let eval = |input:&[u8]| match value(input) {
+        Ok(Value::FunCall(f)) => Color::from_function(&f.name, &f.arguments),
+        other => panic!("not a function {:?}", other),
+    };
+    assert_eq!(eval(b"rgb(255,0,0)"), color(255, 0, 0, 255));
+    assert_eq!(eval(b"rgba(255, 0, 0, 0.5)"), color(255, 0, 0, 128));
+    assert_eq!(eval(b"rgb(100% 50% 0% / 25%)"), color(255, 128, 0, 64));
+    assert_eq!(eval(b"RGB(0 0 255)"), color(0, 0, 255, 255));
+    assert_eq!(eval(b"hsl(120, 100%, 50%)"), color(0, 255, 0, 255));
+    assert_eq!(eval(b"hsla(0.5turn 100% 25% / 0.5)"), color(0, 128, 128, 128));
+    assert_eq!(eval(b"hsl(240deg 100% 50%)"), color(0, 0, 255, 255));
+    assert_eq!(eval(b"hwb(0 0% 0%)"), color(255, 0, 0, 255));
+    assert_eq!(eval(b"hwb(90 50% 50%)"), color(128, 128, 128, 255));
+    assert_eq!(eval(b"rgb(255 0)"), None);
+    assert_eq!(eval(b"rgb(255 0 0 /)"), None);
+    assert_eq!(eval(b"lab(50% 0 0)"), None);
 }
 
 #[test]
 fn test_keyword() {
     let input = br"black";
-    println!("{:#?}",keyword().parse(input))
+    println!("{:#?}",value(input))
 }
 #[test]
 fn test_keyword_dash() {
     let input = b"inline-block";
-    let result = keyword().parse(input);
+    let result = value(input);
     println!("{:?}", result);
     assert_eq!( Value::Keyword("inline-block".to_lowercase()), result.unwrap());
 }
 
-fn url<'a>() -> Parser<'a, u8, Value> {
-    let p = none_of(b")>").repeat(1..);
-    p.map(|s|{
-        Value::StringLiteral(v2s(&s))
-    })
-}
-
-fn hex4<'a>() -> Parser<'a,u8,i32> {
-    one_of(b"0123456789ABCDEFabcdef").repeat(4).map(|c| i32::from_str_radix(&v2s(&c),16).unwrap())
-}
-
-fn unicode_codepoint<'a>() -> Parser<'a, u8, Value> {
-    (space() * seq(b"U+") * hex4()).map(Value::UnicodeCodepoint)
-}
 #[test]
 fn test_unicode_codepoint() {
-    assert_eq!(one_value().parse(b"U+0100"),Ok(Value::UnicodeCodepoint(0x100)));
-    assert_eq!(one_value().parse(b" U+0100"),Ok(Value::UnicodeCodepoint(0x100)));
+    assert_eq!(value(b"U+0100"),Ok(Value::UnicodeCodepoint(0x100)));
+    assert_eq!(value(b" U+0100"),Ok(Value::UnicodeCodepoint(0x100)));
 }
 
-fn unicode_range<'a>() -> Parser<'a, u8, Value> {
-    // U+0100-024F
-    (space() - seq(b"U+") + hex4() - sym(b'-')+hex4()).map(|((_,a),b)|Value::UnicodeRange(a,b))
-}
 #[test]
 fn test_unicode_range() {
-    assert_eq!(one_value().parse(b"U+0100-024F"),Ok(Value::UnicodeRange(0x100, 0x24f)));
+    assert_eq!(value(b"U+0100-024F"),Ok(Value::UnicodeRange(0x100, 0x24f)));
 }
 
-
-fn one_value<'a>() -> Parser<'a, u8, Value> {
-    unicode_range() | unicode_codepoint() | funcall() | hexcolor() | length_unit() | keyword() | string_literal() | simple_number()
-}
-
-fn list_array_value<'a>() -> Parser<'a, u8, Value> {
-    let p = list(one_value(), sym(b','));
-        p.map(|a| {
-            if a.len() == 1 {
-                a[0].clone()
-            } else {
-                Value::ArrayValue(a)
-            }
-        })
-}
-fn array_value_2<'a>() -> Parser<'a, u8, Value> {
-    let t = one_value() - space() + one_value();
-    t.map(|(v1,v2)|{
-        Value::ArrayValue(vec![v1,v2])
-    })
-}
-
-fn array_value_3<'a>() -> Parser<'a, u8, Value> {
-    let t = one_value() - space() + one_value() - space() + one_value();
-    t.map(|((v1,v2),v3)|{
-        Value::ArrayValue(vec![v1,v2,v3])
-    })
-}
-
-fn array_value_4<'a>() -> Parser<'a, u8, Value> {
-    let t = one_value() - space() + one_value() - space() + one_value() - space() + one_value();
-    t.map(|(((v1,v2),v3),v4)|{
-        Value::ArrayValue(vec![v1,v2,v3,v4])
-    })
-}
 #[test]
 fn test_array_values() {
-    assert_eq!(array_value_2().parse(b"3px 4px"),
+    assert_eq!(value(b"3px 4px"),
                Ok(Value::ArrayValue(vec![Value::Length(3.0,Unit::Px), Value::Length(4.0,Unit::Px)])));
-    assert_eq!(array_value_2().parse(b"3em 4.0rem"),
+    assert_eq!(value(b"3em 4.0rem"),
                Ok(Value::ArrayValue(vec![Value::Length(3.0,Unit::Em), Value::Length(4.0,Unit::Rem)])));
-    assert_eq!(array_value_2().parse(b"0.3em 0.4rem"),
+    assert_eq!(value(b"0.3em 0.4rem"),
                Ok(Value::ArrayValue(vec![Value::Length(0.3,Unit::Em), Value::Length(0.4,Unit::Rem)])));
-    assert_eq!(array_value_2().parse(b".3em 0.4rem"),
+    assert_eq!(value(b".3em 0.4rem"),
                Ok(Value::ArrayValue(vec![Value::Length(0.3,Unit::Em), Value::Length(0.4,Unit::Rem)])));
-    assert_eq!(array_value_3().parse(b"1px solid black"),
+    assert_eq!(value(b"1px solid black"),
                Ok(Value::ArrayValue(vec![Value::Length(1.0,Unit::Px),
                                          Value::Keyword(String::from("solid")),
                                          Value::Keyword(String::from("black"))])));
-    assert_eq!(array_value_3().parse(b"1px solid #cccccc"),
+    assert_eq!(value(b"1px solid #cccccc"),
                Ok(Value::ArrayValue(vec![Value::Length(1.0,Unit::Px),
                                          Value::Keyword(String::from("solid")),
                                          Value::HexColor(String::from("#cccccc"))])));
-    assert_eq!(value().parse(b"1px solid #cccccc"),
+    assert_eq!(value(b"1px solid #cccccc"),
                Ok(Value::ArrayValue(vec![Value::Length(1.0,Unit::Px),
                                          Value::Keyword(String::from("solid")),
                                          Value::HexColor(String::from("#cccccc"))])));
+    assert_eq!(value(b"italic bold 12px/1.5 Georgia, serif"),
+               Ok(Value::ArrayValue(vec![Value::Keyword(String::from("italic")),
+                                         Value::Keyword(String::from("bold")),
+                                         Value::Length(12.0,Unit::Px),
+                                         Value::Keyword(String::from("/")),
+                                         Value::Number(1.5),
+                                         Value::ArrayValue(vec![Value::Keyword(String::from("Georgia")),
+                                                                Value::Keyword(String::from("serif"))])])));
+    assert_eq!(value(b"1px 2px 3px 4px 5px").map(|v| matches!(v, Value::ArrayValue(items) if items.len() == 5)), Ok(true));
 }
 
 #[test]
 fn test_list_array_values() {
     assert_eq!(
-        list_array_value().parse(br#" et-book, Palatino, "Palatino Linotype", "Palatino LT STD", "Book Antiqua", Georgia, serif"#),
+        value(br#" et-book, Palatino, "Palatino Linotype", "Palatino LT STD", "Book Antiqua", Georgia, serif"#),
         Ok(Value::ArrayValue(vec![
             Value::Keyword(String::from("et-book")),
             Value::Keyword(String::from("Palatino")),
@@ -828,69 +868,32 @@ fn test_list_array_values() {
     );
 }
 
-fn value<'a>() -> Parser<'a, u8, Value> {
-    call(array_value_4)
-        | call(array_value_3)
-        | call(array_value_2)
-        | call(list_array_value)
-        | one_value()
-}
-
-
-fn declaration<'a>() -> Parser<'a, u8, Declaration> {
-    let r = space()
-        + identifier()
-        - (space() - sym(b':') - space())
-        + value()
-        - (space() - sym(b';') - space())
-    ;
-    r.map(|(((), name), value)| Declaration { name, value })
-}
-
 #[test]
 fn test_prop_def() {
     let input = br#"border:black;"#;
-    println!("{:?}", declaration().parse(input))
+    println!("{:?}", declaration(input))
 }
 #[test]
 fn test_prop_def2() {
     let input = b"border-color:black;";
-    println!("{:?}", declaration().parse(input))
+    println!("{:?}", declaration(input))
 }
 #[test]
 fn test_prop_def3() {
     let input = b"border-width:1px;";
-    println!("{:?}", declaration().parse(input))
+    println!("{:?}", declaration(input))
 }
 #[test]
 fn test_prop_def4() {
     let input = b"border-color:#ff00aa;";
-    let result = declaration().parse(input);
+    let result = declaration(input);
     println!("{:?}", result);
     assert_eq!(Declaration {
         name: "border-color".to_string(),
-        value: Value::HexColor("#ff00aa".to_lowercase())
+        value: Value::HexColor("#ff00aa".to_lowercase()),
+        important: false
     },result.unwrap());
-    println!("{:?}", declaration().parse(input))
-}
-
-fn ws_sym<'a>(ch:u8) -> Parser<'a, u8,u8> {
-    space() * sym(ch) - space()
-}
-
-fn rule<'a>() -> Parser<'a, u8, RuleType> {
-    let r
-        = list(selector(),sym(b','))
-        - ws_sym(b'{')
-        - comment().opt()
-        + declaration().repeat(0..)
-        - comment().opt()
-        - ws_sym(b'}')
-        ;
-    r.map(|(sel, declarations)| RuleType::Rule(Rule {
-        selectors: sel,
-        declarations,
-    }))
+    println!("{:?}", declaration(input))
 }
 
 #[test]
@@ -902,114 +905,148 @@ fn test_multipart_selector() {
     }
     "#;
 //    div.epigraph > blockquote > p {
-    assert_eq!(rule().parse(input),Ok(RuleType::Rule(Rule{
+    assert_eq!(rule(input),Ok(RuleType::Rule(Rule{
         selectors: vec![Selector::Ancestor(
             AncestorSelector {
                 ancestor: Box::new(Selector::Simple(SimpleSelector{
                     tag_name: Some(String::from("div")),
                     id: None,
                     class: vec![String::from("epigraph")],
-                    pseudo_class: vec![]
+                    pseudo_class: vec![],
+                    attributes: vec![],
+                    pseudo_element: None,
                 })),
-                child: Box::new(Selector::Simple(SimpleSelector{
-                    tag_name: Some(String::from("blockquote")),
-                    id: None,
-                    class: vec![],
-                    pseudo_class: vec![]
-                })),
-                immediate: true
+                child: type_selector("blockquote"),
+                combinator: Combinator::Child,
             }),
             Selector::Ancestor(
                 AncestorSelector {
-                    ancestor: Box::new(Selector::Simple(SimpleSelector{
-                        tag_name: Some(String::from("div")),
-                        id: None,
-                        class: vec![String::from("epigraph")],
-                        pseudo_class: vec![]
-                    })),
-                    child: Box::new(Selector::Ancestor(
+                    ancestor: Box::new(Selector::Ancestor(
                         AncestorSelector {
                             ancestor: Box::new(Selector::Simple(SimpleSelector{
-                                tag_name: Some(String::from("blockquote")),
+                                tag_name: Some(String::from("div")),
                                 id: None,
-                                class: vec![],
-                                pseudo_class: vec![]
+                                class: vec![String::from("epigraph")],
+                                pseudo_class: vec![],
+                                attributes: vec![],
+                                pseudo_element: None,
                             })),
-                            child: Box::new(Selector::Simple(SimpleSelector{
-                                tag_name: Some(String::from("p")),
-                                id: None,
-                                class: vec![],
-                                pseudo_class: vec![]
-                            })),
-                            immediate: true
+                            child: type_selector("blockquote"),
+                            combinator: Combinator::Child,
                         }
                     )),
-                    immediate: true
+                    child: type_selector("p"),
+                    combinator: Combinator::Child,
                 }
             )
         ],
         declarations: vec![
             Declaration{
                 name: "font-style".to_string(),
-                value: Value::Keyword("italic".to_string())
+                value: Value::Keyword("italic".to_string()),
+                important: false
             }
         ]
     })))
 }
 
-fn comment<'a>() -> Parser<'a, u8, RuleType> {
-    let p
-        =
-        space()
-        - seq(b"/*")
-        + (!seq(b"*/") * take(1)).repeat(0..)
-        + seq(b"*/");
-    p.map(|((_a,c),_b)| {
-        let mut s:Vec<u8> = Vec::new();
-        for cc in c {
-            s.push(cc[0]);
-        }
-        RuleType::Comment(v2s(&s))
-    })
-}
 #[test]
 fn test_comment() {
-    assert_eq!(comment().parse(b"/* a cool comment */"),
+    assert_eq!(rule(b"/* a cool comment */"),
                Ok(Comment(String::from(" a cool comment "))));
 }
 
 #[test]
 fn test_rule() {
     let input = b"div { border-width:1px; }";
-    println!("{:#?}",rule().parse(input))
+    println!("{:#?}",rule(input))
 }
-fn stylesheet<'a>() -> Parser<'a, u8, Stylesheet> {
-    (comment() | rule() | import_rule() | at_rule()).repeat(0..).map(|rules| Stylesheet {
-        rules,
-        base_url: Url::parse("https://www.mozilla.com/").unwrap()
-    })
-}
-
 #[test]
 fn test_stylesheet() {
     let input = b"div { border-width:1px; } .cool { color: red; }";
-    println!("{:#?}",stylesheet().parse(input))
+    println!("{:#?}",stylesheet(input))
 }
 
 #[test]
 fn test_font_style() {
     let input = b"div { font-size: 18px; }";
-    println!("{:#?}",stylesheet().parse(input))
+    println!("{:#?}",stylesheet(input))
 }
 
 pub fn parse_stylesheet_from_buffer(content:Vec<u8>) -> Result<Stylesheet, BrowserError> {
-    Ok(stylesheet().parse(content.as_slice())?)
+    parse_stylesheet_from_bytestring(content.as_slice())
 }
 pub fn parse_stylesheet_from_bytestring(content:&[u8]) -> Result<Stylesheet, BrowserError> {
-    Ok(stylesheet().parse(content)?)
+    parse_stylesheet_with_encoding(content, None, None)
+}
+/// Decodes the stylesheet first, using the BOM, the charset from the
+/// Content-Type header, `@charset`, or the encoding of the referring document.
+pub fn parse_stylesheet_with_encoding(content:&[u8], content_type:Option<&str>, referrer:Option<&'static Encoding>) -> Result<Stylesheet, BrowserError> {
+    let encoding = sniff_css_encoding(content, content_type, referrer);
+    let (text, encoding) = decode(content, encoding);
+    let mut ss = parse_stylesheet(&text)?;
+    ss.encoding = encoding;
+    Ok(ss)
 }
 pub fn parse_stylesheet(text:&str) -> Result<Stylesheet, BrowserError> {
-    Ok(stylesheet().parse(text.as_ref())?)
+    Ok(parse_stylesheet_with_diagnostics(text).0)
+}
+/// Parses a stylesheet the way a browser does. Rules and declarations that
+/// don't parse are dropped and returned alongside, and everything else is kept.
+pub fn parse_stylesheet_with_diagnostics(text:&str) -> (Stylesheet, Vec<SkippedSpan>) {
+    let (rules, skipped) = parse_rules(text);
+    let stylesheet = Stylesheet {
+        rules,
+        base_url: Url::parse("https://www.mozilla.com/").unwrap(),
+        encoding: UTF_8,
+        origin: Origin::Author,
+    };
+    (stylesheet, skipped)
+}
+
+// test shorthands over the real parser. they fail if anything was skipped.
+#[cfg(test)]
+fn stylesheet(text:&[u8]) -> Result<Stylesheet, Vec<SkippedSpan>> {
+    let (stylesheet, skipped) = parse_stylesheet_with_diagnostics(str::from_utf8(text).unwrap());
+    if skipped.is_empty() { Ok(stylesheet) } else { Err(skipped) }
+}
+#[cfg(test)]
+fn rule(text:&[u8]) -> Result<RuleType, Vec<SkippedSpan>> {
+    stylesheet(text).map(|mut ss| ss.rules.remove(0))
+}
+#[cfg(test)]
+fn value(text:&[u8]) -> Result<Value, ()> {
+    parse_value_text(str::from_utf8(text).unwrap()).ok_or(())
+}
+#[cfg(test)]
+fn declaration(text:&[u8]) -> Result<Declaration, Vec<SkippedSpan>> {
+    let (mut declarations, skipped) = parser::parse_declarations(str::from_utf8(text).unwrap());
+    if skipped.is_empty() { Ok(declarations.remove(0)) } else { Err(skipped) }
+}
+
+#[cfg(test)]
+fn selector(text:&[u8]) -> Result<Selector, Diagnostic> {
+    match parse_selector_list(str::from_utf8(text).unwrap()) {
+        Ok(mut list) => Ok(list.remove(0)),
+        Err(BrowserError::ParseError(diagnostic)) => Err(diagnostic),
+        Err(err) => panic!("unexpected error {:?}", err),
+    }
+}
+
+#[test]
+fn test_parse_selector_list() {
+    let list = parse_selector_list("div.cool, #main > p").unwrap();
+    assert_eq!(list.len(), 2);
+    assert_eq!(list[0], Selector::Simple(SimpleSelector {
+        tag_name: Some(String::from("div")),
+        id: None,
+        class: vec![String::from("cool")],
+        pseudo_class: vec![],
+        attributes: vec![],
+        pseudo_element: None,
+    }));
+    assert!(parse_selector_list("").is_err());
+    assert!(parse_selector_list("div {").is_err());
 }
 
 #[test]
@@ -1017,7 +1054,7 @@ fn test_file_load() {
     let mut file = File::open("tests/foo.css").unwrap();
     let mut content:Vec<u8>= Vec::new();
     file.read_to_end(&mut content);
-    let parsed = stylesheet().parse(content.as_slice()).unwrap();
+    let parsed = stylesheet(content.as_slice()).unwrap();
     println!("{:#?}", parsed);
     let ss = Stylesheet {
         rules: vec![
@@ -1028,31 +1065,39 @@ fn test_file_load() {
                         tag_name: Some(String::from("body")),
                         id: None,
                         class: vec![],
-                        pseudo_class: vec![]
+                        pseudo_class: vec![],
+                        attributes: vec![],
+                        pseudo_element: None,
                     }),
                     Selector::Simple(SimpleSelector{
                         tag_name: Some(String::from("div")),
                         id: None,
                         class: vec![],
-                        pseudo_class: vec![]
+                        pseudo_class: vec![],
+                        attributes: vec![],
+                        pseudo_element: None,
                     })
                 ],
                 declarations: vec![
                     Declaration {
                         name: "background-color".to_string(),
                         value: Value::Keyword("white".to_string()),
+                        important: false,
                     },
                     Declaration {
                         name: "border-color".to_string(),
                         value: Value::Keyword("red".to_string()),
+                        important: false,
                     },
                     Declaration {
                         name: "border-width".to_string(),
                         value: Value::Length(1.0,Unit::Px),
+                        important: false,
                     },
                     Declaration {
                         name: "color".to_string(),
                         value: Value::Keyword("black".to_string()),
+                        important: false,
                     },
                 ],
             }),
@@ -1063,19 +1108,24 @@ fn test_file_load() {
                         tag_name: None,
                         id: None,
                         class: vec![String::from("cool")],
-                        pseudo_class: vec![]
+                        pseudo_class: vec![],
+                        attributes: vec![],
+                        pseudo_element: None,
                     })
                 ],
                 declarations: vec![
                     Declaration {
                         name: "color".to_string(),
                         value: Value::Keyword("green".to_string()),
+                        important: false,
                     },
                 ],
             }
             )
         ],
-        base_url: Url::parse("https://www.mozilla.com/").unwrap()
+        base_url: Url::parse("https://www.mozilla.com/").unwrap(),
+        encoding: UTF_8,
+        origin: Origin::Author,
     };
     assert_eq!(ss,parsed)
 }
@@ -1085,145 +1135,144 @@ fn test_tufte_rules() {
     let mut file = File::open("tests/tufte/tufte.css").unwrap();
     let mut content:Vec<u8>= Vec::new();
     file.read_to_end(&mut content);
-    let parsed = stylesheet().parse(content.as_slice()).unwrap();
+    let parsed = parse_stylesheet(str::from_utf8(&content).unwrap()).unwrap();
     println!("parsed {:#?}",parsed);
-
-}
-
-fn import_rule<'a>() -> Parser<'a, u8, RuleType> {
-    let p =
-            - space()
-            - sym(b'@')
-            + identifier()
-            - space()
-            - seq(b"url")
-            - sym(b'(')
-            + url()
-            - sym(b')')
-            - sym(b';')
-        ;
-    p.map(|( (_a,name), url)| {
-        RuleType::AtRule(AtRule {
-            name,
-            value: Some(Value::FunCall(FunCallValue{
-                name: String::from("url"),
-                arguments: vec![url]
-            })),
-            rules: vec![]
-        })
-    })
+    //rules after the ones that are skipped are still there
+    assert!(parsed.rules.iter().any(|rule| match rule {
+        RuleType::Rule(rule) => rule.selectors.iter().any(|sel| sel == &Selector::Simple(SimpleSelector{
+            tag_name: None, id: None, class: vec![String::from("iframe-wrapper")], pseudo_class: vec![], attributes: vec![], pseudo_element: None })),
+        _ => false,
+    }));
 }
 
-
 #[test]
 fn test_import_rule() {
-    let input = br#"http://fonts.googleapis.com/css?family=Lato"#;
-    println!("{:#?}", url().parse(input));
     // let input = br#"url(http://fonts.googleapis.com/css?family=Lato)"#;
     // println!("{:#?}", funcall().parse(input));
     let input = br#"@import url(http://fonts.googleapis.com/css?family=Lato);"#;
+    let import = |url:&str| ImportRule {
+        url: String::from(url),
+        layer: None,
+        supports: None,
+        media: MediaQueryList::default(),
+        stylesheet: None,
+    };
 
-    assert_eq!(import_rule().parse(input),Ok(RuleType::AtRule(AtRule{
-        name: "import".to_string(),
-        value: Some(Value::FunCall(FunCallValue{
-            name: "url".to_string(),
-            arguments: vec![Value::StringLiteral(String::from("http://fonts.googleapis.com/css?family=Lato"))]
-        })),
-        rules: vec![]
-    })));
-
-    let ss = stylesheet().parse(input);
-    println!("{:#?}",ss);
-
-    assert_eq!(stylesheet().parse(input),Ok(
+    assert_eq!(stylesheet(input),Ok(
         Stylesheet{
             rules: vec![
-                RuleType::AtRule(AtRule{
-                    name: "import".to_string(),
-                    value: Some(Value::FunCall(FunCallValue{
-                        name: "url".to_string(),
-                        arguments: vec![Value::StringLiteral(String::from("http://fonts.googleapis.com/css?family=Lato"))]
-                    })),
-                    rules: vec![]
-                })
+                RuleType::Import(import("http://fonts.googleapis.com/css?family=Lato"))
             ],
-            base_url: Url::parse("https://www.mozilla.com/").unwrap()
+            base_url: Url::parse("https://www.mozilla.com/").unwrap(),
+            encoding: UTF_8,
+            origin: Origin::Author,
         }
     ));
+    assert_eq!(rule(br#"@import "a.css";"#), Ok(RuleType::Import(import("a.css"))));
+    assert_eq!(rule(br#"@import url("a.css") layer(base.reset) supports(display: block) screen, print;"#),
+               Ok(RuleType::Import(ImportRule {
+                   layer: Some(String::from("base.reset")),
+                   supports: Some(parse_supports_condition("(display: block)")),
+                   media: parse_media_query_list("screen, print"),
+                   ..import("a.css")
+               })));
+    assert_eq!(rule(br#"@import 'a.css' layer (min-width: 100px);"#),
+               Ok(RuleType::Import(ImportRule {
+                   layer: Some(String::new()),
+                   media: parse_media_query_list("(min-width: 100px)"),
+                   ..import("a.css")
+               })));
+    // imports after other rules are dropped
+    let (ss, skipped) = parse_stylesheet_with_diagnostics(r#"@charset "utf-8"; @layer a; @import "a.css"; p {} @import "b.css";"#);
+    assert_eq!(ss.rules.iter().filter(|r| matches!(r, RuleType::Import(_))).count(), 1);
+    assert_eq!(skipped[0].text, r#"@import "b.css";"#);
+    assert!(rule(b"@import 12px;").is_err());
 }
 
-
-//https://developer.mozilla.org/en-US/docs/Web/CSS/At-rule
-fn at_rule<'a>() -> Parser<'a, u8, RuleType> {
-    let p
-        = space()
-        - sym(b'@')
-        + identifier()
-        - space()
-        + keyword().opt()
-        - space()
-        + string_literal().opt()
-        //+ funcall().opt()
-        // + (
-        //      sym(b'{')
-        - space()
-        + rule().opt()
-        - space()
-             // - sym(b'}')
-             // ).opt()
-        - sym(b';').opt()
-
-        ;
-    p.map(|((((_,name),kw),value), rule)|{
-        //we are ignoring the keyword currently
-        if let Some(rt) = rule {
-            RuleType::AtRule(AtRule { name, value, rules:vec![rt]})
-        } else {
-            RuleType::AtRule(AtRule {  name,  value,  rules:vec![] })
+#[test]
+fn test_layer_and_supports_rules() {
+    let ss = stylesheet(br#"
+        @layer reset, base.fonts;
+        @layer base { p { color: red } }
+        @layer { p { color: blue } }
+        @supports (display: block) { @layer x { p { color: green } } }
+    "#).unwrap();
+    assert_eq!(ss.rules[0], RuleType::LayerStatement(vec![String::from("reset"), String::from("base.fonts")]));
+    match &ss.rules[1] {
+        RuleType::Layer(layer) => {
+            assert_eq!(layer.name, Some(String::from("base")));
+            assert_eq!(layer.rules.len(), 1);
+        }
+        other => panic!("expected a layer {:?}", other),
+    }
+    assert!(matches!(&ss.rules[2], RuleType::Layer(LayerRule { name: None, .. })));
+    match &ss.rules[3] {
+        RuleType::Supports(supports) => {
+            assert_eq!(supports.condition, parse_supports_condition("(display: block)"));
+            assert!(matches!(&supports.rules[0], RuleType::Layer(_)));
         }
-    })
+        other => panic!("expected a supports rule {:?}", other),
+    }
+    assert!(rule(b"@layer a b;").is_err());
+    assert!(rule(b"@layer a, b { }").is_err());
+    assert!(rule(b"@layer;").is_err());
 }
 
 #[test]
 fn test_atrules() {
     assert_eq!(
-        at_rule().parse(br#"@charset "UTF-8";"#),
+        rule(br#"@charset "UTF-8";"#),
         Ok(RuleType::AtRule(AtRule{
             name: String::from("charset"),
             value: Some(StringLiteral(String::from("UTF-8"))),
+            raw: None,
             rules: vec![]
         })),
     );
     assert_eq!(
-        at_rule().parse(br#"@page { size: letter; margin: 1in;  }"#),
+        rule(br#"@page { size: letter; margin: 1in;  }"#),
         Ok(RuleType::AtRule(AtRule{
             name: String::from("page"),
             value: None,
+            raw: None,
             rules: vec![RuleType::Rule(Rule {
                 selectors: vec![],
                 declarations: vec![
                     Declaration { name: String::from("size"),
-                        value:Value::Keyword(String::from("letter"))},
+                        value:Value::Keyword(String::from("letter")),
+                        important: false},
                     Declaration {
                         name: String::from("margin"),
-                        value: Value::ArrayValue(vec![Value::Number(1.0), Keyword(String::from("in"))]),
+                        value: Length(1.0, Unit::In),
+                        important: false,
                     }
                 ]
             })]
         })),
     );
     assert_eq!(
-        at_rule().parse(br#"@media screen { body { margin: 3em; }}"#),
-        Ok(RuleType::AtRule(AtRule{
-            name: String::from("media"),
-            value: None,
-            rules: vec![]
+        rule(br#"@media screen { body { margin: 3em; }}"#),
+        Ok(RuleType::Media(MediaRule{
+            queries: parse_media_query_list("screen"),
+            rules: vec![RuleType::Rule(Rule {
+                selectors: vec![Selector::Simple(SimpleSelector{
+                    tag_name: Some(String::from("body")),
+                    id: None,
+                    class: vec![],
+                    pseudo_class: vec![],
+                    attributes: vec![],
+                    pseudo_element: None,
+                })],
+                declarations: vec![
+                    Declaration { name: String::from("margin"), value: Length(3.0, Unit::Em), important: false },
+                ]
+            })]
         }))
     );
 
 
     assert_eq!(
-        stylesheet().parse(br#"@charset "UTF-8";
+        stylesheet(br#"@charset "UTF-8";
 /*foo*/
 @font-face {
 }
@@ -1233,12 +1282,14 @@ fn test_atrules() {
             RuleType::AtRule(AtRule{
                 name: "charset".to_string(),
                 value: Some(Value::StringLiteral(String::from("UTF-8"))),
+                raw: None,
                 rules: vec![]
             }),
             RuleType::Comment(String::from("foo")),
             RuleType::AtRule(AtRule{
                 name: "font-face".to_string(),
                 value: None,
+                raw: None,
                 rules: vec![
                     RuleType::Rule(Rule{
                         selectors: vec![],
@@ -1247,7 +1298,9 @@ fn test_atrules() {
                 ]
             })
         ],
-        base_url: Url::parse("https://www.mozilla.com/").unwrap()
+        base_url: Url::parse("https://www.mozilla.com/").unwrap(),
+        encoding: UTF_8,
+        origin: Origin::Author,
     }));
 
 
@@ -1261,7 +1314,7 @@ fn test_fontface() {
             StringLiteral(String::from("foo"))
         ]
     })),
-           funcall().parse(br#"url("foo")"#));
+           value(br#"url("foo")"#));
 
     assert_eq!(Ok(Declaration{
         name: String::from("src"),
@@ -1270,9 +1323,10 @@ fn test_fontface() {
             arguments: vec![
                 Value::StringLiteral(String::from("et-book/et-book-roman-line-figures/et-book-roman-line-figures.eot"))
             ]
-        })
+        }),
+        important: false
     }),
-               declaration().parse(br#"src: url("et-book/et-book-roman-line-figures/et-book-roman-line-figures.eot");"#));
+               declaration(br#"src: url("et-book/et-book-roman-line-figures/et-book-roman-line-figures.eot");"#));
 
     let mut input = br#"@font-face {
                 font-family: "et-book";
@@ -1282,17 +1336,18 @@ fn test_fontface() {
                 font-display: swap;
             }
             "#;
-    let result = stylesheet().parse(input.as_ref());
+    let result = stylesheet(input.as_ref());
     println!("{:?}", result);
     assert_eq!(Ok(Stylesheet {
         rules: vec![
             RuleType::AtRule(AtRule {
             name: "font-face".to_string(),
             value: None,
+            raw: None,
             rules: vec![RuleType::Rule(Rule {
                 selectors: vec![],
                 declarations: vec![
-                    Declaration { name: String::from("font-family"), value: Value::StringLiteral(String::from("et-book")) },
+                    Declaration { name: String::from("font-family"), value: Value::StringLiteral(String::from("et-book")), important: false },
                     Declaration {
                         name: String::from("src"),
                         value: Value::FunCall(FunCallValue {
@@ -1300,15 +1355,18 @@ fn test_fontface() {
                             arguments: vec![
                                 Value::StringLiteral(String::from("et-book/et-book-roman-line-figures/et-book-roman-line-figures.eot")),
                             ]
-                        })
+                        }),
+                        important: false
                     },
-                    Declaration { name: String::from("font-weight"), value: Keyword(String::from("normal")) },
-                    Declaration { name: String::from("font-style"), value: Keyword(String::from("normal")) },
-                    Declaration { name: String::from("font-display"), value: Keyword(String::from("swap")) },
+                    Declaration { name: String::from("font-weight"), value: Keyword(String::from("normal")), important: false },
+                    Declaration { name: String::from("font-style"), value: Keyword(String::from("normal")), important: false },
+                    Declaration { name: String::from("font-display"), value: Keyword(String::from("swap")), important: false },
                 ]
             })]
         })],
         base_url: Url::parse("https://www.mozilla.com/").unwrap(),
+        encoding: UTF_8,
+        origin: Origin::Author,
     }
     ),result);
 }
@@ -1316,25 +1374,27 @@ fn test_fontface() {
 #[test]
 fn test_percentage() {
     assert_eq!(Length(100.0,Unit::Per),
-               length_unit().parse(br"100%").unwrap());
+               value(br"100%").unwrap());
     assert_eq!(Declaration{
         name: String::from("width"),
-        value: (Value::Length(100.0, Unit::Per))
+        value: (Value::Length(100.0, Unit::Per)),
+        important: false
     },
-               declaration().parse(br"width:100%;").unwrap());
+               declaration(br"width:100%;").unwrap());
 }
 
 #[test]
 fn test_rem() {
     assert_eq!(Length(40.0,Unit::Rem),
-               length_unit().parse(br"40rem").unwrap());
+               value(br"40rem").unwrap());
     assert_eq!(Length(40.0,Unit::Rem),
-               length_unit().parse(br"40.0rem").unwrap());
+               value(br"40.0rem").unwrap());
     assert_eq!(Declaration{
         name: String::from("width"),
-        value: (Value::Length(99.90, Unit::Rem))
+        value: (Value::Length(99.90, Unit::Rem)),
+        important: false
     },
-               declaration().parse(br"width:99.9rem;").unwrap());
+               declaration(br"width:99.9rem;").unwrap());
 }
 
 #[test]
@@ -1345,21 +1405,25 @@ fn test_multiple_selectors() {
                 tag_name: Some(String::from("a")),
                 id: None,
                 class: vec![],
-                pseudo_class: vec![]
+                pseudo_class: vec![],
+                attributes: vec![],
+                pseudo_element: None,
             }),
             Selector::Simple(SimpleSelector{
                 tag_name: Some(String::from("b")),
                 id: None,
                 class: vec![],
-                pseudo_class: vec![]
+                pseudo_class: vec![],
+                attributes: vec![],
+                pseudo_element: None,
             })
         ],
         declarations: vec![
-            Declaration{ name: String::from("foo"), value: Keyword(String::from("bar")) }
+            Declaration{ name: String::from("foo"), value: Keyword(String::from("bar")), important: false }
         ]
     });
-    assert_eq!(answer, rule().parse(br"a,b { foo: bar; }").unwrap());
-    assert_eq!(answer, rule().parse(br" a , b{ foo: bar; }").unwrap());
+    assert_eq!(answer, rule(br"a,b { foo: bar; }").unwrap());
+    assert_eq!(answer, rule(br" a , b{ foo: bar; }").unwrap());
 }
 #[test]
 fn test_child_selector() {
@@ -1378,8 +1442,8 @@ fn test_not_pseudo_selector() {
 
 #[test]
 fn test_four_part_margin() {
-    println!("parsed {:#?}", value().parse(b"1px 2px 3px 4px"));
-    println!("parsed {:#?}", declaration().parse(b"margin: 1px 2px 3px 4px;"));
+    println!("parsed {:#?}", value(b"1px 2px 3px 4px"));
+    println!("parsed {:#?}", declaration(b"margin: 1px 2px 3px 4px;"));
     let answer = Declaration {
         name: String::from("margin"),
         value: Value::ArrayValue(vec![
@@ -1387,10 +1451,11 @@ fn test_four_part_margin() {
             Length(2.0,Unit::Px),
             Length(3.0,Unit::Px),
             Length(4.0,Unit::Px),
-        ])
+        ]),
+        important: false
     };
-    assert_eq!(answer, declaration().parse(b"margin: 1px 2px 3px 4px;").unwrap());
-    println!("parsed {:#?}", declaration().parse(b"margin: 1px 2px 3px 4em;"));
+    assert_eq!(answer, declaration(b"margin: 1px 2px 3px 4px;").unwrap());
+    println!("parsed {:#?}", declaration(b"margin: 1px 2px 3px 4em;"));
     let answer = Declaration {
         name: String::from("margin"),
         value: Value::ArrayValue(vec![
@@ -1398,31 +1463,34 @@ fn test_four_part_margin() {
             Length(2.0,Unit::Px),
             Length(3.0,Unit::Px),
             Length(4.0,Unit::Em),
-        ])
+        ]),
+        important: false
     };
-    assert_eq!(answer, declaration().parse(b"margin: 1px 2px 3px 4em;").unwrap());
+    assert_eq!(answer, declaration(b"margin: 1px 2px 3px 4em;").unwrap());
 }
 #[test]
 fn test_two_part_margin() {
-    println!("parsed {:#?}", array_value_2().parse(b"1px 2px"));
-    println!("parsed {:#?}",value().parse(b"1px 2px"));
-    println!("parsed {:#?}",value().parse(b"1px 2px"));
+    println!("parsed {:#?}", value(b"1px 2px"));
+    println!("parsed {:#?}",value(b"1px 2px"));
+    println!("parsed {:#?}",value(b"1px 2px"));
     let answer = Declaration {
         name: String::from("margin"),
         value: Value::ArrayValue(vec![
             Length(1.0,Unit::Px),
             Length(2.0,Unit::Px),
-        ])
+        ]),
+        important: false
     };
-    assert_eq!(answer, declaration().parse(b"margin: 1px 2px;").unwrap());
+    assert_eq!(answer, declaration(b"margin: 1px 2px;").unwrap());
 }
 #[test]
 fn test_one_part_margin() {
     let answer = Declaration {
         name: String::from("margin"),
-        value: Length(1.0,Unit::Px)
+        value: Length(1.0,Unit::Px),
+        important: false
     };
-    assert_eq!(answer, declaration().parse(b"margin: 1px;").unwrap());
+    assert_eq!(answer, declaration(b"margin: 1px;").unwrap());
 }
 
 #[test]
@@ -1435,9 +1503,10 @@ fn test_funcall_dec() {
                 Value::HexColor(String::from("#fffff8")),
                 Value::HexColor(String::from("#fffff8")),
             ]
-        })
+        }),
+        important: false
     },
-        declaration().parse(b"background: linear-gradient(#fffff8, #fffff8);").unwrap()
+        declaration(b"background: linear-gradient(#fffff8, #fffff8);").unwrap()
     )
 }
 #[test]
@@ -1466,44 +1535,48 @@ fn test_linear_gradient() {
                     Value::Keyword(String::from("currentColor")),
                 ]
             }),
-        ])
+        ]),
+        important: false
     }),
-       declaration().parse(br"background: linear-gradient(#fffff8, #fffff8), linear-gradient(#fffff8, #fffff8), linear-gradient(currentColor, currentColor);")
+       declaration(br"background: linear-gradient(#fffff8, #fffff8), linear-gradient(#fffff8, #fffff8), linear-gradient(currentColor, currentColor);")
     );
 }
 
 #[test]
 fn test_keyword_list() {
-    println!("keyword {:#?}", keyword().parse(b"foo"));
-    println!("keyword {:#?}", keyword().parse(b"foo-bar"));
-    println!("list value {:#?}", value().parse(b"foo-bar,baz-zoo"));
-    println!("decl {:#?}", declaration().parse(b"blah:foo-bar,baz-zoo;"));
+    println!("keyword {:#?}", value(b"foo"));
+    println!("keyword {:#?}", value(b"foo-bar"));
+    println!("list value {:#?}", value(b"foo-bar,baz-zoo"));
+    println!("decl {:#?}", declaration(b"blah:foo-bar,baz-zoo;"));
     let answer = Declaration {
         name: String::from("background-repeat"),
         value: Value::ArrayValue(vec![
             Keyword(String::from("no-repeat")),
             Keyword(String::from("no-repeat")),
             Keyword(String::from("repeat-x")),
-        ])
+        ]),
+        important: false
     };
-    assert_eq!(answer, declaration().parse(b"background-repeat:no-repeat,no-repeat,repeat-x;").unwrap());
-    assert_eq!(answer, declaration().parse(b"background-repeat: no-repeat, no-repeat, repeat-x;").unwrap());
+    assert_eq!(answer, declaration(b"background-repeat:no-repeat,no-repeat,repeat-x;").unwrap());
+    assert_eq!(answer, declaration(b"background-repeat: no-repeat, no-repeat, repeat-x;").unwrap());
 }
 
 #[test]
 fn test_font_weight() {
     assert_eq!(
-        declaration().parse(br#"font-weight: normal;"#),
+        declaration(br#"font-weight: normal;"#),
         Ok(Declaration{
             name: String::from("font-weight"),
             value: Value::Keyword(String::from("normal")),
+            important: false,
         }),
     );
     assert_eq!(
-        declaration().parse(br#"font-weight: 400;"#),
+        declaration(br#"font-weight: 400;"#),
         Ok(Declaration{
             name: String::from("font-weight"),
             value: Value::Number(400.0),
+            important: false,
         }),
     );
 }
@@ -1511,10 +1584,11 @@ fn test_font_weight() {
 #[test]
 fn test_unitless_number() {
     assert_eq!(
-        declaration().parse(br#"line-height: 1.6;"#),
+        declaration(br#"line-height: 1.6;"#),
         Ok(Declaration{
             name: String::from("line-height"),
             value: Value::Number(1.6),
+            important: false,
         }),
     );
 }
@@ -1522,7 +1596,7 @@ fn test_unitless_number() {
 #[test]
 fn test_list() {
     let input = b"U+0100-024F, U+0259";
-    assert_eq!(list_array_value().parse(input),Ok(Value::ArrayValue(vec![UnicodeRange(0x0100,0x024f),UnicodeCodepoint(0x0259)])))
+    assert_eq!(value(input),Ok(Value::ArrayValue(vec![UnicodeRange(0x0100,0x024f),UnicodeCodepoint(0x0259)])))
 }
 
 #[test]
@@ -1546,7 +1620,7 @@ fn test_gfonts() {
 }
 "#;
 
-    println!("{:#?}",stylesheet().parse(input));
+    println!("{:#?}",stylesheet(input));
 }
 
 #[test]
@@ -1554,6 +1628,10 @@ fn test_tufte_css() {
     let mut file = File::open("tests/tufte/tufte.css").unwrap();
     let mut content:Vec<u8>= Vec::new();
     file.read_to_end(&mut content);
-    let parsed = stylesheet().parse(content.as_slice()).unwrap();
+    let (parsed, skipped) = parse_stylesheet_with_diagnostics(str::from_utf8(&content).unwrap());
     println!("parsed the stylesheet {:#?}",parsed);
+    //selectors we don't handle yet
+    assert_eq!(skipped.iter().map(|span| span.diagnostic.line).collect::<Vec<usize>>(),
+               vec![242]);
+    assert_eq!(skipped[0].diagnostic.expected, "a valid selector");
 }
@@ -0,0 +1,575 @@
+// https://www.w3.org/TR/css-syntax-3/#parsing
+//
+// Consumes the tokens into rules and declarations and recovers from errors the
+// way the spec says: a bad declaration is dropped up to the next semicolon and
+// a bad rule up to the end of its block, and everything else is kept. The
+// selectors are then read from the source text of each piece, and values from
+// their tokens. A declaration whose value the property doesn't take is bad
+// too. Whatever gets dropped is reported as a `SkippedSpan`.
+
+use crate::css::tokenizer::{tokenize, Token, SpannedToken};
+use crate::css::{RuleType, Rule, AtRule, MediaRule, SupportsRule, ImportRule, LayerRule, SupportsCondition, Declaration,
+    Value, UnparsedValue, parse_selector_list, parse_media_query_list, parse_supports_condition};
+use crate::css::value::parse_value;
+use crate::style::accepts_declaration;
+use crate::net::Diagnostic;
+
+// at-rules whose block holds declarations, and ones whose block holds rules.
+// other at-rules keep their prelude and block as tokens.
+pub(crate) const DECLARATION_AT_RULES: &[&str] = &["font-face", "page", "counter-style", "property", "font-palette-values", "viewport"];
+pub(crate) const RULE_AT_RULES: &[&str] = &["document", "-moz-document", "container", "scope", "starting-style"];
+
+/// A piece of a stylesheet that was dropped. The diagnostic gives the line and
+/// column where it starts and what the parser expected there. `end` is the
+/// byte offset just past it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SkippedSpan {
+    pub diagnostic: Diagnostic,
+    pub end: usize,
+    pub text: String,
+}
+
+/// The rules of a stylesheet, keeping the comments between them.
+pub fn parse_rules(text: &str) -> (Vec<RuleType>, Vec<SkippedSpan>) {
+    let mut parser = Parser::new(text);
+    let end = parser.tokens.len() - 1;
+    let rules = parser.rule_list(0, end, true);
+    (rules, parser.skipped)
+}
+
+/// A list of declarations without the braces, like a `style` attribute.
+pub fn parse_declarations(text: &str) -> (Vec<Declaration>, Vec<SkippedSpan>) {
+    let mut parser = Parser::new(text);
+    let end = parser.tokens.len() - 1;
+    let declarations = parser.declaration_list(0, end);
+    (declarations, parser.skipped)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    // every token but the comments, ending with Eof
+    tokens: Vec<SpannedToken>,
+    comments: Vec<SpannedToken>,
+    next_comment: usize,
+    skipped: Vec<SkippedSpan>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        let (comments, tokens) = tokenize(text).into_iter()
+            .partition(|t| matches!(t.token, Token::Comment(_)));
+        Parser { text, tokens, comments, next_comment: 0, skipped: vec![] }
+    }
+
+    fn token(&self, i: usize) -> &Token {
+        &self.tokens[i].token
+    }
+
+    // the index just past the component value that starts at i. blocks and
+    // functions are skipped as a whole.
+    fn skip_value(&self, i: usize) -> usize {
+        match self.token(i) {
+            Token::OpenCurly | Token::OpenSquare | Token::OpenParen | Token::Function(_) => self.block(i).1,
+            Token::Eof => i,
+            _ => i + 1,
+        }
+    }
+    // for the block opened at i, the index of its closing token and the index
+    // after it. an unclosed block runs to the end of the input.
+    fn block(&self, open: usize) -> (usize, usize) {
+        let closer = match self.token(open) {
+            Token::OpenCurly => Token::CloseCurly,
+            Token::OpenSquare => Token::CloseSquare,
+            _ => Token::CloseParen,
+        };
+        let mut i = open + 1;
+        loop {
+            if *self.token(i) == Token::Eof {
+                return (i, i);
+            }
+            if *self.token(i) == closer {
+                return (i, i + 1);
+            }
+            i = self.skip_value(i);
+        }
+    }
+
+    // the source text of tokens from..to, without comments
+    fn source(&self, from: usize, to: usize) -> String {
+        if from >= to {
+            return String::new();
+        }
+        let (start, end) = (self.tokens[from].start, self.tokens[to - 1].end);
+        let mut out = String::new();
+        let mut pos = start;
+        for comment in self.comments.iter().filter(|c| c.start >= start && c.end <= end) {
+            out.push_str(&self.text[pos..comment.start]);
+            pos = comment.end;
+        }
+        out.push_str(&self.text[pos..end]);
+        out
+    }
+
+    fn skip(&mut self, start: usize, end: usize, expected: &str) {
+        self.skipped.push(SkippedSpan {
+            diagnostic: Diagnostic::new(self.text.as_bytes(), start, expected),
+            end,
+            text: String::from(&self.text[start..end]),
+        });
+    }
+    // skips the tokens from..to
+    fn skip_tokens(&mut self, from: usize, to: usize, expected: &str) {
+        let start = self.tokens[from].start;
+        let end = if to > from { self.tokens[to - 1].end } else { start };
+        self.skip(start, end, expected);
+    }
+
+    // top level comments become rules. the ones inside rules are dropped.
+    fn take_comments(&mut self, before: usize, rules: Option<&mut Vec<RuleType>>) {
+        let mut kept = vec![];
+        while self.next_comment < self.comments.len() && self.comments[self.next_comment].start < before {
+            if let Token::Comment(text) = &self.comments[self.next_comment].token {
+                kept.push(RuleType::Comment(text.clone()));
+            }
+            self.next_comment += 1;
+        }
+        if let Some(rules) = rules {
+            rules.append(&mut kept);
+        }
+    }
+
+    fn rule_list(&mut self, start: usize, end: usize, top_level: bool) -> Vec<RuleType> {
+        let mut rules = vec![];
+        let mut imports_allowed = top_level;
+        let mut i = start;
+        loop {
+            if top_level {
+                self.take_comments(self.tokens[i].start, Some(&mut rules));
+            }
+            if i >= end {
+                return rules;
+            }
+            let next = match self.token(i) {
+                Token::Whitespace => i + 1,
+                Token::Eof => return rules,
+                Token::Cdo | Token::Cdc if top_level => i + 1,
+                Token::AtKeyword(_) => {
+                    let (rule, next) = self.at_rule(i, end);
+                    match rule {
+                        // imports have to come before everything but @charset and @layer statements
+                        Some(RuleType::Import(_)) if !imports_allowed => self.skip_tokens(i, next, "@import before the other rules"),
+                        Some(rule) => {
+                            imports_allowed &= matches!(rule, RuleType::Import(_) | RuleType::LayerStatement(_))
+                                || matches!(&rule, RuleType::AtRule(ar) if ar.name == "charset");
+                            rules.push(rule);
+                        }
+                        None => {}
+                    }
+                    next
+                }
+                _ => {
+                    let (rule, next) = self.qualified_rule(i, end);
+                    if let Some(rule) = rule {
+                        imports_allowed = false;
+                        rules.push(rule);
+                    }
+                    next
+                }
+            };
+            if top_level && next > i + 1 {
+                self.take_comments(self.tokens[next - 1].end, None);
+            }
+            i = next;
+        }
+    }
+
+    fn qualified_rule(&mut self, start: usize, end: usize) -> (Option<RuleType>, usize) {
+        let mut i = start;
+        while *self.token(i) != Token::OpenCurly {
+            if i >= end || *self.token(i) == Token::Eof {
+                self.skip_tokens(start, i, "a '{' to start the rule's block");
+                return (None, i);
+            }
+            i = self.skip_value(i);
+        }
+        let (close, next) = self.block(i);
+        match parse_selector_list(&self.source(start, i)) {
+            Ok(selectors) => {
+                let declarations = self.declaration_list(i + 1, close);
+                (Some(RuleType::Rule(Rule { selectors, declarations })), next)
+            }
+            Err(_) => {
+                self.skip_tokens(start, next, "a valid selector");
+                (None, next)
+            }
+        }
+    }
+
+    fn at_rule(&mut self, start: usize, end: usize) -> (Option<RuleType>, usize) {
+        let name = match self.token(start) {
+            Token::AtKeyword(name) => name.to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        let mut i = start + 1;
+        let (prelude_end, block, next) = loop {
+            match self.token(i) {
+                _ if i >= end => break (i, None, i),
+                Token::Eof => break (i, None, i),
+                Token::Semicolon => break (i, None, i + 1),
+                Token::OpenCurly => {
+                    let (close, next) = self.block(i);
+                    break (i, Some((i + 1, close)), next);
+                }
+                _ => i = self.skip_value(i),
+            }
+        };
+        let prelude = self.source(start + 1, prelude_end);
+        let nested = |parser:&mut Self| match block {
+            Some((from, to)) => parser.rule_list(from, to, false),
+            None => vec![],
+        };
+        match name.as_str() {
+            "media" => {
+                let queries = parse_media_query_list(&prelude);
+                return (Some(RuleType::Media(MediaRule { queries, rules: nested(self) })), next);
+            }
+            "supports" => {
+                let condition = parse_supports_condition(&prelude);
+                return (Some(RuleType::Supports(SupportsRule { condition, rules: nested(self) })), next);
+            }
+            "import" if block.is_none() => {
+                let rule = self.import_rule(start + 1, prelude_end).map(RuleType::Import);
+                if rule.is_none() {
+                    self.skip_tokens(start, next, "a url or string to import");
+                }
+                return (rule, next);
+            }
+            "layer" => {
+                let names = layer_names(&prelude);
+                let rule = match (names, block) {
+                    (Some(names), None) if !names.is_empty() => Some(RuleType::LayerStatement(names)),
+                    (Some(mut names), Some(_)) if names.len() <= 1 => Some(RuleType::Layer(LayerRule { name: names.pop(), rules: nested(self) })),
+                    _ => None,
+                };
+                if rule.is_none() {
+                    self.skip_tokens(start, next, "layer names");
+                }
+                return (rule, next);
+            }
+            _ => {}
+        }
+        let value = parse_value(&self.tokens[start + 1..prelude_end], self.text);
+        let (rules, raw) = match block {
+            Some((from, to)) if DECLARATION_AT_RULES.contains(&name.as_str()) =>
+                (vec![RuleType::Rule(Rule { selectors: vec![], declarations: self.declaration_list(from, to) })], None),
+            Some((from, to)) if RULE_AT_RULES.contains(&name.as_str()) => (self.rule_list(from, to, false), None),
+            _ if name == "charset" => (vec![], None),
+            _ => (vec![], Some(self.raw_at_rule(start + 1, prelude_end, block))),
+        };
+        (Some(RuleType::AtRule(AtRule { name, value, rules, raw })), next)
+    }
+
+    // the prelude without the whitespace around it, then the block with its
+    // braces. an unclosed block is closed.
+    fn raw_at_rule(&self, mut first: usize, mut last: usize, block: Option<(usize, usize)>) -> Vec<Token> {
+        while first < last && *self.token(first) == Token::Whitespace {
+            first += 1;
+        }
+        while last > first && *self.token(last - 1) == Token::Whitespace {
+            last -= 1;
+        }
+        let mut tokens:Vec<Token> = (first..last).map(|t| self.token(t).clone()).collect();
+        if let Some((from, to)) = block {
+            if !tokens.is_empty() {
+                tokens.push(Token::Whitespace);
+            }
+            tokens.extend((from - 1..to).map(|t| self.token(t).clone()));
+            tokens.push(Token::CloseCurly);
+        }
+        tokens
+    }
+
+    // the url, then the optional layer, supports() and media queries
+    fn import_rule(&mut self, start: usize, end: usize) -> Option<ImportRule> {
+        let mut i = start;
+        let skip_whitespace = |parser:&Self, mut i:usize| {
+            while i < end && *parser.token(i) == Token::Whitespace {
+                i += 1;
+            }
+            i
+        };
+        i = skip_whitespace(self, i);
+        let url = match self.token(i) {
+            Token::Url(url) | Token::QuotedString(url) => url.clone(),
+            Token::Function(name) if name.eq_ignore_ascii_case("url") => match self.token(skip_whitespace(self, i + 1)) {
+                Token::QuotedString(url) => url.clone(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        i = skip_whitespace(self, self.skip_value(i));
+        let mut layer = None;
+        match self.token(i) {
+            Token::Ident(word) if word.eq_ignore_ascii_case("layer") => {
+                layer = Some(String::new());
+                i = skip_whitespace(self, i + 1);
+            }
+            Token::Function(name) if name.eq_ignore_ascii_case("layer") => {
+                let (close, after) = self.block(i);
+                let mut names = layer_names(&self.source(i + 1, close))?;
+                if names.len() != 1 {
+                    return None;
+                }
+                layer = names.pop();
+                i = skip_whitespace(self, after);
+            }
+            _ => {}
+        }
+        let mut supports = None;
+        if let Token::Function(name) = self.token(i) {
+            if name.eq_ignore_ascii_case("supports") {
+                let (close, after) = self.block(i);
+                let inside = self.source(i + 1, close);
+                // supports() takes a condition or a bare declaration
+                let condition = match parse_supports_condition(&inside) {
+                    SupportsCondition::Unknown => parse_supports_condition(&format!("({})", inside)),
+                    condition => condition,
+                };
+                supports = Some(condition);
+                i = skip_whitespace(self, after);
+            }
+        }
+        let media = parse_media_query_list(&self.source(i, end));
+        Some(ImportRule { url, layer, supports, media, stylesheet: None })
+    }
+
+    fn declaration_list(&mut self, start: usize, end: usize) -> Vec<Declaration> {
+        let mut declarations = vec![];
+        let mut i = start;
+        while i < end {
+            match self.token(i) {
+                Token::Eof => break,
+                Token::Whitespace | Token::Semicolon => i += 1,
+                Token::AtKeyword(_) => {
+                    let (_, next) = self.at_rule(i, end);
+                    self.skip_tokens(i, next, "a declaration");
+                    i = next;
+                }
+                token => {
+                    let is_name = matches!(token, Token::Ident(_));
+                    let mut next = i;
+                    while next < end && !matches!(self.token(next), Token::Semicolon | Token::Eof) {
+                        next = self.skip_value(next);
+                    }
+                    if is_name {
+                        if let Some(dec) = self.declaration(i, next) {
+                            declarations.push(dec);
+                        }
+                    } else {
+                        self.skip_tokens(i, next, "a property name");
+                    }
+                    i = next;
+                }
+            }
+        }
+        declarations
+    }
+
+    // the tokens from start..end, beginning with the property name
+    fn declaration(&mut self, start: usize, end: usize) -> Option<Declaration> {
+        let name = match self.token(start) {
+            Token::Ident(name) if name.starts_with("--") => name.clone(),
+            Token::Ident(name) => name.to_ascii_lowercase(),
+            _ => return None,
+        };
+        let mut i = start + 1;
+        while i < end && *self.token(i) == Token::Whitespace {
+            i += 1;
+        }
+        if i >= end || *self.token(i) != Token::Colon {
+            self.skip_tokens(start, end, "a ':' after the property name");
+            return None;
+        }
+        let mut value_end = end;
+        while value_end > i + 1 && *self.token(value_end - 1) == Token::Whitespace {
+            value_end -= 1;
+        }
+        // a trailing !important is a flag, not part of the value
+        let mut important = false;
+        if let Token::Ident(word) = self.token(value_end - 1) {
+            if word.eq_ignore_ascii_case("important") {
+                let mut bang = value_end - 1;
+                while bang > i + 1 && *self.token(bang - 1) == Token::Whitespace {
+                    bang -= 1;
+                }
+                if bang > i + 1 && *self.token(bang - 1) == Token::Delim('!') {
+                    value_end = bang - 1;
+                    important = true;
+                }
+            }
+        }
+        // custom properties, and values that wait for var(), are kept as tokens
+        let mut first = i + 1;
+        while first < value_end && *self.token(first) == Token::Whitespace {
+            first += 1;
+        }
+        let mut last = value_end;
+        while last > first && *self.token(last - 1) == Token::Whitespace {
+            last -= 1;
+        }
+        let tokens:Vec<Token> = (first..last).map(|t| self.token(t).clone()).collect();
+        let is_var = |token:&Token| matches!(token, Token::Function(f) if f.eq_ignore_ascii_case("var"));
+        if name.starts_with("--") || tokens.iter().any(is_var) {
+            if !is_balanced(&tokens) || (tokens.is_empty() && !name.starts_with("--")) {
+                self.skip_tokens(start, end, &format!("a valid value for '{}'", name));
+                return None;
+            }
+            let value = Value::Unparsed(UnparsedValue { tokens, shorthand: None });
+            return Some(Declaration { name, value, important });
+        }
+        if first == last {
+            self.skip_tokens(start, end, "a value");
+            return None;
+        }
+        // a value the property doesn't take is dropped like one that doesn't parse
+        let declaration = parse_value(&self.tokens[first..last], self.text)
+            .map(|value| Declaration { name: name.clone(), value, important })
+            .filter(accepts_declaration);
+        if declaration.is_none() {
+            self.skip_tokens(start, end, &format!("a valid value for '{}'", name));
+        }
+        declaration
+    }
+}
+
+// whether the brackets close in the right order. blocks left open at the end
+// are fine, as they are closed by the end of the declaration.
+fn is_balanced(tokens: &[Token]) -> bool {
+    let mut closers = vec![];
+    for token in tokens {
+        match token {
+            Token::OpenParen | Token::Function(_) => closers.push(Token::CloseParen),
+            Token::OpenSquare => closers.push(Token::CloseSquare),
+            Token::OpenCurly => closers.push(Token::CloseCurly),
+            Token::CloseParen | Token::CloseSquare | Token::CloseCurly if closers.pop().as_ref() != Some(token) => return false,
+            Token::BadString | Token::BadUrl => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+// the comma separated names of `@layer a.b, c`, or None if one isn't a name
+fn layer_names(prelude: &str) -> Option<Vec<String>> {
+    if prelude.trim().is_empty() {
+        return Some(vec![]);
+    }
+    let is_ident = |part: &str| {
+        let starts_well = matches!(part.chars().next(), Some(ch) if ch.is_alphabetic() || ch == '_' || ch == '-');
+        starts_well && part.chars().all(|ch| ch.is_alphanumeric() || ch == '_' || ch == '-')
+    };
+    prelude.split(',')
+        .map(|name| name.trim())
+        .map(|name| if name.split('.').all(is_ident) { Some(name.to_string()) } else { None })
+        .collect()
+}
+
+#[cfg(test)]
+fn skipped_lines(text: &str) -> Vec<(usize, usize, String)> {
+    parse_rules(text).1.into_iter()
+        .map(|span| (span.diagnostic.line, span.diagnostic.column, span.text))
+        .collect()
+}
+
+#[test]
+fn test_skip_bad_declarations() {
+    let (rules, skipped) = parse_rules("p { color: red; width 10px; : x; height: 5px }");
+    assert_eq!(rules, vec![RuleType::Rule(Rule {
+        selectors: crate::css::parse_selector_list("p").unwrap(),
+        declarations: vec![
+            Declaration { name: String::from("color"), value: Value::Keyword(String::from("red")), important: false },
+            Declaration { name: String::from("height"), value: Value::Length(5.0, crate::css::Unit::Px), important: false },
+        ],
+    })]);
+    assert_eq!(skipped.iter().map(|s| s.text.as_str()).collect::<Vec<&str>>(), vec!["width 10px", ": x"]);
+    assert_eq!(skipped[0].diagnostic.expected, "a ':' after the property name");
+    assert_eq!(skipped[1].diagnostic.expected, "a property name");
+}
+
+#[test]
+fn test_skip_invalid_values() {
+    let (declarations, skipped) = parse_declarations("color: red; color: 12px; display: bogus; border-top-style: 3px; width: calc(1px + 2)");
+    assert_eq!(declarations, vec![
+        Declaration { name: String::from("color"), value: Value::Keyword(String::from("red")), important: false },
+    ]);
+    assert_eq!(skipped.iter().map(|s| s.text.as_str()).collect::<Vec<&str>>(),
+               vec!["color: 12px", "display: bogus", "border-top-style: 3px", "width: calc(1px + 2)"]);
+    assert_eq!(skipped[0].diagnostic.expected, "a valid value for 'color'");
+}
+
+#[test]
+fn test_escaped_strings() {
+    let (declarations, skipped) = parse_declarations(r#"content: "\201C"; quotes: "a\"b" 'c'; font-family: 'It\'s', serif"#);
+    assert!(skipped.is_empty());
+    let values: Vec<Value> = declarations.into_iter().map(|d| d.value).collect();
+    assert_eq!(values, vec![
+        Value::StringLiteral(String::from("\u{201C}")),
+        Value::ArrayValue(vec![Value::StringLiteral(String::from("a\"b")), Value::StringLiteral(String::from("c"))]),
+        Value::ArrayValue(vec![Value::StringLiteral(String::from("It's")), Value::Keyword(String::from("serif"))]),
+    ]);
+}
+
+#[test]
+fn test_skip_bad_rules() {
+    let text = "a { color: red }\n\
+                a[href=\"{\"] ]{ color: blue }\n\
+                b { color: {red}; width: 1px }\n  \
+                @unknown foo { bar } i { color: green }\n\
+                } u { color: black";
+    let (rules, _) = parse_rules(text);
+    let names: Vec<String> = rules.iter().filter_map(|r| match r {
+        RuleType::Rule(rule) => Some(rule.declarations.iter().map(|d| d.name.clone()).collect::<Vec<String>>().join(",")),
+        RuleType::AtRule(ar) => Some(format!("@{}", ar.name)),
+        _ => None,
+    }).collect();
+    assert_eq!(names, vec!["color", "width", "@unknown", "color"]);
+    assert_eq!(skipped_lines(text), vec![
+        (2, 1, String::from("a[href=\"{\"] ]{ color: blue }")),
+        (3, 5, String::from("color: {red}")),
+        (5, 1, String::from("} u { color: black")),
+    ]);
+}
+
+#[test]
+fn test_comments_and_important() {
+    let (rules, skipped) = parse_rules("/* one */ p { color /* x */ : red !important; } /* two */ <!-- -->");
+    assert!(skipped.is_empty());
+    assert_eq!(rules.len(), 3);
+    assert_eq!(rules[0], RuleType::Comment(String::from(" one ")));
+    assert_eq!(rules[2], RuleType::Comment(String::from(" two ")));
+    if let RuleType::Rule(rule) = &rules[1] {
+        assert_eq!(rule.declarations, vec![Declaration { name: String::from("color"), value: Value::Keyword(String::from("red")), important: true }]);
+    } else {
+        panic!("expected a rule");
+    }
+    let (declarations, skipped) = parse_declarations("COLOR: blue; --Custom: x; margin:");
+    assert_eq!(declarations.iter().map(|d| d.name.as_str()).collect::<Vec<&str>>(), vec!["color", "--Custom"]);
+    assert_eq!(skipped[0].diagnostic.expected, "a value");
+}
+
+#[test]
+fn test_custom_property_declarations() {
+    let (declarations, skipped) = parse_declarations("--a: { x: y } [1,  2] ; --empty:; --bad: a); color: VAR(--a) !important; width: var(--a");
+    let values: Vec<(String, String, bool)> = declarations.iter().map(|d| match &d.value {
+        Value::Unparsed(unparsed) => (d.name.clone(), unparsed.to_css(), d.important),
+        value => panic!("expected tokens, got {:?}", value),
+    }).collect();
+    assert_eq!(values, vec![
+        (String::from("--a"), String::from("{ x: y } [1, 2]"), false),
+        (String::from("--empty"), String::new(), false),
+        (String::from("color"), String::from("VAR(--a)"), true),
+        (String::from("width"), String::from("var(--a"), false),
+    ]);
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].text, "--bad: a)");
+}
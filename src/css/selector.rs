@@ -0,0 +1,478 @@
+// https://www.w3.org/TR/selectors-4/#grammar
+//
+// Parses selector lists from the css tokens. Complex selectors are built up
+// from the left, so the `child` of an `AncestorSelector` is always the
+// rightmost compound selector and matching can walk the tree starting from the
+// element itself.
+
+use crate::css::tokenizer::{tokenize, Token, SpannedToken};
+use crate::css::{Selector, SimpleSelector, AncestorSelector, AttributeSelector, AttributeOp, Combinator, PseudoClass, PseudoElement, Nth};
+use crate::net::{BrowserError, Diagnostic};
+
+// the byte offset of the problem and what was expected there
+type Failure = (usize, &'static str);
+
+/// Parses a comma separated selector list, like the prelude of a style rule or
+/// the argument to `querySelectorAll`. If any selector in the list is invalid
+/// the whole list is.
+pub fn parse_selector_list(text:&str) -> Result<Vec<Selector>, BrowserError> {
+    let tokens = tokenize(text).into_iter()
+        .filter(|t| !matches!(t.token, Token::Comment(_)))
+        .collect();
+    let mut parser = SelectorParser { text, tokens, pos: 0, pseudo_elements: true };
+    parser.selector_list()
+        .map_err(|(offset, expected)| BrowserError::ParseError(Diagnostic::new(text.as_bytes(), offset, expected)))
+}
+
+struct SelectorParser<'a> {
+    text: &'a str,
+    tokens: Vec<SpannedToken>,
+    pos: usize,
+    // not allowed inside functional pseudo-classes
+    pseudo_elements: bool,
+}
+
+impl<'a> SelectorParser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].token
+    }
+    fn peek_next(&self) -> &Token {
+        &self.tokens[(self.pos + 1).min(self.tokens.len() - 1)].token
+    }
+    fn fail(&self, expected:&'static str) -> Failure {
+        (self.tokens[self.pos].start, expected)
+    }
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while *self.peek() == Token::Whitespace {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn selector_list(&mut self) -> Result<Vec<Selector>, Failure> {
+        let mut list = vec![];
+        loop {
+            self.skip_whitespace();
+            list.push(self.complex()?);
+            match self.peek() {
+                Token::Comma => self.pos += 1,
+                Token::Eof => return Ok(list),
+                _ => return Err(self.fail("a ',' or the end of the selector")),
+            }
+        }
+    }
+
+    fn complex(&mut self) -> Result<Selector, Failure> {
+        let first = Selector::Simple(self.compound()?);
+        self.combinators(first)
+    }
+
+    // a selector inside :has() starts with an optional combinator and is
+    // anchored on the element being matched
+    fn relative_selector_list(&mut self) -> Result<Vec<Selector>, Failure> {
+        let mut list = vec![];
+        loop {
+            self.skip_whitespace();
+            let combinator = self.combinator().unwrap_or(Combinator::Descendant);
+            let scope = SimpleSelector {
+                tag_name: None,
+                id: None,
+                class: vec![],
+                pseudo_class: vec![PseudoClass::Scope],
+                attributes: vec![],
+                pseudo_element: None,
+            };
+            let first = Selector::Ancestor(AncestorSelector {
+                ancestor: Box::new(Selector::Simple(scope)),
+                child: Box::new(Selector::Simple(self.compound()?)),
+                combinator,
+            });
+            list.push(self.combinators(first)?);
+            match self.peek() {
+                Token::Comma => self.pos += 1,
+                Token::Eof => return Ok(list),
+                _ => return Err(self.fail("a ',' or the end of the selector")),
+            }
+        }
+    }
+
+    // an explicit combinator and the whitespace after it
+    fn combinator(&mut self) -> Option<Combinator> {
+        let combinator = match self.peek() {
+            Token::Delim('>') => Combinator::Child,
+            Token::Delim('+') => Combinator::NextSibling,
+            Token::Delim('~') => Combinator::SubsequentSibling,
+            _ => return None,
+        };
+        self.pos += 1;
+        self.skip_whitespace();
+        Some(combinator)
+    }
+
+    fn combinators(&mut self, mut selector:Selector) -> Result<Selector, Failure> {
+        loop {
+            let space = self.skip_whitespace();
+            if selector.pseudo_element().is_some() {
+                return match self.peek() {
+                    Token::Comma | Token::Eof => Ok(selector),
+                    _ => Err(self.fail("the end of the selector after a pseudo-element")),
+                };
+            }
+            let combinator = match self.combinator() {
+                Some(combinator) => combinator,
+                None => match self.peek() {
+                    Token::Comma | Token::Eof => return Ok(selector),
+                    _ if space => Combinator::Descendant,
+                    _ => return Err(self.fail("a combinator")),
+                },
+            };
+            let child = self.compound()?;
+            selector = Selector::Ancestor(AncestorSelector {
+                ancestor: Box::new(selector),
+                child: Box::new(Selector::Simple(child)),
+                combinator,
+            });
+        }
+    }
+
+    fn compound(&mut self) -> Result<SimpleSelector, Failure> {
+        let start = self.pos;
+        let mut sel = SimpleSelector {
+            tag_name: None,
+            id: None,
+            class: vec![],
+            pseudo_class: vec![],
+            attributes: vec![],
+            pseudo_element: None,
+        };
+        match self.peek() {
+            Token::Ident(name) => sel.tag_name = Some(name.to_ascii_lowercase()),
+            Token::Delim('*') => sel.tag_name = Some(String::from("*")),
+            _ => {}
+        }
+        if sel.tag_name.is_some() {
+            self.pos += 1;
+        }
+        loop {
+            match self.peek().clone() {
+                Token::Hash { value, id: true } if sel.id.is_none() => {
+                    sel.id = Some(value);
+                    self.pos += 1;
+                }
+                Token::Hash { .. } => return Err(self.fail("an id")),
+                Token::Delim('.') => {
+                    self.pos += 1;
+                    match self.peek().clone() {
+                        Token::Ident(name) => sel.class.push(name),
+                        _ => return Err(self.fail("a class name")),
+                    }
+                    self.pos += 1;
+                }
+                Token::OpenSquare => sel.attributes.push(self.attribute()?),
+                Token::Colon => {
+                    self.pos += 1;
+                    if let Some(pe) = self.pseudo_element()? {
+                        sel.pseudo_element = Some(pe);
+                        break;
+                    }
+                    sel.pseudo_class.push(self.pseudo_class()?);
+                }
+                _ => break,
+            }
+        }
+        if self.pos == start {
+            return Err(self.fail("a selector"));
+        }
+        Ok(sel)
+    }
+
+    // `::before`, or the older `:before`. Returns None for a pseudo-class.
+    fn pseudo_element(&mut self) -> Result<Option<PseudoElement>, Failure> {
+        let double = *self.peek() == Token::Colon;
+        let name = match self.tokens[self.pos + double as usize].token {
+            Token::Ident(ref name) => name.to_ascii_lowercase(),
+            _ if double => return Err(self.fail("a pseudo-element")),
+            _ => return Ok(None),
+        };
+        let pe = match name.as_str() {
+            "before" => PseudoElement::Before,
+            "after" => PseudoElement::After,
+            _ if double => return Err(self.fail("a supported pseudo-element")),
+            _ => return Ok(None),
+        };
+        if !self.pseudo_elements {
+            return Err(self.fail("a selector without a pseudo-element"));
+        }
+        self.pos += 1 + double as usize;
+        Ok(Some(pe))
+    }
+
+    fn pseudo_class(&mut self) -> Result<PseudoClass, Failure> {
+        let start = self.tokens[self.pos].start;
+        let unsupported = (start, "a supported pseudo-class");
+        match self.peek().clone() {
+            Token::Ident(name) => {
+                let pc = match name.to_ascii_lowercase().as_str() {
+                    "root" => PseudoClass::Root,
+                    "empty" => PseudoClass::Empty,
+                    "first-child" => PseudoClass::FirstChild,
+                    "last-child" => PseudoClass::LastChild,
+                    "only-child" => PseudoClass::OnlyChild,
+                    "first-of-type" => PseudoClass::FirstOfType,
+                    "last-of-type" => PseudoClass::LastOfType,
+                    "only-of-type" => PseudoClass::OnlyOfType,
+                    "hover" => PseudoClass::Hover,
+                    "active" => PseudoClass::Active,
+                    "focus" => PseudoClass::Focus,
+                    "visited" => PseudoClass::Visited,
+                    "link" => PseudoClass::Link,
+                    "checked" => PseudoClass::Checked,
+                    "disabled" => PseudoClass::Disabled,
+                    "enabled" => PseudoClass::Enabled,
+                    _ => return Err(unsupported),
+                };
+                self.pos += 1;
+                Ok(pc)
+            }
+            Token::Function(name) => {
+                let mut args = self.arguments()?;
+                match name.to_ascii_lowercase().as_str() {
+                    "not" => Ok(PseudoClass::Not(args.selector_list()?)),
+                    "is" => Ok(PseudoClass::Is(args.selector_list()?)),
+                    "where" => Ok(PseudoClass::Where(args.selector_list()?)),
+                    "has" => Ok(PseudoClass::Has(args.relative_selector_list()?)),
+                    "nth-child" => {
+                        let (nth, of) = args.nth(true)?;
+                        Ok(PseudoClass::NthChild(nth, of))
+                    }
+                    "nth-last-child" => {
+                        let (nth, of) = args.nth(true)?;
+                        Ok(PseudoClass::NthLastChild(nth, of))
+                    }
+                    "nth-of-type" => Ok(PseudoClass::NthOfType(args.nth(false)?.0)),
+                    "nth-last-of-type" => Ok(PseudoClass::NthLastOfType(args.nth(false)?.0)),
+                    _ => Err(unsupported),
+                }
+            }
+            _ => Err(self.fail("a pseudo-class")),
+        }
+    }
+
+    // moves past a function and returns a parser for its arguments. errors
+    // in the arguments still point into the whole text.
+    fn arguments(&mut self) -> Result<SelectorParser<'a>, Failure> {
+        let open = self.pos;
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Token::Function(_) | Token::OpenParen => depth += 1,
+                Token::CloseParen => depth -= 1,
+                Token::Eof => return Err(self.fail("a ')'")),
+                _ => {}
+            }
+            self.pos += 1;
+            if depth == 0 {
+                break;
+            }
+        }
+        let close = &self.tokens[self.pos - 1];
+        let mut tokens = self.tokens[open + 1..self.pos - 1].to_vec();
+        tokens.push(SpannedToken { token: Token::Eof, start: close.start, end: close.start });
+        Ok(SelectorParser { text: self.text, tokens, pos: 0, pseudo_elements: false })
+    }
+
+    // https://drafts.csswg.org/css-syntax-3/#anb-microsyntax
+    // the whole argument, with an optional `of S` after it
+    fn nth(&mut self, allow_of:bool) -> Result<(Nth, Vec<Selector>), Failure> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let of = self.tokens.iter().position(|t| match &t.token {
+            Token::Ident(name) => allow_of && name.eq_ignore_ascii_case("of"),
+            _ => false,
+        });
+        let end = of.unwrap_or(self.tokens.len() - 1);
+        let nth = parse_nth(&self.text[self.tokens[start].start..self.tokens[end].start])
+            .ok_or_else(|| self.fail("an an+b expression"))?;
+        match of {
+            Some(of) => {
+                self.pos = of + 1;
+                Ok((nth, self.selector_list()?))
+            }
+            None => Ok((nth, vec![])),
+        }
+    }
+
+    fn attribute(&mut self) -> Result<AttributeSelector, Failure> {
+        self.pos += 1;
+        self.skip_whitespace();
+        let name = match self.peek() {
+            Token::Ident(name) => name.to_ascii_lowercase(),
+            _ => return Err(self.fail("an attribute name")),
+        };
+        self.pos += 1;
+        self.skip_whitespace();
+        let op = match (self.peek(), self.peek_next()) {
+            (Token::CloseSquare, _) => AttributeOp::Exists,
+            (Token::Delim('='), _) => AttributeOp::Equals,
+            (Token::Delim('~'), Token::Delim('=')) => AttributeOp::Includes,
+            (Token::Delim('|'), Token::Delim('=')) => AttributeOp::DashMatch,
+            (Token::Delim('^'), Token::Delim('=')) => AttributeOp::Prefix,
+            (Token::Delim('$'), Token::Delim('=')) => AttributeOp::Suffix,
+            (Token::Delim('*'), Token::Delim('=')) => AttributeOp::Substring,
+            _ => return Err(self.fail("an attribute operator or ']'")),
+        };
+        let mut attr = AttributeSelector { name, op, value: String::new(), case_insensitive: false };
+        if op != AttributeOp::Exists {
+            self.pos += if op == AttributeOp::Equals { 1 } else { 2 };
+            self.skip_whitespace();
+            attr.value = match self.peek() {
+                Token::Ident(value) | Token::QuotedString(value) => value.clone(),
+                _ => return Err(self.fail("an attribute value")),
+            };
+            self.pos += 1;
+            self.skip_whitespace();
+            if let Token::Ident(modifier) = self.peek() {
+                match modifier.to_ascii_lowercase().as_str() {
+                    "i" => attr.case_insensitive = true,
+                    "s" => {}
+                    _ => return Err(self.fail("'i' or 's'")),
+                }
+                self.pos += 1;
+                self.skip_whitespace();
+            }
+        }
+        if *self.peek() != Token::CloseSquare {
+            return Err(self.fail("a ']'"));
+        }
+        self.pos += 1;
+        Ok(attr)
+    }
+}
+
+fn parse_nth(text:&str) -> Option<Nth> {
+    let text = text.split_whitespace().collect::<String>().to_ascii_lowercase();
+    match text.as_str() {
+        "odd" => return Some(Nth { a: 2, b: 1 }),
+        "even" => return Some(Nth { a: 2, b: 0 }),
+        _ => {}
+    }
+    match text.find('n') {
+        None => Some(Nth { a: 0, b: text.parse().ok()? }),
+        Some(n) => {
+            let a = match &text[..n] {
+                "" | "+" => 1,
+                "-" => -1,
+                a => a.parse().ok()?,
+            };
+            let b = match &text[n + 1..] {
+                "" => 0,
+                b if b.starts_with('+') || b.starts_with('-') => b.parse().ok()?,
+                _ => return None,
+            };
+            Some(Nth { a, b })
+        }
+    }
+}
+
+#[cfg(test)]
+fn compound(text:&str) -> SimpleSelector {
+    match parse_selector_list(text).unwrap().remove(0) {
+        Selector::Simple(simple) => simple,
+        sel => panic!("expected a compound selector, got {:?}", sel),
+    }
+}
+
+#[test]
+fn test_attribute_selectors() {
+    let sel = compound(r#"a[href][ lang |= "en" ][title='x y' i][rel~=next]"#);
+    assert_eq!(sel.tag_name, Some(String::from("a")));
+    assert_eq!(sel.attributes, vec![
+        AttributeSelector { name: String::from("href"), op: AttributeOp::Exists, value: String::new(), case_insensitive: false },
+        AttributeSelector { name: String::from("lang"), op: AttributeOp::DashMatch, value: String::from("en"), case_insensitive: false },
+        AttributeSelector { name: String::from("title"), op: AttributeOp::Equals, value: String::from("x y"), case_insensitive: true },
+        AttributeSelector { name: String::from("rel"), op: AttributeOp::Includes, value: String::from("next"), case_insensitive: false },
+    ]);
+    let ops:Vec<AttributeOp> = compound("[a^=b][a$=b][a*=b]").attributes.iter().map(|a| a.op).collect();
+    assert_eq!(ops, vec![AttributeOp::Prefix, AttributeOp::Suffix, AttributeOp::Substring]);
+    for bad in &["[", "[a=]", "[a==b]", "[a=b c]", "a[1]", "a..b", "#1a", "a:"] {
+        assert!(parse_selector_list(bad).is_err(), "{} should not parse", bad);
+    }
+}
+
+#[test]
+fn test_combinators() {
+    let list = parse_selector_list("ul > li + li ~ p  span,div.a.b#c:hover:not(.x)").unwrap();
+    assert_eq!(list.len(), 2);
+    let mut combinators = vec![];
+    let mut sel = &list[0];
+    while let Selector::Ancestor(anc) = sel {
+        assert!(matches!(*anc.child, Selector::Simple(_)));
+        combinators.push(anc.combinator);
+        sel = &anc.ancestor;
+    }
+    assert_eq!(combinators, vec![Combinator::Descendant, Combinator::SubsequentSibling, Combinator::NextSibling, Combinator::Child]);
+    let sel = compound("div.a.b#c:hover:not(.x)");
+    assert_eq!(sel.class, vec![String::from("a"), String::from("b")]);
+    assert_eq!(sel.id, Some(String::from("c")));
+    assert_eq!(sel.pseudo_class.len(), 2);
+    assert_eq!(sel.pseudo_class[0], PseudoClass::Hover);
+    match parse_selector_list("a, b >") {
+        Err(BrowserError::ParseError(diag)) => assert_eq!((diag.column, diag.expected.as_str()), (7, "a selector")),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_pseudo_classes() {
+    let nth = |text:&str| match compound(text).pseudo_class.remove(0) {
+        PseudoClass::NthChild(nth, of) => (nth.a, nth.b, of.len()),
+        PseudoClass::NthOfType(nth) => (nth.a, nth.b, 0),
+        pc => panic!("unexpected {:?}", pc),
+    };
+    assert_eq!(nth(":nth-child(odd)"), (2, 1, 0));
+    assert_eq!(nth(":nth-child( EVEN )"), (2, 0, 0));
+    assert_eq!(nth(":nth-child(2n+1)"), (2, 1, 0));
+    assert_eq!(nth(":nth-child(-n + 3 of li.x, p)"), (-1, 3, 2));
+    assert_eq!(nth(":nth-child(n)"), (1, 0, 0));
+    assert_eq!(nth(":nth-of-type(-2n-1)"), (-2, -1, 0));
+    assert_eq!(nth(":nth-of-type(7)"), (0, 7, 0));
+    match compound("div:has(> img, p + a)").pseudo_class.remove(0) {
+        PseudoClass::Has(list) => {
+            assert_eq!(list.len(), 2);
+            match &list[0] {
+                Selector::Ancestor(anc) => {
+                    assert_eq!(anc.combinator, Combinator::Child);
+                    assert_eq!(*anc.ancestor, Selector::Simple(SimpleSelector {
+                        tag_name: None,
+                        id: None,
+                        class: vec![],
+                        pseudo_class: vec![PseudoClass::Scope],
+                        attributes: vec![],
+                        pseudo_element: None,
+                    }));
+                }
+                sel => panic!("unexpected {:?}", sel),
+            }
+        }
+        pc => panic!("unexpected {:?}", pc),
+    }
+    for bad in &[":bogus", ":nth-child(2x)", ":nth-child(n of)", ":nth-of-type(n of p)", ":not(a,)", ":is(", ":first-line"] {
+        assert!(parse_selector_list(bad).is_err(), "{} should not parse", bad);
+    }
+    assert!(Nth { a: 2, b: 1 }.matches(5) && !Nth { a: 2, b: 1 }.matches(4));
+    assert!(Nth { a: -1, b: 3 }.matches(3) && !Nth { a: -1, b: 3 }.matches(4));
+    assert!(Nth { a: 0, b: 2 }.matches(2) && !Nth { a: 0, b: 2 }.matches(4));
+}
+
+#[test]
+fn test_pseudo_elements() {
+    let list = parse_selector_list("p::before, a.x:hover:AFTER, ul > li::after").unwrap();
+    let pes:Vec<Option<PseudoElement>> = list.iter().map(|sel| sel.pseudo_element()).collect();
+    assert_eq!(pes, vec![Some(PseudoElement::Before), Some(PseudoElement::After), Some(PseudoElement::After)]);
+    assert_eq!(compound("a.x:hover:after").pseudo_class, vec![PseudoClass::Hover]);
+    assert_eq!(list[2].specificity(), (0, 0, 3));
+    for bad in &["p::before span", "p::before.x", "::first-line", "p::", ":not(::before)", "li:has(> a::after)"] {
+        assert!(parse_selector_list(bad).is_err(), "{} should not parse", bad);
+    }
+}
@@ -0,0 +1,627 @@
+// https://drafts.csswg.org/cssom-1/#serializing-css-values
+//
+// Writes parsed stylesheets back out as css text. What the parser threw away
+// stays lost: lengths in media queries come out in px, angles in degrees, and
+// at-rules the parser doesn't know keep only their prelude. The longhands a
+// shorthand with var() in it was expanded to are written as the shorthand.
+
+use std::fmt;
+use crate::css::{Stylesheet, RuleType, Rule, AtRule, ImportRule, Declaration, Value, UnparsedValue, Unit, Color, Calc,
+    Selector, SimpleSelector, PseudoClass, PseudoElement, Nth, AttributeSelector, AttributeOp, Combinator,
+    MediaQueryList, MediaQuery, MediaCondition, MediaFeature, MediaValue, Comparison, SupportsCondition, Token};
+use crate::css::parser::{DECLARATION_AT_RULES, RULE_AT_RULES};
+
+impl fmt::Display for Stylesheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rule in self.rules.iter() {
+            writeln!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RuleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_rule(f, self, 0)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_selectors(f, &self.selectors)?;
+        write!(f, " {{")?;
+        write_declarations(f, &self.declarations)?;
+        write!(f, " }}")
+    }
+}
+
+// rules inside blocks are indented two spaces a level
+fn write_rule(f: &mut fmt::Formatter<'_>, rule:&RuleType, indent:usize) -> fmt::Result {
+    match rule {
+        RuleType::Rule(rule) => write!(f, "{}", rule),
+        RuleType::AtRule(rule) => write_at_rule(f, rule, indent),
+        RuleType::Media(rule) => {
+            write!(f, "@media ")?;
+            if !rule.queries.queries.is_empty() {
+                write!(f, "{} ", rule.queries)?;
+            }
+            write_block(f, &rule.rules, indent)
+        }
+        RuleType::Supports(rule) => {
+            write!(f, "@supports {} ", rule.condition)?;
+            write_block(f, &rule.rules, indent)
+        }
+        RuleType::Import(rule) => write!(f, "{}", rule),
+        RuleType::Layer(rule) => {
+            write!(f, "@layer ")?;
+            if let Some(name) = &rule.name {
+                write!(f, "{} ", name)?;
+            }
+            write_block(f, &rule.rules, indent)
+        }
+        RuleType::LayerStatement(names) => write!(f, "@layer {};", names.join(", ")),
+        RuleType::Comment(text) => write!(f, "/*{}*/", text),
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter<'_>, rules:&[RuleType], indent:usize) -> fmt::Result {
+    writeln!(f, "{{")?;
+    for rule in rules {
+        write!(f, "{}", "  ".repeat(indent + 1))?;
+        write_rule(f, rule, indent + 1)?;
+        writeln!(f)?;
+    }
+    write!(f, "{}}}", "  ".repeat(indent))
+}
+
+fn write_at_rule(f: &mut fmt::Formatter<'_>, rule:&AtRule, indent:usize) -> fmt::Result {
+    write!(f, "@{}", rule.name)?;
+    // one the parser doesn't know goes back out as it came in
+    if let Some(raw) = &rule.raw {
+        if !raw.is_empty() {
+            write!(f, " ")?;
+        }
+        for token in raw.iter() {
+            write!(f, "{}", token)?;
+        }
+        return match raw.last() {
+            Some(Token::CloseCurly) => Ok(()),
+            _ => write!(f, ";"),
+        };
+    }
+    if let Some(value) = &rule.value {
+        write!(f, " {}", value)?;
+    }
+    if DECLARATION_AT_RULES.contains(&rule.name.as_str()) {
+        write!(f, " {{")?;
+        for rule in rule.rules.iter() {
+            if let RuleType::Rule(rule) = rule {
+                write_declarations(f, &rule.declarations)?;
+            }
+        }
+        return write!(f, " }}");
+    }
+    if RULE_AT_RULES.contains(&rule.name.as_str()) {
+        write!(f, " ")?;
+        return write_block(f, &rule.rules, indent);
+    }
+    write!(f, ";")
+}
+
+impl fmt::Display for ImportRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@import url({})", Token::QuotedString(self.url.clone()))?;
+        match self.layer.as_deref() {
+            Some("") => write!(f, " layer")?,
+            Some(name) => write!(f, " layer({})", name)?,
+            None => {}
+        }
+        if let Some(condition) = &self.supports {
+            write!(f, " supports({})", condition)?;
+        }
+        if !self.media.queries.is_empty() {
+            write!(f, " {}", self.media)?;
+        }
+        write!(f, ";")
+    }
+}
+
+// the longhands of a shorthand with var() in it all hold the shorthand's
+// tokens, so they are written once as the shorthand
+fn write_declarations(f: &mut fmt::Formatter<'_>, declarations:&[Declaration]) -> fmt::Result {
+    let mut written:Vec<&UnparsedValue> = vec![];
+    for dec in declarations {
+        match &dec.value {
+            Value::Unparsed(unparsed) => match &unparsed.shorthand {
+                Some(shorthand) => {
+                    if written.contains(&unparsed) {
+                        continue;
+                    }
+                    written.push(unparsed);
+                    write!(f, " {}: {}", shorthand, unparsed.to_css())?;
+                    if dec.important {
+                        write!(f, " !important")?;
+                    }
+                    write!(f, ";")?;
+                }
+                None => write!(f, " {};", dec)?,
+            },
+            _ => write!(f, " {};", dec)?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.value_css())?;
+        if self.important {
+            write!(f, " !important")?;
+        }
+        Ok(())
+    }
+}
+
+impl Declaration {
+    /// The value as css text, without the `!important`.
+    pub fn value_css(&self) -> String {
+        match &self.value {
+            Value::ArrayValue(items) if is_comma_list(&self.name) => {
+                items.iter().map(|item| item.to_string()).collect::<Vec<String>>().join(", ")
+            }
+            value => value.to_string(),
+        }
+    }
+}
+
+// the properties whose lists are separated by commas rather than spaces
+fn is_comma_list(name:&str) -> bool {
+    matches!(name, "font-family" | "src")
+}
+
+// a list inside a list is separated by commas, as the families are in
+// `bold 12px Georgia, serif`
+fn write_items(f: &mut fmt::Formatter<'_>, items:&[Value], separator:&str) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        match item {
+            Value::ArrayValue(inner) => write_items(f, inner, ", ")?,
+            item => write!(f, "{}", item)?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Keyword(word) => write!(f, "{}", word),
+            Value::Length(v, unit) => write!(f, "{}{}", v, unit),
+            Value::ColorValue(color) => write!(f, "{}", color),
+            Value::HexColor(hex) => write!(f, "{}", hex),
+            Value::ArrayValue(items) => write_items(f, items, " "),
+            Value::FunCall(call) if call.name == "url" => match call.arguments.first() {
+                Some(Value::StringLiteral(url)) => write!(f, "url({})", Token::QuotedString(url.clone())),
+                _ => write!(f, "url()"),
+            },
+            Value::FunCall(call) => {
+                // a '/' means the arguments were separated by spaces, as in rgb(0 0 0 / 50%)
+                let slash = call.arguments.iter().any(|arg| matches!(arg, Value::Keyword(k) if k == "/"));
+                write!(f, "{}(", call.name)?;
+                write_items(f, &call.arguments, if slash { " " } else { ", " })?;
+                write!(f, ")")
+            }
+            Value::StringLiteral(text) => write!(f, "{}", Token::QuotedString(text.clone())),
+            Value::UnicodeCodepoint(codepoint) => write!(f, "U+{:X}", codepoint),
+            Value::UnicodeRange(start, end) => write!(f, "U+{:X}-{:X}", start, end),
+            Value::Number(v) => write!(f, "{}", v),
+            Value::Unparsed(unparsed) => write!(f, "{}", unparsed.to_css()),
+            Value::Calc(calc) => write!(f, "{}", calc),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self {
+            Unit::Px => "px",
+            Unit::Em => "em",
+            Unit::Per => "%",
+            Unit::Rem => "rem",
+            Unit::Ex => "ex",
+            Unit::Ch => "ch",
+            Unit::Vw => "vw",
+            Unit::Vh => "vh",
+            Unit::Vmin => "vmin",
+            Unit::Vmax => "vmax",
+            Unit::Pt => "pt",
+            Unit::Pc => "pc",
+            Unit::In => "in",
+            Unit::Cm => "cm",
+            Unit::Mm => "mm",
+        };
+        write!(f, "{}", unit)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Calc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arguments = |f: &mut fmt::Formatter<'_>, items:&[&Calc]| -> fmt::Result {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_math(f, item, false)?;
+            }
+            write!(f, ")")
+        };
+        match self {
+            Calc::Min(items) => {
+                write!(f, "min(")?;
+                arguments(f, &items.iter().collect::<Vec<&Calc>>())
+            }
+            Calc::Max(items) => {
+                write!(f, "max(")?;
+                arguments(f, &items.iter().collect::<Vec<&Calc>>())
+            }
+            Calc::Clamp(min, value, max) => {
+                write!(f, "clamp(")?;
+                arguments(f, &[min, value, max])
+            }
+            calc => {
+                write!(f, "calc(")?;
+                write_math(f, calc, false)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+// operations inside other operations get parens, so the order is kept
+fn write_math(f: &mut fmt::Formatter<'_>, calc:&Calc, nested:bool) -> fmt::Result {
+    let (a, operator, b) = match calc {
+        Calc::Number(v) => return write!(f, "{}", v),
+        Calc::Length(v, unit) => return write!(f, "{}{}", v, unit),
+        Calc::Min(_) | Calc::Max(_) | Calc::Clamp(_, _, _) => return write!(f, "{}", calc),
+        Calc::Sum(a, b) => (a, "+", b),
+        Calc::Difference(a, b) => (a, "-", b),
+        Calc::Product(a, b) => (a, "*", b),
+        Calc::Quotient(a, b) => (a, "/", b),
+    };
+    if nested {
+        write!(f, "(")?;
+    }
+    write_math(f, a, true)?;
+    write!(f, " {} ", operator)?;
+    write_math(f, b, true)?;
+    if nested {
+        write!(f, ")")?;
+    }
+    Ok(())
+}
+
+fn write_selectors(f: &mut fmt::Formatter<'_>, selectors:&[Selector]) -> fmt::Result {
+    for (i, selector) in selectors.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", selector)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Simple(simple) => write!(f, "{}", simple),
+            Selector::Ancestor(ancestor) => {
+                let combinator = match ancestor.combinator {
+                    Combinator::Descendant => " ",
+                    Combinator::Child => " > ",
+                    Combinator::NextSibling => " + ",
+                    Combinator::SubsequentSibling => " ~ ",
+                };
+                write!(f, "{}{}{}", ancestor.ancestor, combinator, ancestor.child)
+            }
+        }
+    }
+}
+
+impl fmt::Display for SimpleSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.tag_name {
+            Some(tag_name) => write!(f, "{}", tag_name)?,
+            None if self.id.is_none() && self.class.is_empty() && self.attributes.is_empty()
+                && self.pseudo_class.is_empty() && self.pseudo_element.is_none() => write!(f, "*")?,
+            None => {}
+        }
+        if let Some(id) = &self.id {
+            write!(f, "#{}", id)?;
+        }
+        for class in self.class.iter() {
+            write!(f, ".{}", class)?;
+        }
+        for attribute in self.attributes.iter() {
+            write!(f, "{}", attribute)?;
+        }
+        for pseudo_class in self.pseudo_class.iter() {
+            write!(f, "{}", pseudo_class)?;
+        }
+        match self.pseudo_element {
+            Some(PseudoElement::Before) => write!(f, "::before"),
+            Some(PseudoElement::After) => write!(f, "::after"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for AttributeSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.op {
+            AttributeOp::Exists => return write!(f, "[{}]", self.name),
+            AttributeOp::Equals => "=",
+            AttributeOp::Includes => "~=",
+            AttributeOp::DashMatch => "|=",
+            AttributeOp::Prefix => "^=",
+            AttributeOp::Suffix => "$=",
+            AttributeOp::Substring => "*=",
+        };
+        write!(f, "[{}{}{}", self.name, op, Token::QuotedString(self.value.clone()))?;
+        if self.case_insensitive {
+            write!(f, " i")?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl fmt::Display for PseudoClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nth = |f: &mut fmt::Formatter<'_>, name:&str, nth:&Nth, of:&[Selector]| -> fmt::Result {
+            write!(f, ":{}({}", name, nth)?;
+            if !of.is_empty() {
+                write!(f, " of ")?;
+                write_selectors(f, of)?;
+            }
+            write!(f, ")")
+        };
+        let list = |f: &mut fmt::Formatter<'_>, name:&str, selectors:&[Selector]| -> fmt::Result {
+            write!(f, ":{}(", name)?;
+            write_selectors(f, selectors)?;
+            write!(f, ")")
+        };
+        match self {
+            PseudoClass::NthChild(an_b, of) => nth(f, "nth-child", an_b, of),
+            PseudoClass::NthLastChild(an_b, of) => nth(f, "nth-last-child", an_b, of),
+            PseudoClass::NthOfType(an_b) => nth(f, "nth-of-type", an_b, &[]),
+            PseudoClass::NthLastOfType(an_b) => nth(f, "nth-last-of-type", an_b, &[]),
+            PseudoClass::Not(selectors) => list(f, "not", selectors),
+            PseudoClass::Is(selectors) => list(f, "is", selectors),
+            PseudoClass::Where(selectors) => list(f, "where", selectors),
+            // written relative to the element again, as `:has(> img)`
+            PseudoClass::Has(selectors) => {
+                write!(f, ":has(")?;
+                for (i, selector) in selectors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    let text = selector.to_string();
+                    write!(f, "{}", text.strip_prefix(":scope ").unwrap_or(&text))?;
+                }
+                write!(f, ")")
+            }
+            PseudoClass::Root => write!(f, ":root"),
+            PseudoClass::Empty => write!(f, ":empty"),
+            PseudoClass::FirstChild => write!(f, ":first-child"),
+            PseudoClass::LastChild => write!(f, ":last-child"),
+            PseudoClass::OnlyChild => write!(f, ":only-child"),
+            PseudoClass::FirstOfType => write!(f, ":first-of-type"),
+            PseudoClass::LastOfType => write!(f, ":last-of-type"),
+            PseudoClass::OnlyOfType => write!(f, ":only-of-type"),
+            PseudoClass::Scope => write!(f, ":scope"),
+            PseudoClass::Hover => write!(f, ":hover"),
+            PseudoClass::Active => write!(f, ":active"),
+            PseudoClass::Focus => write!(f, ":focus"),
+            PseudoClass::Visited => write!(f, ":visited"),
+            PseudoClass::Link => write!(f, ":link"),
+            PseudoClass::Checked => write!(f, ":checked"),
+            PseudoClass::Disabled => write!(f, ":disabled"),
+            PseudoClass::Enabled => write!(f, ":enabled"),
+        }
+    }
+}
+
+impl fmt::Display for Nth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.a {
+            0 => return write!(f, "{}", self.b),
+            1 => write!(f, "n")?,
+            -1 => write!(f, "-n")?,
+            a => write!(f, "{}n", a)?,
+        }
+        match self.b {
+            0 => Ok(()),
+            b if b > 0 => write!(f, "+{}", b),
+            b => write!(f, "{}", b),
+        }
+    }
+}
+
+impl fmt::Display for MediaQueryList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, query) in self.queries.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", query)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MediaQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negated {
+            write!(f, "not ")?;
+        }
+        let media_type = match (&self.media_type, &self.condition) {
+            (Some(media_type), _) => Some(media_type.as_str()),
+            (None, None) => Some("all"),
+            (None, Some(_)) if self.negated => Some("all"),
+            (None, Some(_)) => None,
+        };
+        match (media_type, &self.condition) {
+            (Some(media_type), Some(condition)) => {
+                write!(f, "{} and ", media_type)?;
+                // `or` can't follow a media type without parens
+                match condition {
+                    MediaCondition::Or(_) => write!(f, "({})", condition),
+                    condition => write!(f, "{}", condition),
+                }
+            }
+            (Some(media_type), None) => write!(f, "{}", media_type),
+            (None, Some(condition)) => write!(f, "{}", condition),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for MediaCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let in_parens = |f: &mut fmt::Formatter<'_>, condition:&MediaCondition| match condition {
+            MediaCondition::Feature(_) | MediaCondition::Unknown => write!(f, "{}", condition),
+            condition => write!(f, "({})", condition),
+        };
+        let joined = |f: &mut fmt::Formatter<'_>, conditions:&[MediaCondition], joiner:&str| -> fmt::Result {
+            for (i, condition) in conditions.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {} ", joiner)?;
+                }
+                in_parens(f, condition)?;
+            }
+            Ok(())
+        };
+        match self {
+            MediaCondition::Feature(feature) => write!(f, "{}", feature),
+            MediaCondition::Not(condition) => {
+                write!(f, "not ")?;
+                in_parens(f, condition)
+            }
+            MediaCondition::And(conditions) => joined(f, conditions, "and"),
+            MediaCondition::Or(conditions) => joined(f, conditions, "or"),
+            // a feature nothing knows, which never matches either
+            MediaCondition::Unknown => write!(f, "(unknown)"),
+        }
+    }
+}
+
+impl fmt::Display for MediaFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (op, value) = match &self.test {
+            None => return write!(f, "({})", self.name),
+            Some(test) => test,
+        };
+        let op = match op {
+            Comparison::Less => " < ",
+            Comparison::LessOrEqual => " <= ",
+            Comparison::Equal => ": ",
+            Comparison::GreaterOrEqual => " >= ",
+            Comparison::Greater => " > ",
+        };
+        write!(f, "({}{}", self.name, op)?;
+        match value {
+            MediaValue::Ident(ident) => write!(f, "{}", ident)?,
+            // the parser keeps lengths in px and resolutions in dppx
+            MediaValue::Number(n) => match self.name.as_str() {
+                "width" | "height" | "device-width" | "device-height" => write!(f, "{}px", n)?,
+                "resolution" => write!(f, "{}dppx", n)?,
+                _ => write!(f, "{}", n)?,
+            },
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Display for SupportsCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let in_parens = |f: &mut fmt::Formatter<'_>, condition:&SupportsCondition| match condition {
+            SupportsCondition::Not(_) | SupportsCondition::And(_) | SupportsCondition::Or(_) => write!(f, "({})", condition),
+            condition => write!(f, "{}", condition),
+        };
+        let joined = |f: &mut fmt::Formatter<'_>, conditions:&[SupportsCondition], joiner:&str| -> fmt::Result {
+            for (i, condition) in conditions.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {} ", joiner)?;
+                }
+                in_parens(f, condition)?;
+            }
+            Ok(())
+        };
+        match self {
+            SupportsCondition::Declaration(dec) => write!(f, "({})", dec),
+            SupportsCondition::Selector(selector) => write!(f, "selector({})", selector),
+            SupportsCondition::Not(condition) => {
+                write!(f, "not ")?;
+                in_parens(f, condition)
+            }
+            SupportsCondition::And(conditions) => joined(f, conditions, "and"),
+            SupportsCondition::Or(conditions) => joined(f, conditions, "or"),
+            SupportsCondition::Unknown => write!(f, "(unknown)"),
+        }
+    }
+}
+
+#[test]
+fn test_serialize_stylesheet() {
+    use crate::css::parse_stylesheet;
+    let css = r#"@charset "UTF-8";
+@import url("print.css") layer(base) supports((display: flex)) print and (min-width: 600px);
+@layer base, theme;
+/* comment */
+div > p.note#first, a:hover, li:nth-child(2n+1 of .a), img:not([alt]), p:has(> img) { margin: 1px 2em; color: #ff0000 !important; }
+@media screen and (max-width: 40em), not print { @layer theme { .x::before { content: "a 'b'"; font-family: "Open Sans", serif; } } }
+@supports not (display: grid) { body { width: calc(100% - 2 * 10px); height: clamp(1rem, 2.5vw, 3rem); } }
+@font-face { font-family: Foo; src: url("foo.woff2"), url("foo.ttf"); }
+.v { margin: var(--gap) 4px; --brand: red; }
+"#;
+    let text = parse_stylesheet(css).unwrap().to_string();
+    assert_eq!(text, r#"@charset "UTF-8";
+@import url("print.css") layer(base) supports((display: flex)) print and (width >= 600px);
+@layer base, theme;
+/* comment */
+div > p#first.note, a:hover, li:nth-child(2n+1 of .a), img:not([alt]), p:has(> img) { margin: 1px 2em; color: #ff0000 !important; }
+@media screen and (width <= 720px), not print {
+  @layer theme {
+    .x::before { content: "a 'b'"; font-family: "Open Sans", serif; }
+  }
+}
+@supports not (display: grid) {
+  body { width: calc(100% - (2 * 10px)); height: clamp(1rem, 2.5vw, 3rem); }
+}
+@font-face { font-family: Foo; src: url("foo.woff2"), url("foo.ttf"); }
+.v { margin: var(--gap) 4px; --brand: red; }
+"#);
+    // the text parses back to the same rules
+    assert_eq!(parse_stylesheet(&text).unwrap().rules, parse_stylesheet(css).unwrap().rules);
+
+    // at-rules the parser doesn't know keep their prelude and block
+    let css = "@keyframes k { from { color: red } 50% { width: calc(1px + 2px) } }\n@unknown foo(a, b);\n@empty{}\n";
+    let text = parse_stylesheet(css).unwrap().to_string();
+    assert_eq!(text, css.replace("@empty{}", "@empty {}"));
+    assert_eq!(parse_stylesheet(&text).unwrap().rules, parse_stylesheet(css).unwrap().rules);
+
+    let mut expanded = parse_stylesheet(".v { margin: var(--gap) 4px; padding: 1px 2px; }").unwrap();
+    crate::style::expand_styles(&mut expanded);
+    assert_eq!(expanded.to_string(), ".v { margin: var(--gap) 4px; padding-top: 1px; padding-right: 2px; padding-bottom: 1px; padding-left: 2px; }\n");
+}
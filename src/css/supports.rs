@@ -0,0 +1,195 @@
+// https://drafts.csswg.org/css-conditional-3/#at-supports
+//
+// Parses the conditions of @supports rules and of the supports() in @import.
+// Declarations are parsed with the value grammar here, but whether the engine
+// supports them is up to the style module. Anything in parens that isn't a
+// declaration or a condition is unknown, which is false, and so is a
+// condition that doesn't parse at all.
+
+use crate::css::tokenizer::{tokenize, Token, SpannedToken};
+use crate::css::{Declaration, Value, UnparsedValue};
+use crate::css::value::parse_value;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SupportsCondition {
+    Declaration(Declaration),
+    /// the selector list inside `selector()`
+    Selector(String),
+    Not(Box<SupportsCondition>),
+    And(Vec<SupportsCondition>),
+    Or(Vec<SupportsCondition>),
+    Unknown,
+}
+
+/// Parses a supports condition, like the prelude of an `@supports` rule.
+pub fn parse_supports_condition(text:&str) -> SupportsCondition {
+    let tokens = tokenize(text).into_iter()
+        .filter(|t| !matches!(t.token, Token::Comment(_)))
+        .collect();
+    let mut parser = SupportsParser { text, tokens, pos: 0 };
+    match parser.condition() {
+        Some(condition) if *parser.lookahead() == Token::Eof => condition,
+        _ => SupportsCondition::Unknown,
+    }
+}
+
+struct SupportsParser<'a> {
+    text: &'a str,
+    tokens: Vec<SpannedToken>,
+    pos: usize,
+}
+
+impl<'a> SupportsParser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].token
+    }
+    fn skip_whitespace(&mut self) {
+        while *self.peek() == Token::Whitespace {
+            self.pos += 1;
+        }
+    }
+    // the next token that isn't whitespace, without moving
+    fn lookahead(&self) -> &Token {
+        let mut i = self.pos;
+        while self.tokens[i].token == Token::Whitespace {
+            i += 1;
+        }
+        &self.tokens[i].token
+    }
+    fn ident(&self) -> Option<String> {
+        match self.peek() {
+            Token::Ident(name) => Some(name.to_ascii_lowercase()),
+            _ => None,
+        }
+    }
+    // the index of the token that closes the block opened at `open`
+    fn close_of(&self, open:usize) -> usize {
+        let mut depth = 0;
+        let mut i = open;
+        loop {
+            match self.tokens[i].token {
+                Token::Eof => return i,
+                Token::OpenParen | Token::Function(_) | Token::OpenSquare | Token::OpenCurly => depth += 1,
+                Token::CloseParen | Token::CloseSquare | Token::CloseCurly => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return i;
+            }
+            i += 1;
+        }
+    }
+    // the source text between two tokens
+    fn source(&self, from:usize, to:usize) -> &'a str {
+        if from >= to {
+            return "";
+        }
+        &self.text[self.tokens[from].start..self.tokens[to - 1].end]
+    }
+
+    // `not` a condition, or conditions joined by all `and` or all `or`
+    fn condition(&mut self) -> Option<SupportsCondition> {
+        self.skip_whitespace();
+        if self.ident().as_deref() == Some("not") {
+            self.pos += 1;
+            return Some(SupportsCondition::Not(Box::new(self.in_parens()?)));
+        }
+        let mut conditions = vec![self.in_parens()?];
+        let mut joiner:Option<String> = None;
+        while let Token::Ident(word) = self.lookahead() {
+            let word = word.to_ascii_lowercase();
+            if (word != "and" && word != "or") || matches!(&joiner, Some(j) if *j != word) {
+                return None;
+            }
+            self.skip_whitespace();
+            self.pos += 1;
+            conditions.push(self.in_parens()?);
+            joiner = Some(word);
+        }
+        match joiner.as_deref() {
+            None => conditions.pop(),
+            Some("or") => Some(SupportsCondition::Or(conditions)),
+            _ => Some(SupportsCondition::And(conditions)),
+        }
+    }
+
+    fn in_parens(&mut self) -> Option<SupportsCondition> {
+        self.skip_whitespace();
+        let open = self.pos;
+        let close = self.close_of(open);
+        let inside = match self.peek() {
+            Token::OpenParen => None,
+            Token::Function(name) => Some(name.to_ascii_lowercase()),
+            _ => return None,
+        };
+        if self.tokens[close].token != Token::CloseParen {
+            return None;
+        }
+        self.pos = close + 1;
+        if inside.is_some() {
+            return Some(match inside.as_deref() {
+                Some("selector") => SupportsCondition::Selector(self.source(open + 1, close).trim().to_string()),
+                _ => SupportsCondition::Unknown,
+            });
+        }
+        // a nested condition, a declaration, or something unknown
+        let mut nested = SupportsParser { text: self.text, tokens: self.tokens[open + 1..close].to_vec(), pos: 0 };
+        nested.tokens.push(SpannedToken { token: Token::Eof, start: self.tokens[close].start, end: self.tokens[close].start });
+        nested.skip_whitespace();
+        let starts_condition = matches!(nested.peek(), Token::OpenParen | Token::Function(_)) || nested.ident().as_deref() == Some("not");
+        if starts_condition {
+            return Some(match nested.condition() {
+                Some(condition) if *nested.lookahead() == Token::Eof => condition,
+                _ => SupportsCondition::Unknown,
+            });
+        }
+        Some(nested.declaration().unwrap_or(SupportsCondition::Unknown))
+    }
+
+    fn declaration(&mut self) -> Option<SupportsCondition> {
+        let name = match self.peek() {
+            Token::Ident(name) if name.starts_with("--") => name.clone(),
+            Token::Ident(name) => name.to_ascii_lowercase(),
+            _ => return None,
+        };
+        self.pos += 1;
+        self.skip_whitespace();
+        if *self.peek() != Token::Colon {
+            return None;
+        }
+        // custom properties, and values with var(), aren't parsed until they're used
+        let is_var = |t:&SpannedToken| matches!(&t.token, Token::Function(f) if f.eq_ignore_ascii_case("var"));
+        if name.starts_with("--") || self.tokens[self.pos..].iter().any(is_var) {
+            let mut tokens:Vec<Token> = self.tokens[self.pos + 1..self.tokens.len() - 1].iter().map(|t| t.token.clone()).collect();
+            while tokens.first() == Some(&Token::Whitespace) {
+                tokens.remove(0);
+            }
+            while tokens.last() == Some(&Token::Whitespace) {
+                tokens.pop();
+            }
+            let value = Value::Unparsed(UnparsedValue { tokens, shorthand: None });
+            return Some(SupportsCondition::Declaration(Declaration { name, value, important: false }));
+        }
+        let value = parse_value(&self.tokens[self.pos + 1..self.tokens.len() - 1], self.text)?;
+        Some(SupportsCondition::Declaration(Declaration { name, value, important: false }))
+    }
+}
+
+#[test]
+fn test_parse_supports_conditions() {
+    use crate::css::Value::Keyword;
+    let display = |keyword:&str| SupportsCondition::Declaration(Declaration {
+        name: String::from("display"), value: Keyword(String::from(keyword)), important: false });
+    assert_eq!(parse_supports_condition("(display: flex)"), display("flex"));
+    assert_eq!(parse_supports_condition("not (display: grid)"), SupportsCondition::Not(Box::new(display("grid"))));
+    assert_eq!(parse_supports_condition("(display: flex) and ((display: grid) or (display: block))"),
+               SupportsCondition::And(vec![display("flex"), SupportsCondition::Or(vec![display("grid"), display("block")])]));
+    assert_eq!(parse_supports_condition("selector(a > b)"), SupportsCondition::Selector(String::from("a > b")));
+    assert_eq!(parse_supports_condition("(Display : Block)"), display("Block"));
+    // unknown inside parens, but the rest still counts
+    assert_eq!(parse_supports_condition("(whatever) or (display: block)"),
+               SupportsCondition::Or(vec![SupportsCondition::Unknown, display("block")]));
+    assert_eq!(parse_supports_condition("(a: b) and (c: d) or (e: f)"), SupportsCondition::Unknown);
+    assert_eq!(parse_supports_condition("display: block"), SupportsCondition::Unknown);
+    assert_eq!(parse_supports_condition(""), SupportsCondition::Unknown);
+}
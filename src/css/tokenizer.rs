@@ -0,0 +1,498 @@
+// https://www.w3.org/TR/css-syntax-3/#tokenization
+//
+// Works on the decoded text without the preprocessing step, so the offsets of
+// the tokens point into the original source. CR, CRLF and form feeds are
+// treated as newlines where they are read. Comments are kept as tokens so the
+// parser can hold on to the ones between rules.
+
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Token {
+    Ident(String),
+    Function(String),
+    AtKeyword(String),
+    /// `id` is set when the name would also be a valid identifier
+    Hash { value: String, id: bool },
+    QuotedString(String),
+    BadString,
+    Url(String),
+    BadUrl,
+    Delim(char),
+    Number { value: f32, integer: bool },
+    Percentage(f32),
+    Dimension { value: f32, integer: bool, unit: String },
+    Whitespace,
+    Cdo,
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+    Comment(String),
+    Eof,
+}
+
+/// A token and the byte range of the source it came from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub start: usize,
+    pub end: usize,
+}
+
+// writes the token back as css. names aren't escaped, so only tokens that came
+// from plain names round trip exactly.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Function(name) => write!(f, "{}(", name),
+            Token::AtKeyword(name) => write!(f, "@{}", name),
+            Token::Hash { value, .. } => write!(f, "#{}", value),
+            Token::QuotedString(text) => write!(f, "\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\a ")),
+            Token::Url(url) => write!(f, "url({})", url),
+            Token::Delim(ch) => write!(f, "{}", ch),
+            Token::Number { value, .. } => write!(f, "{}", value),
+            Token::Percentage(value) => write!(f, "{}%", value),
+            Token::Dimension { value, unit, .. } => write!(f, "{}{}", value, unit),
+            Token::Whitespace => write!(f, " "),
+            Token::Cdo => write!(f, "<!--"),
+            Token::Cdc => write!(f, "-->"),
+            Token::Colon => write!(f, ":"),
+            Token::Semicolon => write!(f, ";"),
+            Token::Comma => write!(f, ","),
+            Token::OpenSquare => write!(f, "["),
+            Token::CloseSquare => write!(f, "]"),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
+            Token::OpenCurly => write!(f, "{{"),
+            Token::CloseCurly => write!(f, "}}"),
+            Token::Comment(text) => write!(f, "/*{}*/", text),
+            Token::BadString | Token::BadUrl | Token::Eof => Ok(()),
+        }
+    }
+}
+
+/// Splits the whole text into tokens. The last one is always `Eof`.
+pub fn tokenize(text: &str) -> Vec<SpannedToken> {
+    let mut tokenizer = Tokenizer::new(text);
+    let mut tokens = vec![];
+    loop {
+        let start = tokenizer.offset();
+        let token = tokenizer.next_token();
+        let eof = token == Token::Eof;
+        tokens.push(SpannedToken { token, start, end: tokenizer.offset() });
+        if eof {
+            return tokens;
+        }
+    }
+}
+
+fn is_newline(ch: char) -> bool {
+    ch == '\n' || ch == '\r' || ch == '\x0C'
+}
+fn is_whitespace(ch: char) -> bool {
+    is_newline(ch) || ch == ' ' || ch == '\t'
+}
+fn is_ident_start(ch: char) -> bool {
+    ch.is_ascii_alphabetic() || ch == '_' || !ch.is_ascii()
+}
+fn is_ident_char(ch: char) -> bool {
+    is_ident_start(ch) || ch.is_ascii_digit() || ch == '-'
+}
+fn is_non_printable(ch: char) -> bool {
+    matches!(ch, '\u{0}'..='\u{8}' | '\u{b}' | '\u{e}'..='\u{1f}' | '\u{7f}')
+}
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && second.map(|ch| !is_newline(ch)).unwrap_or(false)
+}
+fn starts_ident(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    match first {
+        Some('-') => second.map(|ch| is_ident_start(ch) || ch == '-').unwrap_or(false) || is_valid_escape(second, third),
+        Some('\\') => is_valid_escape(first, second),
+        Some(ch) => is_ident_start(ch),
+        None => false,
+    }
+}
+fn starts_number(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    let digit = |ch: Option<char>| ch.map(|ch| ch.is_ascii_digit()).unwrap_or(false);
+    match first {
+        Some('+') | Some('-') => digit(second) || (second == Some('.') && digit(third)),
+        Some('.') => digit(second),
+        ch => digit(ch),
+    }
+}
+// nulls become the replacement character
+fn clean(ch: char) -> char {
+    if ch == '\0' { '\u{FFFD}' } else { ch }
+}
+
+struct Tokenizer<'a> {
+    text: &'a str,
+    chars: Vec<(usize, char)>,
+    pos: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(text: &'a str) -> Self {
+        Tokenizer { text, chars: text.char_indices().collect(), pos: 0 }
+    }
+    fn offset(&self) -> usize {
+        self.chars.get(self.pos).map(|(offset, _)| *offset).unwrap_or_else(|| self.text.len())
+    }
+    fn peek(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).map(|(_, ch)| *ch)
+    }
+    fn advance(&mut self) -> Option<char> {
+        let ch = self.peek(0);
+        if ch.is_some() {
+            self.pos += 1;
+        }
+        ch
+    }
+    // a CRLF pair counts as one newline
+    fn consume_newline(&mut self) {
+        if self.advance() == Some('\r') && self.peek(0) == Some('\n') {
+            self.pos += 1;
+        }
+    }
+
+    fn next_token(&mut self) -> Token {
+        let ch = match self.peek(0) {
+            Some(ch) => ch,
+            None => return Token::Eof,
+        };
+        if ch == '/' && self.peek(1) == Some('*') {
+            return self.comment();
+        }
+        if is_whitespace(ch) {
+            while self.peek(0).map(is_whitespace).unwrap_or(false) {
+                self.pos += 1;
+            }
+            return Token::Whitespace;
+        }
+        if starts_number(Some(ch), self.peek(1), self.peek(2)) {
+            return self.numeric();
+        }
+        self.pos += 1;
+        match ch {
+            '"' | '\'' => self.string(ch),
+            '#' => {
+                if self.peek(0).map(is_ident_char).unwrap_or(false) || is_valid_escape(self.peek(0), self.peek(1)) {
+                    let id = starts_ident(self.peek(0), self.peek(1), self.peek(2));
+                    Token::Hash { value: self.ident_sequence(), id }
+                } else {
+                    Token::Delim('#')
+                }
+            }
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '[' => Token::OpenSquare,
+            ']' => Token::CloseSquare,
+            '{' => Token::OpenCurly,
+            '}' => Token::CloseCurly,
+            '-' if self.peek(0) == Some('-') && self.peek(1) == Some('>') => {
+                self.pos += 2;
+                Token::Cdc
+            }
+            '<' if self.peek(0) == Some('!') && self.peek(1) == Some('-') && self.peek(2) == Some('-') => {
+                self.pos += 3;
+                Token::Cdo
+            }
+            '@' if starts_ident(self.peek(0), self.peek(1), self.peek(2)) => Token::AtKeyword(self.ident_sequence()),
+            '-' | '\\' if starts_ident(Some(ch), self.peek(0), self.peek(1)) => {
+                self.pos -= 1;
+                self.ident_like()
+            }
+            ch if is_ident_start(ch) => {
+                self.pos -= 1;
+                self.ident_like()
+            }
+            ch => Token::Delim(ch),
+        }
+    }
+
+    fn comment(&mut self) -> Token {
+        self.pos += 2;
+        let start = self.offset();
+        loop {
+            match self.peek(0) {
+                None => return Token::Comment(String::from(&self.text[start..])),
+                Some('*') if self.peek(1) == Some('/') => {
+                    let text = String::from(&self.text[start..self.offset()]);
+                    self.pos += 2;
+                    return Token::Comment(text);
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn string(&mut self, quote: char) -> Token {
+        let mut value = String::new();
+        loop {
+            match self.peek(0) {
+                None => return Token::QuotedString(value),
+                Some(ch) if ch == quote => {
+                    self.pos += 1;
+                    return Token::QuotedString(value);
+                }
+                // the newline is left for the next token
+                Some(ch) if is_newline(ch) => return Token::BadString,
+                Some('\\') => {
+                    match self.peek(1) {
+                        None => self.pos += 1,
+                        Some(next) if is_newline(next) => {
+                            self.pos += 1;
+                            self.consume_newline();
+                        }
+                        Some(_) => {
+                            self.pos += 1;
+                            value.push(self.escape());
+                        }
+                    }
+                }
+                Some(ch) => {
+                    self.pos += 1;
+                    value.push(clean(ch));
+                }
+            }
+        }
+    }
+
+    // the backslash is already consumed
+    fn escape(&mut self) -> char {
+        let ch = match self.advance() {
+            Some(ch) => ch,
+            None => return '\u{FFFD}',
+        };
+        if !ch.is_ascii_hexdigit() {
+            return clean(ch);
+        }
+        let mut hex = String::new();
+        hex.push(ch);
+        while hex.len() < 6 && self.peek(0).map(|ch| ch.is_ascii_hexdigit()).unwrap_or(false) {
+            hex.push(self.advance().unwrap());
+        }
+        if self.peek(0).map(is_whitespace).unwrap_or(false) {
+            self.consume_newline();
+        }
+        let code = u32::from_str_radix(&hex, 16).unwrap_or(0);
+        if code == 0 {
+            return '\u{FFFD}';
+        }
+        std::char::from_u32(code).unwrap_or('\u{FFFD}')
+    }
+
+    fn ident_sequence(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            match self.peek(0) {
+                Some(ch) if is_ident_char(ch) || ch == '\0' => {
+                    self.pos += 1;
+                    name.push(clean(ch));
+                }
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(1)) => {
+                    self.pos += 1;
+                    name.push(self.escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+
+    fn numeric(&mut self) -> Token {
+        let start = self.offset();
+        let mut integer = true;
+        if let Some('+') | Some('-') = self.peek(0) {
+            self.pos += 1;
+        }
+        self.digits();
+        if self.peek(0) == Some('.') && self.peek(1).map(|ch| ch.is_ascii_digit()).unwrap_or(false) {
+            integer = false;
+            self.pos += 1;
+            self.digits();
+        }
+        if let Some('e') | Some('E') = self.peek(0) {
+            let digit = |ch: Option<char>| ch.map(|ch| ch.is_ascii_digit()).unwrap_or(false);
+            let sign = matches!(self.peek(1), Some('+') | Some('-'));
+            if digit(self.peek(1)) || (sign && digit(self.peek(2))) {
+                integer = false;
+                self.pos += if sign { 2 } else { 1 };
+                self.digits();
+            }
+        }
+        let repr = &self.text[start..self.offset()];
+        let value = repr.trim_start_matches('+').parse::<f32>().unwrap_or(0.0);
+        if starts_ident(self.peek(0), self.peek(1), self.peek(2)) {
+            return Token::Dimension { value, integer, unit: self.ident_sequence() };
+        }
+        if self.peek(0) == Some('%') {
+            self.pos += 1;
+            return Token::Percentage(value);
+        }
+        Token::Number { value, integer }
+    }
+    fn digits(&mut self) {
+        while self.peek(0).map(|ch| ch.is_ascii_digit()).unwrap_or(false) {
+            self.pos += 1;
+        }
+    }
+
+    fn ident_like(&mut self) -> Token {
+        let name = self.ident_sequence();
+        if self.peek(0) != Some('(') {
+            return Token::Ident(name);
+        }
+        self.pos += 1;
+        if !name.eq_ignore_ascii_case("url") {
+            return Token::Function(name);
+        }
+        while self.peek(0).map(is_whitespace).unwrap_or(false) && self.peek(1).map(is_whitespace).unwrap_or(false) {
+            self.pos += 1;
+        }
+        let quote = |ch: Option<char>| ch == Some('"') || ch == Some('\'');
+        if quote(self.peek(0)) || (self.peek(0).map(is_whitespace).unwrap_or(false) && quote(self.peek(1))) {
+            // a quoted url is a normal function with a string argument
+            return Token::Function(name);
+        }
+        self.url()
+    }
+
+    fn url(&mut self) -> Token {
+        let mut value = String::new();
+        while self.peek(0).map(is_whitespace).unwrap_or(false) {
+            self.pos += 1;
+        }
+        loop {
+            match self.advance() {
+                None | Some(')') => return Token::Url(value),
+                Some(ch) if is_whitespace(ch) => {
+                    while self.peek(0).map(is_whitespace).unwrap_or(false) {
+                        self.pos += 1;
+                    }
+                    return match self.peek(0) {
+                        None => Token::Url(value),
+                        Some(')') => {
+                            self.pos += 1;
+                            Token::Url(value)
+                        }
+                        _ => self.bad_url(),
+                    };
+                }
+                Some('"') | Some('\'') | Some('(') => return self.bad_url(),
+                Some(ch) if is_non_printable(ch) => return self.bad_url(),
+                Some('\\') => {
+                    if is_valid_escape(Some('\\'), self.peek(0)) {
+                        value.push(self.escape());
+                    } else {
+                        return self.bad_url();
+                    }
+                }
+                Some(ch) => value.push(ch),
+            }
+        }
+    }
+    // skips the rest of a broken url so parsing picks up after it
+    fn bad_url(&mut self) -> Token {
+        loop {
+            match self.advance() {
+                None | Some(')') => return Token::BadUrl,
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+                    self.escape();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn tokens(text: &str) -> Vec<Token> {
+    tokenize(text).into_iter().map(|t| t.token).filter(|t| *t != Token::Eof).collect()
+}
+
+#[test]
+fn test_tokenize_rule() {
+    assert_eq!(tokens("a.b > #c{color:red;width:-1.5e1px}"), vec![
+        Token::Ident(String::from("a")),
+        Token::Delim('.'),
+        Token::Ident(String::from("b")),
+        Token::Whitespace,
+        Token::Delim('>'),
+        Token::Whitespace,
+        Token::Hash { value: String::from("c"), id: true },
+        Token::OpenCurly,
+        Token::Ident(String::from("color")),
+        Token::Colon,
+        Token::Ident(String::from("red")),
+        Token::Semicolon,
+        Token::Ident(String::from("width")),
+        Token::Colon,
+        Token::Dimension { value: -15.0, integer: false, unit: String::from("px") },
+        Token::CloseCurly,
+    ]);
+    let spans: Vec<(usize, usize)> = tokenize("@media  screen").iter().map(|t| (t.start, t.end)).collect();
+    assert_eq!(spans, vec![(0, 6), (6, 8), (8, 14), (14, 14)]);
+}
+
+#[test]
+fn test_tokenize_values() {
+    assert_eq!(tokens("50% +3 #fff 10.0 U+0100"), vec![
+        Token::Percentage(50.0),
+        Token::Whitespace,
+        Token::Number { value: 3.0, integer: true },
+        Token::Whitespace,
+        Token::Hash { value: String::from("fff"), id: true },
+        Token::Whitespace,
+        Token::Number { value: 10.0, integer: false },
+        Token::Whitespace,
+        Token::Ident(String::from("U")),
+        Token::Number { value: 100.0, integer: true },
+    ]);
+    assert_eq!(tokens(r##"url( a.png ) url("b.png") url(c d) rgb("#0"##), vec![
+        Token::Url(String::from("a.png")),
+        Token::Whitespace,
+        Token::Function(String::from("url")),
+        Token::QuotedString(String::from("b.png")),
+        Token::CloseParen,
+        Token::Whitespace,
+        Token::BadUrl,
+        Token::Whitespace,
+        Token::Function(String::from("rgb")),
+        Token::QuotedString(String::from("#0")),
+    ]);
+}
+
+#[test]
+fn test_tokenize_strings_and_escapes() {
+    assert_eq!(tokens("'it\\'s' \"a\\\nb\" \\31 0 -\\-x \"open\nx"), vec![
+        Token::QuotedString(String::from("it's")),
+        Token::Whitespace,
+        Token::QuotedString(String::from("ab")),
+        Token::Whitespace,
+        Token::Ident(String::from("10")),
+        Token::Whitespace,
+        Token::Ident(String::from("--x")),
+        Token::Whitespace,
+        Token::BadString,
+        Token::Whitespace,
+        Token::Ident(String::from("x")),
+    ]);
+    assert_eq!(tokens("<!-- /* note */ -->"), vec![
+        Token::Cdo,
+        Token::Whitespace,
+        Token::Comment(String::from(" note ")),
+        Token::Whitespace,
+        Token::Cdc,
+    ]);
+}
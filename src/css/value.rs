@@ -0,0 +1,263 @@
+// https://drafts.csswg.org/css-values-4/#component-types
+//
+// Values are read from the tokens of a declaration, so the escapes in strings
+// and names are already decoded. Items separated by whitespace make an
+// ArrayValue, a comma separated run of items is one item, and a '/' is kept as
+// an item of its own. Anything that isn't one of the types here makes the
+// whole value invalid, and so does a math function that doesn't parse.
+
+use crate::css::tokenizer::{Token, SpannedToken};
+#[cfg(test)]
+use crate::css::tokenizer::tokenize;
+use crate::css::{Value, Unit, FunCallValue};
+use crate::css::calc::{is_math_function, math_value};
+
+/// The value of the tokens, which point into `text`, or None if they aren't one.
+pub(crate) fn parse_value(tokens:&[SpannedToken], text:&str) -> Option<Value> {
+    let mut reader = ValueReader { tokens, text, pos: 0 };
+    let mut groups:Vec<Vec<Value>> = vec![];
+    let mut joined = false;
+    loop {
+        reader.skip_whitespace();
+        match reader.peek() {
+            None => break,
+            Some(Token::Comma) => {
+                // a comma goes between two items, and not after a '/'
+                match groups.last().and_then(|group| group.last()) {
+                    Some(Value::Keyword(slash)) if slash == "/" => return None,
+                    Some(_) if !joined => joined = true,
+                    _ => return None,
+                }
+                reader.pos += 1;
+            }
+            Some(Token::Delim('/')) => {
+                if joined || groups.is_empty() {
+                    return None;
+                }
+                reader.pos += 1;
+                groups.push(vec![Value::Keyword(String::from("/"))]);
+            }
+            Some(_) => {
+                let value = reader.one_value()?;
+                match groups.last_mut() {
+                    Some(group) if joined => group.push(value),
+                    _ => groups.push(vec![value]),
+                }
+                joined = false;
+            }
+        }
+    }
+    if joined || matches!(groups.last().and_then(|group| group.last()), Some(Value::Keyword(slash)) if slash == "/") {
+        return None;
+    }
+    let mut items:Vec<Value> = groups.into_iter()
+        .map(|mut group| if group.len() == 1 { group.remove(0) } else { Value::ArrayValue(group) })
+        .collect();
+    match items.len() {
+        0 => None,
+        1 => Some(css_wide_lowercase(items.remove(0))),
+        _ => Some(Value::ArrayValue(items)),
+    }
+}
+
+/// The value of some css text, read the same way as a declaration's.
+#[cfg(test)]
+pub(crate) fn parse_value_text(text:&str) -> Option<Value> {
+    let tokens:Vec<SpannedToken> = tokenize(text).into_iter()
+        .filter(|t| !matches!(t.token, Token::Comment(_) | Token::Eof))
+        .collect();
+    parse_value(&tokens, text)
+}
+
+/// The unit of a dimension. Units are case-insensitive.
+pub(crate) fn unit_from_name(name:&str) -> Option<Unit> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "px" => Unit::Px,
+        "em" => Unit::Em,
+        "rem" => Unit::Rem,
+        "ex" => Unit::Ex,
+        "ch" => Unit::Ch,
+        "vw" => Unit::Vw,
+        "vh" => Unit::Vh,
+        "vmin" => Unit::Vmin,
+        "vmax" => Unit::Vmax,
+        "pt" => Unit::Pt,
+        "pc" => Unit::Pc,
+        "in" => Unit::In,
+        "cm" => Unit::Cm,
+        "mm" => Unit::Mm,
+        _ => return None,
+    })
+}
+
+// degrees in one of an angle unit
+fn angle_scale(name:&str) -> Option<f32> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "deg" => 1.0,
+        "grad" => 0.9,
+        "rad" => 180.0 / std::f32::consts::PI,
+        "turn" => 360.0,
+        _ => return None,
+    })
+}
+
+// inherit, initial, unset and revert are case-insensitive like the other
+// keywords, but are compared everywhere, so they are kept in lowercase
+fn css_wide_lowercase(value:Value) -> Value {
+    match value {
+        Value::Keyword(k) if ["inherit", "initial", "unset", "revert"].iter().any(|w| w.eq_ignore_ascii_case(&k)) =>
+            Value::Keyword(k.to_ascii_lowercase()),
+        value => value,
+    }
+}
+
+struct ValueReader<'a> {
+    tokens: &'a [SpannedToken],
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> ValueReader<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+    fn skip_whitespace(&mut self) {
+        while self.peek() == Some(&Token::Whitespace) {
+            self.pos += 1;
+        }
+    }
+    // the index just past the block or function opened at `open`. an
+    // unclosed one runs to the end.
+    fn block_end(&self, open:usize) -> usize {
+        let mut depth = 0;
+        for i in open..self.tokens.len() {
+            match self.tokens[i].token {
+                Token::OpenParen | Token::Function(_) | Token::OpenSquare | Token::OpenCurly => depth += 1,
+                Token::CloseParen | Token::CloseSquare | Token::CloseCurly => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return i + 1;
+            }
+        }
+        self.tokens.len()
+    }
+
+    fn one_value(&mut self) -> Option<Value> {
+        let start = self.pos;
+        let token = self.peek()?;
+        self.pos += 1;
+        Some(match token {
+            Token::Ident(name) if name.eq_ignore_ascii_case("u") && matches!(self.peek(), Some(Token::Delim('+'))
+                | Some(Token::Number { .. }) | Some(Token::Dimension { .. })) => self.unicode_range(start)?,
+            Token::Ident(name) => Value::Keyword(name.clone()),
+            Token::Number { value, .. } => Value::Number(*value),
+            Token::Percentage(value) => Value::Length(*value, Unit::Per),
+            Token::Dimension { value, unit, .. } => match (unit_from_name(unit), angle_scale(unit)) {
+                (Some(unit), _) => Value::Length(*value, unit),
+                (None, Some(scale)) => Value::Number(*value * scale),
+                (None, None) => return None,
+            },
+            Token::Hash { value, .. } if matches!(value.len(), 3 | 4 | 6 | 8) && value.chars().all(|ch| ch.is_ascii_hexdigit()) =>
+                Value::HexColor(format!("#{}", value.to_ascii_lowercase())),
+            Token::QuotedString(text) => Value::StringLiteral(text.clone()),
+            Token::Url(url) => self.url(url.clone()),
+            Token::Function(name) => {
+                let end = self.block_end(start);
+                let tokens = &self.tokens[start..end];
+                self.pos = end;
+                if is_math_function(name) {
+                    let tokens:Vec<Token> = tokens.iter().map(|t| t.token.clone()).collect();
+                    return math_value(&tokens);
+                }
+                // the arguments, without the closing paren if there is one
+                let inner = match tokens.last() {
+                    Some(t) if end - start > 1 && t.token == Token::CloseParen => &tokens[1..tokens.len() - 1],
+                    _ => &tokens[1..],
+                };
+                let name = name.to_ascii_lowercase();
+                if name == "url" {
+                    let inner:Vec<&Token> = inner.iter().map(|t| &t.token).filter(|t| **t != Token::Whitespace).collect();
+                    return match inner.as_slice() {
+                        [Token::QuotedString(url)] => Some(self.url(url.clone())),
+                        _ => None,
+                    };
+                }
+                let mut arguments = ValueReader { tokens: inner, text: self.text, pos: 0 };
+                Value::FunCall(FunCallValue { name, arguments: arguments.arguments()? })
+            }
+            _ => return None,
+        })
+    }
+
+    // the arguments of a function, separated by whitespace or commas
+    fn arguments(&mut self) -> Option<Vec<Value>> {
+        let mut arguments = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Some(arguments),
+                Some(Token::Comma) => self.pos += 1,
+                Some(Token::Delim('/')) => {
+                    self.pos += 1;
+                    arguments.push(Value::Keyword(String::from("/")));
+                }
+                Some(_) => arguments.push(self.one_value()?),
+            }
+        }
+    }
+
+    // a `format()` after the url of a font source is skipped
+    fn url(&mut self, url:String) -> Value {
+        let mut next = self.pos;
+        while self.tokens.get(next).map(|t| &t.token) == Some(&Token::Whitespace) {
+            next += 1;
+        }
+        if matches!(self.tokens.get(next).map(|t| &t.token), Some(Token::Function(name)) if name.eq_ignore_ascii_case("format")) {
+            self.pos = self.block_end(next);
+        }
+        Value::FunCall(FunCallValue { name: String::from("url"), arguments: vec![Value::StringLiteral(url)] })
+    }
+
+    // U+0100-024F or U+0259, which the tokenizer splits into numbers and
+    // names, so it's read from the source
+    fn unicode_range(&mut self, start:usize) -> Option<Value> {
+        while matches!(self.peek(), Some(Token::Number { .. }) | Some(Token::Dimension { .. }) | Some(Token::Ident(_))
+            | Some(Token::Delim('+')) | Some(Token::Delim('-')) | Some(Token::Delim('?'))) {
+            self.pos += 1;
+        }
+        let text = &self.text[self.tokens[start].start..self.tokens[self.pos - 1].end];
+        let hex = |digits:&str| match digits.len() {
+            1..=6 => i32::from_str_radix(digits, 16).ok(),
+            _ => None,
+        };
+        let range = text.get(2..)?;
+        match range.split_once('-') {
+            Some((first, last)) => Some(Value::UnicodeRange(hex(first)?, hex(last)?)),
+            None => Some(Value::UnicodeCodepoint(hex(range)?)),
+        }
+    }
+}
+
+#[test]
+fn test_parse_values() {
+    use crate::css::Value::{Keyword, Length, ArrayValue, StringLiteral};
+    let value = parse_value_text;
+    assert_eq!(value("1px solid #ABC"), Some(ArrayValue(vec![Length(1.0, Unit::Px), Keyword(String::from("solid")), Value::HexColor(String::from("#abc"))])));
+    assert_eq!(value("a b, c d"), Some(ArrayValue(vec![Keyword(String::from("a")),
+        ArrayValue(vec![Keyword(String::from("b")), Keyword(String::from("c"))]), Keyword(String::from("d"))])));
+    assert_eq!(value("\"\\201C\""), Some(StringLiteral(String::from("\u{201C}"))));
+    assert_eq!(value("'It\\'s'"), Some(StringLiteral(String::from("It's"))));
+    assert_eq!(value("10PX"), Some(Length(10.0, Unit::Px)));
+    assert_eq!(value("INHERIT"), Some(Keyword(String::from("inherit"))));
+    assert_eq!(value("url(a.png) format('woff')"), value("url('a.png')"));
+    assert_eq!(value("#12345"), None);
+    assert_eq!(value("3s"), None);
+    assert_eq!(value("a,"), None);
+    assert_eq!(value(", a"), None);
+    assert_eq!(value("a / , b"), None);
+    assert_eq!(value("/ a"), None);
+    assert_eq!(value("(a)"), None);
+    assert_eq!(value("foo (a)"), None);
+    assert_eq!(value("url(a b)"), None);
+}
@@ -0,0 +1,167 @@
+// https://html.spec.whatwg.org/multipage/semantics.html#document-metadata
+//
+// Pulls the title, base url, meta tags and link relations out of a parsed
+// document. Everything is read fresh from the tree on each call.
+
+use crate::dom::{Document, NodeId, NodeType, AttrMap, meta_charset};
+use encoding_rs::Encoding;
+use url::Url;
+#[cfg(test)]
+use crate::dom::load_doc_from_bytestring;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Metadata {
+    /// the text of the first `<title>`, with whitespace collapsed
+    pub title: Option<String>,
+    /// the url relative links resolve against. see `Document::effective_base_url`
+    pub base_url: Url,
+    /// the charset declared with `<meta charset>` or `http-equiv`
+    pub charset: Option<&'static Encoding>,
+    pub viewport: Option<String>,
+    pub description: Option<String>,
+    pub icons: Vec<LinkRelation>,
+    /// alternate versions of the page, like feeds and translations
+    pub alternates: Vec<LinkRelation>,
+    pub canonical: Option<Url>,
+}
+
+/// A `<link>` with its href already resolved.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LinkRelation {
+    pub url: Url,
+    pub mime_type: Option<String>,
+    pub title: Option<String>,
+    pub hreflang: Option<String>,
+    pub sizes: Option<String>,
+}
+
+impl Document {
+    /// The url of the first `<base href>`, or the document's own url if there
+    /// isn't one or it doesn't parse.
+    pub fn effective_base_url(&self) -> Url {
+        self.getElementsByTagName("base").into_iter()
+            .find_map(|id| attribute(self, id, "href"))
+            .and_then(|href| self.base_url.join(href.trim()).ok())
+            .unwrap_or_else(|| self.base_url.clone())
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        let base_url = self.effective_base_url();
+        let title = self.getElementsByTagName("title").into_iter().next().map(|id| {
+            let text:String = self.children(id).iter().filter_map(|ch| match &self.node(*ch).node_type {
+                NodeType::Text(text) => Some(text.as_str()),
+                _ => None,
+            }).collect();
+            text.split_whitespace().collect::<Vec<&str>>().join(" ")
+        });
+        let mut metadata = Metadata {
+            title,
+            base_url,
+            charset: meta_charset(self),
+            viewport: self.named_meta("viewport"),
+            description: self.named_meta("description"),
+            icons: vec![],
+            alternates: vec![],
+            canonical: None,
+        };
+        for id in self.getElementsByTagName("link") {
+            let rel:Vec<String> = match attribute(self, id, "rel") {
+                Some(rel) => rel.split_whitespace().map(|r| r.to_ascii_lowercase()).collect(),
+                None => continue,
+            };
+            let has = |name:&str| rel.iter().any(|r| r == name);
+            let link = match self.link_relation(id, &metadata.base_url) {
+                Some(link) => link,
+                None => continue,
+            };
+            if has("icon") {
+                metadata.icons.push(link.clone());
+            }
+            // alternate stylesheets are loaded as styles, not listed here
+            if has("alternate") && !has("stylesheet") {
+                metadata.alternates.push(link.clone());
+            }
+            if has("canonical") && metadata.canonical.is_none() {
+                metadata.canonical = Some(link.url);
+            }
+        }
+        metadata
+    }
+
+    // the content of the first <meta name=...>
+    fn named_meta(&self, name:&str) -> Option<String> {
+        self.descendants(self.root()).into_iter().find_map(|id| match &self.node(id).node_type {
+            NodeType::Meta(md) if md.attributes.get("name").map(|n| n.trim().eq_ignore_ascii_case(name)).unwrap_or(false) =>
+                md.attributes.get("content").cloned(),
+            _ => None,
+        })
+    }
+
+    fn link_relation(&self, id:NodeId, base_url:&Url) -> Option<LinkRelation> {
+        let attributes = attributes(self, id)?;
+        let url = base_url.join(attributes.get("href")?.trim()).ok()?;
+        Some(LinkRelation {
+            url,
+            mime_type: attributes.get("type").cloned(),
+            title: attributes.get("title").cloned(),
+            hreflang: attributes.get("hreflang").cloned(),
+            sizes: attributes.get("sizes").cloned(),
+        })
+    }
+}
+
+fn attributes(doc:&Document, id:NodeId) -> Option<&AttrMap> {
+    match &doc.node(id).node_type {
+        NodeType::Element(ed) => Some(&ed.attributes),
+        _ => None,
+    }
+}
+
+fn attribute<'a>(doc:&'a Document, id:NodeId, name:&str) -> Option<&'a String> {
+    attributes(doc, id)?.get(name)
+}
+
+#[test]
+fn test_metadata() {
+    let mut doc = load_doc_from_bytestring(br#"<!DOCTYPE html><html><head>
+        <meta charset="iso-8859-2">
+        <title>
+          A   page
+          title</title>
+        <title>not this one</title>
+        <base href="/docs/">
+        <base href="/ignored/">
+        <meta name="Viewport" content="width=device-width, initial-scale=1">
+        <meta name="description" content="all about pages">
+        <link rel="shortcut icon" href="favicon.ico">
+        <link rel=icon href="/big.png" sizes="64x64" type="image/png">
+        <link rel=alternate type="application/rss+xml" title="Feed" href="feed.xml">
+        <link rel="alternate stylesheet" href="dark.css">
+        <link rel=alternate hreflang=fr href="https://example.fr/">
+        <link rel=canonical href="page.html">
+        </head><body></body></html>"#).unwrap();
+    doc.base_url = Url::parse("https://example.com/a/b.html").unwrap();
+    let md = doc.metadata();
+    assert_eq!(md.title, Some(String::from("A page title")));
+    assert_eq!(md.base_url.as_str(), "https://example.com/docs/");
+    assert_eq!(md.charset, Some(encoding_rs::ISO_8859_2));
+    assert_eq!(md.viewport, Some(String::from("width=device-width, initial-scale=1")));
+    assert_eq!(md.description, Some(String::from("all about pages")));
+    assert_eq!(md.icons.iter().map(|l| l.url.as_str()).collect::<Vec<&str>>(),
+               vec!["https://example.com/docs/favicon.ico", "https://example.com/big.png"]);
+    assert_eq!(md.icons[1].sizes, Some(String::from("64x64")));
+    assert_eq!(md.icons[1].mime_type, Some(String::from("image/png")));
+    assert_eq!(md.alternates.len(), 2);
+    assert_eq!(md.alternates[0].title, Some(String::from("Feed")));
+    assert_eq!(md.alternates[1].hreflang, Some(String::from("fr")));
+    assert_eq!(md.canonical.unwrap().as_str(), "https://example.com/docs/page.html");
+    assert_eq!(crate::net::calculate_url_from_doc(&doc, "img/x.png").unwrap().as_str(),
+               "https://example.com/docs/img/x.png");
+
+    let mut doc = load_doc_from_bytestring(b"<p>nothing here</p>").unwrap();
+    doc.base_url = Url::parse("https://example.com/a/b.html").unwrap();
+    let md = doc.metadata();
+    assert_eq!(md.title, None);
+    assert_eq!(md.base_url, doc.base_url);
+    assert!(md.icons.is_empty() && md.alternates.is_empty() && md.canonical.is_none());
+}
@@ -0,0 +1,95 @@
+// https://dom.spec.whatwg.org/#interface-document
+//
+// Lookups over the whole document. Every method returns the matching
+// elements in document order.
+
+use crate::css::parse_selector_list;
+use crate::dom::{Document, NodeId, NodeType, ElementData};
+use crate::net::BrowserError;
+use crate::style::matches;
+
+#[allow(non_snake_case)]
+impl Document {
+    /// All elements with the given tag name, or all elements for `*`.
+    pub fn getElementsByTagName(&self, name:&str) -> Vec<NodeId> {
+        self.elements(|ed| name == "*" || ed.tag_name == name)
+    }
+    /// The first element with the given id.
+    pub fn getElementById(&self, id:&str) -> Option<NodeId> {
+        self.elements(|ed| ed.id().map(|v| v == id).unwrap_or(false)).into_iter().next()
+    }
+    /// All elements that have every class in the space separated list.
+    pub fn getElementsByClassName(&self, names:&str) -> Vec<NodeId> {
+        let names:Vec<&str> = names.split_whitespace().collect();
+        if names.is_empty() {
+            return vec![];
+        }
+        self.elements(|ed| {
+            let classes = ed.classes();
+            names.iter().all(|name| classes.contains(name))
+        })
+    }
+    /// The first element matching any of the comma separated selectors.
+    pub fn querySelector(&self, selectors:&str) -> Result<Option<NodeId>, BrowserError> {
+        Ok(self.querySelectorAll(selectors)?.into_iter().next())
+    }
+    /// All elements matching any of the comma separated selectors. A selector
+    /// that doesn't parse is returned as a `ParseError`.
+    pub fn querySelectorAll(&self, selectors:&str) -> Result<Vec<NodeId>, BrowserError> {
+        let selectors = parse_selector_list(selectors)?;
+        Ok(self.descendants(self.root()).into_iter()
+            .filter(|id| selectors.iter().any(|sel| matches(self, *id, sel)))
+            .collect())
+    }
+
+    fn elements<F:Fn(&ElementData) -> bool>(&self, pred:F) -> Vec<NodeId> {
+        self.descendants(self.root()).into_iter()
+            .filter(|id| match &self.node(*id).node_type {
+                NodeType::Element(ed) => pred(ed),
+                _ => false,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+fn tag_name(doc:&Document, id:NodeId) -> String {
+    match &doc.node(id).node_type {
+        NodeType::Element(ed) => ed.tag_name.clone(),
+        _ => String::new(),
+    }
+}
+
+#[test]
+fn test_get_elements() {
+    let doc = crate::dom::load_doc_from_bytestring(br#"<div id=top class="a b"><p class=b>one</p><p id=second class="b  c">two</p></div><span class=a>x</span>"#).unwrap();
+    let ps = doc.getElementsByTagName("p");
+    assert_eq!(ps.len(), 2);
+    assert_eq!(doc.getElementsByTagName("*").iter().map(|id| tag_name(&doc, *id)).collect::<Vec<String>>(),
+               vec!["html", "head", "body", "div", "p", "p", "span"]);
+    assert_eq!(doc.getElementById("second"), Some(ps[1]));
+    assert_eq!(doc.getElementById("missing"), None);
+    assert_eq!(doc.getElementsByClassName("b").len(), 3);
+    assert_eq!(doc.getElementsByClassName(" c  b "), vec![ps[1]]);
+    assert_eq!(doc.getElementsByClassName("a").iter().map(|id| tag_name(&doc, *id)).collect::<Vec<String>>(),
+               vec!["div", "span"]);
+    assert!(doc.getElementsByClassName("").is_empty());
+}
+
+#[test]
+fn test_query_selector() {
+    let doc = crate::dom::load_doc_from_bytestring(br#"<div id=top class="a b"><p class=b>one</p><p id=second>two</p></div><p>three</p><span class=a>x</span>"#).unwrap();
+    let ps = doc.getElementsByTagName("p");
+    assert_eq!(doc.querySelectorAll("p").unwrap(), ps);
+    assert_eq!(doc.querySelector("p").unwrap(), Some(ps[0]));
+    assert_eq!(doc.querySelector("#second").unwrap(), Some(ps[1]));
+    assert_eq!(doc.querySelectorAll("div > p").unwrap(), vec![ps[0], ps[1]]);
+    //results are in document order, not selector order
+    assert_eq!(doc.querySelectorAll("span.a, div.a").unwrap().iter().map(|id| tag_name(&doc, *id)).collect::<Vec<String>>(),
+               vec!["div", "span"]);
+    assert_eq!(doc.querySelector("table").unwrap(), None);
+    match doc.querySelectorAll("p {") {
+        Err(BrowserError::ParseError(_)) => {}
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
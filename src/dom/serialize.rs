@@ -0,0 +1,165 @@
+// https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+//
+// Turns nodes back into markup. Attributes are written in name order so the
+// output is the same from run to run.
+
+use crate::dom::{Document, Node, NodeType, AttrMap};
+#[cfg(test)]
+use crate::dom::{load_doc, expand_entities};
+#[cfg(test)]
+use std::path::Path;
+
+const VOID_ELEMENTS:&[&str] = &["area", "base", "basefont", "bgsound", "br", "col", "embed", "frame",
+    "hr", "img", "input", "keygen", "link", "meta", "param", "source", "track", "wbr"];
+// text inside these is written as is
+const RAW_TEXT_PARENTS:&[&str] = &["style", "script", "xmp", "iframe", "noembed", "noframes", "plaintext", "noscript"];
+
+impl Node {
+    /// The markup for this node and everything inside it.
+    pub fn outer_html(&self, doc:&Document) -> String {
+        let mut out = String::new();
+        serialize_node(doc, self, None, &mut out);
+        out
+    }
+    /// The markup for the children of this node.
+    pub fn inner_html(&self, doc:&Document) -> String {
+        let mut out = String::new();
+        serialize_children(doc, self, &mut out);
+        out
+    }
+}
+
+fn serialize_children(doc:&Document, node:&Node, out:&mut String) {
+    let tag_name = match &node.node_type {
+        NodeType::Element(ed) => Some(ed.tag_name.as_str()),
+        _ => None,
+    };
+    if let Some("pre") | Some("textarea") | Some("listing") = tag_name {
+        // the parser drops a newline right after the start tag, so add one back
+        if let Some(NodeType::Text(text)) = node.children().first().map(|ch| &doc.node(*ch).node_type) {
+            if text.starts_with('\n') {
+                out.push('\n');
+            }
+        }
+    }
+    for ch in node.children() {
+        serialize_node(doc, doc.node(*ch), tag_name, out);
+    }
+}
+
+fn serialize_node(doc:&Document, node:&Node, parent:Option<&str>, out:&mut String) {
+    match &node.node_type {
+        NodeType::Element(ed) => {
+            start_tag(&ed.tag_name, &ed.attributes, out);
+            if VOID_ELEMENTS.contains(&ed.tag_name.as_str()) {
+                return;
+            }
+            serialize_children(doc, node, out);
+            out.push_str("</");
+            out.push_str(&ed.tag_name);
+            out.push('>');
+        }
+        NodeType::Meta(md) => start_tag("meta", &md.attributes, out),
+        NodeType::Text(text) => {
+            if parent.map(|name| RAW_TEXT_PARENTS.contains(&name)).unwrap_or(false) {
+                out.push_str(text);
+            } else {
+                escape(text, false, out);
+            }
+        }
+        NodeType::Comment(text) => {
+            out.push_str("<!--");
+            out.push_str(text);
+            out.push_str("-->");
+        }
+        NodeType::Cdata(text) => {
+            out.push_str("<![CDATA[");
+            out.push_str(text);
+            out.push_str("]]>");
+        }
+        NodeType::Doctype(dt) => {
+            out.push_str("<!DOCTYPE ");
+            out.push_str(&dt.name);
+            out.push('>');
+        }
+    }
+}
+
+fn start_tag(name:&str, attributes:&AttrMap, out:&mut String) {
+    out.push('<');
+    out.push_str(name);
+    let mut names:Vec<&String> = attributes.keys().collect();
+    names.sort();
+    for key in names {
+        out.push(' ');
+        out.push_str(key);
+        out.push_str("=\"");
+        escape(&attributes[key], true, out);
+        out.push('"');
+    }
+    out.push('>');
+}
+
+fn escape(text:&str, in_attribute:bool, out:&mut String) {
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if in_attribute => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+}
+
+#[cfg(test)]
+fn parse(input:&[u8]) -> Document {
+    let mut doc = crate::dom::load_doc_from_bytestring(input).unwrap();
+    expand_entities(&mut doc);
+    doc
+}
+
+#[test]
+fn test_serialize() {
+    let doc = parse(br#"<p class="a" id=x title='say "hi"'>1 &lt; 2 &amp;&nbsp;<br>done<img src=a.png></p><!-- note -->"#);
+    let body = doc.node(doc.children(doc.root())[1]);
+    assert_eq!(body.inner_html(&doc),
+        r#"<p class="a" id="x" title="say &quot;hi&quot;">1 &lt; 2 &amp;&nbsp;<br>done<img src="a.png"></p><!-- note -->"#);
+    let p = doc.node(body.children()[0]);
+    assert_eq!(doc.node(p.children()[0]).outer_html(&doc), "1 &lt; 2 &amp;&nbsp;");
+}
+
+#[test]
+fn test_serialize_raw_text() {
+    let doc = parse(b"<head><style>p > a { content: \"&amp;\" }</style><meta charset=utf-8></head><pre>\n\nindented</pre><textarea>a<b</textarea>");
+    assert_eq!(doc.node(doc.root()).outer_html(&doc),
+        "<html><head><style>p > a { content: \"&amp;\" }</style><meta charset=\"utf-8\"></head>\
+         <body><pre>\n\nindented</pre><textarea>a&lt;b</textarea></body></html>");
+}
+
+#[cfg(test)]
+fn whole_document(doc:&Document) -> String {
+    let mut html = doc.doctype().map(|dt| doc.node(dt).outer_html(doc)).unwrap_or_default();
+    html.push_str(&doc.node(doc.root()).outer_html(doc));
+    html
+}
+
+#[test]
+fn test_round_trip() {
+    let mut paths:Vec<_> = std::fs::read_dir("tests").unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map(|ext| ext == "html").unwrap_or(false))
+        .collect();
+    paths.push(Path::new("tests/tufte/tufte.html").to_path_buf());
+    paths.push(Path::new("tests/html5/index.html").to_path_buf());
+    for path in paths {
+        let mut first = load_doc(&path).unwrap();
+        expand_entities(&mut first);
+        let html = whole_document(&first);
+        let second = parse(html.as_bytes());
+        assert_eq!(whole_document(&second), html, "{} changed on the round trip", path.display());
+        assert_eq!(second.descendants(second.root()).len(), first.descendants(first.root()).len());
+        assert_eq!(second.quirks_mode, first.quirks_mode);
+    }
+}
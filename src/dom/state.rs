@@ -0,0 +1,104 @@
+// https://html.spec.whatwg.org/multipage/semantics-other.html#pseudo-classes
+//
+// The dynamic states matched by :hover, :active, :focus, :visited, :checked
+// and :disabled. The shell sets them as the user interacts with the page and
+// then styles the document again.
+
+use crate::dom::{Document, NodeId, NodeType};
+#[cfg(test)]
+use crate::dom::load_doc_from_bytestring;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ElementState {
+    Hover,
+    Active,
+    Focus,
+    Visited,
+    Checked,
+    Disabled,
+}
+
+impl Document {
+    pub fn has_element_state(&self, id:NodeId, state:ElementState) -> bool {
+        self.element_states.contains(&(id, state))
+    }
+
+    pub fn set_element_state(&mut self, id:NodeId, state:ElementState, on:bool) {
+        if on {
+            self.element_states.insert((id, state));
+        } else {
+            self.element_states.remove(&(id, state));
+        }
+    }
+
+    /// Moves the hover state to the element and its ancestors. Returns true if
+    /// anything changed.
+    pub fn set_hovered(&mut self, target:Option<NodeId>) -> bool {
+        self.move_state(ElementState::Hover, target, true)
+    }
+
+    /// Like `set_hovered`, for the element being pressed.
+    pub fn set_active(&mut self, target:Option<NodeId>) -> bool {
+        self.move_state(ElementState::Active, target, true)
+    }
+
+    /// Only one element has the focus. Its ancestors don't match `:focus`.
+    pub fn set_focused(&mut self, target:Option<NodeId>) -> bool {
+        self.move_state(ElementState::Focus, target, false)
+    }
+
+    fn move_state(&mut self, state:ElementState, target:Option<NodeId>, ancestors:bool) -> bool {
+        let mut wanted = vec![];
+        let mut next = target;
+        while let Some(id) = next {
+            wanted.push((id, state));
+            next = if ancestors { self.parent(id) } else { None };
+        }
+        let current:Vec<(NodeId, ElementState)> = self.element_states.iter()
+            .filter(|(_, s)| *s == state).cloned().collect();
+        if current.len() == wanted.len() && wanted.iter().all(|w| current.contains(w)) {
+            return false;
+        }
+        self.element_states.retain(|(_, s)| *s != state);
+        self.element_states.extend(wanted);
+        true
+    }
+
+    // checkboxes and options start out with the checkedness from their markup
+    pub(crate) fn init_element_states(&mut self) {
+        for id in self.descendants(self.root()) {
+            if let NodeType::Element(ed) = &self.node(id).node_type {
+                let checked = match ed.tag_name.as_str() {
+                    "input" => ed.attributes.contains_key("checked"),
+                    "option" => ed.attributes.contains_key("selected"),
+                    _ => false,
+                };
+                if checked {
+                    self.element_states.insert((id, ElementState::Checked));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_element_states() {
+    let mut doc = load_doc_from_bytestring(b"<div><p><a href=x>a</a></p><input type=checkbox checked></div>").unwrap();
+    let a = doc.getElementsByTagName("a")[0];
+    let p = doc.getElementsByTagName("p")[0];
+    let input = doc.getElementsByTagName("input")[0];
+    assert!(doc.has_element_state(input, ElementState::Checked));
+    assert!(doc.set_hovered(Some(a)));
+    assert!(!doc.set_hovered(Some(a)));
+    assert!(doc.has_element_state(a, ElementState::Hover));
+    assert!(doc.has_element_state(p, ElementState::Hover));
+    assert!(doc.has_element_state(doc.root(), ElementState::Hover));
+    assert!(doc.set_hovered(Some(input)));
+    assert!(!doc.has_element_state(p, ElementState::Hover));
+    assert!(doc.set_focused(Some(a)));
+    assert!(!doc.has_element_state(p, ElementState::Focus));
+    assert!(doc.set_hovered(None));
+    assert!(!doc.has_element_state(doc.root(), ElementState::Hover));
+    doc.set_element_state(input, ElementState::Checked, false);
+    assert!(!doc.has_element_state(input, ElementState::Checked));
+}
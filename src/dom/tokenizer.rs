@@ -0,0 +1,566 @@
+// https://html.spec.whatwg.org/multipage/parsing.html#tokenization
+//
+// A pull tokenizer. The tree builder asks for one token at a time and switches
+// the content model (rcdata, rawtext, script data, plaintext) after it sees the
+// start tag of an element like <style> or <title>.
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Tag {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub self_closing: bool,
+}
+
+impl Tag {
+    pub fn new(name: &str) -> Self {
+        Tag {
+            name: String::from(name),
+            attributes: vec![],
+            self_closing: false,
+        }
+    }
+    pub fn attribute(&self, name: &str) -> Option<&String> {
+        self.attributes.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DoctypeToken {
+    pub name: Option<String>,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+    pub force_quirks: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Token {
+    Doctype(DoctypeToken),
+    StartTag(Tag),
+    EndTag(Tag),
+    Comment(String),
+    Cdata(String),
+    Text(String),
+    Eof,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ContentModel {
+    Data,
+    RcData,
+    RawText,
+    ScriptData,
+    PlainText,
+}
+
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    pos: usize,
+    model: ContentModel,
+    last_start_tag: String,
+    errors: Vec<(usize, &'static str)>,
+}
+
+fn is_space(ch: char) -> bool {
+    ch == ' ' || ch == '\t' || ch == '\n' || ch == '\r' || ch == '\x0C'
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Tokenizer {
+            input,
+            pos: 0,
+            model: ContentModel::Data,
+            last_start_tag: String::new(),
+            errors: vec![],
+        }
+    }
+
+    /// byte offset of the next unread character
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// parse errors found so far, as a byte offset and the construct that was expected
+    pub fn take_errors(&mut self) -> Vec<(usize, &'static str)> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn error(&mut self, offset: usize, expected: &'static str) {
+        self.errors.push((offset, expected));
+    }
+
+    pub fn set_content_model(&mut self, model: ContentModel) {
+        self.model = model;
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+    fn next_char(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += ch.len_utf8();
+        Some(ch)
+    }
+    fn starts_with_ci(&self, s: &str) -> bool {
+        let rest = self.rest().as_bytes();
+        rest.len() >= s.len() && rest[..s.len()].eq_ignore_ascii_case(s.as_bytes())
+    }
+    fn skip_whitespace(&mut self) {
+        while let Some(ch) = self.peek() {
+            if !is_space(ch) {
+                break;
+            }
+            self.next_char();
+        }
+    }
+
+    pub fn next_token(&mut self) -> Token {
+        if self.pos >= self.input.len() {
+            return Token::Eof;
+        }
+        match self.model {
+            ContentModel::PlainText => {
+                let text = self.rest().replace('\0', "\u{FFFD}");
+                self.pos = self.input.len();
+                Token::Text(text)
+            }
+            ContentModel::RcData | ContentModel::RawText | ContentModel::ScriptData => self.raw_text(),
+            ContentModel::Data => {
+                if self.peek() == Some('<') {
+                    if let Some(token) = self.tag_open() {
+                        return token;
+                    }
+                }
+                self.data_text()
+            }
+        }
+    }
+
+    // text in the data state runs until something that opens markup
+    fn data_text(&mut self) -> Token {
+        let start = self.pos;
+        // always consume at least one char so a stray '<' becomes text
+        self.next_char();
+        while let Some(ch) = self.peek() {
+            if ch == '<' && self.opens_markup() {
+                break;
+            }
+            self.next_char();
+        }
+        Token::Text(self.input[start..self.pos].replace('\0', "\u{FFFD}"))
+    }
+
+    fn opens_markup(&self) -> bool {
+        match self.peek_at(1) {
+            Some(ch) => ch.is_ascii_alphabetic() || ch == '/' || ch == '!' || ch == '?',
+            None => false,
+        }
+    }
+
+    // rawtext, rcdata and script data only end at the matching end tag
+    fn raw_text(&mut self) -> Token {
+        let start = self.pos;
+        while self.pos < self.input.len() {
+            if self.is_appropriate_end_tag() {
+                break;
+            }
+            self.next_char();
+        }
+        if self.pos > start {
+            return Token::Text(self.input[start..self.pos].replace('\0', "\u{FFFD}"));
+        }
+        // we are sitting on the end tag
+        self.model = ContentModel::Data;
+        self.pos += 2;
+        self.tag(true)
+    }
+
+    fn is_appropriate_end_tag(&self) -> bool {
+        let rest = self.rest();
+        if !rest.starts_with("</") {
+            return false;
+        }
+        let name = &self.last_start_tag;
+        let after = &rest.as_bytes()[2..];
+        if after.len() < name.len() || !after[..name.len()].eq_ignore_ascii_case(name.as_bytes()) {
+            return false;
+        }
+        match after.get(name.len()) {
+            Some(b) => is_space(*b as char) || *b == b'/' || *b == b'>',
+            None => true,
+        }
+    }
+
+    // called with pos on a '<'. returns None if it's just text.
+    fn tag_open(&mut self) -> Option<Token> {
+        match self.peek_at(1) {
+            Some('!') => {
+                self.pos += 2;
+                Some(self.markup_declaration())
+            }
+            Some('/') => {
+                match self.peek_at(2) {
+                    Some(ch) if ch.is_ascii_alphabetic() => {
+                        self.pos += 2;
+                        Some(self.tag(true))
+                    }
+                    Some('>') => {
+                        // </> is dropped entirely
+                        self.error(self.pos, "an end tag name");
+                        self.pos += 3;
+                        Some(self.next_token())
+                    }
+                    None => None,
+                    Some(_) => {
+                        self.error(self.pos, "an end tag name");
+                        self.pos += 2;
+                        Some(self.bogus_comment())
+                    }
+                }
+            }
+            Some('?') => {
+                self.error(self.pos, "a tag name");
+                self.pos += 1;
+                Some(self.bogus_comment())
+            }
+            Some(ch) if ch.is_ascii_alphabetic() => {
+                self.pos += 1;
+                Some(self.tag(false))
+            }
+            _ => None,
+        }
+    }
+
+    fn tag(&mut self, end: bool) -> Token {
+        let start = self.input[..self.pos].rfind('<').unwrap_or(0);
+        let mut tag = Tag::new("");
+        while let Some(ch) = self.peek() {
+            if is_space(ch) || ch == '/' || ch == '>' {
+                break;
+            }
+            self.next_char();
+            tag.name.push(if ch == '\0' { '\u{FFFD}' } else { ch.to_ascii_lowercase() });
+        }
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => {
+                    // eof in a tag drops the tag
+                    self.error(start, "'>' to close the tag");
+                    return Token::Eof;
+                }
+                Some('>') => {
+                    self.next_char();
+                    break;
+                }
+                Some('/') => {
+                    self.next_char();
+                    if self.peek() == Some('>') {
+                        self.next_char();
+                        tag.self_closing = true;
+                        break;
+                    }
+                }
+                Some(_) => {
+                    let attr_start = self.pos;
+                    let (name, value) = self.attribute();
+                    if tag.attributes.iter().any(|(k, _)| *k == name) {
+                        self.error(attr_start, "a unique attribute name");
+                    } else {
+                        tag.attributes.push((name, value));
+                    }
+                }
+            }
+        }
+        if end {
+            Token::EndTag(tag)
+        } else {
+            self.last_start_tag = tag.name.clone();
+            Token::StartTag(tag)
+        }
+    }
+
+    fn attribute(&mut self) -> (String, String) {
+        let mut name = String::new();
+        // a leading '=' is part of the name
+        if let Some(ch) = self.next_char() {
+            name.push(ch.to_ascii_lowercase());
+        }
+        while let Some(ch) = self.peek() {
+            if is_space(ch) || ch == '/' || ch == '>' || ch == '=' {
+                break;
+            }
+            self.next_char();
+            name.push(if ch == '\0' { '\u{FFFD}' } else { ch.to_ascii_lowercase() });
+        }
+        self.skip_whitespace();
+        if self.peek() != Some('=') {
+            return (name, String::new());
+        }
+        self.next_char();
+        self.skip_whitespace();
+        let mut value = String::new();
+        match self.peek() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                self.next_char();
+                while let Some(ch) = self.next_char() {
+                    if ch == quote {
+                        break;
+                    }
+                    value.push(if ch == '\0' { '\u{FFFD}' } else { ch });
+                }
+            }
+            _ => {
+                while let Some(ch) = self.peek() {
+                    if is_space(ch) || ch == '>' {
+                        break;
+                    }
+                    self.next_char();
+                    value.push(if ch == '\0' { '\u{FFFD}' } else { ch });
+                }
+            }
+        }
+        (name, value)
+    }
+
+    // called after "<!"
+    fn markup_declaration(&mut self) -> Token {
+        let start = self.pos - 2;
+        if self.rest().starts_with("--") {
+            self.pos += 2;
+            return self.comment();
+        }
+        if self.starts_with_ci("doctype") {
+            self.pos += 7;
+            return self.doctype();
+        }
+        if self.rest().starts_with("[CDATA[") {
+            self.pos += 7;
+            let rest = self.rest();
+            return match rest.find("]]>") {
+                Some(end) => {
+                    self.pos += end + 3;
+                    Token::Cdata(String::from(&rest[..end]))
+                }
+                None => {
+                    self.error(start, "']]>' to close the CDATA section");
+                    self.pos = self.input.len();
+                    Token::Cdata(String::from(rest))
+                }
+            };
+        }
+        self.error(start, "a comment or doctype");
+        self.bogus_comment()
+    }
+
+    fn comment(&mut self) -> Token {
+        // <!--> and <!---> are empty comments
+        if self.rest().starts_with('>') {
+            self.pos += 1;
+            return Token::Comment(String::new());
+        }
+        if self.rest().starts_with("->") {
+            self.pos += 2;
+            return Token::Comment(String::new());
+        }
+        let rest = self.rest();
+        let end = match (rest.find("-->"), rest.find("--!>")) {
+            (Some(a), Some(b)) if b < a => Some((b, 4)),
+            (Some(a), _) => Some((a, 3)),
+            (None, Some(b)) => Some((b, 4)),
+            (None, None) => None,
+        };
+        match end {
+            Some((end, len)) => {
+                self.pos += end + len;
+                Token::Comment(String::from(&rest[..end]))
+            }
+            None => {
+                self.error(self.pos - 4, "'-->' to close the comment");
+                self.pos = self.input.len();
+                Token::Comment(String::from(rest))
+            }
+        }
+    }
+
+    fn bogus_comment(&mut self) -> Token {
+        let rest = self.rest();
+        match rest.find('>') {
+            Some(end) => {
+                self.pos += end + 1;
+                Token::Comment(rest[..end].replace('\0', "\u{FFFD}"))
+            }
+            None => {
+                self.pos = self.input.len();
+                Token::Comment(rest.replace('\0', "\u{FFFD}"))
+            }
+        }
+    }
+
+    fn doctype(&mut self) -> Token {
+        let mut dt = DoctypeToken {
+            name: None,
+            public_id: None,
+            system_id: None,
+            force_quirks: false,
+        };
+        self.skip_whitespace();
+        let mut name = String::new();
+        while let Some(ch) = self.peek() {
+            if is_space(ch) || ch == '>' {
+                break;
+            }
+            self.next_char();
+            name.push(ch.to_ascii_lowercase());
+        }
+        if name.is_empty() {
+            self.error(self.pos, "a doctype name");
+            dt.force_quirks = true;
+        } else {
+            dt.name = Some(name);
+        }
+        self.skip_whitespace();
+        if self.starts_with_ci("public") {
+            self.pos += 6;
+            dt.public_id = self.doctype_identifier();
+            if dt.public_id.is_none() {
+                dt.force_quirks = true;
+            }
+            dt.system_id = self.doctype_identifier();
+        } else if self.starts_with_ci("system") {
+            self.pos += 6;
+            dt.system_id = self.doctype_identifier();
+            if dt.system_id.is_none() {
+                dt.force_quirks = true;
+            }
+        } else if self.peek().is_some() && self.peek() != Some('>') {
+            dt.force_quirks = true;
+        }
+        // skip anything else up to the end of the doctype
+        loop {
+            match self.next_char() {
+                Some('>') => break,
+                Some(_) => {}
+                None => {
+                    dt.force_quirks = true;
+                    break;
+                }
+            }
+        }
+        Token::Doctype(dt)
+    }
+
+    fn doctype_identifier(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        let quote = self.peek()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        self.next_char();
+        let mut id = String::new();
+        while let Some(ch) = self.peek() {
+            if ch == quote {
+                self.next_char();
+                break;
+            }
+            if ch == '>' {
+                break;
+            }
+            self.next_char();
+            id.push(ch);
+        }
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tok = Tokenizer::new(input);
+    let mut tokens = vec![];
+    loop {
+        let t = tok.next_token();
+        if t == Token::Eof {
+            break;
+        }
+        tokens.push(t);
+    }
+    tokens
+}
+
+#[test]
+fn test_tokenize_attributes() {
+    let tag = |input: &str| match &tokenize(input)[0] {
+        Token::StartTag(tag) => tag.clone(),
+        t => panic!("expected a start tag, got {:?}", t),
+    };
+    //standard attribute with double quotes
+    assert_eq!(tag(r#"<b foo="bar">"#).attribute("foo"), Some(&String::from("bar")));
+    //attribute with complex value
+    assert_eq!(tag(r#"<b foo="bar-foo-8">"#).attribute("foo"), Some(&String::from("bar-foo-8")));
+    //attribute with single quotes
+    assert_eq!(tag("<b foo='bar'>").attribute("foo"), Some(&String::from("bar")));
+    //attribute with no quotes
+    assert_eq!(tag("<b foo=bar>").attribute("foo"), Some(&String::from("bar")));
+    //standalone attribute
+    let t = tag(r#"<iframe width="853" src="https://www.youtube.com/embed/YslQ2625TR4" allowfullscreen></iframe>"#);
+    assert_eq!(t.attribute("allowfullscreen"), Some(&String::from("")));
+    assert_eq!(t.attribute("width"), Some(&String::from("853")));
+    //uppercase names are lowercased, the first duplicate wins
+    let t = tag(r#"<DIV CLASS=a class=b>"#);
+    assert_eq!(t.name, "div");
+    assert_eq!(t.attributes, vec![(String::from("class"), String::from("a"))]);
+    //self closing
+    assert!(tag(r#"<link rel="stylesheet" href="tufte.css"/>"#).self_closing);
+}
+
+#[test]
+fn test_tokenize_stray_less_than() {
+    assert_eq!(tokenize("a < b <3"), vec![Token::Text(String::from("a < b <3"))]);
+}
+
+#[test]
+fn test_tokenize_comments() {
+    assert_eq!(tokenize("<!-- a cool - comment-->after"), vec![
+        Token::Comment(String::from(" a cool - comment")),
+        Token::Text(String::from("after")),
+    ]);
+    assert_eq!(tokenize("<!---->"), vec![Token::Comment(String::from(""))]);
+    assert_eq!(tokenize("<!-->"), vec![Token::Comment(String::from(""))]);
+    assert_eq!(tokenize("<?xml version=\"1.0\"?>"), vec![Token::Comment(String::from("?xml version=\"1.0\"?"))]);
+    assert_eq!(tokenize("<![CDATA[x<y]]>"), vec![Token::Cdata(String::from("x<y"))]);
+}
+
+#[test]
+fn test_tokenize_doctype() {
+    assert_eq!(tokenize("<!DOCTYPE html>"), vec![Token::Doctype(DoctypeToken {
+        name: Some(String::from("html")),
+        public_id: None,
+        system_id: None,
+        force_quirks: false,
+    })]);
+    assert_eq!(tokenize("<!doctype html>"), tokenize("<!DOCTYPE html>"));
+    assert_eq!(tokenize("<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">"),
+               vec![Token::Doctype(DoctypeToken {
+                   name: Some(String::from("html")),
+                   public_id: Some(String::from("-//W3C//DTD XHTML 1.0 Strict//EN")),
+                   system_id: Some(String::from("http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd")),
+                   force_quirks: false,
+               })]);
+}
+
+#[test]
+fn test_tokenize_rawtext() {
+    let mut tok = Tokenizer::new("<style>a > b { x: '</p>' }</style ><p>");
+    assert_eq!(tok.next_token(), Token::StartTag(Tag::new("style")));
+    tok.set_content_model(ContentModel::RawText);
+    assert_eq!(tok.next_token(), Token::Text(String::from("a > b { x: '</p>' }")));
+    assert_eq!(tok.next_token(), Token::EndTag(Tag::new("style")));
+    assert_eq!(tok.next_token(), Token::StartTag(Tag::new("p")));
+    assert_eq!(tok.next_token(), Token::Eof);
+}
@@ -0,0 +1,1994 @@
+// https://html.spec.whatwg.org/multipage/parsing.html#tree-construction
+//
+// The tree builder works on a flat arena of nodes so that the adoption agency
+// and foster parenting can move nodes around freely. The finished tree is
+// copied into the document's own arena at the end, without the unused slots.
+
+use crate::dom::tokenizer::{Tokenizer, Token, Tag, ContentModel, DoctypeToken};
+use crate::dom::{Node, NodeId, NodeType, ElementData, MetaData, DoctypeData, AttrMap, QuirksMode};
+#[cfg(test)]
+use crate::dom::{Document, outline};
+#[cfg(test)]
+use url::Url;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    AfterHead,
+    InBody,
+    Text,
+    InTable,
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    AfterBody,
+    InFrameset,
+    AfterFrameset,
+    AfterAfterBody,
+}
+
+enum Step {
+    Done,
+    Reprocess(Token),
+}
+
+#[derive(Debug)]
+enum ArenaData {
+    Document,
+    Element(Tag),
+    Text(String),
+    Comment(String),
+    Cdata(String),
+}
+
+#[derive(Debug)]
+struct ArenaNode {
+    data: ArenaData,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Formatting {
+    Marker,
+    Element(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+const DOCUMENT: usize = 0;
+
+const SPECIAL: &[&str] = &[
+    "address", "applet", "area", "article", "aside", "base", "basefont", "bgsound", "blockquote",
+    "body", "br", "button", "caption", "center", "col", "colgroup", "dd", "details", "dir", "div",
+    "dl", "dt", "embed", "fieldset", "figcaption", "figure", "footer", "form", "frame", "frameset",
+    "h1", "h2", "h3", "h4", "h5", "h6", "head", "header", "hgroup", "hr", "html", "iframe", "img",
+    "input", "keygen", "li", "link", "listing", "main", "marquee", "menu", "meta", "nav", "noembed",
+    "noframes", "noscript", "object", "ol", "p", "param", "plaintext", "pre", "script", "search",
+    "section", "select", "source", "style", "summary", "table", "tbody", "td", "template",
+    "textarea", "tfoot", "th", "thead", "title", "tr", "track", "ul", "wbr", "xmp",
+];
+const FORMATTING: &[&str] = &[
+    "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong", "tt", "u",
+];
+const IMPLIED_END: &[&str] = &["dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc"];
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+const CLOSES_P: &[&str] = &[
+    "address", "article", "aside", "blockquote", "center", "details", "dialog", "dir", "div", "dl",
+    "fieldset", "figcaption", "figure", "footer", "header", "hgroup", "main", "menu", "nav", "ol",
+    "p", "search", "section", "summary", "ul",
+];
+const BLOCK_END: &[&str] = &[
+    "address", "article", "aside", "blockquote", "button", "center", "details", "dialog", "dir",
+    "div", "dl", "fieldset", "figcaption", "figure", "footer", "header", "hgroup", "listing",
+    "main", "menu", "nav", "ol", "pre", "search", "section", "summary", "ul",
+];
+const VOID_IN_BODY: &[&str] = &["area", "br", "embed", "img", "keygen", "wbr"];
+// https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+const QUIRKY_PUBLIC_IDS: &[&str] = &["-//W3O//DTD W3 HTML Strict 3.0//EN//", "-/W3C/DTD HTML 4.0 Transitional/EN", "HTML"];
+const QUIRKY_PUBLIC_PREFIXES: &[&str] = &[
+    "+//Silmaril//dtd html Pro v0r11 19970101//",
+    "-//AS//DTD HTML 3.0 asWedit + extensions//",
+    "-//AdvaSoft Ltd//DTD HTML 3.0 asWedit + extensions//",
+    "-//IETF//DTD HTML 2.0 Level 1//",
+    "-//IETF//DTD HTML 2.0 Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict Level 1//",
+    "-//IETF//DTD HTML 2.0 Strict Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict//",
+    "-//IETF//DTD HTML 2.0//",
+    "-//IETF//DTD HTML 2.1E//",
+    "-//IETF//DTD HTML 3.0//",
+    "-//IETF//DTD HTML 3.2 Final//",
+    "-//IETF//DTD HTML 3.2//",
+    "-//IETF//DTD HTML 3//",
+    "-//IETF//DTD HTML Level 0//",
+    "-//IETF//DTD HTML Level 1//",
+    "-//IETF//DTD HTML Level 2//",
+    "-//IETF//DTD HTML Level 3//",
+    "-//IETF//DTD HTML Strict Level 0//",
+    "-//IETF//DTD HTML Strict Level 1//",
+    "-//IETF//DTD HTML Strict Level 2//",
+    "-//IETF//DTD HTML Strict Level 3//",
+    "-//IETF//DTD HTML Strict//",
+    "-//IETF//DTD HTML//",
+    "-//Metrius//DTD Metrius Presentational//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 2.0 Tables//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 3.0 Tables//",
+    "-//Netscape Comm. Corp.//DTD HTML//",
+    "-//Netscape Comm. Corp.//DTD Strict HTML//",
+    "-//O'Reilly and Associates//DTD HTML 2.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended 1.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended Relaxed 1.0//",
+    "-//SQ//DTD HTML 2.0 HoTMetaL + extensions//",
+    "-//SoftQuad Software//DTD HoTMetaL PRO 6.0::19990601::extensions to HTML 4.0//",
+    "-//SoftQuad//DTD HoTMetaL PRO 4.0::19971010::extensions to HTML 4.0//",
+    "-//Spyglass//DTD HTML 2.0 Extended//",
+    "-//Sun Microsystems Corp.//DTD HotJava HTML//",
+    "-//Sun Microsystems Corp.//DTD HotJava Strict HTML//",
+    "-//W3C//DTD HTML 3 1995-03-24//",
+    "-//W3C//DTD HTML 3.2 Draft//",
+    "-//W3C//DTD HTML 3.2 Final//",
+    "-//W3C//DTD HTML 3.2//",
+    "-//W3C//DTD HTML 3.2S Draft//",
+    "-//W3C//DTD HTML 4.0 Frameset//",
+    "-//W3C//DTD HTML 4.0 Transitional//",
+    "-//W3C//DTD HTML Experimental 19960712//",
+    "-//W3C//DTD HTML Experimental 970421//",
+    "-//W3C//DTD W3 HTML//",
+    "-//W3O//DTD W3 HTML 3.0//",
+    "-//WebTechs//DTD Mozilla HTML 2.0//",
+    "-//WebTechs//DTD Mozilla HTML//",
+];
+// quirky without a system id, limited quirks with one
+const HTML4_PUBLIC_PREFIXES: &[&str] = &["-//W3C//DTD HTML 4.01 Frameset//", "-//W3C//DTD HTML 4.01 Transitional//"];
+const LIMITED_QUIRKS_PUBLIC_PREFIXES: &[&str] = &["-//W3C//DTD XHTML 1.0 Frameset//", "-//W3C//DTD XHTML 1.0 Transitional//"];
+const TABLE_PARTS: &[&str] = &["caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr"];
+
+fn is_whitespace(ch: char) -> bool {
+    ch == ' ' || ch == '\t' || ch == '\n' || ch == '\r' || ch == '\x0C'
+}
+
+fn is_one_of(name: &str, list: &[&str]) -> bool {
+    list.contains(&name)
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    text.len() >= prefix.len() && text.is_char_boundary(prefix.len()) && text[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn quirks_mode_for(doctype: &DoctypeToken) -> QuirksMode {
+    let public = doctype.public_id.as_deref().unwrap_or("");
+    let system = doctype.system_id.as_deref();
+    let public_starts = |list: &[&str]| list.iter().any(|prefix| starts_with_ignore_case(public, prefix));
+    if doctype.force_quirks
+        || doctype.name.as_deref() != Some("html")
+        || QUIRKY_PUBLIC_IDS.iter().any(|id| public.eq_ignore_ascii_case(id))
+        || system.map(|id| id.eq_ignore_ascii_case("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")).unwrap_or(false)
+        || public_starts(QUIRKY_PUBLIC_PREFIXES)
+        || (system.is_none() && public_starts(HTML4_PUBLIC_PREFIXES)) {
+        return QuirksMode::Quirks;
+    }
+    if public_starts(LIMITED_QUIRKS_PUBLIC_PREFIXES) || (system.is_some() && public_starts(HTML4_PUBLIC_PREFIXES)) {
+        return QuirksMode::LimitedQuirks;
+    }
+    QuirksMode::NoQuirks
+}
+
+// splits a run of text into its leading whitespace and the rest
+fn split_whitespace_prefix(text: &str) -> (&str, &str) {
+    let idx = text.find(|c: char| !is_whitespace(c)).unwrap_or(text.len());
+    text.split_at(idx)
+}
+
+/// What the tree builder hands back to the document.
+pub struct ParsedTree {
+    /// the html element first, then its descendants in document order. the
+    /// doctype, if any, comes last and has no parent.
+    pub nodes: Vec<Node>,
+    pub doctype: Option<NodeId>,
+    pub quirks_mode: QuirksMode,
+}
+
+pub struct TreeBuilder<'a> {
+    tokenizer: Tokenizer<'a>,
+    nodes: Vec<ArenaNode>,
+    open: Vec<usize>,
+    formatting: Vec<Formatting>,
+    mode: InsertionMode,
+    original_mode: InsertionMode,
+    head: Option<usize>,
+    form: Option<usize>,
+    frameset_ok: bool,
+    doctype: Option<DoctypeToken>,
+    quirks_mode: QuirksMode,
+    foster_parenting: bool,
+    ignore_lf: bool,
+    pending_table_text: String,
+    token_start: usize,
+    errors: Vec<(usize, String)>,
+}
+
+impl<'a> TreeBuilder<'a> {
+    pub fn new(input: &'a str) -> Self {
+        TreeBuilder {
+            tokenizer: Tokenizer::new(input),
+            nodes: vec![ArenaNode { data: ArenaData::Document, parent: None, children: vec![] }],
+            open: vec![],
+            formatting: vec![],
+            mode: InsertionMode::Initial,
+            original_mode: InsertionMode::Initial,
+            head: None,
+            form: None,
+            frameset_ok: true,
+            doctype: None,
+            quirks_mode: QuirksMode::NoQuirks,
+            foster_parenting: false,
+            ignore_lf: false,
+            pending_table_text: String::new(),
+            token_start: 0,
+            errors: vec![],
+        }
+    }
+
+    /// Runs the tokenizer to the end of the input and returns the document's
+    /// nodes along with the parse errors as byte offsets and the expected
+    /// construct.
+    pub fn run(mut self) -> (ParsedTree, Vec<(usize, String)>) {
+        loop {
+            self.token_start = self.tokenizer.position();
+            let token = self.tokenizer.next_token();
+            let eof = token == Token::Eof;
+            self.process(token);
+            if eof {
+                break;
+            }
+        }
+        let html = self.nodes[DOCUMENT].children.iter()
+            .find(|id| self.is_element(**id))
+            .cloned()
+            .expect("the tree builder always creates an html element");
+        let mut errors = self.errors.clone();
+        errors.extend(self.tokenizer.take_errors().into_iter().map(|(offset, expected)| (offset, String::from(expected))));
+        errors.sort_by_key(|(offset, _)| *offset);
+        let mut nodes = vec![];
+        self.to_nodes(html, None, &mut nodes);
+        let doctype = self.doctype.take().map(|dt| {
+            let id = NodeId(nodes.len());
+            nodes.push(Node {
+                node_type: NodeType::Doctype(DoctypeData {
+                    name: dt.name.unwrap_or_default(),
+                    public_id: dt.public_id,
+                    system_id: dt.system_id,
+                }),
+                parent: None,
+                children: vec![],
+            });
+            id
+        });
+        (ParsedTree { nodes, doctype, quirks_mode: self.quirks_mode }, errors)
+    }
+
+    fn error(&mut self, expected: String) {
+        self.errors.push((self.token_start, expected));
+    }
+    fn unexpected_end_tag(&mut self, name: &str) {
+        self.error(format!("an open <{}> element", name));
+    }
+
+    fn process(&mut self, token: Token) {
+        let mut token = token;
+        if self.ignore_lf {
+            self.ignore_lf = false;
+            if let Token::Text(text) = &token {
+                if let Some(rest) = text.strip_prefix('\n') {
+                    if rest.is_empty() {
+                        return;
+                    }
+                    token = Token::Text(String::from(rest));
+                }
+            }
+        }
+        loop {
+            let step = match self.mode {
+                InsertionMode::Initial => self.initial(token),
+                InsertionMode::BeforeHtml => self.before_html(token),
+                InsertionMode::BeforeHead => self.before_head(token),
+                InsertionMode::InHead => self.in_head(token),
+                InsertionMode::AfterHead => self.after_head(token),
+                InsertionMode::InBody => self.in_body(token),
+                InsertionMode::Text => self.text(token),
+                InsertionMode::InTable => self.in_table(token),
+                InsertionMode::InTableText => self.in_table_text(token),
+                InsertionMode::InCaption => self.in_caption(token),
+                InsertionMode::InColumnGroup => self.in_column_group(token),
+                InsertionMode::InTableBody => self.in_table_body(token),
+                InsertionMode::InRow => self.in_row(token),
+                InsertionMode::InCell => self.in_cell(token),
+                InsertionMode::InSelect => self.in_select(token),
+                InsertionMode::AfterBody => self.after_body(token),
+                InsertionMode::InFrameset => self.in_frameset(token),
+                InsertionMode::AfterFrameset => self.after_frameset(token),
+                InsertionMode::AfterAfterBody => self.after_after_body(token),
+            };
+            match step {
+                Step::Done => return,
+                Step::Reprocess(t) => token = t,
+            }
+        }
+    }
+
+    // ---- arena helpers ----
+
+    fn new_node(&mut self, data: ArenaData) -> usize {
+        self.nodes.push(ArenaNode { data, parent: None, children: vec![] });
+        self.nodes.len() - 1
+    }
+    fn is_element(&self, id: usize) -> bool {
+        matches!(self.nodes[id].data, ArenaData::Element(_))
+    }
+    fn tag(&self, id: usize) -> &Tag {
+        match &self.nodes[id].data {
+            ArenaData::Element(tag) => tag,
+            _ => panic!("node {} is not an element", id),
+        }
+    }
+    fn name(&self, id: usize) -> &str {
+        match &self.nodes[id].data {
+            ArenaData::Element(tag) => &tag.name,
+            _ => "",
+        }
+    }
+    fn detach(&mut self, id: usize) {
+        if let Some(parent) = self.nodes[id].parent.take() {
+            self.nodes[parent].children.retain(|ch| *ch != id);
+        }
+    }
+    fn append(&mut self, parent: usize, id: usize) {
+        self.detach(id);
+        self.nodes[id].parent = Some(parent);
+        self.nodes[parent].children.push(id);
+    }
+    fn insert_before(&mut self, parent: usize, id: usize, before: usize) {
+        self.detach(id);
+        self.nodes[id].parent = Some(parent);
+        let idx = self.nodes[parent].children.iter().position(|ch| *ch == before)
+            .unwrap_or_else(|| self.nodes[parent].children.len());
+        self.nodes[parent].children.insert(idx, id);
+    }
+
+    // ---- the stack of open elements ----
+
+    fn current(&self) -> usize {
+        *self.open.last().unwrap_or(&DOCUMENT)
+    }
+    fn current_is(&self, name: &str) -> bool {
+        self.name(self.current()) == name
+    }
+    fn current_is_one_of(&self, names: &[&str]) -> bool {
+        is_one_of(self.name(self.current()), names)
+    }
+    fn pop(&mut self) -> Option<usize> {
+        self.open.pop()
+    }
+    fn pop_until(&mut self, name: &str) {
+        while let Some(id) = self.pop() {
+            if self.name(id) == name {
+                break;
+            }
+        }
+    }
+    fn pop_until_one_of(&mut self, names: &[&str]) {
+        while let Some(id) = self.pop() {
+            if is_one_of(self.name(id), names) {
+                break;
+            }
+        }
+    }
+    fn remove_from_stack(&mut self, id: usize) {
+        self.open.retain(|n| *n != id);
+    }
+    fn in_stack(&self, id: usize) -> bool {
+        self.open.contains(&id)
+    }
+    fn has_in_stack(&self, name: &str) -> bool {
+        self.open.iter().any(|id| self.name(*id) == name)
+    }
+
+    fn is_scope_boundary(&self, name: &str, scope: Scope) -> bool {
+        match scope {
+            Scope::Default => is_one_of(name, &["applet", "caption", "html", "table", "td", "th", "marquee", "object", "template"]),
+            Scope::ListItem => is_one_of(name, &["applet", "caption", "html", "table", "td", "th", "marquee", "object", "template", "ol", "ul"]),
+            Scope::Button => is_one_of(name, &["applet", "caption", "html", "table", "td", "th", "marquee", "object", "template", "button"]),
+            Scope::Table => is_one_of(name, &["html", "table", "template"]),
+            Scope::Select => !is_one_of(name, &["optgroup", "option"]),
+        }
+    }
+    fn in_scope_one_of(&self, names: &[&str], scope: Scope) -> bool {
+        for id in self.open.iter().rev() {
+            let name = self.name(*id);
+            if is_one_of(name, names) {
+                return true;
+            }
+            if self.is_scope_boundary(name, scope) {
+                return false;
+            }
+        }
+        false
+    }
+    fn in_scope(&self, name: &str, scope: Scope) -> bool {
+        self.in_scope_one_of(&[name], scope)
+    }
+    fn node_in_scope(&self, target: usize) -> bool {
+        for id in self.open.iter().rev() {
+            if *id == target {
+                return true;
+            }
+            if self.is_scope_boundary(self.name(*id), Scope::Default) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        loop {
+            let name = self.name(self.current());
+            if is_one_of(name, IMPLIED_END) && Some(name) != except {
+                self.pop();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        self.pop_until("p");
+    }
+    fn close_p_if_in_button_scope(&mut self) {
+        if self.in_scope("p", Scope::Button) {
+            self.close_p_element();
+        }
+    }
+
+    fn clear_stack_back_to(&mut self, names: &[&str]) {
+        while !self.open.is_empty() && !self.current_is_one_of(names) {
+            self.pop();
+        }
+    }
+
+    fn reset_insertion_mode(&mut self) {
+        for (i, id) in self.open.iter().enumerate().rev() {
+            let last = i == 0;
+            let mode = match self.name(*id) {
+                "select" => Some(InsertionMode::InSelect),
+                "td" | "th" if !last => Some(InsertionMode::InCell),
+                "tr" => Some(InsertionMode::InRow),
+                "tbody" | "thead" | "tfoot" => Some(InsertionMode::InTableBody),
+                "caption" => Some(InsertionMode::InCaption),
+                "colgroup" => Some(InsertionMode::InColumnGroup),
+                "table" => Some(InsertionMode::InTable),
+                "head" if !last => Some(InsertionMode::InHead),
+                "body" => Some(InsertionMode::InBody),
+                "frameset" => Some(InsertionMode::InFrameset),
+                "html" => Some(if self.head.is_none() { InsertionMode::BeforeHead } else { InsertionMode::AfterHead }),
+                _ => None,
+            };
+            if let Some(mode) = mode {
+                self.mode = mode;
+                return;
+            }
+        }
+        self.mode = InsertionMode::InBody;
+    }
+
+    // ---- inserting nodes ----
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node
+    fn insertion_location(&self, target: usize) -> (usize, Option<usize>) {
+        if self.foster_parenting && is_one_of(self.name(target), &["table", "tbody", "tfoot", "thead", "tr"]) {
+            if let Some(pos) = self.open.iter().rposition(|id| self.name(*id) == "table") {
+                let table = self.open[pos];
+                if let Some(parent) = self.nodes[table].parent {
+                    return (parent, Some(table));
+                }
+                return (self.open[pos - 1], None);
+            }
+            return (self.open[0], None);
+        }
+        (target, None)
+    }
+    fn insert_at(&mut self, location: (usize, Option<usize>), id: usize) {
+        match location {
+            (parent, Some(before)) => self.insert_before(parent, id, before),
+            (parent, None) => self.append(parent, id),
+        }
+    }
+
+    fn insert_element(&mut self, tag: Tag) -> usize {
+        let id = self.new_node(ArenaData::Element(tag));
+        let location = self.insertion_location(self.current());
+        self.insert_at(location, id);
+        self.open.push(id);
+        id
+    }
+    fn insert_void(&mut self, tag: Tag) -> usize {
+        let id = self.insert_element(tag);
+        self.pop();
+        id
+    }
+    fn insert_comment(&mut self, text: String) {
+        let id = self.new_node(ArenaData::Comment(text));
+        let location = self.insertion_location(self.current());
+        self.insert_at(location, id);
+    }
+    fn insert_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let (parent, before) = self.insertion_location(self.current());
+        if parent == DOCUMENT {
+            return;
+        }
+        // merge with an adjacent text node
+        let prev = match before {
+            Some(before) => {
+                let children = &self.nodes[parent].children;
+                let idx = children.iter().position(|ch| *ch == before).unwrap_or(0);
+                if idx > 0 { Some(children[idx - 1]) } else { None }
+            }
+            None => self.nodes[parent].children.last().cloned(),
+        };
+        if let Some(prev) = prev {
+            if let ArenaData::Text(existing) = &mut self.nodes[prev].data {
+                existing.push_str(text);
+                return;
+            }
+        }
+        let id = self.new_node(ArenaData::Text(String::from(text)));
+        self.insert_at((parent, before), id);
+    }
+
+    fn merge_attributes(&mut self, id: usize, tag: Tag) {
+        if let ArenaData::Element(existing) = &mut self.nodes[id].data {
+            for (k, v) in tag.attributes {
+                if existing.attribute(&k).is_none() {
+                    existing.attributes.push((k, v));
+                }
+            }
+        }
+    }
+
+    // generic rcdata and rawtext element parsing
+    fn insert_raw_text_element(&mut self, tag: Tag, model: ContentModel) {
+        self.insert_element(tag);
+        self.tokenizer.set_content_model(model);
+        self.original_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    // ---- the list of active formatting elements ----
+
+    fn push_formatting(&mut self, id: usize) {
+        // noah's ark clause: at most three identical entries after the last marker
+        let mut same = vec![];
+        for (i, entry) in self.formatting.iter().enumerate().rev() {
+            match entry {
+                Formatting::Marker => break,
+                Formatting::Element(other) => {
+                    if self.tag(*other) == self.tag(id) {
+                        same.push(i);
+                    }
+                }
+            }
+        }
+        if same.len() >= 3 {
+            self.formatting.remove(*same.last().unwrap());
+        }
+        self.formatting.push(Formatting::Element(id));
+    }
+    fn formatting_position(&self, id: usize) -> Option<usize> {
+        self.formatting.iter().position(|e| *e == Formatting::Element(id))
+    }
+    fn remove_formatting(&mut self, id: usize) {
+        self.formatting.retain(|e| *e != Formatting::Element(id));
+    }
+    fn clear_formatting_to_marker(&mut self) {
+        while let Some(entry) = self.formatting.pop() {
+            if entry == Formatting::Marker {
+                break;
+            }
+        }
+    }
+    // the last element with this name after the last marker
+    fn formatting_element_named(&self, name: &str) -> Option<usize> {
+        for entry in self.formatting.iter().rev() {
+            match entry {
+                Formatting::Marker => return None,
+                Formatting::Element(id) => {
+                    if self.name(*id) == name {
+                        return Some(*id);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn reconstruct_formatting(&mut self) {
+        let len = self.formatting.len();
+        if len == 0 {
+            return;
+        }
+        let is_open = |tb: &Self, entry: &Formatting| match entry {
+            Formatting::Marker => true,
+            Formatting::Element(id) => tb.in_stack(*id),
+        };
+        if is_open(self, &self.formatting[len - 1]) {
+            return;
+        }
+        let mut i = len - 1;
+        while i > 0 && !is_open(self, &self.formatting[i - 1]) {
+            i -= 1;
+        }
+        for idx in i..len {
+            if let Formatting::Element(old) = self.formatting[idx] {
+                let tag = self.tag(old).clone();
+                let id = self.insert_element(tag);
+                self.formatting[idx] = Formatting::Element(id);
+            }
+        }
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm
+    // returns false if the caller should fall back to the "any other end tag" steps
+    fn adoption_agency(&mut self, subject: &str) -> bool {
+        let current = self.current();
+        if self.name(current) == subject && self.formatting_position(current).is_none() {
+            self.pop();
+            return true;
+        }
+        for round in 0..8 {
+            let fe = match self.formatting_element_named(subject) {
+                Some(fe) => fe,
+                None => return false,
+            };
+            if !self.in_stack(fe) {
+                self.unexpected_end_tag(subject);
+                self.remove_formatting(fe);
+                return true;
+            }
+            if !self.node_in_scope(fe) {
+                self.unexpected_end_tag(subject);
+                return true;
+            }
+            if round == 0 && fe != self.current() {
+                let current = String::from(self.name(self.current()));
+                self.error(format!("</{}> before </{}>", current, subject));
+            }
+            let fe_index = self.open.iter().position(|id| *id == fe).unwrap();
+            let furthest_block = self.open[fe_index + 1..].iter()
+                .find(|id| is_one_of(self.name(**id), SPECIAL))
+                .cloned();
+            let furthest_block = match furthest_block {
+                Some(fb) => fb,
+                None => {
+                    self.open.truncate(fe_index);
+                    self.remove_formatting(fe);
+                    return true;
+                }
+            };
+            let common_ancestor = self.open[fe_index - 1];
+            let mut bookmark = self.formatting_position(fe).unwrap();
+            let mut node_index = self.open.iter().position(|id| *id == furthest_block).unwrap();
+            let mut last_node = furthest_block;
+            let mut counter = 0;
+            loop {
+                counter += 1;
+                node_index -= 1;
+                let node = self.open[node_index];
+                if node == fe {
+                    break;
+                }
+                if counter > 3 {
+                    if let Some(pos) = self.formatting_position(node) {
+                        self.formatting.remove(pos);
+                        if pos < bookmark {
+                            bookmark -= 1;
+                        }
+                    }
+                }
+                let pos = match self.formatting_position(node) {
+                    Some(pos) => pos,
+                    None => {
+                        self.open.remove(node_index);
+                        continue;
+                    }
+                };
+                let tag = self.tag(node).clone();
+                let new_node = self.new_node(ArenaData::Element(tag));
+                self.formatting[pos] = Formatting::Element(new_node);
+                self.open[node_index] = new_node;
+                if last_node == furthest_block {
+                    bookmark = pos + 1;
+                }
+                self.append(new_node, last_node);
+                last_node = new_node;
+            }
+            let location = self.insertion_location(common_ancestor);
+            self.insert_at(location, last_node);
+
+            let tag = self.tag(fe).clone();
+            let new_element = self.new_node(ArenaData::Element(tag));
+            let children = self.nodes[furthest_block].children.clone();
+            for ch in children {
+                self.append(new_element, ch);
+            }
+            self.append(furthest_block, new_element);
+
+            let fe_pos = self.formatting_position(fe).unwrap();
+            self.formatting.remove(fe_pos);
+            if fe_pos < bookmark {
+                bookmark -= 1;
+            }
+            let bookmark = bookmark.min(self.formatting.len());
+            self.formatting.insert(bookmark, Formatting::Element(new_element));
+
+            self.remove_from_stack(fe);
+            let fb_index = self.open.iter().position(|id| *id == furthest_block).unwrap();
+            self.open.insert(fb_index + 1, new_element);
+        }
+        true
+    }
+
+    // ---- insertion modes ----
+
+    fn initial(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                let (_, rest) = split_whitespace_prefix(&text);
+                if rest.is_empty() {
+                    return Step::Done;
+                }
+                self.error(String::from("a doctype"));
+                self.quirks_mode = QuirksMode::Quirks;
+                self.mode = InsertionMode::BeforeHtml;
+                Step::Reprocess(Token::Text(String::from(rest)))
+            }
+            Token::Comment(_) => Step::Done,
+            Token::Doctype(doctype) => {
+                let legacy_compat = doctype.system_id.as_deref().map(|id| id == "about:legacy-compat").unwrap_or(true);
+                if doctype.name.as_deref() != Some("html") || doctype.public_id.is_some() || !legacy_compat {
+                    self.error(String::from("<!DOCTYPE html>"));
+                }
+                self.quirks_mode = quirks_mode_for(&doctype);
+                self.doctype = Some(doctype);
+                self.mode = InsertionMode::BeforeHtml;
+                Step::Done
+            }
+            t => {
+                self.error(String::from("a doctype"));
+                self.quirks_mode = QuirksMode::Quirks;
+                self.mode = InsertionMode::BeforeHtml;
+                Step::Reprocess(t)
+            }
+        }
+    }
+
+    fn before_html(&mut self, token: Token) -> Step {
+        match token {
+            Token::Doctype(_) | Token::Comment(_) => return Step::Done,
+            Token::Text(text) => {
+                let (_, rest) = split_whitespace_prefix(&text);
+                if rest.is_empty() {
+                    return Step::Done;
+                }
+                let html = self.new_node(ArenaData::Element(Tag::new("html")));
+                self.append(DOCUMENT, html);
+                self.open.push(html);
+                self.mode = InsertionMode::BeforeHead;
+                return Step::Reprocess(Token::Text(String::from(rest)));
+            }
+            Token::StartTag(tag) if tag.name == "html" => {
+                let html = self.new_node(ArenaData::Element(tag));
+                self.append(DOCUMENT, html);
+                self.open.push(html);
+                self.mode = InsertionMode::BeforeHead;
+                return Step::Done;
+            }
+            Token::EndTag(ref tag) if !is_one_of(&tag.name, &["head", "body", "html", "br"]) => {
+                let name = tag.name.clone();
+                self.unexpected_end_tag(&name);
+                return Step::Done;
+            }
+            _ => {}
+        }
+        let html = self.new_node(ArenaData::Element(Tag::new("html")));
+        self.append(DOCUMENT, html);
+        self.open.push(html);
+        self.mode = InsertionMode::BeforeHead;
+        Step::Reprocess(token)
+    }
+
+    fn before_head(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                let (_, rest) = split_whitespace_prefix(&text);
+                if rest.is_empty() {
+                    return Step::Done;
+                }
+                self.head = Some(self.insert_element(Tag::new("head")));
+                self.mode = InsertionMode::InHead;
+                Step::Reprocess(Token::Text(String::from(rest)))
+            }
+            Token::Comment(text) => {
+                self.insert_comment(text);
+                Step::Done
+            }
+            Token::Doctype(_) => Step::Done,
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::StartTag(tag) if tag.name == "head" => {
+                self.head = Some(self.insert_element(tag));
+                self.mode = InsertionMode::InHead;
+                Step::Done
+            }
+            Token::EndTag(ref tag) if !is_one_of(&tag.name, &["head", "body", "html", "br"]) => {
+                let name = tag.name.clone();
+                self.unexpected_end_tag(&name);
+                Step::Done
+            }
+            t => {
+                self.head = Some(self.insert_element(Tag::new("head")));
+                self.mode = InsertionMode::InHead;
+                Step::Reprocess(t)
+            }
+        }
+    }
+
+    fn in_head(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                let (ws, rest) = split_whitespace_prefix(&text);
+                self.insert_text(ws);
+                if rest.is_empty() {
+                    return Step::Done;
+                }
+                self.pop();
+                self.mode = InsertionMode::AfterHead;
+                Step::Reprocess(Token::Text(String::from(rest)))
+            }
+            Token::Comment(text) => {
+                self.insert_comment(text);
+                Step::Done
+            }
+            Token::Doctype(_) => Step::Done,
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => self.in_body(Token::StartTag(tag)),
+                "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                    self.insert_void(tag);
+                    Step::Done
+                }
+                "title" => {
+                    self.insert_raw_text_element(tag, ContentModel::RcData);
+                    Step::Done
+                }
+                "noframes" | "style" => {
+                    self.insert_raw_text_element(tag, ContentModel::RawText);
+                    Step::Done
+                }
+                "script" => {
+                    self.insert_raw_text_element(tag, ContentModel::ScriptData);
+                    Step::Done
+                }
+                "head" => Step::Done,
+                _ => {
+                    self.pop();
+                    self.mode = InsertionMode::AfterHead;
+                    Step::Reprocess(Token::StartTag(tag))
+                }
+            },
+            Token::EndTag(tag) => match tag.name.as_str() {
+                "head" => {
+                    self.pop();
+                    self.mode = InsertionMode::AfterHead;
+                    Step::Done
+                }
+                "body" | "html" | "br" => {
+                    self.pop();
+                    self.mode = InsertionMode::AfterHead;
+                    Step::Reprocess(Token::EndTag(tag))
+                }
+                _ => {
+                    self.unexpected_end_tag(&tag.name);
+                    Step::Done
+                }
+            },
+            t => {
+                self.pop();
+                self.mode = InsertionMode::AfterHead;
+                Step::Reprocess(t)
+            }
+        }
+    }
+
+    fn after_head(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                let (ws, rest) = split_whitespace_prefix(&text);
+                self.insert_text(ws);
+                if rest.is_empty() {
+                    return Step::Done;
+                }
+                self.insert_element(Tag::new("body"));
+                self.mode = InsertionMode::InBody;
+                Step::Reprocess(Token::Text(String::from(rest)))
+            }
+            Token::Comment(text) => {
+                self.insert_comment(text);
+                Step::Done
+            }
+            Token::Doctype(_) => Step::Done,
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => self.in_body(Token::StartTag(tag)),
+                "body" => {
+                    self.insert_element(tag);
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InBody;
+                    Step::Done
+                }
+                "frameset" => {
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InFrameset;
+                    Step::Done
+                }
+                "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style" | "title" => {
+                    let head = self.head.unwrap();
+                    self.open.push(head);
+                    let step = self.in_head(Token::StartTag(tag));
+                    self.remove_from_stack(head);
+                    step
+                }
+                "head" => Step::Done,
+                _ => {
+                    self.insert_element(Tag::new("body"));
+                    self.mode = InsertionMode::InBody;
+                    Step::Reprocess(Token::StartTag(tag))
+                }
+            },
+            Token::EndTag(ref tag) if !is_one_of(&tag.name, &["body", "html", "br"]) => {
+                let name = tag.name.clone();
+                self.unexpected_end_tag(&name);
+                Step::Done
+            }
+            t => {
+                self.insert_element(Tag::new("body"));
+                self.mode = InsertionMode::InBody;
+                Step::Reprocess(t)
+            }
+        }
+    }
+
+    fn in_body(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                self.reconstruct_formatting();
+                if text.chars().any(|c| !is_whitespace(c)) {
+                    self.frameset_ok = false;
+                }
+                self.insert_text(&text);
+                Step::Done
+            }
+            Token::Cdata(text) => {
+                let id = self.new_node(ArenaData::Cdata(text));
+                let location = self.insertion_location(self.current());
+                self.insert_at(location, id);
+                Step::Done
+            }
+            Token::Comment(text) => {
+                self.insert_comment(text);
+                Step::Done
+            }
+            Token::Doctype(_) => Step::Done,
+            Token::StartTag(tag) => self.in_body_start_tag(tag),
+            Token::EndTag(tag) => self.in_body_end_tag(tag),
+            Token::Eof => {
+                const MAY_BE_OPEN: &[&str] = &["dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+                    "tbody", "td", "tfoot", "th", "thead", "tr", "body", "html"];
+                let unclosed = self.open.iter().rev()
+                    .map(|id| self.name(*id))
+                    .find(|name| !is_one_of(name, MAY_BE_OPEN))
+                    .map(String::from);
+                if let Some(name) = unclosed {
+                    self.error(format!("</{}> before the end of the document", name));
+                }
+                Step::Done
+            }
+        }
+    }
+
+    fn in_body_start_tag(&mut self, mut tag: Tag) -> Step {
+        let name = tag.name.clone();
+        match name.as_str() {
+            "html" => {
+                if let Some(html) = self.open.first().cloned() {
+                    self.merge_attributes(html, tag);
+                }
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style" | "title" => {
+                return self.in_head(Token::StartTag(tag));
+            }
+            "body" => {
+                if self.open.len() > 1 && self.name(self.open[1]) == "body" {
+                    self.frameset_ok = false;
+                    let body = self.open[1];
+                    self.merge_attributes(body, tag);
+                }
+            }
+            "frameset" => {
+                // we never replace the body with a frameset
+            }
+            n if is_one_of(n, CLOSES_P) => {
+                self.close_p_if_in_button_scope();
+                self.insert_element(tag);
+            }
+            n if is_one_of(n, HEADINGS) => {
+                self.close_p_if_in_button_scope();
+                if self.current_is_one_of(HEADINGS) {
+                    self.pop();
+                }
+                self.insert_element(tag);
+            }
+            "pre" | "listing" => {
+                self.close_p_if_in_button_scope();
+                self.insert_element(tag);
+                self.ignore_lf = true;
+                self.frameset_ok = false;
+            }
+            "form" => {
+                if self.form.is_some() {
+                    return Step::Done;
+                }
+                self.close_p_if_in_button_scope();
+                self.form = Some(self.insert_element(tag));
+            }
+            "li" | "dd" | "dt" => {
+                self.frameset_ok = false;
+                let closes: &[&str] = if name == "li" { &["li"] } else { &["dd", "dt"] };
+                for id in self.open.clone().iter().rev() {
+                    let node_name = String::from(self.name(*id));
+                    if is_one_of(&node_name, closes) {
+                        self.generate_implied_end_tags(Some(&node_name));
+                        self.pop_until(&node_name);
+                        break;
+                    }
+                    if is_one_of(&node_name, SPECIAL) && !is_one_of(&node_name, &["address", "div", "p"]) {
+                        break;
+                    }
+                }
+                self.close_p_if_in_button_scope();
+                self.insert_element(tag);
+            }
+            "plaintext" => {
+                self.close_p_if_in_button_scope();
+                self.insert_element(tag);
+                self.tokenizer.set_content_model(ContentModel::PlainText);
+            }
+            "button" => {
+                if self.in_scope("button", Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until("button");
+                }
+                self.reconstruct_formatting();
+                self.insert_element(tag);
+                self.frameset_ok = false;
+            }
+            "a" => {
+                if let Some(a) = self.formatting_element_named("a") {
+                    self.adoption_agency("a");
+                    self.remove_formatting(a);
+                    self.remove_from_stack(a);
+                }
+                self.reconstruct_formatting();
+                let id = self.insert_element(tag);
+                self.push_formatting(id);
+            }
+            "nobr" => {
+                self.reconstruct_formatting();
+                if self.in_scope("nobr", Scope::Default) {
+                    self.adoption_agency("nobr");
+                    self.reconstruct_formatting();
+                }
+                let id = self.insert_element(tag);
+                self.push_formatting(id);
+            }
+            n if is_one_of(n, FORMATTING) => {
+                self.reconstruct_formatting();
+                let id = self.insert_element(tag);
+                self.push_formatting(id);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_formatting();
+                self.insert_element(tag);
+                self.formatting.push(Formatting::Marker);
+                self.frameset_ok = false;
+            }
+            "table" => {
+                // in quirks mode a table can sit inside a paragraph
+                if self.quirks_mode != QuirksMode::Quirks {
+                    self.close_p_if_in_button_scope();
+                }
+                self.insert_element(tag);
+                self.frameset_ok = false;
+                self.mode = InsertionMode::InTable;
+            }
+            n if is_one_of(n, VOID_IN_BODY) => {
+                self.reconstruct_formatting();
+                self.insert_void(tag);
+                self.frameset_ok = false;
+            }
+            "input" => {
+                self.reconstruct_formatting();
+                let hidden = tag.attribute("type").map(|t| t.eq_ignore_ascii_case("hidden")).unwrap_or(false);
+                self.insert_void(tag);
+                if !hidden {
+                    self.frameset_ok = false;
+                }
+            }
+            "param" | "source" | "track" => {
+                self.insert_void(tag);
+            }
+            "hr" => {
+                self.close_p_if_in_button_scope();
+                self.insert_void(tag);
+                self.frameset_ok = false;
+            }
+            "image" => {
+                tag.name = String::from("img");
+                return Step::Reprocess(Token::StartTag(tag));
+            }
+            "textarea" => {
+                self.insert_element(tag);
+                self.ignore_lf = true;
+                self.frameset_ok = false;
+                self.tokenizer.set_content_model(ContentModel::RcData);
+                self.original_mode = self.mode;
+                self.mode = InsertionMode::Text;
+            }
+            "xmp" => {
+                self.close_p_if_in_button_scope();
+                self.reconstruct_formatting();
+                self.frameset_ok = false;
+                self.insert_raw_text_element(tag, ContentModel::RawText);
+            }
+            "iframe" => {
+                self.frameset_ok = false;
+                self.insert_raw_text_element(tag, ContentModel::RawText);
+            }
+            "noembed" => {
+                self.insert_raw_text_element(tag, ContentModel::RawText);
+            }
+            "select" => {
+                self.reconstruct_formatting();
+                self.insert_element(tag);
+                self.frameset_ok = false;
+                self.mode = InsertionMode::InSelect;
+            }
+            "optgroup" | "option" => {
+                if self.current_is("option") {
+                    self.pop();
+                }
+                self.reconstruct_formatting();
+                self.insert_element(tag);
+            }
+            "rb" | "rtc" | "rp" | "rt" => {
+                if self.in_scope("ruby", Scope::Default) {
+                    let except = if name == "rp" || name == "rt" { Some("rtc") } else { None };
+                    self.generate_implied_end_tags(except);
+                }
+                self.insert_element(tag);
+            }
+            "head" => {}
+            n if is_one_of(n, TABLE_PARTS) => {}
+            _ => {
+                self.reconstruct_formatting();
+                let self_closing = tag.self_closing;
+                self.insert_element(tag);
+                // we don't build a separate foreign content tree, but <svg>
+                // and <math> children can still close themselves
+                if self_closing && (name == "svg" || name == "math" || self.has_in_stack("svg") || self.has_in_stack("math")) {
+                    self.pop();
+                }
+            }
+        }
+        Step::Done
+    }
+
+    fn in_body_end_tag(&mut self, tag: Tag) -> Step {
+        let name = tag.name.clone();
+        match name.as_str() {
+            "body" => {
+                if self.in_scope("body", Scope::Default) {
+                    self.mode = InsertionMode::AfterBody;
+                }
+            }
+            "html" => {
+                if self.in_scope("body", Scope::Default) {
+                    self.mode = InsertionMode::AfterBody;
+                    return Step::Reprocess(Token::EndTag(tag));
+                }
+            }
+            n if is_one_of(n, BLOCK_END) => {
+                if self.in_scope(n, Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(n);
+                } else {
+                    self.unexpected_end_tag(n);
+                }
+            }
+            "form" => {
+                if let Some(form) = self.form.take() {
+                    if self.node_in_scope(form) {
+                        self.generate_implied_end_tags(None);
+                        self.remove_from_stack(form);
+                    }
+                }
+            }
+            "p" => {
+                if !self.in_scope("p", Scope::Button) {
+                    self.unexpected_end_tag("p");
+                    self.insert_element(Tag::new("p"));
+                }
+                self.close_p_element();
+            }
+            "li" => {
+                if self.in_scope("li", Scope::ListItem) {
+                    self.generate_implied_end_tags(Some("li"));
+                    self.pop_until("li");
+                } else {
+                    self.unexpected_end_tag("li");
+                }
+            }
+            "dd" | "dt" => {
+                if self.in_scope(&name, Scope::Default) {
+                    self.generate_implied_end_tags(Some(&name));
+                    self.pop_until(&name);
+                }
+            }
+            n if is_one_of(n, HEADINGS) => {
+                if self.in_scope_one_of(HEADINGS, Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until_one_of(HEADINGS);
+                } else {
+                    self.unexpected_end_tag(n);
+                }
+            }
+            n if is_one_of(n, FORMATTING) => {
+                if !self.adoption_agency(n) {
+                    self.any_other_end_tag(n);
+                }
+            }
+            "applet" | "marquee" | "object" => {
+                if self.in_scope(&name, Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&name);
+                    self.clear_formatting_to_marker();
+                }
+            }
+            "br" => {
+                return self.in_body_start_tag(Tag::new("br"));
+            }
+            _ => self.any_other_end_tag(&name),
+        }
+        Step::Done
+    }
+
+    fn any_other_end_tag(&mut self, name: &str) {
+        for i in (0..self.open.len()).rev() {
+            let id = self.open[i];
+            if self.name(id) == name {
+                self.generate_implied_end_tags(Some(name));
+                self.open.truncate(i);
+                return;
+            }
+            if is_one_of(self.name(id), SPECIAL) {
+                self.unexpected_end_tag(name);
+                return;
+            }
+        }
+    }
+
+    fn text(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                self.insert_text(&text);
+                Step::Done
+            }
+            Token::Eof => {
+                let name = String::from(self.name(self.current()));
+                self.error(format!("</{}> before the end of the document", name));
+                self.pop();
+                self.mode = self.original_mode;
+                Step::Reprocess(Token::Eof)
+            }
+            _ => {
+                self.pop();
+                self.mode = self.original_mode;
+                Step::Done
+            }
+        }
+    }
+
+    fn in_table(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(_) if self.current_is_one_of(&["table", "tbody", "tfoot", "thead", "tr"]) => {
+                self.pending_table_text = String::new();
+                self.original_mode = self.mode;
+                self.mode = InsertionMode::InTableText;
+                Step::Reprocess(token)
+            }
+            Token::Comment(text) => {
+                self.insert_comment(text);
+                Step::Done
+            }
+            Token::Doctype(_) => Step::Done,
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "caption" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.formatting.push(Formatting::Marker);
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InCaption;
+                    Step::Done
+                }
+                "colgroup" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InColumnGroup;
+                    Step::Done
+                }
+                "col" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_element(Tag::new("colgroup"));
+                    self.mode = InsertionMode::InColumnGroup;
+                    Step::Reprocess(Token::StartTag(tag))
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InTableBody;
+                    Step::Done
+                }
+                "td" | "th" | "tr" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_element(Tag::new("tbody"));
+                    self.mode = InsertionMode::InTableBody;
+                    Step::Reprocess(Token::StartTag(tag))
+                }
+                "table" => {
+                    if !self.in_scope("table", Scope::Table) {
+                        return Step::Done;
+                    }
+                    self.pop_until("table");
+                    self.reset_insertion_mode();
+                    Step::Reprocess(Token::StartTag(tag))
+                }
+                "style" | "script" => self.in_head(Token::StartTag(tag)),
+                "input" if tag.attribute("type").map(|t| t.eq_ignore_ascii_case("hidden")).unwrap_or(false) => {
+                    self.insert_void(tag);
+                    Step::Done
+                }
+                "form" => {
+                    if self.form.is_none() {
+                        let form = self.insert_void(tag);
+                        self.form = Some(form);
+                    }
+                    Step::Done
+                }
+                _ => self.in_table_anything_else(Token::StartTag(tag)),
+            },
+            Token::EndTag(tag) => match tag.name.as_str() {
+                "table" => {
+                    if self.in_scope("table", Scope::Table) {
+                        self.pop_until("table");
+                        self.reset_insertion_mode();
+                    }
+                    Step::Done
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr" => Step::Done,
+                _ => self.in_table_anything_else(Token::EndTag(tag)),
+            },
+            Token::Eof => self.in_body(Token::Eof),
+            t => self.in_table_anything_else(t),
+        }
+    }
+
+    fn in_table_anything_else(&mut self, token: Token) -> Step {
+        self.error(String::from("table content inside a cell or caption"));
+        self.foster_parenting = true;
+        let step = self.in_body(token);
+        self.foster_parenting = false;
+        step
+    }
+
+    fn in_table_text(&mut self, token: Token) -> Step {
+        if let Token::Text(text) = token {
+            self.pending_table_text.push_str(&text);
+            return Step::Done;
+        }
+        let pending = std::mem::take(&mut self.pending_table_text);
+        if pending.chars().any(|c| !is_whitespace(c)) {
+            self.in_table_anything_else(Token::Text(pending));
+        } else {
+            self.insert_text(&pending);
+        }
+        self.mode = self.original_mode;
+        Step::Reprocess(token)
+    }
+
+    fn close_caption(&mut self) -> bool {
+        if !self.in_scope("caption", Scope::Table) {
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        self.pop_until("caption");
+        self.clear_formatting_to_marker();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    fn in_caption(&mut self, token: Token) -> Step {
+        match &token {
+            Token::EndTag(tag) if tag.name == "caption" => {
+                self.close_caption();
+                Step::Done
+            }
+            Token::StartTag(tag) if is_one_of(&tag.name, TABLE_PARTS) => {
+                if self.close_caption() { Step::Reprocess(token) } else { Step::Done }
+            }
+            Token::EndTag(tag) if tag.name == "table" => {
+                if self.close_caption() { Step::Reprocess(token) } else { Step::Done }
+            }
+            Token::EndTag(tag) if is_one_of(&tag.name, &["body", "col", "colgroup", "html", "tbody", "td", "tfoot", "th", "thead", "tr"]) => Step::Done,
+            _ => self.in_body(token),
+        }
+    }
+
+    fn in_column_group(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                let (ws, rest) = split_whitespace_prefix(&text);
+                self.insert_text(ws);
+                if rest.is_empty() {
+                    return Step::Done;
+                }
+                self.in_column_group_anything_else(Token::Text(String::from(rest)))
+            }
+            Token::Comment(text) => {
+                self.insert_comment(text);
+                Step::Done
+            }
+            Token::Doctype(_) => Step::Done,
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::StartTag(tag) if tag.name == "col" => {
+                self.insert_void(tag);
+                Step::Done
+            }
+            Token::EndTag(ref tag) if tag.name == "colgroup" => {
+                if self.current_is("colgroup") {
+                    self.pop();
+                    self.mode = InsertionMode::InTable;
+                }
+                Step::Done
+            }
+            Token::EndTag(ref tag) if tag.name == "col" => Step::Done,
+            Token::Eof => self.in_body(token),
+            t => self.in_column_group_anything_else(t),
+        }
+    }
+    fn in_column_group_anything_else(&mut self, token: Token) -> Step {
+        if !self.current_is("colgroup") {
+            return Step::Done;
+        }
+        self.pop();
+        self.mode = InsertionMode::InTable;
+        Step::Reprocess(token)
+    }
+
+    fn in_table_body(&mut self, token: Token) -> Step {
+        const CONTEXT: &[&str] = &["tbody", "tfoot", "thead", "template", "html"];
+        match &token {
+            Token::StartTag(tag) if tag.name == "tr" => {
+                self.clear_stack_back_to(CONTEXT);
+                if let Token::StartTag(tag) = token {
+                    self.insert_element(tag);
+                }
+                self.mode = InsertionMode::InRow;
+                Step::Done
+            }
+            Token::StartTag(tag) if tag.name == "th" || tag.name == "td" => {
+                self.clear_stack_back_to(CONTEXT);
+                self.insert_element(Tag::new("tr"));
+                self.mode = InsertionMode::InRow;
+                Step::Reprocess(token)
+            }
+            Token::EndTag(tag) if is_one_of(&tag.name, &["tbody", "tfoot", "thead"]) => {
+                if self.in_scope(&tag.name, Scope::Table) {
+                    self.clear_stack_back_to(CONTEXT);
+                    self.pop();
+                    self.mode = InsertionMode::InTable;
+                }
+                Step::Done
+            }
+            Token::StartTag(tag) if is_one_of(&tag.name, &["caption", "col", "colgroup", "tbody", "tfoot", "thead"]) => {
+                self.leave_table_body(token)
+            }
+            Token::EndTag(tag) if tag.name == "table" => self.leave_table_body(token),
+            Token::EndTag(tag) if is_one_of(&tag.name, &["body", "caption", "col", "colgroup", "html", "td", "th", "tr"]) => Step::Done,
+            _ => self.in_table(token),
+        }
+    }
+    fn leave_table_body(&mut self, token: Token) -> Step {
+        if !self.in_scope_one_of(&["tbody", "thead", "tfoot"], Scope::Table) {
+            return Step::Done;
+        }
+        self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+        self.pop();
+        self.mode = InsertionMode::InTable;
+        Step::Reprocess(token)
+    }
+
+    fn close_row(&mut self) -> bool {
+        if !self.in_scope("tr", Scope::Table) {
+            return false;
+        }
+        self.clear_stack_back_to(&["tr", "template", "html"]);
+        self.pop();
+        self.mode = InsertionMode::InTableBody;
+        true
+    }
+
+    fn in_row(&mut self, token: Token) -> Step {
+        match &token {
+            Token::StartTag(tag) if tag.name == "th" || tag.name == "td" => {
+                self.clear_stack_back_to(&["tr", "template", "html"]);
+                if let Token::StartTag(tag) = token {
+                    self.insert_element(tag);
+                }
+                self.mode = InsertionMode::InCell;
+                self.formatting.push(Formatting::Marker);
+                Step::Done
+            }
+            Token::EndTag(tag) if tag.name == "tr" => {
+                self.close_row();
+                Step::Done
+            }
+            Token::StartTag(tag) if is_one_of(&tag.name, &["caption", "col", "colgroup", "tbody", "tfoot", "thead", "tr"]) => {
+                if self.close_row() { Step::Reprocess(token) } else { Step::Done }
+            }
+            Token::EndTag(tag) if tag.name == "table" => {
+                if self.close_row() { Step::Reprocess(token) } else { Step::Done }
+            }
+            Token::EndTag(tag) if is_one_of(&tag.name, &["tbody", "tfoot", "thead"]) => {
+                if !self.in_scope(&tag.name, Scope::Table) {
+                    return Step::Done;
+                }
+                if self.close_row() { Step::Reprocess(token) } else { Step::Done }
+            }
+            Token::EndTag(tag) if is_one_of(&tag.name, &["body", "caption", "col", "colgroup", "html", "td", "th"]) => Step::Done,
+            _ => self.in_table(token),
+        }
+    }
+
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        self.pop_until_one_of(&["td", "th"]);
+        self.clear_formatting_to_marker();
+        self.mode = InsertionMode::InRow;
+    }
+
+    fn in_cell(&mut self, token: Token) -> Step {
+        match &token {
+            Token::EndTag(tag) if tag.name == "td" || tag.name == "th" => {
+                if self.in_scope(&tag.name, Scope::Table) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&tag.name);
+                    self.clear_formatting_to_marker();
+                    self.mode = InsertionMode::InRow;
+                }
+                Step::Done
+            }
+            Token::StartTag(tag) if is_one_of(&tag.name, TABLE_PARTS) => {
+                if !self.in_scope_one_of(&["td", "th"], Scope::Table) {
+                    return Step::Done;
+                }
+                self.close_cell();
+                Step::Reprocess(token)
+            }
+            Token::EndTag(tag) if is_one_of(&tag.name, &["body", "caption", "col", "colgroup", "html"]) => Step::Done,
+            Token::EndTag(tag) if is_one_of(&tag.name, &["table", "tbody", "tfoot", "thead", "tr"]) => {
+                if !self.in_scope(&tag.name, Scope::Table) {
+                    return Step::Done;
+                }
+                self.close_cell();
+                Step::Reprocess(token)
+            }
+            _ => self.in_body(token),
+        }
+    }
+
+    fn in_select(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                self.insert_text(&text);
+                Step::Done
+            }
+            Token::Comment(text) => {
+                self.insert_comment(text);
+                Step::Done
+            }
+            Token::Doctype(_) => Step::Done,
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => self.in_body(Token::StartTag(tag)),
+                "option" => {
+                    if self.current_is("option") {
+                        self.pop();
+                    }
+                    self.insert_element(tag);
+                    Step::Done
+                }
+                "optgroup" => {
+                    if self.current_is("option") {
+                        self.pop();
+                    }
+                    if self.current_is("optgroup") {
+                        self.pop();
+                    }
+                    self.insert_element(tag);
+                    Step::Done
+                }
+                "select" => {
+                    if self.in_scope("select", Scope::Select) {
+                        self.pop_until("select");
+                        self.reset_insertion_mode();
+                    }
+                    Step::Done
+                }
+                "input" | "keygen" | "textarea" | "caption" | "table" | "tbody" | "tfoot" | "thead" | "tr" | "td" | "th" => {
+                    if !self.in_scope("select", Scope::Select) {
+                        return Step::Done;
+                    }
+                    self.pop_until("select");
+                    self.reset_insertion_mode();
+                    Step::Reprocess(Token::StartTag(tag))
+                }
+                "script" | "template" => self.in_head(Token::StartTag(tag)),
+                _ => Step::Done,
+            },
+            Token::EndTag(tag) => match tag.name.as_str() {
+                "optgroup" => {
+                    let len = self.open.len();
+                    if self.current_is("option") && len > 1 && self.name(self.open[len - 2]) == "optgroup" {
+                        self.pop();
+                    }
+                    if self.current_is("optgroup") {
+                        self.pop();
+                    }
+                    Step::Done
+                }
+                "option" => {
+                    if self.current_is("option") {
+                        self.pop();
+                    }
+                    Step::Done
+                }
+                "select" => {
+                    if self.in_scope("select", Scope::Select) {
+                        self.pop_until("select");
+                        self.reset_insertion_mode();
+                    }
+                    Step::Done
+                }
+                "caption" | "table" | "tbody" | "tfoot" | "thead" | "tr" | "td" | "th" => {
+                    if !self.in_scope(&tag.name, Scope::Table) {
+                        return Step::Done;
+                    }
+                    self.pop_until("select");
+                    self.reset_insertion_mode();
+                    Step::Reprocess(Token::EndTag(tag))
+                }
+                _ => Step::Done,
+            },
+            Token::Eof => self.in_body(Token::Eof),
+            Token::Cdata(_) => Step::Done,
+        }
+    }
+
+    fn after_body(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(ref text) if text.chars().all(is_whitespace) => self.in_body(token),
+            Token::Comment(text) => {
+                let html = self.open[0];
+                let id = self.new_node(ArenaData::Comment(text));
+                self.append(html, id);
+                Step::Done
+            }
+            Token::Doctype(_) => Step::Done,
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::EndTag(ref tag) if tag.name == "html" => {
+                self.mode = InsertionMode::AfterAfterBody;
+                Step::Done
+            }
+            Token::Eof => Step::Done,
+            t => {
+                self.mode = InsertionMode::InBody;
+                Step::Reprocess(t)
+            }
+        }
+    }
+
+    fn in_frameset(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                let ws: String = text.chars().filter(|c| is_whitespace(*c)).collect();
+                self.insert_text(&ws);
+            }
+            Token::Comment(text) => self.insert_comment(text),
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => return self.in_body(Token::StartTag(tag)),
+                "frameset" => { self.insert_element(tag); }
+                "frame" => { self.insert_void(tag); }
+                "noframes" => return self.in_head(Token::StartTag(tag)),
+                _ => {}
+            },
+            Token::EndTag(ref tag) if tag.name == "frameset" => {
+                if !self.current_is("html") {
+                    self.pop();
+                }
+                if !self.current_is("frameset") {
+                    self.mode = InsertionMode::AfterFrameset;
+                }
+            }
+            _ => {}
+        }
+        Step::Done
+    }
+
+    fn after_frameset(&mut self, token: Token) -> Step {
+        match token {
+            Token::Text(text) => {
+                let ws: String = text.chars().filter(|c| is_whitespace(*c)).collect();
+                self.insert_text(&ws);
+            }
+            Token::Comment(text) => self.insert_comment(text),
+            Token::StartTag(ref tag) if tag.name == "html" => return self.in_body(token),
+            Token::StartTag(tag) if tag.name == "noframes" => return self.in_head(Token::StartTag(tag)),
+            Token::EndTag(ref tag) if tag.name == "html" => self.mode = InsertionMode::AfterAfterBody,
+            _ => {}
+        }
+        Step::Done
+    }
+
+    fn after_after_body(&mut self, token: Token) -> Step {
+        match token {
+            Token::Comment(_) | Token::Doctype(_) | Token::Eof => Step::Done,
+            Token::Text(ref text) if text.chars().all(is_whitespace) => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            t => {
+                self.mode = InsertionMode::InBody;
+                Step::Reprocess(t)
+            }
+        }
+    }
+
+    // ---- conversion to the owned dom ----
+
+    fn to_nodes(&self, id: usize, parent: Option<NodeId>, nodes: &mut Vec<Node>) -> NodeId {
+        let node_type = match &self.nodes[id].data {
+            ArenaData::Element(tag) => {
+                let mut attributes = AttrMap::new();
+                for (k, v) in tag.attributes.iter() {
+                    attributes.insert(k.clone(), v.clone());
+                }
+                if tag.name == "meta" {
+                    NodeType::Meta(MetaData { attributes })
+                } else {
+                    NodeType::Element(ElementData { tag_name: tag.name.clone(), attributes })
+                }
+            }
+            ArenaData::Text(text) => NodeType::Text(text.clone()),
+            ArenaData::Comment(text) => NodeType::Comment(text.clone()),
+            ArenaData::Cdata(text) => NodeType::Cdata(text.clone()),
+            ArenaData::Document => NodeType::Comment(String::new()),
+        };
+        let node_id = NodeId(nodes.len());
+        nodes.push(Node { node_type, parent, children: vec![] });
+        let children = self.nodes[id].children.iter()
+            .map(|ch| self.to_nodes(*ch, Some(node_id), nodes))
+            .collect();
+        nodes[node_id.0].children = children;
+        node_id
+    }
+}
+
+#[cfg(test)]
+fn tree(input: &str) -> Document {
+    let parsed = TreeBuilder::new(input).run().0;
+    Document {
+        nodes: parsed.nodes,
+        root: NodeId(0),
+        doctype: parsed.doctype,
+        quirks_mode: parsed.quirks_mode,
+        base_url: Url::parse("https://www.mozilla.org/").unwrap(),
+        encoding: encoding_rs::UTF_8,
+        element_states: Default::default(),
+    }
+}
+
+#[cfg(test)]
+fn child(doc: &Document, index: usize) -> String {
+    outline(doc, doc.children(doc.root())[index])
+}
+
+#[cfg(test)]
+fn tag_names(doc: &Document) -> Vec<String> {
+    doc.children(doc.root()).iter().filter_map(|ch| match &doc.node(*ch).node_type {
+        NodeType::Element(ed) => Some(ed.tag_name.clone()),
+        NodeType::Meta(_) => Some(String::from("meta")),
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn test_implied_html_head_body() {
+    let doc = tree("hello");
+    assert_eq!(outline(&doc, doc.root()), "<html><head></head><body>hello</body></html>");
+    let doc = tree("<title>t</title><p>x");
+    assert_eq!(outline(&doc, doc.root()), "<html><head><title>t</title></head><body><p>x</p></body></html>");
+    let doc = tree("");
+    assert_eq!(outline(&doc, doc.root()), "<html><head></head><body></body></html>");
+}
+
+#[test]
+fn test_unclosed_p_and_li() {
+    let doc = tree("<p>one<p>two<div>three</div>");
+    assert_eq!(child(&doc, 1), "<body><p>one</p><p>two</p><div>three</div></body>");
+    let doc = tree("<ul><li>one<li>two</ul>");
+    assert_eq!(child(&doc, 1), "<body><ul><li>one</li><li>two</li></ul></body>");
+    let doc = tree("<dl><dt>a<dd>b<dt>c</dl>");
+    assert_eq!(child(&doc, 1), "<body><dl><dt>a</dt><dd>b</dd><dt>c</dt></dl></body>");
+    //a stray end tag makes an empty p
+    let doc = tree("<div></p></div>");
+    assert_eq!(child(&doc, 1), "<body><div><p></p></div></body>");
+}
+
+#[test]
+fn test_raw_lt_in_text() {
+    let doc = tree("<p>1 < 2 & 3 <> 4</p>");
+    assert_eq!(child(&doc, 1), "<body><p>1 < 2 & 3 <> 4</p></body>");
+}
+
+#[test]
+fn test_adoption_agency() {
+    let doc = tree("<b>1<p>2</b>3</p>");
+    assert_eq!(child(&doc, 1), "<body><b>1</b><p><b>2</b>3</p></body>");
+    let doc = tree("<a><p>x<a>y</a></p>");
+    assert_eq!(child(&doc, 1), "<body><a></a><p><a>x</a><a>y</a></p></body>");
+    let doc = tree("<b><i>a</b>b</i>");
+    assert_eq!(child(&doc, 1), "<body><b><i>a</i></b><i>b</i></body>");
+    //formatting carries into the next paragraph
+    let doc = tree("<p><em>one<p>two");
+    assert_eq!(child(&doc, 1), "<body><p><em>one</em></p><p><em>two</em></p></body>");
+}
+
+#[test]
+fn test_table_structure() {
+    let doc = tree("<table><tr><td>a<td>b</table>");
+    assert_eq!(child(&doc, 1),
+               "<body><table><tbody><tr><td>a</td><td>b</td></tr></tbody></table></body>");
+    //text inside a table is fostered out in front of it
+    let doc = tree("<table>oops<tr><td>a</td></tr></table>");
+    assert_eq!(child(&doc, 1),
+               "<body>oops<table><tbody><tr><td>a</td></tr></tbody></table></body>");
+}
+
+#[test]
+fn test_void_and_raw_text_elements() {
+    let doc = tree("<head><style>p > b { x: \"</p>\" }</style></head><p>a<br>b<img src=x>c");
+    assert_eq!(outline(&doc, doc.root()),
+               "<html><head><style>p > b { x: \"</p>\" }</style></head><body><p>a<br></br>b<img></img>c</p></body></html>");
+    let doc = tree("<script>if (a<b) { document.write('<p>') }</script>");
+    assert_eq!(child(&doc, 0), "<head><script>if (a<b) { document.write('<p>') }</script></head>");
+    let doc = tree("<pre>\nfoo\n</pre>");
+    assert_eq!(child(&doc, 1), "<body><pre>foo\n</pre></body>");
+}
+
+#[test]
+fn test_misnested_close_tags() {
+    let doc = tree("<div><span>a</div>b</span>");
+    assert_eq!(child(&doc, 1), "<body><div><span>a</span></div>b</body>");
+    let doc = tree("<html><body><div>x</div></body></html><p>after");
+    assert_eq!(child(&doc, 1), "<body><div>x</div><p>after</p></body>");
+    assert_eq!(tag_names(&doc), vec!["head", "body"]);
+}
+
+#[test]
+fn test_parse_errors() {
+    let (_, errors) = TreeBuilder::new("<!DOCTYPE html><p>ok</p>").run();
+    assert!(errors.is_empty());
+    let (_, errors) = TreeBuilder::new("<p>a</div><b><i>x</b></i>").run();
+    assert_eq!(errors, vec![
+        (0, String::from("a doctype")),
+        (4, String::from("an open <div> element")),
+        (17, String::from("</i> before </b>")),
+        (21, String::from("an open <i> element")),
+    ]);
+    let (_, errors) = TreeBuilder::new("<!DOCTYPE html><div><span title=a title=b>x<!-- open").run();
+    assert_eq!(errors, vec![
+        (34, String::from("a unique attribute name")),
+        (43, String::from("'-->' to close the comment")),
+        (52, String::from("</span> before the end of the document")),
+    ]);
+}
+
+#[test]
+fn test_doctype_and_quirks_mode() {
+    let doc = tree("<!DOCTYPE html><p>a");
+    assert_eq!(doc.quirks_mode, QuirksMode::NoQuirks);
+    let dt = doc.doctype().unwrap();
+    assert_eq!(doc.parent(dt), None);
+    assert_eq!(doc.node(dt).node_type, NodeType::Doctype(DoctypeData { name: String::from("html"), public_id: None, system_id: None }));
+    assert!(!doc.descendants(doc.root()).contains(&dt));
+
+    let doc = tree("<p>no doctype");
+    assert_eq!(doc.doctype(), None);
+    assert_eq!(doc.quirks_mode, QuirksMode::Quirks);
+    let doc = tree(r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.0 Transitional//EN">"#);
+    assert_eq!(doc.quirks_mode, QuirksMode::Quirks);
+    let doc = tree(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">"#);
+    assert_eq!(doc.quirks_mode, QuirksMode::Quirks);
+    let doc = tree(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN" "http://www.w3.org/TR/html4/loose.dtd">"#);
+    assert_eq!(doc.quirks_mode, QuirksMode::LimitedQuirks);
+    let doc = tree(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd">"#);
+    assert_eq!(doc.quirks_mode, QuirksMode::NoQuirks);
+    let doc = tree("<!DOCTYPE svg><p>a");
+    assert_eq!(doc.quirks_mode, QuirksMode::Quirks);
+
+    // only a quirks mode table can sit inside a paragraph
+    assert_eq!(child(&tree("<p><table></table>"), 1), "<body><p><table></table></p></body>");
+    assert_eq!(child(&tree("<!DOCTYPE html><p><table></table>"), 1), "<body><p></p><table></table></body>");
+
+    let (_, errors) = TreeBuilder::new(r#"<!DOCTYPE html SYSTEM "about:legacy-compat">"#).run();
+    assert!(errors.is_empty());
+    let (_, errors) = TreeBuilder::new(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01//EN">"#).run();
+    assert_eq!(errors, vec![(0, String::from("<!DOCTYPE html>"))]);
+}
+
+#[test]
+fn test_markup_in_raw_text() {
+    let doc = tree("<!DOCTYPE html><title>a <b>bold</b> &amp; <!-- c --></title>\
+                    <style><!-- p { color: red } --></style><script><![CDATA[ x ]]><?php ?></script>\
+                    <textarea><p>x</p></textarea>");
+    assert_eq!(child(&doc, 0), "<head><title>a <b>bold</b> &amp; <!-- c --></title>\
+                                <style><!-- p { color: red } --></style><script><![CDATA[ x ]]><?php ?></script></head>");
+    assert_eq!(child(&doc, 1), "<body><textarea><p>x</p></textarea></body>");
+    // outside of raw text they are parsed
+    let doc = tree("<!DOCTYPE html><p>a<?xml version=\"1.0\"?><![CDATA[b]]><!--c--></p>");
+    assert_eq!(child(&doc, 1), "<body><p>a<!--?xml version=\"1.0\"?--><![CDATA[b]]><!--c--></p></body>");
+}
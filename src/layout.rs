@@ -1,10 +1,8 @@
-use crate::dom::{NodeType, Document, load_doc_from_bytestring, strip_empty_nodes};
-use crate::style::{StyledNode, Display, dom_tree_to_stylednodes, expand_styles, StyledTree};
-use crate::css::{Color, Unit, Value, parse_stylesheet_from_bytestring, Stylesheet};
+use crate::dom::{NodeType, NodeId, Document, load_doc_from_bytestring};
+use crate::style::{StyledNode, ComputedStyle, Display, WhiteSpace, TextAlign, VerticalAlign, FontStyle, TextDecorationLine, Size, dom_tree_to_stylednodes, expand_styles, StyledTree};
+use crate::css::{Color, MediaContext, parse_stylesheet_from_bytestring, Stylesheet};
 use crate::layout::BoxType::{BlockNode, InlineNode, AnonymousBlock, InlineBlockNode, TableNode, TableRowGroupNode, TableRowNode, TableCellNode, ListItemNode};
-use crate::css::Value::{Keyword, Length};
-use crate::css::Unit::Px;
-use crate::render::{BLACK, FontCache};
+use crate::render::FontCache;
 use crate::image::{LoadedImage};
 use crate::dom::NodeType::{Text, Element};
 use crate::net::{load_image, load_stylesheet_from_net, relative_filepath_to_url, load_doc_from_net, BrowserError, StylesheetSet, load_stylesheets_new};
@@ -81,6 +79,14 @@ pub struct EdgeSizes {
     pub bottom: f32,
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct EdgeColors {
+    pub left: Option<Color>,
+    pub right: Option<Color>,
+    pub top: Option<Color>,
+    pub bottom: Option<Color>,
+}
+
 #[derive(Debug)]
 pub struct LayoutBox {
     pub dimensions: Dimensions,
@@ -141,16 +147,16 @@ pub struct RenderBlockBox {
     pub margin:EdgeSizes,
     pub padding:EdgeSizes,
     pub background_color: Option<Color>,
-    pub border_color: Option<Color>,
+    pub border_color: EdgeColors,
     pub border_width: EdgeSizes,
-    pub valign:String,
+    pub valign:VerticalAlign,
     pub children: Vec<RenderBox>,
     pub marker:ListMarker,
     pub color:Option<Color>,
     pub font_size:f32,
     pub font_family:String,
     pub font_weight:i32,
-    pub font_style:String,
+    pub font_style:FontStyle,
 }
 
 impl RenderBlockBox {
@@ -168,7 +174,7 @@ impl RenderBlockBox {
             x: self.rect.x - self.padding.left - self.border_width.left,
             y: self.rect.y - self.padding.top - self.border_width.top,
             width: self.rect.width + self.padding.left + self.padding.right + self.border_width.left + self.border_width.right,
-            height: self.rect.height + self.padding.top + self.padding.bottom + self.border_width.left + self.border_width.right,
+            height: self.rect.height + self.padding.top + self.padding.bottom + self.border_width.top + self.border_width.bottom,
         }
     }
 }
@@ -223,15 +229,17 @@ pub enum RenderInlineBoxType {
 pub struct RenderTextBox {
     pub rect:Rect,
     pub text:String,
+    /// the element the text is in
+    pub node:NodeId,
     pub color:Option<Color>,
     pub background_color: Option<Color>,
     pub font_size:f32,
     pub font_family:String,
     pub link:Option<String>,
     pub font_weight:i32,
-    pub font_style:String,
-    pub valign:String,
-    pub text_decoration_line:String,
+    pub font_style:FontStyle,
+    pub valign:VerticalAlign,
+    pub text_decoration_line:TextDecorationLine,
 }
 impl RenderTextBox {
     pub fn find_box_containing(&self, x: f32, y: f32) -> QueryResult {
@@ -246,16 +254,16 @@ impl RenderTextBox {
 pub struct RenderImageBox {
     pub rect:Rect,
     pub image:LoadedImage,
-    pub valign:String,
+    pub valign:VerticalAlign,
 }
 #[derive(Debug)]
 pub struct RenderErrorBox {
     pub rect:Rect,
-    pub valign:String,
+    pub valign:VerticalAlign,
 }
 
 pub fn build_layout_tree<'a>(style_node: &Rc<StyledNode>, doc:&Document) -> LayoutBox {
-    let mut root = LayoutBox::new(match style_node.display() {
+    let mut root = LayoutBox::new(match style_node.style.display {
         Display::Block => BlockNode(Rc::clone(style_node)),
         Display::Inline => InlineNode(Rc::clone(style_node)),
         Display::InlineBlock => InlineBlockNode(Rc::clone(style_node)),
@@ -269,10 +277,18 @@ pub fn build_layout_tree<'a>(style_node: &Rc<StyledNode>, doc:&Document) -> Layo
 
 
     for child in style_node.children.borrow().iter() {
-        match child.display() {
+        match child.style.display {
             Display::Block =>  root.children.push(build_layout_tree(child, doc)),
             Display::ListItem =>  root.children.push(build_layout_tree(child, doc)),
-            Display::Inline => root.get_inline_container().children.push(build_layout_tree(&child, doc)),
+            Display::Inline => {
+                // collapsible whitespace between blocks doesn't get a box of its own
+                if !root.has_open_inline_container()
+                    && style_node.style.white_space.collapses_spaces()
+                    && is_whitespace_text(doc, child) {
+                    continue;
+                }
+                root.get_inline_container().children.push(build_layout_tree(&child, doc))
+            },
             Display::InlineBlock => root.get_inline_container().children.push(build_layout_tree(&child, doc)),
             Display::Table => root.children.push(build_layout_tree(&child,doc)),
             Display::TableRowGroup => root.children.push(build_layout_tree(&child, doc)),
@@ -284,6 +300,68 @@ pub fn build_layout_tree<'a>(style_node: &Rc<StyledNode>, doc:&Document) -> Layo
     root
 }
 
+fn is_whitespace_text(doc:&Document, style_node:&StyledNode) -> bool {
+    match &doc.node(style_node.node).node_type {
+        Text(txt) => txt.chars().all(|ch| ch == ' ' || ch == '\t' || ch == '\n' || ch == '\r' || ch == '\x0C'),
+        _ => false,
+    }
+}
+
+impl WhiteSpace {
+    fn collapses_spaces(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::NoWrap | WhiteSpace::PreLine)
+    }
+    fn preserves_newlines(self) -> bool {
+        !matches!(self, WhiteSpace::Normal | WhiteSpace::NoWrap)
+    }
+    fn wraps(self) -> bool {
+        !matches!(self, WhiteSpace::NoWrap | WhiteSpace::Pre)
+    }
+    // splits the text into lines at the forced breaks. collapsible spaces
+    // become single spaces and tabs that are kept are expanded to spaces.
+    fn process(self, text:&str) -> Vec<String> {
+        let mut lines = vec![];
+        let text = if self.preserves_newlines() { text.to_string() } else { text.replace('\n', " ") };
+        for line in text.split('\n') {
+            if self.collapses_spaces() {
+                let mut out = String::new();
+                let mut last_space = false;
+                for ch in line.chars() {
+                    let space = ch == ' ' || ch == '\t' || ch == '\r' || ch == '\x0C';
+                    if !(space && last_space) {
+                        out.push(if space { ' ' } else { ch });
+                    }
+                    last_space = space;
+                }
+                lines.push(out);
+            } else {
+                let mut out = String::new();
+                let mut column = 0;
+                for ch in line.chars() {
+                    if ch == '\t' {
+                        let stop = 8 - column % 8;
+                        out.extend(std::iter::repeat(' ').take(stop));
+                        column += stop;
+                    } else {
+                        out.push(ch);
+                        column += 1;
+                    }
+                }
+                lines.push(out);
+            }
+        }
+        if self == WhiteSpace::PreLine {
+            //spaces next to a preserved newline go away
+            let last = lines.len() - 1;
+            for (i, line) in lines.iter_mut().enumerate() {
+                if i > 0 { *line = line.trim_start_matches(' ').to_string(); }
+                if i < last { *line = line.trim_end_matches(' ').to_string(); }
+            }
+        }
+        lines
+    }
+}
+
 impl LayoutBox {
     fn new(box_type: BoxType) -> LayoutBox {
         LayoutBox {
@@ -306,6 +384,17 @@ impl LayoutBox {
         }
     }
 
+    // whether inline content added now would join an existing line, instead of starting a new anonymous block
+    fn has_open_inline_container(&self) -> bool {
+        match &self.box_type {
+            InlineNode(_) | InlineBlockNode(_) | AnonymousBlock(_) => true,
+            _ => match self.children.last() {
+                Some(ch) => matches!(ch.box_type, AnonymousBlock(_)),
+                None => false,
+            }
+        }
+    }
+
     fn get_inline_container(&mut self) -> &mut LayoutBox {
         match &self.box_type {
             InlineNode(_) | InlineBlockNode(_) | AnonymousBlock(_) => self,
@@ -350,7 +439,7 @@ impl LayoutBox {
             ListItemNode(_node) =>      RenderBox::Block(self.layout_block(containing, font, doc)),
         }
     }
-    fn debug_calculate_element_name(&self) -> String{
+    fn debug_calculate_element_name(&self, doc:&Document) -> String{
         match &self.box_type {
             BlockNode(sn)
             | TableNode(sn)
@@ -358,7 +447,7 @@ impl LayoutBox {
             | TableRowNode(sn)
             | TableCellNode(sn)
             | InlineNode(sn)
-            => match &sn.node.node_type {
+            => match &doc.node(sn.node).node_type {
                 NodeType::Element(data) => data.tag_name.clone(),
                 _ => "non-element".to_string(),
             }
@@ -370,37 +459,30 @@ impl LayoutBox {
         self.calculate_block_position(containing_block);
         let children:Vec<RenderBox> = self.layout_block_children(font_cache, doc);
         self.calculate_block_height();
-        let zero = Length(0.0, Px);
-        let style = self.get_style_node();
-        // println!("border top for block is {} {:#?}", self.debug_calculate_element_name(), &style.lookup("border-top", "border-width", &zero));
+        let style = &self.get_style_node().style;
         RenderBlockBox{
             rect:self.dimensions.content,
             margin: self.dimensions.margin,
             padding: self.dimensions.padding,
             children,
-            title: self.debug_calculate_element_name(),
-            background_color: style.color("background-color"),
-            border_width: EdgeSizes {
-                top: style.lookup_length_as_px("border-width-top", 0.0),
-                bottom: style.lookup_length_as_px("border-width-bottom",0.0),
-                left: style.lookup_length_as_px("border-width-top",0.0),
-                right: style.lookup_length_as_px("border-width-bottom",0.0),
-            },
-            border_color: style.color("border-color"),
-            valign: String::from("baseline"),
-            marker: if style.lookup_string("display","block") == "list-item" {
-                match &*style.lookup_string("list-style-type", "none") {
+            title: self.debug_calculate_element_name(doc),
+            background_color: style.background_color.clone(),
+            border_width: border_widths(style),
+            border_color: border_colors(style),
+            valign: VerticalAlign::Baseline,
+            marker: if style.display == Display::ListItem {
+                match style.list_style_type.as_str() {
                     "disc" => ListMarker::Disc,
                     _ => ListMarker::None,
                 }
             } else {
                 ListMarker::None
             },
-            color: Some(style.lookup_color("color", &BLACK)),
-            font_family: style.lookup_font_family(font_cache),
-            font_weight : style.lookup_font_weight(400),
-            font_style : style.lookup_string("font-style", "normal"),
-            font_size: style.lookup_font_size(),
+            color: Some(style.color.clone()),
+            font_family: style.find_font_family(font_cache),
+            font_weight : style.font_weight,
+            font_style : style.font_style,
+            font_size: style.font_size,
         }
     }
 
@@ -441,36 +523,31 @@ impl LayoutBox {
                 }
                 BoxType::AnonymousBlock(_)=>println!(" anonymous child"),
                 _ => {
-                    println!("table_row can't have child of {:#?}",child.get_type());
+                    println!("table_row can't have child of {:#?}",child.get_type(doc));
                 }
             };
         };
-        let style = self.get_style_node();
+        let style = &self.get_style_node().style;
         RenderBlockBox {
-            title: self.debug_calculate_element_name(),
+            title: self.debug_calculate_element_name(doc),
             rect:self.dimensions.content,
             margin: self.dimensions.margin,
             padding: self.dimensions.padding,
-            background_color: self.get_style_node().color("background-color"),
-            border_width: EdgeSizes {
-                top: style.lookup_length_as_px("border-width-top", 0.0),
-                bottom: style.lookup_length_as_px("border-width-bottom",0.0),
-                left: style.lookup_length_as_px("border-width-top",0.0),
-                right: style.lookup_length_as_px("border-width-bottom",0.0),
-            },
-            border_color: self.get_style_node().color("border-color"),
-            valign: String::from("baseline"),
+            background_color: style.background_color.clone(),
+            border_width: border_widths(style),
+            border_color: border_colors(style),
+            valign: VerticalAlign::Baseline,
             children: children,
             marker: ListMarker::None,
-            color: Some(style.lookup_color("color", &BLACK)),
-            font_family: style.lookup_font_family(font_cache),
-            font_weight : style.lookup_font_weight(400),
-            font_style : style.lookup_string("font-style", "normal"),
-            font_size: style.lookup_font_size(),
+            color: Some(style.color.clone()),
+            font_family: style.find_font_family(font_cache),
+            font_weight : style.font_weight,
+            font_style : style.font_style,
+            font_size: style.font_size,
         }
     }
 
-    fn get_type(&self) -> String {
+    fn get_type(&self, doc:&Document) -> String {
         match &self.box_type {
             BoxType::AnonymousBlock(styled)
             | BoxType::ListItemNode(styled)
@@ -480,12 +557,12 @@ impl LayoutBox {
             | BoxType::TableRowNode(styled)
             | BoxType::TableCellNode(styled)
             | BoxType::InlineBlockNode(styled)
-            | BoxType::InlineNode(styled) => format!("{:#?}",styled.node.node_type)
+            | BoxType::InlineNode(styled) => format!("{:#?}",doc.node(styled.node).node_type)
         }
     }
 
     fn layout_anonymous_2(&mut self, dim:&mut Dimensions, font_cache:&mut FontCache, doc:&Document) -> RenderAnonymousBox {
-        // println!("parent is {:#?}",self.get_type());
+        // println!("parent is {:#?}",self.get_type(doc));
         // println!("parent style node is {:#?}",self.get_style_node());
         let mut looper = Looper {
             lines: vec![],
@@ -511,9 +588,10 @@ impl LayoutBox {
             font_cache:font_cache,
             doc,
             style_node:Rc::clone(self.get_style_node()),
+            pending_space: false,
         };
         for child in self.children.iter_mut() {
-            // println!("working on child {:#?}", child.get_type());
+            // println!("working on child {:#?}", child.get_type(doc));
             // println!("current start and end is {} {} ",looper.current_start, looper.current_end);
             match &child.box_type {
                 InlineBlockNode(_styled) => child.do_inline_block(&mut looper),
@@ -544,11 +622,15 @@ impl LayoutBox {
     }
 
     fn do_inline_block(&mut self, looper:&mut Looper) {
+        looper.place_pending_space();
         let mut image_size = Rect { x:0.0, y:0.0, width: 30.0, height:30.0};
         let mut src = String::from("");
         // let w = 100.0;
         if let InlineBlockNode(styled) = &self.box_type {
-            if let Element(data) = &styled.node.node_type {
+            if styled.pseudo_element.is_some() {
+                return;
+            }
+            if let Element(data) = &looper.doc.node(styled.node).node_type {
                 match data.tag_name.as_str() {
                     "img" => {
                         let width = if data.attributes.contains_key("width") {
@@ -568,17 +650,20 @@ impl LayoutBox {
                     "button" => {
                         // let font_family = self.find_font_family(looper.font_cache);
                         let font_family = "sans-serif";
-                        let font_weight = self.get_style_node().lookup_font_weight(400);
-                        let font_size = self.get_style_node().lookup_font_size();
-                        let font_style = self.get_style_node().lookup_string("font-style", "normal");
+                        let font_weight = styled.style.font_weight;
+                        let font_size = styled.style.font_size;
+                        let font_style = styled.style.font_style;
                         // println!("button font size is {}",font_size);
                         // let font = looper.font_cache.get_font(&font_family, font_weight, &font_style);
-                        let text_node = &styled.children.borrow()[0].node;
-                        let text = match &text_node.node_type {
+                        let text_node = match styled.children.borrow().iter().find(|ch| ch.pseudo_element.is_none()) {
+                            Some(ch) => ch.node,
+                            None => return,
+                        };
+                        let text = match &looper.doc.node(text_node).node_type {
                             NodeType::Text(str) => str,
                             _ => panic!("can't do inline block layout if child isn't text"),
                         };
-                        let w: f32 = calculate_word_length(&text, looper.font_cache, font_size, &font_family, font_weight, &font_style);
+                        let w: f32 = calculate_word_length(&text, looper.font_cache, font_size, &font_family, font_weight, font_style.as_str());
                         // println!("calculated width is {}",w);
                         looper.current_end += w;
                         let mut containing_block = Dimensions {
@@ -610,31 +695,17 @@ impl LayoutBox {
         let bx = match load_image(looper.doc, &src) {
             Ok(image) => {
                 println!("Loaded the image {} {}", image.width, image.height);
-                let width_prop = self.get_style_node().lookup("width", "max-width", &Value::Keyword(String::from("auto")));
-                let mut width = image.width as f32;
-                //calculate width as a percentage
-                if let Value::Length(v,u) = width_prop {
-                    match u {
-                        Unit::Per => width = looper.extents.width * v/100.0,
-                        _ => { }
-                    }
-                }
-                let mut height = image.height as f32;
-                let height_prop = self.get_style_node().lookup("height","height",&Value::Keyword(String::from("auto")));
-                //calculate height as a percentage
-                if let Value::Length(v,u) = &height_prop {
-                    match u {
-                        Unit::Per => height = looper.extents.height * v/100.0,
-                        _ => {  }
-                    }
-                }
-                //calculate height from width to preserve aspect ratio
-                if let Value::Keyword(keyword) = &height_prop {
-                    match keyword.as_str() {
-                        "auto" => height = width * (image.height as f32) / (image.width as f32),
-                        _ => {}
-                    }
-                }
+                let style = &self.get_style_node().style;
+                let width_prop = if style.width == Size::Auto { &style.max_width } else { &style.width };
+                let width = match width_prop {
+                    Size::Auto => image.width as f32,
+                    size => size.to_px(looper.extents.width),
+                };
+                let height = match &style.height {
+                    //calculate height from width to preserve aspect ratio
+                    Size::Auto => width * (image.height as f32) / (image.width as f32),
+                    size => size.to_px(looper.extents.height),
+                };
                 RenderInlineBoxType::Image(RenderImageBox {
                     rect: Rect {
                         x:looper.current_start,
@@ -642,7 +713,7 @@ impl LayoutBox {
                         width,
                         height,
                     },
-                    valign: self.get_style_node().lookup_string("vertical-align","baseline"),
+                    valign: self.get_style_node().style.vertical_align,
                     image
                 })
             },
@@ -655,7 +726,7 @@ impl LayoutBox {
                         width: image_size.width,
                         height: image_size.height,
                     },
-                    valign: self.get_style_node().lookup_string("vertical-align","baseline"),
+                    valign: self.get_style_node().style.vertical_align,
                 })
             }
         };
@@ -670,133 +741,90 @@ impl LayoutBox {
         }
     }
 
-    fn do_pre_layout(&self, looper:&mut Looper, txt:&str, link:&Option<String>) {
-        let color = looper.style_node.lookup_color("color", &BLACK);
-        let font_size = looper.style_node.lookup_font_size();
-        // println!("font size is {:#?} ",font_size, color);
-        let font_family = looper.style_node.lookup_font_family(looper.font_cache);
-
-        let font_weight = looper.style_node.lookup_font_weight(400);
-        let font_style = looper.style_node.lookup_string("font-style", "normal");
-        let valign = looper.style_node.lookup_string("vertical-align", "baseline");
-        for line in txt.split_terminator('\n') {
-            let bounds = calculate_text_bounds(line, looper.font_cache, font_size, &font_family, font_weight, &font_style);
-            if let Some(bounds) = bounds {
-                let bx = RenderInlineBoxType::Text(RenderTextBox {
-                    rect: Rect {
-                        x: looper.current_start + bounds.min.x,
-                        y: looper.current_bottom + bounds.min.y,
-                        width: looper.extents.width,
-                        height: font_size
-                    },
-                    text: line.to_string(),
-                    color: Some(color.clone()),
-                    background_color: looper.style_node.color("background-color"),
-                    font_size,
-                    font_family: font_family.clone(),
-                    link: link.clone(),
-                    font_weight,
-                    font_style:font_style.clone(),
-                    valign:valign.clone(),
-                    text_decoration_line: looper.style_node.lookup_text_decoration_line(),
-                });
-                looper.add_box_to_current_line(bx);
-                looper.current_bottom += looper.current.rect.height;
-                looper.extents.height += looper.current.rect.height;
-                looper.adjust_current_line_vertical();
-                looper.adjust_current_line_horizontal();
-                looper.start_new_line();
-            }
-        }
-    }
-
-    fn do_normal_inline_layout(&self, looper:&mut Looper, txt:&str, link:&Option<String>) {
-        // println!("processing text '{}'", txt);
-        let font_family = looper.style_node.lookup_font_family(looper.font_cache);
-        // println!("using font family {}", font_family);
-        let font_weight = looper.style_node.lookup_font_weight(400);
-        let font_size = looper.style_node.lookup_font_size();
-        let font_style = looper.style_node.lookup_string("font-style", "normal");
-        let vertical_align = looper.style_node.lookup_string("vertical-align","baseline");
+    // lays out a run of text with the white-space rules of the enclosing element.
+    // a collapsible space is held in the looper until the next word, so spaces
+    // collapse across element boundaries and never start or end a line.
+    fn do_text_layout(&self, looper:&mut Looper, txt:&str, link:&Option<String>) {
+        let style = looper.style_node.style.clone();
+        let white_space = style.white_space;
+        let font_family = style.find_font_family(looper.font_cache);
+        let font_weight = style.font_weight;
+        let font_size = style.font_size;
+        let font_style = style.font_style;
         let line_height = font_size;
         // let line_height = looper.style_node.lookup_length_px("line-height", line_height);
-        let color = looper.style_node.lookup_color("color", &BLACK);
-        // println!("text is family={:#?} size={} weight={} style={} line-height={}", font_family,  font_size, font_weight, font_style, line_height);
-        // println!("styles={:#?}",looper.style_node);
-        // println!("parent={:#?}", parent.get_style_node());
-        // println!("looper is {} {} {}",looper.current_start, looper.current_end, looper.current_start);
-        let mut curr_text = String::new();
-        for word in txt.split_whitespace() {
-            let mut word2 = String::from(" ");
-            word2.push_str(word);
-            let w: f32 = calculate_word_length(word2.as_str(), looper.font_cache, font_size, &font_family, font_weight, &font_style);
-            //if it's too long then we need to wrap
-            // println!("end = {} w = {} extents.width = {}", looper.current_end, w, looper.extents.x + looper.extents.width);
-            if looper.current_end + w > looper.extents.x + looper.extents.width {
-                //add current text to the current line
-                // println!("wrapping: {} cb = {}", curr_text, looper.current_bottom);
-                let bx = RenderInlineBoxType::Text(RenderTextBox{
-                    rect: Rect{
-                        x: looper.current_start,
-                        y: looper.current_bottom,
-                        width: looper.current_end - looper.current_start,
-                        height: line_height
-                    },
-                    text: curr_text,
-                    color: Some(color.clone()),
-                    background_color: looper.style_node.color("background-color"),
-                    font_size,
-                    font_family: font_family.clone(),
-                    font_style: font_style.clone(),
-                    link: link.clone(),
-                    font_weight,
-                    valign: vertical_align.clone(),
-                    text_decoration_line: looper.style_node.lookup_text_decoration_line(),
-                });
-                looper.add_box_to_current_line(bx);
-                //make new current text with the current word
-                curr_text = String::new();
-                curr_text.push_str(&word2);
-                curr_text.push_str(" ");
-                looper.current_bottom += looper.current.rect.height;
-                looper.extents.height += looper.current.rect.height;
-                looper.adjust_current_line_vertical();
-                looper.adjust_current_line_horizontal();
-                looper.start_new_line();
-                looper.current_end += w;
-            } else {
-                looper.current_end += w;
-                curr_text.push_str(&word2);
-            }
-        }
-        let bx = RenderInlineBoxType::Text(RenderTextBox{
+        let make_box = |looper:&Looper, text:String| RenderInlineBoxType::Text(RenderTextBox {
             rect: Rect {
                 x: looper.current_start,
                 y: looper.current_bottom,
                 width: looper.current_end - looper.current_start,
                 height: line_height,
             },
-            text: curr_text,
-            color: Some(color.clone()),
-            background_color: looper.style_node.color("background-color"),
+            text,
+            node: looper.style_node.node,
+            color: Some(style.color.clone()),
+            background_color: style.background_color.clone(),
             font_size,
-            font_family,
+            font_family: font_family.clone(),
             link: link.clone(),
             font_weight,
             font_style,
-            valign: vertical_align.clone(),
-            text_decoration_line: looper.style_node.lookup_text_decoration_line(),
+            valign: style.vertical_align,
+            text_decoration_line: style.text_decoration_line,
         });
-        // println!("added text box {:#?}",bx);
-        looper.add_box_to_current_line(bx);
+        let measure = |looper:&mut Looper, text:&str| text_advance(text, looper.font_cache, font_size, &font_family, font_weight, font_style.as_str());
+        let limit = looper.extents.x + looper.extents.width;
+        let mut curr_text = String::new();
+        for (index, line) in white_space.process(txt).iter().enumerate() {
+            if index > 0 {
+                //a preserved newline. an empty line still takes up a line of height.
+                if !curr_text.is_empty() || looper.current.children.is_empty() {
+                    let bx = make_box(looper, mem::take(&mut curr_text));
+                    looper.add_box_to_current_line(bx);
+                }
+                looper.break_line();
+            }
+            for (word, fit, space) in split_words(line, white_space) {
+                if space {
+                    if !curr_text.is_empty() || !looper.current.children.is_empty() {
+                        looper.pending_space = true;
+                    }
+                    continue;
+                }
+                let mut piece = String::new();
+                if looper.pending_space {
+                    piece.push(' ');
+                }
+                piece.push_str(word);
+                let line_has_content = !curr_text.is_empty() || !looper.current.children.is_empty();
+                let width = measure(looper, fit);
+                let space_width = if looper.pending_space { measure(looper, " ") } else { 0.0 };
+                if white_space.wraps() && line_has_content && looper.current_end + space_width + width > limit {
+                    if !curr_text.is_empty() {
+                        let bx = make_box(looper, mem::take(&mut curr_text));
+                        looper.add_box_to_current_line(bx);
+                    }
+                    looper.break_line();
+                    piece = word.to_string();
+                }
+                looper.pending_space = false;
+                looper.current_end += measure(looper, &piece);
+                curr_text.push_str(&piece);
+            }
+        }
+        if !curr_text.is_empty() {
+            let bx = make_box(looper, curr_text);
+            looper.add_box_to_current_line(bx);
+        }
     }
 
     fn do_inline(&self, looper:&mut Looper) {
-        // println!("doing inline {:#?}", &self.debug_calculate_element_name());
-        let link:Option<String> = match &looper.style_node.node.node_type {
+        // println!("doing inline {:#?}", &self.debug_calculate_element_name(looper.doc));
+        let link:Option<String> = match &looper.doc.node(looper.style_node.node).node_type {
             Text(_) => None,
             NodeType::Comment(_) => None,
             NodeType::Cdata(_) => None,
+            NodeType::Doctype(_) => None,
             Element(ed) => {
                 if ed.tag_name == "a" {
                     ed.attributes.get("href").map(String::from)
@@ -807,22 +835,13 @@ impl LayoutBox {
             NodeType::Meta(_) => None,
         };
         if let BoxType::InlineNode(snode) = &self.box_type {
-            match &snode.node.node_type {
-                 NodeType::Text(txt) => {
-                     let whitespace = looper.style_node.lookup_keyword("white-space", &Keyword(String::from("normal")));
-                     // println!("laying out using whitespace {:#?}", whitespace);
-                     match whitespace {
-                         Keyword(str) => {
-                             match &*str {
-                                 "pre" => return self.do_pre_layout(looper,txt,&link),
-                                 _ => return self.do_normal_inline_layout(looper,txt,&link),
-                             }
-                         },
-                         _ => {
-                             println!("invalid whitespace type");
-                         }
-                     }
-                }
+            // the text of a ::before or ::after
+            if let Some(txt) = &snode.text {
+                self.do_text_layout(looper, txt, &link);
+                return;
+            }
+            match &looper.doc.node(snode.node).node_type {
+                NodeType::Text(txt) => self.do_text_layout(looper, txt, &link),
                 //     if child is element
                 NodeType::Element(_ed) => {
                     // println!("recursing");
@@ -845,36 +864,31 @@ impl LayoutBox {
     ///
     /// Sets the horizontal margin/padding/border dimensions, and the `width`.
     fn calculate_block_width(&mut self, containing:&mut Dimensions) {
-        let style = self.get_style_node();
+        let style = &self.get_style_node().style;
+        // percentages are of the width of the containing block
+        let px = |size:&Size| size.to_px(containing.content.width);
 
         // 'width' has initial value 'auto'
-        let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or_else(||auto.clone());
-        // println!("width set to {:#?}",width);
-        //width percentage
-        if let Length(per, Unit::Per) = width {
-            // println!("its a percentage width {} {}",per,containing.content.width);
-            width = Length(containing.content.width*(per/100.0), Px);
-        }
+        let auto = Size::Auto;
+        let mut width = style.width.clone();
 
         // margin, border, and padding have initial value of 0
-        let zero = Length(0.0, Px);
-        let mut margin_left = style.lookup("margin-left","margin", &zero);
-        let mut margin_right = style.lookup("margin-right","margin", &zero);
-        let border_left = style.lookup("border-width-left","border-width", &zero);
-        let border_right = style.lookup("border-width-right","border-width", &zero);
-        let padding_left = style.lookup("padding-left","padding", &zero);
-        let padding_right = style.lookup("padding-right","padding", &zero);
+        let mut margin_left = style.margin_left.clone();
+        let mut margin_right = style.margin_right.clone();
+        let border_left = style.border_left_width;
+        let border_right = style.border_right_width;
+        let padding_left = px(&style.padding_left);
+        let padding_right = px(&style.padding_right);
 
         // If width is not auto and the total is wider than the container, treat auto margins as 0.
-        let total = sum([&margin_left, &margin_right, &border_left, &border_right,
-            &padding_left, &padding_right, &width].iter().map(|v| self.length_to_px(v)));
+        let total = sum([px(&margin_left), px(&margin_right), border_left, border_right,
+            padding_left, padding_right, px(&width)].iter().copied());
         if width != auto && total > containing.content.width {
             if margin_left == auto {
-                margin_left = Length(0.0, Px);
+                margin_left = Size::Px(0.0);
             }
             if margin_right == auto {
-                margin_right = Length(0.0,Px);
+                margin_right = Size::Px(0.0);
             }
         }
 
@@ -886,78 +900,54 @@ impl LayoutBox {
 
         match (width == auto, margin_left == auto, margin_right == auto) {
             (false,false,false) => {
-                margin_right = Length(self.length_to_px(&margin_right) + underflow, Px);
+                margin_right = Size::Px(px(&margin_right) + underflow);
             }
-            (false,false,true) => { margin_right = Length(underflow, Px); }
-            (false,true,false) => { margin_left = Length(underflow, Px); }
+            (false,false,true) => { margin_right = Size::Px(underflow); }
+            (false,true,false) => { margin_left = Size::Px(underflow); }
             (true, _, _) => {
-                if margin_left == auto { margin_left = Length(0.0, Px); }
-                if margin_right == auto { margin_right = Length(0.0, Px); }
+                if margin_left == auto { margin_left = Size::Px(0.0); }
+                if margin_right == auto { margin_right = Size::Px(0.0); }
                 if underflow >= 0.0 {
-                    width = Length(underflow, Px);
+                    width = Size::Px(underflow);
                 } else {
-                    width = Length(0.0, Px);
-                    margin_right = Length(self.length_to_px(&margin_right) + underflow, Px);
+                    width = Size::Px(0.0);
+                    margin_right = Size::Px(px(&margin_right) + underflow);
                 }
             }
             (false, true, true) => {
-                margin_left = Length(underflow / 2.0, Px);
-                margin_right = Length(underflow / 2.0, Px);
+                margin_left = Size::Px(underflow / 2.0);
+                margin_right = Size::Px(underflow / 2.0);
             }
         }
-        // println!("final margin left is {:#?}",margin_left);
-        // println!("width set to {:#?}",width);
-
-        self.dimensions.content.width = self.length_to_px(&width);
-        self.dimensions.padding.left = self.length_to_px(&padding_left);
-        self.dimensions.padding.right = self.length_to_px(&padding_right);
-        self.dimensions.border.left = self.length_to_px(&border_left);
-        self.dimensions.border.right = self.length_to_px(&border_right);
-        self.dimensions.margin.left = self.length_to_px_size(&margin_left, &width);
-        self.dimensions.margin.right = self.length_to_px_size(&margin_right,&width);
-        // println!("final width is width= {} padding = {} margin: {}",
-        //          self.dimensions.content.width,
-        //          self.dimensions.padding.left,
-        //          self.dimensions.margin.left);
-    }
 
-    fn length_to_px_size(&self, value:&Value, dimension:&Value) -> f32 {
-        match value {
-            Length(v, Unit::Per) => self.length_to_px(dimension)*v/100.0,
-            _ => self.length_to_px(value),
-        }
-    }
-    fn length_to_px(&self, value:&Value) -> f32{
-        let font_size = self.get_style_node().lookup_font_size();
-        match value {
-            Length(v, Unit::Px) => *v,
-            Length(v, Unit::Em) => (*v)*font_size,
-            Length(v, Unit::Rem) => (*v)*font_size, // TODO: use real document font size
-            Length(_v, Unit::Per) => {
-                println!("WARNING: percentage in length_to_px. should have be converted to pixels already");
-                0.0
-            }
-            _ => {0.0}
-        }
+        let d = &mut self.dimensions;
+        d.content.width = px(&width);
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
+        d.border.left = border_left;
+        d.border.right = border_right;
+        d.margin.left = px(&margin_left);
+        d.margin.right = px(&margin_right);
     }
+
     fn calculate_block_position(&mut self, containing: &mut Dimensions) {
-        let zero = Length(0.0, Px);
-        let style = self.get_style_node();
-        //println!("caculating block position {:#?} border {:#?}",style, style.lookup("border-width-top","border-width",&zero));
+        let style = &self.get_style_node().style;
+        // vertical percentages are of the width of the containing block too
+        let px = |size:&Size| size.to_px(containing.content.width);
         let margin = EdgeSizes {
-            top: style.lookup_length_as_px("margin-top",0.0),
-            bottom: style.lookup_length_as_px("margin-bottom",0.0),
+            top: px(&style.margin_top),
+            bottom: px(&style.margin_bottom),
             ..(self.dimensions.margin)
         };
 
         let border = EdgeSizes {
-            top: style.lookup_length_as_px("border-width-top",0.0),
-            bottom: style.lookup_length_as_px("border-width-bottom",0.0),
+            top: style.border_top_width,
+            bottom: style.border_bottom_width,
             ..(self.dimensions.border)
         };
         let padding = EdgeSizes {
-            top: style.lookup_length_as_px("padding-top",0.0),
-            bottom: style.lookup_length_as_px("padding-bottom",0.0),
+            top: px(&style.padding_top),
+            bottom: px(&style.padding_bottom),
             ..(self.dimensions.padding)
         };
 
@@ -980,9 +970,10 @@ impl LayoutBox {
         children
     }
 
+    // a percentage of a height that depends on the content is treated as auto
     fn calculate_block_height(&mut self) {
-        if let Some(val) = self.get_style_node().value("height") {
-            self.dimensions.content.height = self.length_to_px(&val);
+        if let Size::Px(height) = self.get_style_node().style.height {
+            self.dimensions.content.height = height;
         }
     }
 
@@ -1003,16 +994,68 @@ fn calculate_word_length(text:&str, fc:&mut FontCache, font_size:f32, font_famil
         None => 0.0,
     }
 }
-fn calculate_text_bounds(text:&str, fc:&mut FontCache, font_size:f32, font_family:&str, font_weight:i32, font_style:&str) -> Option<GBRect<f32>> {
-    let scale = Scale::uniform(font_size  as f32);
-    let font = fc.lookup_font(font_family,font_weight, font_style);
-    let sec = Section {
-        text,
-        scale,
-        font_id: *font,
-        ..Section::default()
-    };
-    fc.brush.glyph_bounds(sec)
+// the distance the pen moves for the text. unlike the glyph bounds this
+// includes trailing spaces.
+fn text_advance(text:&str, fc:&mut FontCache, font_size:f32, font_family:&str, font_weight:i32, font_style:&str) -> f32 {
+    if text.is_empty() {
+        return 0.0;
+    }
+    let with_end = format!("{}x", text);
+    calculate_word_length(&with_end, fc, font_size, font_family, font_weight, font_style)
+        - calculate_word_length("x", fc, font_size, font_family, font_weight, font_style)
+}
+
+// breaks a line of processed text into the pieces that line wrapping works
+// with: the piece, the part of it that has to fit on the line, and whether
+// it is a collapsible space.
+fn split_words(line:&str, white_space:WhiteSpace) -> Vec<(&str, &str, bool)> {
+    let mut words = vec![];
+    match white_space {
+        WhiteSpace::Pre => if !line.is_empty() { words.push((line, line, false)) },
+        WhiteSpace::Normal | WhiteSpace::NoWrap | WhiteSpace::PreLine => {
+            let mut start = 0;
+            for (i, ch) in line.char_indices() {
+                if ch == ' ' {
+                    if i > start { words.push((&line[start..i], &line[start..i], false)); }
+                    words.push((" ", " ", true));
+                    start = i + 1;
+                }
+            }
+            if start < line.len() { words.push((&line[start..], &line[start..], false)); }
+        }
+        WhiteSpace::PreWrap => {
+            //spaces stay with the word before them and hang past the end of the line
+            let mut start = 0;
+            let mut in_spaces = false;
+            for (i, ch) in line.char_indices() {
+                if ch == ' ' {
+                    in_spaces = true;
+                } else if in_spaces {
+                    let piece = &line[start..i];
+                    words.push((piece, piece.trim_end_matches(' '), false));
+                    start = i;
+                    in_spaces = false;
+                }
+            }
+            if start < line.len() {
+                let piece = &line[start..];
+                words.push((piece, piece.trim_end_matches(' '), false));
+            }
+        }
+        WhiteSpace::BreakSpaces => {
+            //every space is a place to wrap and takes up room
+            let mut start = 0;
+            for (i, ch) in line.char_indices() {
+                if ch == ' ' {
+                    if i > start { words.push((&line[start..i], &line[start..i], false)); }
+                    words.push((" ", " ", false));
+                    start = i + 1;
+                }
+            }
+            if start < line.len() { words.push((&line[start..], &line[start..], false)); }
+        }
+    }
+    words
 }
 
 struct Looper<'a> {
@@ -1025,6 +1068,8 @@ struct Looper<'a> {
     font_cache:&'a mut FontCache,
     doc: &'a Document,
     style_node: Rc<StyledNode>,
+    // a collapsed space waiting for the next word on this line
+    pending_space: bool,
 }
 
 impl Looper<'_> {
@@ -1042,6 +1087,25 @@ impl Looper<'_> {
         self.lines.push(old);
         self.current_start = self.extents.x;
         self.current_end = self.extents.x;
+        self.pending_space = false;
+    }
+    // an inline box takes the place of the next word, so it gets the waiting space
+    fn place_pending_space(&mut self) {
+        if self.pending_space {
+            let style = &self.style_node.style;
+            let font_family = style.find_font_family(self.font_cache);
+            let (font_size, font_weight, font_style) = (style.font_size, style.font_weight, style.font_style);
+            self.current_end += text_advance(" ", self.font_cache, font_size, &font_family, font_weight, font_style.as_str());
+            self.current_start = self.current_end;
+            self.pending_space = false;
+        }
+    }
+    fn break_line(&mut self) {
+        self.current_bottom += self.current.rect.height;
+        self.extents.height += self.current.rect.height;
+        self.adjust_current_line_vertical();
+        self.adjust_current_line_horizontal();
+        self.start_new_line();
     }
     fn add_box_to_current_line(&mut self, bx:RenderInlineBoxType) {
         let rect = match &bx {
@@ -1056,82 +1120,95 @@ impl Looper<'_> {
     }
     fn adjust_current_line_vertical(&mut self) {
         for ch in self.current.children.iter_mut() {
-            let (mut rect, string) =  match ch {
+            let (mut rect, valign) =  match ch {
                 RenderInlineBoxType::Text(bx)    => (&mut bx.rect,&bx.valign),
                 RenderInlineBoxType::Error(bx)  => (&mut bx.rect,&bx.valign),
                 RenderInlineBoxType::Image(bx) => (&mut bx.rect,&bx.valign),
                 RenderInlineBoxType::Block(bx)  => (&mut bx.rect,&bx.valign),
             };
-            match string.as_str() {
-                "bottom" => {
+            match valign {
+                VerticalAlign::Bottom => {
                     rect.y = self.current.rect.y + self.current.rect.height - rect.height;
                 },
-                "sub" => {
+                VerticalAlign::Sub => {
                     rect.y = self.current.rect.y + self.current.rect.height - rect.height + 10.0;
                 },
-                "baseline" => {
+                VerticalAlign::Baseline => {
                     rect.y = self.current.rect.y + self.current.rect.height - rect.height;
                 },
-                "super" => {
+                VerticalAlign::Super => {
                     rect.y = self.current.rect.y + self.current.rect.height - rect.height - 10.0;
                 },
-                "middle" => {
+                VerticalAlign::Middle => {
                     rect.y = self.current.rect.y + (self.current.rect.height - rect.height)/2.0;
                 },
-                "top" => {
+                VerticalAlign::Top => {
                     rect.y = self.current.rect.y;
                 },
-                _ => {}
             }
         }
     }
     fn adjust_current_line_horizontal(&mut self) {
-        let text_align = self.style_node.lookup_keyword("text-align",&Value::Keyword(String::from("none")));
-        // println!("fixing horiz {:#?}", text_align);
-        if let Keyword(text_align) = text_align {
-            match text_align.as_str() {
-                "center" => {
-                    println!("going to center it. line.x={} line.width={}",
-                             self.current.rect.x, self.current.rect.width);
-                    let mut left:f32 =   10000.0;
-                    let mut right:f32 = -10000.0;
-                    for ch in self.current.children.iter_mut() {
-                        if let RenderInlineBoxType::Text(ch) = ch {
-                            left = left.min(ch.rect.x);
-                            right = right.max(ch.rect.x+ch.rect.width);
-                        }
+        match self.style_node.style.text_align {
+            TextAlign::Center => {
+                println!("going to center it. line.x={} line.width={}",
+                         self.current.rect.x, self.current.rect.width);
+                let mut left:f32 =   10000.0;
+                let mut right:f32 = -10000.0;
+                for ch in self.current.children.iter_mut() {
+                    if let RenderInlineBoxType::Text(ch) = ch {
+                        left = left.min(ch.rect.x);
+                        right = right.max(ch.rect.x+ch.rect.width);
                     }
-                    let shift_x = (self.current.rect.width - (right-left))/2.0;
-                    for ch in self.current.children.iter_mut() {
-                        if let RenderInlineBoxType::Text(ch) = ch {
-                            ch.rect.x += shift_x;
-                        }
+                }
+                let shift_x = (self.current.rect.width - (right-left))/2.0;
+                for ch in self.current.children.iter_mut() {
+                    if let RenderInlineBoxType::Text(ch) = ch {
+                        ch.rect.x += shift_x;
                     }
                 }
-                "right" => {
-                    let mut left:f32 =   10000.0;
-                    let mut right:f32 = -10000.0;
-                    for ch in self.current.children.iter_mut() {
-                        if let RenderInlineBoxType::Text(ch) = ch {
-                            left = left.min(ch.rect.x);
-                            right = right.max(ch.rect.x+ch.rect.width);
-                        }
+            }
+            TextAlign::Right => {
+                let mut left:f32 =   10000.0;
+                let mut right:f32 = -10000.0;
+                for ch in self.current.children.iter_mut() {
+                    if let RenderInlineBoxType::Text(ch) = ch {
+                        left = left.min(ch.rect.x);
+                        right = right.max(ch.rect.x+ch.rect.width);
                     }
-                    let shift_x = self.current.rect.width - (right-left);
-                    for ch in self.current.children.iter_mut() {
-                        if let RenderInlineBoxType::Text(ch) = ch {
-                            ch.rect.x += shift_x;
-                        }
+                }
+                let shift_x = self.current.rect.width - (right-left);
+                for ch in self.current.children.iter_mut() {
+                    if let RenderInlineBoxType::Text(ch) = ch {
+                        ch.rect.x += shift_x;
                     }
-
                 }
-                _ => {}
+
             }
+            _ => {}
         }
     }
 
 }
 
+fn border_widths(style:&ComputedStyle) -> EdgeSizes {
+    EdgeSizes {
+        top: style.border_top_width,
+        bottom: style.border_bottom_width,
+        left: style.border_left_width,
+        right: style.border_right_width,
+    }
+}
+
+fn border_colors(style:&ComputedStyle) -> EdgeColors {
+    EdgeColors {
+        top: style.border_top_color.clone(),
+        bottom: style.border_bottom_color.clone(),
+        left: style.border_left_color.clone(),
+        right: style.border_right_color.clone(),
+    }
+}
+
 fn sum<I>(iter: I) -> f32 where I: Iterator<Item=f32> {
     iter.fold(0., |a, b| a + b)
 }
@@ -1177,11 +1254,10 @@ pub fn standard_test_run_no_default(html:&[u8], css:&[u8]) -> Result<(Document,
         fonts: Default::default()
     };
 
-    let mut doc = load_doc_from_bytestring(html);
-    strip_empty_nodes(&mut doc);
+    let mut doc = load_doc_from_bytestring(html)?;
     let mut stylesheets = StylesheetSet::new();
     let mut stylesheets = load_stylesheets_new(&doc, &mut font_cache)?;
-    let styled = dom_tree_to_stylednodes(&doc.root_node, &stylesheets);
+    let styled = dom_tree_to_stylednodes(&doc, &stylesheets, &MediaContext::new(500.0, 500.0));
     // println!("styled nodes {:#?}",styled);
     let mut viewport = Dimensions {
         content: Rect {
@@ -1218,11 +1294,10 @@ pub fn standard_test_run(html:&[u8], css:&[u8]) -> Result<(Document, StylesheetS
         fonts: Default::default()
     };
 
-    let mut doc = load_doc_from_bytestring(html);
-    strip_empty_nodes(&mut doc);
+    let mut doc = load_doc_from_bytestring(html)?;
     let mut stylesheets = load_stylesheets_new(&doc, &mut font_cache)?;
     stylesheets.append_from_bytestring(&mut font_cache, css);
-    let styled = dom_tree_to_stylednodes(&doc.root_node, &stylesheets);
+    let styled = dom_tree_to_stylednodes(&doc, &stylesheets, &MediaContext::new(500.0, 500.0));
     // println!("styled nodes {:#?}",styled);
     let mut viewport = Dimensions {
         content: Rect {
@@ -1247,14 +1322,24 @@ pub fn standard_test_run(html:&[u8], css:&[u8]) -> Result<(Document, StylesheetS
     Ok((doc,stylesheets,styled,root_box,render_box))
 }
 
+// the parser always creates html, head, and body. head isn't rendered so the
+// body is the first child of the html block.
+#[cfg(test)]
+fn body_render_box(rbox:RenderBox) -> RenderBox {
+    match rbox {
+        RenderBox::Block(mut html) => html.children.remove(0),
+        _ => panic!("the html element should render as a block"),
+    }
+}
+
 #[test]
 fn test_insets() {
     let (doc,sss,stree,lbox, rbox) = standard_test_run(
         br#"<body></body>"#,
-        br#"body { display:block; margin: 50px; padding: 50px; border-width: 50px; } "#
+        br#"body { display:block; margin: 50px; padding: 50px; border-width: 50px; border-style: solid; } "#
     ).unwrap();
     println!("it all ran! {:#?}",rbox);
-    match rbox {
+    match body_render_box(rbox) {
         RenderBox::Block(bx) => {
             assert_eq!(bx.margin.left,50.0);
             assert_eq!(bx.padding.left,50.0);
@@ -1268,6 +1353,27 @@ fn test_insets() {
 
 }
 
+#[test]
+fn test_border_sides() {
+    let (doc,sss,stree,lbox, rbox) = standard_test_run(
+        br#"<body></body>"#,
+        br#"body { display:block; margin: 0; border-width: 1px 2px 3px 4px; border-style: solid; } "#
+    ).unwrap();
+    match body_render_box(rbox) {
+        RenderBox::Block(bx) => {
+            assert_eq!(bx.border_width.top,1.0);
+            assert_eq!(bx.border_width.right,2.0);
+            assert_eq!(bx.border_width.bottom,3.0);
+            assert_eq!(bx.border_width.left,4.0);
+            assert_eq!(bx.rect.x,4.0);
+            assert_eq!(bx.content_area_as_rect().height, bx.rect.height + 4.0);
+        }
+        _ => {
+            panic!("this should have been a block box");
+        }
+    }
+}
+
 #[test]
 fn test_font_weight() {
     let (doc,sss,stree,lbox, rbox) = standard_test_run(
@@ -1332,14 +1438,19 @@ fn test_unordered_listitem() {
 fn test_margin_em() {
     let (doc,sss,stree,lbox, rbox) = standard_test_run(
         br#"<div>foo</div>"#,
-        br#"div {
+        br#"body { margin: 0; }
+        div {
             display:block;
             margin-left: 2.5em;
             font-size: 20px;
         }"#,
     ).unwrap();
     println!("ul render is {:#?}", rbox);
-    if let RenderBox::Block(rbx) = rbox {
+    if let RenderBox::Block(body) = body_render_box(rbox) {
+        let rbx = match &body.children[0] {
+            RenderBox::Block(rbx) => rbx,
+            _ => panic!("the div should render as a block"),
+        };
         assert_eq!(rbx.rect.x,50.0);
         assert_eq!(rbx.rect.width,450.0);
         assert_eq!(rbx.font_size,20.0);
@@ -1351,16 +1462,22 @@ fn test_margin_em() {
 fn test_margin_percentage() {
     let (doc,sss,stree,lbox, rbox) = standard_test_run(
         br#"<div>foo</div>"#,
-        br#"div {
+        br#"body { margin: 0; }
+        div {
             display:block;
             margin-left: 50%;
             font-size: 20px;
         }"#,
     ).unwrap();
     println!("ul render is {:#?}", rbox);
-    if let RenderBox::Block(rbx) = rbox {
+    if let RenderBox::Block(body) = body_render_box(rbox) {
+        let rbx = match &body.children[0] {
+            RenderBox::Block(rbx) => rbx,
+            _ => panic!("the div should render as a block"),
+        };
+        // the margin is a percentage of the containing block, which leaves half for the div
         assert_eq!(rbx.rect.x, 250.0);
-        assert_eq!(rbx.rect.width, 500.0);
+        assert_eq!(rbx.rect.width, 250.0);
         assert_eq!(rbx.font_size, 20.0);
     }
 }
@@ -1375,7 +1492,7 @@ fn test_text_position() {
         "#,
     ).unwrap();
     println!("body render is {:#?}",render_box);
-    if let RenderBox::Block(rbx) = render_box {
+    if let RenderBox::Block(rbx) = body_render_box(render_box) {
         assert_eq!(rbx.rect.y,0.0);
         assert_eq!(rbx.rect.height,14.0);
         if let RenderBox::Block(rbx) = &rbx.children[1] {
@@ -1404,3 +1521,69 @@ fn test_text_position() {
         panic!("invalid");
     }
 }
+
+// the text of every line box, in order
+#[cfg(test)]
+fn line_texts(rbox:&RenderBox) -> Vec<String> {
+    match rbox {
+        RenderBox::Block(bx) => bx.children.iter().flat_map(line_texts).collect(),
+        RenderBox::Anonymous(bx) => bx.children.iter()
+            .filter(|line| !line.children.is_empty())
+            .map(|line| line.children.iter().map(|ch| match ch {
+                RenderInlineBoxType::Text(txt) => txt.text.clone(),
+                _ => String::from("[box]"),
+            }).collect())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[test]
+fn test_space_between_inline_elements() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        b"<p><b>a</b> <span>b</span></p>\n<p>one <b> two </b>  three</p>",
+        br#"b, span { display: inline; }"#,
+    ).unwrap();
+    assert_eq!(line_texts(&rbox), vec!["a b", "one two three"]);
+}
+
+#[test]
+fn test_white_space_modes() {
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(
+        b"<pre>one\n\n  three\n</pre><div class=line>a   b\n   c</div><div class=wrap>x  y\tz</div>",
+        br#".line { white-space: pre-line; } .wrap { white-space: pre-wrap; }"#,
+    ).unwrap();
+    assert_eq!(line_texts(&rbox), vec!["one", "", "  three", "a b", "c", "x  y    z"]);
+    if let RenderBox::Block(body) = body_render_box(rbox) {
+        if let RenderBox::Block(pre) = &body.children[0] {
+            if let RenderBox::Anonymous(anon) = &pre.children[0] {
+                //the blank line still takes up room
+                assert!(anon.children[1].rect.height > 0.0);
+                assert!(anon.children[2].rect.y > anon.children[1].rect.y);
+            } else {
+                panic!("pre should contain lines");
+            }
+        } else {
+            panic!("pre should be a block");
+        }
+    }
+}
+
+#[test]
+fn test_nowrap() {
+    let text = b"<div>word word word word word word word word word word word word word word word word word word word word</div>";
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(text, b"").unwrap();
+    assert!(line_texts(&rbox).len() > 1);
+    let (_doc, _sss, _stree, _lbox, rbox) = standard_test_run(text, b"div { white-space: nowrap; }").unwrap();
+    assert_eq!(line_texts(&rbox).len(), 1);
+}
+
+#[test]
+fn test_split_words() {
+    assert_eq!(WhiteSpace::Normal.process(" a \n\t b "), vec![" a b "]);
+    assert_eq!(WhiteSpace::PreLine.process(" a  \n  b"), vec![" a", "b"]);
+    assert_eq!(WhiteSpace::Pre.process("a\tb\n\tc"), vec!["a       b", "        c"]);
+    assert_eq!(split_words(" a b", WhiteSpace::Normal), vec![(" ", " ", true), ("a", "a", false), (" ", " ", true), ("b", "b", false)]);
+    assert_eq!(split_words("a  b ", WhiteSpace::PreWrap), vec![("a  ", "a", false), ("b ", "b", false)]);
+    assert_eq!(split_words("a  b", WhiteSpace::BreakSpaces), vec![("a", "a", false), (" ", " ", false), (" ", " ", false), ("b", "b", false)]);
+}
@@ -1,6 +1,3 @@
-#[macro_use]
-extern crate lazy_static;
-
 pub mod render;
 pub mod layout;
 pub mod dom;
@@ -2,12 +2,14 @@
 extern crate glium;
 extern crate glium_glyph;
 
-use rust_minibrowser::layout::{Dimensions, Rect, RenderBox, QueryResult, RenderInlineBoxType, EdgeSizes, Brush, ListMarker};
-use rust_minibrowser::render::{FontCache};
+use rust_minibrowser::layout::{Dimensions, Rect, RenderBox, QueryResult, RenderInlineBoxType, EdgeSizes, EdgeColors, Brush, ListMarker};
+use rust_minibrowser::render::{FontCache, BLUE, MAGENTA};
+use rust_minibrowser::style::TextDecorationLine;
 use rust_minibrowser::net::{calculate_url_from_doc, BrowserError};
+use rust_minibrowser::dom::{Document, NodeId};
 
 
-use rust_minibrowser::app::{parse_args, navigate_to_doc, install_standard_fonts};
+use rust_minibrowser::app::{parse_args, navigate_to_doc, layout_doc, install_standard_fonts};
 
 use cgmath::{Matrix4, Vector3};
 use glium::glutin::{
@@ -28,8 +30,8 @@ use glium_glyph::glyph_brush::{Section,
                                rusttype::{
                                    Scale
                                }};
-use rust_minibrowser::css::Color;
-use std::collections::HashMap;
+use rust_minibrowser::css::{Color, MediaContext};
+use std::collections::{HashMap, HashSet};
 use glium::texture::{Texture2d, RawImage2d};
 use std::rc::Rc;
 
@@ -61,6 +63,9 @@ pub fn make_box(shape:&mut Vec<Vertex>, rect:&Rect, color:&Color) {
 }
 
 pub fn make_box2(shape:&mut Vec<Vertex>, x1:f32,y1:f32,x2:f32,y2:f32, color:&Color) {
+    if color.a == 0 {
+        return;
+    }
     shape.push(Vertex { position: [x1,  y1], color:color.to_array() });
     shape.push(Vertex { position: [ x2,  y1], color:color.to_array() });
     shape.push(Vertex { position: [ x2, y2], color:color.to_array() });
@@ -90,37 +95,44 @@ fn make_image_box2(images:&mut Vec<ImageRect>, x1:f32, y1:f32, x2:f32, y2:f32, t
 }
 
 
-pub fn make_border(shapes:&mut Vec<Vertex>, rect:&Rect, border_width:&EdgeSizes, color:&Color) {
-    // println!("making border {:#?} {:#?}",border_width,color);
+pub fn make_border(shapes:&mut Vec<Vertex>, rect:&Rect, border_width:&EdgeSizes, colors:&EdgeColors) {
+    // println!("making border {:#?} {:#?}",border_width,colors);
+    let mut side = |r:Rect, width:f32, color:&Option<Color>| {
+        if let Some(color) = color {
+            if width > 0.0 {
+                make_box(shapes, &r, color);
+            }
+        }
+    };
     //left
-    make_box(shapes, &Rect {
+    side(Rect {
         x: rect.x,
         y: rect.y,
         width: border_width.left,
         height: rect.height
-    }, color);
+    }, border_width.left, &colors.left);
     //right
-    make_box(shapes, &Rect {
+    side(Rect {
         x: rect.x + rect.width - border_width.right,
         y: rect.y,
         width: border_width.right,
         height: rect.height
-    }, color);
+    }, border_width.right, &colors.right);
 
     //top
-    make_box(shapes, &Rect {
+    side(Rect {
         x: rect.x,
         y: rect.y,
         width: rect.width,
         height: border_width.top
-    }, color);
+    }, border_width.top, &colors.top);
     //bottom
-    make_box(shapes, &Rect {
+    side(Rect {
         x: rect.x,
         y: rect.y+rect.height - border_width.bottom,
         width: rect.width,
         height: border_width.bottom
-    }, color);
+    }, border_width.bottom, &colors.bottom);
 }
 
 pub fn make_line(shapes:&mut Vec<Vertex>,rect:&Rect,yoff:f32,color:&Color) {
@@ -140,15 +152,12 @@ fn draw_render_box(bx:&RenderBox, gb:&mut FontCache, img:&mut HashMap<String, Rc
             if let Some(color) = &rbx.background_color {
                 make_box(shapes, &rbx.content_area_as_rect(), color);
             }
-            if rbx.border_color.is_some() {
-                let color = rbx.border_color.as_ref().unwrap();
-                make_border(shapes, &rbx.content_area_as_rect(), &rbx.border_width, &color);
-            }
+            make_border(shapes, &rbx.content_area_as_rect(), &rbx.border_width, &rbx.border_color);
             for ch in rbx.children.iter() {
                 draw_render_box(ch, gb, img,width, height, shapes, images, text_scale, display);
             }
             if let ListMarker::Disc = rbx.marker {
-                let font = gb.lookup_font(&rbx.font_family, rbx.font_weight, &rbx.font_style);
+                let font = gb.lookup_font(&rbx.font_family, rbx.font_weight, rbx.font_style.as_str());
                 let color = rbx.color.as_ref().unwrap().clone();
                 let section = Section{
                     text: "•",
@@ -169,14 +178,14 @@ fn draw_render_box(bx:&RenderBox, gb:&mut FontCache, img:&mut HashMap<String, Rc
         }
         RenderBox::Anonymous(bx) => {
             for lb in bx.children.iter() {
-                // make_box(shapes, &lb.rect, &Color::from_hex("#ff0000"));
+                // make_box(shapes, &lb.rect, &RED);
                 for inline in lb.children.iter() {
                     match inline {
                         RenderInlineBoxType::Text(text) => {
                             if text.color.is_some() && !text.text.is_empty() {
                                 let color = text.color.as_ref().unwrap().clone();
                                 let scale = Scale::uniform(text.font_size* text_scale);
-                                let font = gb.lookup_font(&text.font_family, text.font_weight, &text.font_style);
+                                let font = gb.lookup_font(&text.font_family, text.font_weight, text.font_style.as_str());
                                 let section = Section {
                                     text: &text.text.trim(),
                                     scale,
@@ -192,12 +201,12 @@ fn draw_render_box(bx:&RenderBox, gb:&mut FontCache, img:&mut HashMap<String, Rc
                                     ..Section::default()
                                 };
                                 gb.brush.queue(section);
-                                match text.text_decoration_line.as_str() {
-                                    "line-through" => make_line(shapes,&text.rect,-text.font_size*0.5,&color),
-                                    "underline" => make_line(shapes,&text.rect,-text.font_size*0.1,&color),
+                                match text.text_decoration_line {
+                                    TextDecorationLine::LineThrough => make_line(shapes,&text.rect,-text.font_size*0.5,&color),
+                                    TextDecorationLine::Underline => make_line(shapes,&text.rect,-text.font_size*0.1,&color),
                                     _ => {}
                                 }
-                                // make_box(shapes, &text.rect, &Color::from_hex("#ff00ff"));
+                                // make_box(shapes, &text.rect, &MAGENTA);
                             }
                             if text.background_color.is_some() {
                                 make_box(shapes, &text.rect, text.background_color.as_ref().unwrap());
@@ -214,13 +223,13 @@ fn draw_render_box(bx:&RenderBox, gb:&mut FontCache, img:&mut HashMap<String, Rc
                             }
                             let tex_ref:&Rc<Texture2d> = img.get(image.image.path.as_str()).unwrap();
                             make_image_box(images, &image.rect, &tex_ref);
-                            make_box(shapes, &image.rect, &Color::from_hex("#ff00ff"))
+                            make_box(shapes, &image.rect, &MAGENTA)
                         }
                         RenderInlineBoxType::Error(err) => {
-                            make_box(shapes, &err.rect, &Color::from_hex("#ff00ff"))
+                            make_box(shapes, &err.rect, &MAGENTA)
                         }
                         RenderInlineBoxType::Block(block) => {
-                            make_box(shapes, &block.rect, &Color::from_hex("#0000ff"))
+                            make_box(shapes, &block.rect, &BLUE)
                         }
                     }
                 }
@@ -230,6 +239,20 @@ fn draw_render_box(bx:&RenderBox, gb:&mut FontCache, img:&mut HashMap<String, Rc
     }
 }
 
+// the page title, or the address for pages without one
+fn window_title(doc:&Document) -> String {
+    doc.metadata().title
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| doc.base_url.to_string())
+}
+
+// the element under the mouse, for :hover and friends
+fn element_at(render_root:&RenderBox, position:PhysicalPosition<f64>) -> Option<NodeId> {
+    match render_root.find_box_containing((position.x / 2.0) as f32, (position.y / 2.0) as f32) {
+        QueryResult::Text(bx) => Some(bx.node),
+        QueryResult::None() => None,
+    }
+}
 
 fn main() -> Result<(),BrowserError>{
     let start_page = parse_args().unwrap();
@@ -265,7 +288,10 @@ fn main() -> Result<(),BrowserError>{
         border: Default::default(),
         margin: Default::default()
     };
-    let (mut doc, mut render_root) = navigate_to_doc(&start_page, &mut font_cache, containing_block).unwrap();
+    let mut media = MediaContext::new(screen_dims.0 as f32 / 2.0, screen_dims.1 as f32 / 2.0);
+    let mut visited:HashSet<url::Url> = HashSet::new();
+    let (mut doc, mut stylesheets, mut render_root) = navigate_to_doc(&start_page, &mut font_cache, containing_block, &media, &visited).unwrap();
+    display.gl_window().window().set_title(&window_title(&doc));
 
 
     let rect_vertex_shader_src = r#"
@@ -359,11 +385,27 @@ fn main() -> Result<(),BrowserError>{
                     device_id, position, modifiers
                 } => {
                     last_mouse = position;
+                    if doc.set_hovered(element_at(&render_root, last_mouse)) {
+                        render_root = layout_doc(&doc, &stylesheets, &mut font_cache, containing_block, &media);
+                    }
                 }
                 WindowEvent::MouseInput {
                     device_id, state, button, modifiers
                 } => {
                     // println!("mouse click {:#?}", button);
+                    if let MouseButton::Left = button {
+                        let target = match state {
+                            ElementState::Pressed => element_at(&render_root, last_mouse),
+                            ElementState::Released => None,
+                        };
+                        let mut changed = doc.set_active(target);
+                        if target.is_some() {
+                            changed |= doc.set_focused(target);
+                        }
+                        if changed {
+                            render_root = layout_doc(&doc, &stylesheets, &mut font_cache, containing_block, &media);
+                        }
+                    }
                     if let ElementState::Pressed = state {
                         if let MouseButton::Left = button {
                             let res = render_root.find_box_containing((last_mouse.x / 2.0) as f32, (last_mouse.y / 2.0) as f32);
@@ -371,9 +413,12 @@ fn main() -> Result<(),BrowserError>{
                                 if let Some(href) = &bx.link {
                                     println!("following the link {:#?}", href);
                                     let url = calculate_url_from_doc(&doc, href).unwrap();
-                                    let res = navigate_to_doc(&url, &mut font_cache, containing_block).unwrap();
+                                    visited.insert(url.clone());
+                                    let res = navigate_to_doc(&url, &mut font_cache, containing_block, &media, &visited).unwrap();
                                     doc = res.0;
-                                    render_root = res.1;
+                                    stylesheets = res.1;
+                                    render_root = res.2;
+                                    display.gl_window().window().set_title(&window_title(&doc));
                                 }
                             }
                         }
@@ -387,10 +432,14 @@ fn main() -> Result<(),BrowserError>{
         let new_w = screen_dims.0 as f32/2.0;
         let new_h = screen_dims.1 as f32/2.0;
         if prev_w != new_w || prev_h != new_h {
+            // the media queries are evaluated again for the new size
             containing_block.content.width = new_w;
-            let (doc2, render_root2) = navigate_to_doc(&start_page, &mut font_cache, containing_block).unwrap();
-            doc = doc2;
-            render_root = render_root2;
+            media = MediaContext::new(new_w, new_h);
+            render_root = layout_doc(&doc, &stylesheets, &mut font_cache, containing_block, &media);
+        }
+        // the sheets were changed through the CSSOM
+        if stylesheets.take_changed() {
+            render_root = layout_doc(&doc, &stylesheets, &mut font_cache, containing_block, &media);
         }
         prev_w = new_w;
         prev_h = new_h;
@@ -414,13 +463,18 @@ fn main() -> Result<(),BrowserError>{
         let box_scale = Matrix4::from_nonuniform_scale(2.0*2.0/w,-2.0*2.0/h,1.0);
         let box_trans: [[f32; 4]; 4] = (box_translate * box_scale).into();
         let uniforms = uniform! { matrix: box_trans  };
-        target.draw(&vertex_buffer, &indices, &rect_program, &uniforms, &Default::default()).unwrap();
+        // colors can be translucent, so the boxes are blended over what is already drawn
+        let params = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            .. Default::default()
+        };
+        target.draw(&vertex_buffer, &indices, &rect_program, &uniforms, &params).unwrap();
 
         for image in images {
             let tex:&Texture2d = &image.texture;
             let image_uniforms = uniform! { matrix: box_trans, tex: tex };
             let img_vertex_buffer = glium::VertexBuffer::new(&display, &image.vertices).unwrap();
-            target.draw(&img_vertex_buffer, &indices, &tex_program, &image_uniforms, &Default::default()).unwrap();
+            target.draw(&img_vertex_buffer, &indices, &tex_program, &image_uniforms, &params).unwrap();
         }
 
         //draw fonts
@@ -0,0 +1,258 @@
+// https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding
+// https://drafts.csswg.org/css-syntax-3/#input-byte-stream
+//
+// Works out which encoding a page or stylesheet uses and decodes it to UTF-8
+// before the parsers see it.
+
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252, X_USER_DEFINED};
+
+/// A byte order mark wins over everything else.
+pub fn sniff_bom(bytes:&[u8]) -> Option<&'static Encoding> {
+    Encoding::for_bom(bytes).map(|(enc, _)| enc)
+}
+
+/// Pulls the charset parameter out of a Content-Type header value,
+/// like `text/html; charset=Shift_JIS`.
+pub fn charset_from_content_type(content_type:&str) -> Option<&'static Encoding> {
+    let lower = content_type.to_ascii_lowercase();
+    let start = lower.find("charset")? + "charset".len();
+    let rest = content_type[start..].trim_start();
+    let rest = rest.strip_prefix('=')?.trim_start();
+    let value = match rest.chars().next() {
+        Some(quote) if quote == '"' || quote == '\'' => {
+            let rest = &rest[1..];
+            &rest[..rest.find(quote).unwrap_or(rest.len())]
+        }
+        _ => {
+            let end = rest.find(|c:char| c == ';' || c.is_whitespace()).unwrap_or(rest.len());
+            &rest[..end]
+        }
+    };
+    Encoding::for_label(value.as_bytes())
+}
+
+/// The encoding for an html document: BOM, then the transport layer, then a
+/// meta charset in the first 1024 bytes. Returns None if nothing declared one.
+pub fn sniff_html_encoding(bytes:&[u8], content_type:Option<&str>) -> Option<&'static Encoding> {
+    if let Some(enc) = sniff_bom(bytes) {
+        return Some(enc);
+    }
+    if let Some(enc) = content_type.and_then(charset_from_content_type) {
+        return Some(enc);
+    }
+    prescan(&bytes[..bytes.len().min(1024)])
+}
+
+/// The encoding to use when nothing was declared. Pages that are valid
+/// UTF-8 are assumed to be UTF-8, everything else is treated as windows-1252,
+/// which is also what browsers use for latin-1.
+pub fn fallback_encoding(bytes:&[u8]) -> &'static Encoding {
+    if std::str::from_utf8(bytes).is_ok() {
+        UTF_8
+    } else {
+        WINDOWS_1252
+    }
+}
+
+/// The encoding for a stylesheet: BOM, then the transport layer, then an
+/// `@charset` rule, then the encoding of the document that linked it.
+pub fn sniff_css_encoding(bytes:&[u8], content_type:Option<&str>, referrer:Option<&'static Encoding>) -> &'static Encoding {
+    if let Some(enc) = sniff_bom(bytes) {
+        return enc;
+    }
+    if let Some(enc) = content_type.and_then(charset_from_content_type) {
+        return enc;
+    }
+    let prefix = b"@charset \"";
+    if bytes.starts_with(prefix) {
+        let rest = &bytes[prefix.len()..];
+        if let Some(end) = rest.iter().position(|b| *b == b'"') {
+            if rest.get(end + 1) == Some(&b';') {
+                if let Some(enc) = Encoding::for_label(&rest[..end]) {
+                    // a stylesheet that could say @charset in ascii can't be utf-16
+                    return if enc == UTF_16BE || enc == UTF_16LE { UTF_8 } else { enc };
+                }
+            }
+        }
+    }
+    referrer.unwrap_or(UTF_8)
+}
+
+/// Decodes to UTF-8, replacing malformed sequences. A BOM overrides the
+/// given encoding and is removed. Returns the encoding that was actually used.
+pub fn decode(bytes:&[u8], encoding:&'static Encoding) -> (String, &'static Encoding) {
+    let (text, used, _had_errors) = encoding.decode(bytes);
+    (text.into_owned(), used)
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+fn prescan(bytes:&[u8]) -> Option<&'static Encoding> {
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+        if rest.starts_with(b"<!--") {
+            pos += 4 + find(&rest[4..], b"-->").map(|i| i + 3)?;
+            continue;
+        }
+        if starts_with_ci(rest, b"<meta") && rest.get(5).map(|b| is_space(*b) || *b == b'/').unwrap_or(false) {
+            pos += 5;
+            let mut got_pragma = false;
+            let mut need_pragma = None;
+            let mut charset = None;
+            let mut seen:Vec<Vec<u8>> = vec![];
+            while let Some((name, value, next)) = prescan_attribute(bytes, pos) {
+                pos = next;
+                if seen.contains(&name) {
+                    continue;
+                }
+                seen.push(name.clone());
+                match name.as_slice() {
+                    b"http-equiv" if value.eq_ignore_ascii_case(b"content-type") => got_pragma = true,
+                    b"content" if charset.is_none() => {
+                        let content = String::from_utf8_lossy(&value);
+                        if let Some(enc) = charset_from_content_type(&content) {
+                            charset = Some(enc);
+                            need_pragma = Some(true);
+                        }
+                    },
+                    b"charset" => {
+                        charset = Encoding::for_label(&value);
+                        need_pragma = Some(false);
+                    },
+                    _ => {}
+                }
+            }
+            match (need_pragma, charset) {
+                (Some(true), Some(_)) if !got_pragma => {}
+                (Some(_), Some(enc)) => {
+                    if enc == UTF_16BE || enc == UTF_16LE {
+                        return Some(UTF_8);
+                    }
+                    if enc == X_USER_DEFINED {
+                        return Some(WINDOWS_1252);
+                    }
+                    return Some(enc);
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if rest.starts_with(b"<") && rest.get(1).map(|b| b.is_ascii_alphabetic() || *b == b'/').unwrap_or(false) {
+            // skip over any other tag, including its attributes
+            let name_start = if rest[1] == b'/' { 2 } else { 1 };
+            if !rest.get(name_start).map(|b| b.is_ascii_alphabetic()).unwrap_or(false) {
+                pos += 1;
+                continue;
+            }
+            pos += name_start;
+            while pos < bytes.len() && !is_space(bytes[pos]) && bytes[pos] != b'>' {
+                pos += 1;
+            }
+            while let Some((_, _, next)) = prescan_attribute(bytes, pos) {
+                pos = next;
+            }
+            continue;
+        }
+        if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos += find(rest, b">").map(|i| i + 1)?;
+            continue;
+        }
+        pos += 1;
+    }
+    None
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+// returns the lowercased name, the value, and the position after the attribute.
+// returns None at the end of the tag.
+fn prescan_attribute(bytes:&[u8], start:usize) -> Option<(Vec<u8>, Vec<u8>, usize)> {
+    let mut pos = start;
+    while pos < bytes.len() && (is_space(bytes[pos]) || bytes[pos] == b'/') {
+        pos += 1;
+    }
+    if pos >= bytes.len() || bytes[pos] == b'>' {
+        return None;
+    }
+    let mut name = vec![];
+    while pos < bytes.len() {
+        let b = bytes[pos];
+        if (b == b'=' && !name.is_empty()) || is_space(b) || b == b'/' || b == b'>' {
+            break;
+        }
+        name.push(b.to_ascii_lowercase());
+        pos += 1;
+    }
+    while pos < bytes.len() && is_space(bytes[pos]) {
+        pos += 1;
+    }
+    if pos >= bytes.len() || bytes[pos] != b'=' {
+        return Some((name, vec![], pos));
+    }
+    pos += 1;
+    while pos < bytes.len() && is_space(bytes[pos]) {
+        pos += 1;
+    }
+    let mut value = vec![];
+    match bytes.get(pos) {
+        Some(quote) if *quote == b'"' || *quote == b'\'' => {
+            let quote = *quote;
+            pos += 1;
+            while pos < bytes.len() && bytes[pos] != quote {
+                value.push(bytes[pos].to_ascii_lowercase());
+                pos += 1;
+            }
+            pos += 1;
+        }
+        _ => {
+            while pos < bytes.len() && !is_space(bytes[pos]) && bytes[pos] != b'>' {
+                value.push(bytes[pos].to_ascii_lowercase());
+                pos += 1;
+            }
+        }
+    }
+    Some((name, value, pos))
+}
+
+fn is_space(b:u8) -> bool {
+    b == b' ' || b == b'\t' || b == b'\n' || b == b'\r' || b == 0x0C
+}
+fn starts_with_ci(bytes:&[u8], prefix:&[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+fn find(bytes:&[u8], needle:&[u8]) -> Option<usize> {
+    bytes.windows(needle.len()).position(|w| w == needle)
+}
+
+#[test]
+fn test_content_type_charset() {
+    assert_eq!(charset_from_content_type("text/html; charset=Shift_JIS"), Some(encoding_rs::SHIFT_JIS));
+    assert_eq!(charset_from_content_type("text/css;charset=\"iso-8859-1\""), Some(WINDOWS_1252));
+    assert_eq!(charset_from_content_type("text/html"), None);
+}
+
+#[test]
+fn test_html_sniffing() {
+    assert_eq!(sniff_html_encoding(b"\xEF\xBB\xBF<meta charset=latin1>", Some("text/html; charset=sjis")), Some(UTF_8));
+    assert_eq!(sniff_html_encoding(b"<meta charset=latin1>", Some("text/html; charset=sjis")), Some(encoding_rs::SHIFT_JIS));
+    assert_eq!(sniff_html_encoding(b"<!-- <meta charset=sjis> --><html><head><meta charset=\"ISO-8859-1\">", None), Some(WINDOWS_1252));
+    assert_eq!(sniff_html_encoding(b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=shift_jis\">", None), Some(encoding_rs::SHIFT_JIS));
+    //content without the http-equiv pragma doesn't count
+    assert_eq!(sniff_html_encoding(b"<meta content=\"text/html; charset=shift_jis\">", None), None);
+    assert_eq!(sniff_html_encoding(b"<meta charset=utf-16le>", None), Some(UTF_8));
+    assert_eq!(sniff_html_encoding(b"<p title='<meta charset=sjis>'>", None), None);
+}
+
+#[test]
+fn test_css_sniffing() {
+    assert_eq!(sniff_css_encoding(b"@charset \"ISO-8859-1\"; p {}", None, None), WINDOWS_1252);
+    assert_eq!(sniff_css_encoding(b"@charset 'ISO-8859-1'; p {}", None, None), UTF_8);
+    assert_eq!(sniff_css_encoding(b"p {}", None, Some(encoding_rs::SHIFT_JIS)), encoding_rs::SHIFT_JIS);
+    assert_eq!(sniff_css_encoding(b"@charset \"utf-16\";", None, None), UTF_8);
+}
+
+#[test]
+fn test_decode_latin1_and_shift_jis() {
+    assert_eq!(decode(b"caf\xE9", WINDOWS_1252), (String::from("caf\u{e9}"), WINDOWS_1252));
+    assert_eq!(decode(b"\x93\xfa\x96\x7b", encoding_rs::SHIFT_JIS).0, "\u{65e5}\u{672c}");
+    assert_eq!(decode(b"\xEF\xBB\xBFhi", WINDOWS_1252), (String::from("hi"), UTF_8));
+}
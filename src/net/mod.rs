@@ -1,5 +1,5 @@
-use crate::dom::{load_doc_from_buffer, getElementsByTagName, NodeType, Document, load_doc};
-use crate::css::{parse_stylesheet, Stylesheet, parse_stylesheet_from_buffer, RuleType, Value, parse_stylesheet_from_bytestring};
+use crate::dom::{NodeType, Document, load_doc, parse_doc_with_content_type, ParseMode};
+use crate::css::{parse_stylesheet, Stylesheet, Origin, RuleType, parse_stylesheet_from_bytestring, parse_stylesheet_with_encoding};
 use crate::style::{dom_tree_to_stylednodes, expand_styles};
 use crate::image::{load_image_from_buffer, LoadedImage, load_image_from_filepath};
 use image::ImageError;
@@ -12,6 +12,9 @@ use glium_glyph::glyph_brush::rusttype::{Font};
 use crate::dom::NodeType::Element;
 use glium_glyph::glyph_brush;
 use crate::render::FontCache;
+use encoding_rs::Encoding;
+
+pub mod encoding;
 
 #[derive(Debug)]
 pub enum BrowserError {
@@ -19,6 +22,46 @@ pub enum BrowserError {
     DiskError(std::io::Error),
     UrlError(ParseError),
     ImageError(ImageError),
+    ParseError(Diagnostic),
+    FontError(glium_glyph::glyph_brush::rusttype::Error),
+    /// an index past the end of a list, like a rule index given to the CSSOM
+    IndexError(usize),
+}
+
+/// A problem found while parsing a document or stylesheet. `offset` is a byte
+/// offset into the source after it was decoded to UTF-8. `line` and `column` start at 1, or are 0 when the
+/// source text wasn't available to compute them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub offset:usize,
+    pub line:usize,
+    pub column:usize,
+    pub expected:String,
+}
+
+impl Diagnostic {
+    pub fn new(source:&[u8], offset:usize, expected:&str) -> Self {
+        let offset = offset.min(source.len());
+        let before = &source[..offset];
+        let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+        let line_start = before.iter().rposition(|b| *b == b'\n').map(|i| i + 1).unwrap_or(0);
+        let column = String::from_utf8_lossy(&before[line_start..]).chars().count() + 1;
+        Diagnostic { offset, line, column, expected:String::from(expected) }
+    }
+    pub fn from_pom(source:&[u8], err:&pom::Error) -> Self {
+        let (offset, expected) = pom_error_position(err, source.len());
+        Diagnostic::new(source, offset, &expected)
+    }
+}
+
+fn pom_error_position(err:&pom::Error, len:usize) -> (usize, String) {
+    match err {
+        pom::Error::Incomplete => (len, String::from("more input")),
+        pom::Error::Mismatch { message, position } => (*position, message.clone()),
+        pom::Error::Conversion { message, position } => (*position, message.clone()),
+        pom::Error::Expect { message, position, .. } => (*position, message.clone()),
+        pom::Error::Custom { message, position, .. } => (*position, message.clone()),
+    }
 }
 impl From<std::io::Error> for BrowserError {
     fn from(err: Error) -> Self {
@@ -39,36 +82,56 @@ impl From<ImageError> for BrowserError {
     fn from(err: ImageError) -> Self { BrowserError::ImageError(err) }
 }
 impl From<pom::Error> for BrowserError {
-    fn from(_: pom::Error) -> Self {
-        unimplemented!()
+    fn from(err: pom::Error) -> Self {
+        // without the source we only know the offset
+        let (offset, expected) = pom_error_position(&err, 0);
+        BrowserError::ParseError(Diagnostic { offset, line: 0, column: 0, expected })
     }
 }
+// the url crate's conversions between urls and file paths fail with ()
 impl From<()> for BrowserError {
-    fn from(_: ()) -> Self { unimplemented!()  }
+    fn from(_: ()) -> Self {
+        BrowserError::DiskError(std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a file path or file url"))
+    }
 }
 impl From<glium_glyph::glyph_brush::rusttype::Error> for BrowserError {
-    fn from(_err:glium_glyph::glyph_brush::rusttype::Error) -> Self { unimplemented!()  }
+    fn from(err:glium_glyph::glyph_brush::rusttype::Error) -> Self { BrowserError::FontError(err) }
 }
 
 
+/// Resolves a link against the document's effective base url, which honors `<base href>`.
 pub fn calculate_url_from_doc(doc:&Document, href:&str) -> Result<Url,BrowserError>{
-    Ok(doc.base_url.join(href)?)
+    Ok(doc.effective_base_url().join(href)?)
 }
 
 #[derive(Debug)]
 pub struct StylesheetSet {
     pub stylesheets:Vec<Stylesheet>,
+    // set when a sheet is handed out for editing, until the shell restyles
+    changed: bool,
 }
 
 impl StylesheetSet {
     pub fn new() -> Self {
         StylesheetSet {
-            stylesheets: vec![]
+            stylesheets: vec![],
+            changed: false,
         }
     }
     pub fn append(&mut self, stylesheet:Stylesheet) {
         self.stylesheets.push(stylesheet)
     }
+    /// A sheet to change with the CSSOM methods. The set counts as changed
+    /// from then on, so the shell styles the document again.
+    pub fn edit(&mut self, index:usize) -> Option<&mut Stylesheet> {
+        let sheet = self.stylesheets.get_mut(index)?;
+        self.changed = true;
+        Some(sheet)
+    }
+    /// Whether a sheet was handed out by `edit` since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
     pub fn append_from_bytestring(&mut self, font_cache:&mut FontCache, css_text:&[u8]) -> Result<(),BrowserError> {
         let ss = parse_stylesheet_from_bytestring(css_text)?;
         process_stylesheet(self,font_cache,ss)
@@ -76,61 +139,78 @@ impl StylesheetSet {
 }
 
 fn process_stylesheet(set:&mut StylesheetSet, font_cache:&mut FontCache, stylesheet:Stylesheet) -> Result<(), BrowserError> {
-    //scan for imports
-    for rule in stylesheet.rules.iter() {
-        if let RuleType::AtRule(ar) = rule {
-            if ar.name == "import" {
-                println!("got an import ");
-                if let Some(Value::FunCall(fcv)) = &ar.value {
-                    println!("the url is {:#?}", fcv.arguments);
-                    if let Value::StringLiteral(str) = &fcv.arguments[0] {
-                        let url = Url::parse(str).unwrap();
-                        println!("parsing the imported stylesheet {:#?}", url);
-                        load_stylesheet_2(set, font_cache,&url)?;
-                    }
+    let ss = prepare_stylesheet(font_cache, stylesheet, &mut vec![]);
+    set.append(ss);
+    Ok(())
+}
+// loads the imports into the sheet, expands the styles and scans for font
+// faces. `importing` is the chain of sheets that led here, to stop cycles.
+fn prepare_stylesheet(font_cache:&mut FontCache, stylesheet:Stylesheet, importing:&mut Vec<Url>) -> Stylesheet {
+    let mut ss = stylesheet;
+    importing.push(ss.base_url.clone());
+    for rule in ss.rules.iter_mut() {
+        if let RuleType::Import(import) = rule {
+            let url = match ss.base_url.join(&import.url) {
+                Ok(url) => url,
+                Err(err) => {
+                    println!("WARNING: bad @import url {:?} {:?}", import.url, err);
+                    continue;
+                }
+            };
+            if importing.contains(&url) {
+                println!("WARNING: skipping @import of {} since it imports itself", url);
+                continue;
+            }
+            // imported sheets keep the origin of the sheet that imports them
+            match load_stylesheet_from_net_with_referrer(&url, Some(ss.encoding)) {
+                Ok(mut imported) => {
+                    imported.origin = ss.origin;
+                    import.stylesheet = Some(Box::new(prepare_stylesheet(font_cache, imported, importing)));
                 }
+                Err(err) => println!("WARNING: couldn't load @import {} {:?}", url, err),
             }
         }
     }
-    //expand the styles
-    let mut ss = stylesheet;
+    importing.pop();
     expand_styles(&mut ss);
-    //scan for font face
     font_cache.scan_for_fontface_rules(&ss);
-    set.append(ss);
-    Ok(())
+    ss
 }
-fn load_stylesheet_2(set:&mut StylesheetSet, font_cache:&mut FontCache, url:&Url) -> Result<(), BrowserError> {
-    process_stylesheet(set,font_cache,load_stylesheet_from_net(url)?)
+fn load_stylesheet_2(set:&mut StylesheetSet, font_cache:&mut FontCache, url:&Url, referrer:Option<&'static Encoding>, origin:Origin) -> Result<(), BrowserError> {
+    let mut ss = load_stylesheet_from_net_with_referrer(url, referrer)?;
+    ss.origin = origin;
+    process_stylesheet(set,font_cache,ss)
 }
-fn parse_stylesheet_2_from_text(set:&mut StylesheetSet, font_cache:&mut FontCache, text:&String) -> Result<(),BrowserError> {
-    process_stylesheet(set,font_cache,parse_stylesheet(text)?)
+// inline sheets resolve their urls against the document
+fn parse_stylesheet_2_from_text(set:&mut StylesheetSet, font_cache:&mut FontCache, text:&str, encoding:&'static Encoding, base_url:Url) -> Result<(),BrowserError> {
+    let mut ss = parse_stylesheet(text)?;
+    ss.encoding = encoding;
+    ss.base_url = base_url;
+    process_stylesheet(set,font_cache,ss)
 }
 
 pub fn load_stylesheets_new(doc:&Document, font_cache:&mut FontCache) -> Result<StylesheetSet, BrowserError> {
     let mut set = StylesheetSet::new();
     //load the default stylesheet
-    load_stylesheet_2(&mut set, font_cache, &relative_filepath_to_url("tests/default.css")?)?;
-    //scan for link nodes
-    let link_nodes = getElementsByTagName(&doc.root_node, "link");
-    for link in link_nodes.iter() {
-        if let Element(ed) = &link.node_type {
+    load_stylesheet_2(&mut set, font_cache, &relative_filepath_to_url("tests/default.css")?, None, Origin::UserAgent)?;
+    //linked and inline stylesheets, in document order so later ones win the cascade
+    for node in doc.querySelectorAll("link, style")?.iter() {
+        let ed = match &doc.node(*node).node_type {
+            Element(ed) => ed,
+            _ => continue,
+        };
+        if ed.tag_name == "link" {
             let rel = ed.attributes.get("rel");
             let href = ed.attributes.get("href");
             if rel.is_some() && rel.unwrap() == "stylesheet" && href.is_some() {
                 let href = href.unwrap();
                 let url = &calculate_url_from_doc(doc, href)?;
                 println!("Loading linked stylesheet {:#?}", url);
-                load_stylesheet_2(&mut set, font_cache, url);
+                load_stylesheet_2(&mut set, font_cache, url, Some(doc.encoding), Origin::Author);
             }
-        }
-    }
-    //scan for style nodes
-    let style_nodes = getElementsByTagName(&doc.root_node, "style");
-    for style in style_nodes.iter() {
-        if !style.children.is_empty() {
-            if let NodeType::Text(text) = &style.children[0].node_type {
-                parse_stylesheet_2_from_text(&mut set, font_cache, text)?;
+        } else if let Some(child) = doc.first_child(*node) {
+            if let NodeType::Text(text) = &doc.node(child).node_type {
+                parse_stylesheet_2_from_text(&mut set, font_cache, text, doc.encoding, doc.effective_base_url())?;
             }
         }
     }
@@ -155,10 +235,13 @@ pub fn load_doc_from_net(url:&Url) -> Result<Document,BrowserError> {
             let len = resp.content_length();
             println!("{:#?}\n content length = {:#?}\n status = {:#?}", resp, len, status);
 
+            let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from);
             let mut buf: Vec<u8> = vec![];
             resp.copy_to(&mut buf).ok();
 
-            let mut doc = load_doc_from_buffer(buf);
+            let (mut doc, _) = parse_doc_with_content_type(&buf, content_type.as_deref(), ParseMode::Recover)?;
             doc.base_url = url.clone();
             Ok(doc)
         }
@@ -173,6 +256,11 @@ pub fn load_image_from_net(url:&Url) -> Result<LoadedImage, BrowserError> {
 }
 
 pub fn load_stylesheet_from_net(url:&Url) -> Result<Stylesheet, BrowserError>{
+    load_stylesheet_from_net_with_referrer(url, None)
+}
+/// `referrer` is the encoding of the document that linked the stylesheet. It
+/// is used when the stylesheet doesn't declare its own.
+pub fn load_stylesheet_from_net_with_referrer(url:&Url, referrer:Option<&'static Encoding>) -> Result<Stylesheet, BrowserError>{
     // println!("loading stylesheet from url {:#?}",url);
     match url.scheme() {
         "file" => {
@@ -180,15 +268,18 @@ pub fn load_stylesheet_from_net(url:&Url) -> Result<Stylesheet, BrowserError>{
             let mut file = File::open(path)?;
             let mut content:Vec<u8>= Vec::new();
             file.read_to_end(&mut content).ok();
-            let mut ss = parse_stylesheet_from_buffer(content)?;
+            let mut ss = parse_stylesheet_with_encoding(&content, None, referrer)?;
             ss.base_url = url.clone();
             Ok(ss)
         }
         _ => {
             let mut resp = reqwest::blocking::get(url.as_str())?;
+            let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from);
             let mut buf: Vec<u8> = vec![];
             resp.copy_to(&mut buf)?;
-            let mut ss = parse_stylesheet_from_buffer(buf)?;
+            let mut ss = parse_stylesheet_with_encoding(&buf, content_type.as_deref(), referrer)?;
             ss.base_url = url.clone();
             Ok(ss)
         }
@@ -201,18 +292,35 @@ pub fn load_font_from_net(url:Url) -> Result<Font<'static>, BrowserError> {
             let path = url.to_file_path()?;
             let mut file = File::open(path)?;
             let mut content:Vec<u8>= Vec::new();
-            file.read_to_end(&mut content).ok();
-            Ok(Font::from_bytes(content).unwrap())
+            file.read_to_end(&mut content)?;
+            Ok(Font::from_bytes(content)?)
         }
         _ => {
             let mut resp = reqwest::blocking::get(url.as_str())?;
             let mut buf: Vec<u8> = vec![];
             resp.copy_to(&mut buf)?;
-            Ok(Font::from_bytes(buf).unwrap())
+            Ok(Font::from_bytes(buf)?)
         }
     }
 }
 
+#[test]
+fn test_load_image_with_base() {
+    let mut doc = crate::dom::load_doc_from_bytestring(br#"<head><base href="images/"></head><img src="dog.png">"#).unwrap();
+    doc.base_url = relative_filepath_to_url("tests/image.html").unwrap();
+    assert!(load_image(&doc, "dog.png").is_ok());
+    // without the base it would be tests/dog.png, which doesn't exist
+    assert!(load_image(&doc, "../dog.png").is_err());
+}
+
+#[test]
+fn test_bad_font() {
+    let url = relative_filepath_to_url("tests/default.css").unwrap();
+    assert!(matches!(load_font_from_net(url), Err(BrowserError::FontError(_))));
+    let missing = relative_filepath_to_url("tests/fonts/missing.ttf").unwrap();
+    assert!(matches!(load_font_from_net(missing), Err(BrowserError::DiskError(_))));
+}
+
 #[test]
 fn test_request() -> Result<(), BrowserError> {
     let mut resp = reqwest::blocking::get("https://apps.josh.earth/rust-minibrowser/test1.html")?;
@@ -222,17 +330,17 @@ fn test_request() -> Result<(), BrowserError> {
 
     let mut buf: Vec<u8> = vec![];
     resp.copy_to(&mut buf)?;
-    let doc = load_doc_from_buffer(buf);
+    let doc = crate::dom::load_doc_from_buffer(buf)?;
     // println!("document is {:#?}",doc);
-    let res = getElementsByTagName(&doc.root_node, "style");
+    let res = doc.getElementsByTagName("style");
     println!("result is {:#?}",res);
     if !res.is_empty() {
-        let style_node = res[0];
-        if let NodeType::Text(text) = &style_node.children[0].node_type {
+        let style_node = doc.children(res[0])[0];
+        if let NodeType::Text(text) = &doc.node(style_node).node_type {
             println!("got the text {}", text);
             let stylesheet = parse_stylesheet(text)?;
             println!("got the stylesheet {:#?}",stylesheet);
-            // let styled = dom_tree_to_stylednodes(&doc.root_node, &stylesheet);
+            // let styled = dom_tree_to_stylednodes(&doc, &stylesheet);
             // println!("styled is {:#?}",styled);
         }
     }
@@ -242,7 +350,7 @@ fn test_request() -> Result<(), BrowserError> {
 
 
 pub fn load_image(doc:&Document, href:&str) -> Result<LoadedImage, BrowserError>{
-    let url = doc.base_url.join(href)?;
+    let url = calculate_url_from_doc(doc, href)?;
     match url.scheme() {
         "file" => {
             Ok(load_image_from_filepath(url.path().to_string())?)
@@ -254,3 +362,36 @@ pub fn load_image(doc:&Document, href:&str) -> Result<LoadedImage, BrowserError>
 }
 
 
+
+#[test]
+fn test_imports() -> Result<(), BrowserError> {
+    let glyph_brush:glyph_brush::GlyphBrush<Font> = glyph_brush::GlyphBrushBuilder::without_fonts().build();
+    let mut font_cache = FontCache {
+        brush: crate::layout::Brush::Style2(glyph_brush),
+        families: Default::default(),
+        fonts: Default::default()
+    };
+    let mut ss = load_stylesheet_from_net(&relative_filepath_to_url("tests/imports/main.css")?)?;
+    ss.origin = Origin::User;
+    let ss = prepare_stylesheet(&mut font_cache, ss, &mut vec![]);
+    let imports:Vec<_> = ss.rules.iter().filter_map(|rule| match rule {
+        RuleType::Import(import) => Some(import),
+        _ => None,
+    }).collect();
+    assert_eq!(imports.len(), 3);
+    // relative to the importing sheet, with the origin of the importing sheet
+    let a = imports[0].stylesheet.as_ref().unwrap();
+    assert_eq!(a.base_url, relative_filepath_to_url("tests/imports/a.css")?);
+    assert_eq!(a.origin, Origin::User);
+    assert_eq!(imports[0].layer, Some(String::from("base")));
+    // a.css imports main.css back, which is skipped
+    match &a.rules[0] {
+        RuleType::Import(import) => assert!(import.stylesheet.is_none()),
+        rule => panic!("expected an import, got {:?}", rule),
+    }
+    assert!(imports[1].stylesheet.is_some());
+    assert!(!imports[1].media.queries.is_empty());
+    // a sheet that fails to load is left out
+    assert!(imports[2].stylesheet.is_none());
+    Ok(())
+}
@@ -237,11 +237,12 @@ impl FontCache {
                             println!("got it {:#?} {:#?} {:#?}",font_family, src, font_weight);
                             if font_family.is_some() && src.is_some() && font_weight.is_some() {
                                 let url = src.unwrap();
-                                let font = load_font_from_net(url);
-                                let font = font.unwrap();
-                                self.install_font(font, &*font_family.unwrap(),
-                                                  font_weight.unwrap(),
-                                                  "normal")
+                                match load_font_from_net(url.clone()) {
+                                    Ok(font) => self.install_font(font, &*font_family.unwrap(),
+                                                                  font_weight.unwrap(),
+                                                                  "normal"),
+                                    Err(err) => println!("WARNING: couldn't load the font {} {:?}", url, err),
+                                }
                             }
                         }
                     }
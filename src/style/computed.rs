@@ -0,0 +1,381 @@
+// https://drafts.csswg.org/css-cascade-4/#computed
+//
+// Turns the cascaded values of a node into computed values. Inherited
+// properties come from the parent when nothing sets them and everything else
+// starts at its initial value. `inherit`, `initial` and `unset` are resolved
+// here, and lengths in every unit but percentages become pixels, so layout
+// never has to walk up the tree for them. Math functions are evaluated unless
+// they have percentages in them. Variables are substituted first.
+
+use crate::css::{Calc, Unit, Value};
+use crate::css::Value::{Keyword, Length, ArrayValue, Number, Unparsed};
+use crate::style::variables::{custom_properties, substitute};
+use std::collections::HashMap;
+
+type PropertyMap = HashMap<String, Value>;
+
+/// The font size of the root element when nothing sets it.
+pub const INITIAL_FONT_SIZE:f32 = 18.0;
+
+/// What relative lengths are relative to, besides the font size of the node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComputeContext {
+    pub root_font_size: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+impl ComputeContext {
+    pub fn new(viewport_width:f32, viewport_height:f32) -> Self {
+        ComputeContext { root_font_size: INITIAL_FONT_SIZE, viewport_width, viewport_height }
+    }
+}
+
+impl Default for ComputeContext {
+    fn default() -> Self {
+        ComputeContext::new(800.0, 600.0)
+    }
+}
+
+enum Initial {
+    Keyword(&'static str),
+    Px(f32),
+}
+
+// https://www.w3.org/TR/CSS22/propidx.html
+// the name, whether it inherits, and the initial value
+const PROPERTIES:&[(&str, bool, Initial)] = &[
+    ("background-attachment", false, Initial::Keyword("scroll")),
+    ("background-color", false, Initial::Keyword("transparent")),
+    ("background-image", false, Initial::Keyword("none")),
+    ("background-repeat", false, Initial::Keyword("repeat")),
+    ("border-top-color", false, Initial::Keyword("currentcolor")),
+    ("border-right-color", false, Initial::Keyword("currentcolor")),
+    ("border-bottom-color", false, Initial::Keyword("currentcolor")),
+    ("border-left-color", false, Initial::Keyword("currentcolor")),
+    ("border-top-style", false, Initial::Keyword("none")),
+    ("border-right-style", false, Initial::Keyword("none")),
+    ("border-bottom-style", false, Initial::Keyword("none")),
+    ("border-left-style", false, Initial::Keyword("none")),
+    ("border-top-width", false, Initial::Px(0.0)),
+    ("border-right-width", false, Initial::Px(0.0)),
+    ("border-bottom-width", false, Initial::Px(0.0)),
+    ("border-left-width", false, Initial::Px(0.0)),
+    ("bottom", false, Initial::Keyword("auto")),
+    ("color", true, Initial::Keyword("black")),
+    ("display", false, Initial::Keyword("inline")),
+    ("font-family", true, Initial::Keyword("sans-serif")),
+    ("font-size", true, Initial::Px(INITIAL_FONT_SIZE)),
+    ("font-style", true, Initial::Keyword("normal")),
+    ("font-variant", true, Initial::Keyword("normal")),
+    ("font-weight", true, Initial::Keyword("normal")),
+    ("height", false, Initial::Keyword("auto")),
+    ("left", false, Initial::Keyword("auto")),
+    ("letter-spacing", true, Initial::Keyword("normal")),
+    ("line-height", true, Initial::Keyword("normal")),
+    ("list-style-image", true, Initial::Keyword("none")),
+    ("list-style-position", true, Initial::Keyword("outside")),
+    ("list-style-type", true, Initial::Keyword("disc")),
+    ("margin-top", false, Initial::Px(0.0)),
+    ("margin-right", false, Initial::Px(0.0)),
+    ("margin-bottom", false, Initial::Px(0.0)),
+    ("margin-left", false, Initial::Px(0.0)),
+    ("max-height", false, Initial::Keyword("none")),
+    ("max-width", false, Initial::Keyword("none")),
+    ("min-height", false, Initial::Px(0.0)),
+    ("min-width", false, Initial::Px(0.0)),
+    ("padding-top", false, Initial::Px(0.0)),
+    ("padding-right", false, Initial::Px(0.0)),
+    ("padding-bottom", false, Initial::Px(0.0)),
+    ("padding-left", false, Initial::Px(0.0)),
+    ("position", false, Initial::Keyword("static")),
+    ("quotes", true, Initial::Keyword("auto")),
+    ("right", false, Initial::Keyword("auto")),
+    ("text-align", true, Initial::Keyword("left")),
+    ("text-decoration-line", false, Initial::Keyword("none")),
+    ("text-transform", true, Initial::Keyword("none")),
+    ("top", false, Initial::Keyword("auto")),
+    ("vertical-align", false, Initial::Keyword("baseline")),
+    ("visibility", true, Initial::Keyword("visible")),
+    ("white-space", true, Initial::Keyword("normal")),
+    ("width", false, Initial::Keyword("auto")),
+    ("word-spacing", true, Initial::Keyword("normal")),
+];
+
+fn property(name:&str) -> Option<&'static (&'static str, bool, Initial)> {
+    PROPERTIES.iter().find(|(n, _, _)| *n == name)
+}
+
+/// Whether a property takes its parent's value when nothing sets it.
+/// Unknown properties don't.
+pub fn is_inherited(name:&str) -> bool {
+    property(name).map(|(_, inherited, _)| *inherited).unwrap_or(false)
+}
+
+pub fn initial_value(name:&str) -> Option<Value> {
+    property(name).map(|(_, _, initial)| match initial {
+        Initial::Keyword(keyword) => Keyword(String::from(*keyword)),
+        Initial::Px(px) => Length(*px, Unit::Px),
+    })
+}
+
+/// The computed values of a node from its cascaded values and the computed
+/// values of its parent. The root has no parent.
+pub fn compute_values(specified:&PropertyMap, parent:Option<&PropertyMap>, context:&ComputeContext) -> PropertyMap {
+    let inherit = |name:&str| parent.and_then(|values| values.get(name)).cloned().or_else(|| initial_value(name));
+    let custom = custom_properties(specified, parent);
+    let mut values = custom.clone();
+    for (name, inherited, _) in PROPERTIES.iter() {
+        let value = if *inherited { inherit(name) } else { initial_value(name) };
+        if let Some(value) = value {
+            values.insert(name.to_string(), value);
+        }
+    }
+    for (name, value) in specified.iter().filter(|(name, _)| !name.starts_with("--")) {
+        let value = match value {
+            Unparsed(unparsed) => substitute(name, unparsed, &custom),
+            value => value.clone(),
+        };
+        let value = match &value {
+            Keyword(keyword) => match keyword.as_str() {
+                "inherit" => inherit(name),
+                "initial" => initial_value(name),
+                "unset" if is_inherited(name) => inherit(name),
+                "unset" => initial_value(name),
+                _ => Some(value.clone()),
+            },
+            _ => Some(value.clone()),
+        };
+        match value {
+            Some(value) => values.insert(name.clone(), value),
+            None => values.remove(name),
+        };
+    }
+
+    // font-size goes first since the other lengths are relative to it
+    let parent_font_size = parent.map(font_size).unwrap_or(INITIAL_FONT_SIZE);
+    // percentages of it are of the parent's
+    let parent_length = |v:f32, unit:&Unit| match unit {
+        Unit::Per => Some(v / 100.0 * parent_font_size),
+        unit => length_px(v, unit, parent_font_size, context),
+    };
+    let px = match values.get("font-size") {
+        Some(Length(v, unit)) => parent_length(*v, unit),
+        Some(Value::Calc(calc)) => calc.eval(&parent_length),
+        _ => None,
+    };
+    if let Some(px) = px {
+        values.insert(String::from("font-size"), Length(px, Unit::Px));
+    }
+    if let Some(Keyword(keyword)) = values.get("font-size").cloned() {
+        let px = font_size_keyword(&keyword, parent_font_size);
+        values.insert(String::from("font-size"), Length(px, Unit::Px));
+    }
+    let own_font_size = font_size(&values);
+    for (name, value) in values.iter_mut() {
+        if name != "font-size" {
+            *value = absolute(value, own_font_size, context);
+        }
+    }
+
+    // currentcolor is the color of the element itself, and on color it is the inherited one
+    let is_current_color = |value:&Value| matches!(value, Keyword(k) if k.eq_ignore_ascii_case("currentcolor"));
+    if matches!(values.get("color"), Some(value) if is_current_color(value)) {
+        let color = inherit("color").unwrap_or_else(|| Keyword(String::from("black")));
+        values.insert(String::from("color"), color);
+    }
+    let color = values.get("color").cloned();
+    for value in values.values_mut() {
+        if is_current_color(value) {
+            if let Some(color) = &color {
+                *value = color.clone();
+            }
+        }
+    }
+
+    // https://drafts.csswg.org/css-backgrounds-3/#border-width
+    // a side with no border drawn has no width either
+    for side in ["top", "right", "bottom", "left"].iter() {
+        if matches!(values.get(&format!("border-{}-style", side)), Some(Keyword(k)) if k == "none" || k == "hidden") {
+            values.insert(format!("border-{}-width", side), Length(0.0, Unit::Px));
+        }
+    }
+    values
+}
+
+/// The computed font size in pixels.
+pub fn font_size(values:&PropertyMap) -> f32 {
+    match values.get("font-size") {
+        Some(Length(v, Unit::Px)) => *v,
+        _ => INITIAL_FONT_SIZE,
+    }
+}
+
+// https://drafts.csswg.org/css-fonts-4/#absolute-size-mapping
+// the sizes are scaled from medium, which is the initial font size
+fn font_size_keyword(keyword:&str, parent_font_size:f32) -> f32 {
+    let scale = match keyword {
+        "xx-small" => 3.0 / 5.0,
+        "x-small" => 3.0 / 4.0,
+        "small" => 8.0 / 9.0,
+        "large" => 6.0 / 5.0,
+        "x-large" => 3.0 / 2.0,
+        "xx-large" => 2.0,
+        "xxx-large" => 3.0,
+        "larger" => return parent_font_size * 1.2,
+        "smaller" => return parent_font_size / 1.2,
+        _ => 1.0,
+    };
+    INITIAL_FONT_SIZE * scale
+}
+
+// https://drafts.csswg.org/css-values-4/#lengths
+// a length in pixels, or None for a percentage, which depends on layout.
+// without the font's metrics, ex and ch are half an em.
+fn length_px(v:f32, unit:&Unit, font_size:f32, context:&ComputeContext) -> Option<f32> {
+    Some(match unit {
+        Unit::Px => v,
+        Unit::Em => v * font_size,
+        Unit::Rem => v * context.root_font_size,
+        Unit::Ex | Unit::Ch => v * font_size / 2.0,
+        Unit::Vw => v / 100.0 * context.viewport_width,
+        Unit::Vh => v / 100.0 * context.viewport_height,
+        Unit::Vmin => v / 100.0 * context.viewport_width.min(context.viewport_height),
+        Unit::Vmax => v / 100.0 * context.viewport_width.max(context.viewport_height),
+        Unit::Pt => v * 96.0 / 72.0,
+        Unit::Pc => v * 16.0,
+        Unit::In => v * 96.0,
+        Unit::Cm => v * 96.0 / 2.54,
+        Unit::Mm => v * 96.0 / 25.4,
+        Unit::Per => return None,
+    })
+}
+
+// lengths in pixels, and math functions evaluated. percentages are left alone.
+fn absolute(value:&Value, font_size:f32, context:&ComputeContext) -> Value {
+    match value {
+        Length(v, unit) => match length_px(*v, unit, font_size, context) {
+            Some(px) => Length(px, Unit::Px),
+            None => value.clone(),
+        },
+        Value::Calc(calc) => {
+            let calc = calc.map_lengths(&|v, unit| match length_px(v, unit, font_size, context) {
+                Some(px) => (px, Unit::Px),
+                None => (v, unit.clone()),
+            });
+            simplify(calc)
+        }
+        ArrayValue(items) => ArrayValue(items.iter().map(|item| absolute(item, font_size, context)).collect()),
+        _ => value.clone(),
+    }
+}
+
+// the result of a math function whose lengths are all pixels, or the function
+// itself if it still needs layout to know the percentages
+fn simplify(calc:Calc) -> Value {
+    let px = calc.eval(&|v, unit| match unit {
+        Unit::Px => Some(v),
+        _ => None,
+    });
+    match (px, calc.is_length()) {
+        (Some(px), Some(true)) => Length(px, Unit::Px),
+        (Some(v), _) => Number(v),
+        (None, _) => Value::Calc(calc),
+    }
+}
+
+#[test]
+fn test_compute_values() {
+    let map = |pairs:&[(&str, Value)]| -> PropertyMap {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    };
+    let root = compute_values(&map(&[
+        ("font-size", Length(2.0, Unit::Em)),
+        ("color", Keyword(String::from("red"))),
+        ("display", Keyword(String::from("block"))),
+    ]), None, &ComputeContext::default());
+    assert_eq!(root.get("font-size"), Some(&Length(36.0, Unit::Px)));
+    assert_eq!(root.get("font-weight"), Some(&Keyword(String::from("normal"))));
+
+    let big_root = ComputeContext { root_font_size: 36.0, ..ComputeContext::default() };
+    let child = compute_values(&map(&[
+        ("font-size", Length(50.0, Unit::Per)),
+        ("margin-left", Length(2.0, Unit::Em)),
+        ("margin-right", Length(1.0, Unit::Rem)),
+        ("width", Length(50.0, Unit::Per)),
+        ("display", Keyword(String::from("inherit"))),
+        ("font-weight", Keyword(String::from("bold"))),
+        ("foo", Keyword(String::from("initial"))),
+    ]), Some(&root), &big_root);
+    assert_eq!(child.get("font-size"), Some(&Length(18.0, Unit::Px)));
+    assert_eq!(child.get("margin-left"), Some(&Length(36.0, Unit::Px)));
+    assert_eq!(child.get("margin-right"), Some(&Length(36.0, Unit::Px)));
+    assert_eq!(child.get("width"), Some(&Length(50.0, Unit::Per)));
+    assert_eq!(child.get("color"), Some(&Keyword(String::from("red"))));
+    assert_eq!(child.get("display"), Some(&Keyword(String::from("block"))));
+    assert_eq!(child.get("foo"), None);
+
+    let grandchild = compute_values(&map(&[
+        ("color", Keyword(String::from("initial"))),
+        ("font-weight", Keyword(String::from("unset"))),
+        ("display", Keyword(String::from("unset"))),
+        ("width", Keyword(String::from("initial"))),
+        ("padding-top", Keyword(String::from("unset"))),
+    ]), Some(&child), &big_root);
+    assert_eq!(grandchild.get("color"), Some(&Keyword(String::from("black"))));
+    assert_eq!(grandchild.get("width"), Some(&Keyword(String::from("auto"))));
+    assert_eq!(grandchild.get("padding-top"), Some(&Length(0.0, Unit::Px)));
+    assert_eq!(grandchild.get("font-weight"), Some(&Keyword(String::from("bold"))));
+    assert_eq!(grandchild.get("display"), Some(&Keyword(String::from("inline"))));
+
+    let keyword = compute_values(&map(&[("font-size", Keyword(String::from("xx-large")))]), None, &ComputeContext::default());
+    assert_eq!(keyword.get("font-size"), Some(&Length(36.0, Unit::Px)));
+    assert_eq!(grandchild.get("border-left-color"), Some(&Keyword(String::from("black"))));
+    assert_eq!(child.get("border-left-color"), Some(&Keyword(String::from("red"))));
+}
+
+#[test]
+fn test_border_width_without_style() {
+    use crate::css::parse_declarations;
+    let specified = parse_declarations("border-top-width: 4px; border-right-width: thick; border-bottom-width: 2px; \
+        border-left-width: 3px; border-top-style: solid; border-right-style: hidden; border-bottom-style: none").0
+        .into_iter().map(|dec| (dec.name, dec.value)).collect();
+    let values = compute_values(&specified, None, &ComputeContext::default());
+    assert_eq!(values.get("border-top-width"), Some(&Length(4.0, Unit::Px)));
+    assert_eq!(values.get("border-right-width"), Some(&Length(0.0, Unit::Px)));
+    assert_eq!(values.get("border-bottom-width"), Some(&Length(0.0, Unit::Px)));
+    // the initial style is none
+    assert_eq!(values.get("border-left-width"), Some(&Length(0.0, Unit::Px)));
+}
+
+#[test]
+fn test_compute_lengths() {
+    use crate::css::parse_declarations;
+    let values = |text:&str| -> PropertyMap {
+        let specified = parse_declarations(text).0.into_iter().map(|dec| (dec.name, dec.value)).collect();
+        compute_values(&specified, None, &ComputeContext::new(1000.0, 500.0))
+    };
+    let lengths = values("font-size: 20px; width: 10vw; height: 10vh; max-width: 10vmin; min-width: 10vmax; \
+        margin-top: 72pt; margin-bottom: 1in; margin-left: 2.54cm; margin-right: 2ex; padding-top: 1pc; padding-left: 10mm");
+    let px = |name:&str| match lengths.get(name) {
+        Some(Length(v, Unit::Px)) => (v * 100.0).round() / 100.0,
+        value => panic!("{} is {:?}", name, value),
+    };
+    assert_eq!(px("width"), 100.0);
+    assert_eq!(px("height"), 50.0);
+    assert_eq!(px("max-width"), 50.0);
+    assert_eq!(px("min-width"), 100.0);
+    assert_eq!(px("margin-top"), 96.0);
+    assert_eq!(px("margin-bottom"), 96.0);
+    assert_eq!(px("margin-left"), 96.0);
+    assert_eq!(px("margin-right"), 20.0);
+    assert_eq!(px("padding-top"), 16.0);
+    assert_eq!(px("padding-left"), 37.8);
+
+    // math functions are evaluated, unless they need to know what a percentage is of
+    let math = values("font-size: calc(1em + 2px); width: calc(2em + 10vw); height: min(1in, 50%); margin-top: calc(4 / 2)");
+    assert_eq!(math.get("font-size"), Some(&Length(20.0, Unit::Px)));
+    assert_eq!(math.get("width"), Some(&Length(140.0, Unit::Px)));
+    assert_eq!(math.get("height"), Some(&Value::Calc(Calc::Min(vec![Calc::Length(96.0, Unit::Px), Calc::Length(50.0, Unit::Per)]))));
+    assert_eq!(math.get("margin-top"), Some(&Number(2.0)));
+}
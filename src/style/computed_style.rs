@@ -0,0 +1,398 @@
+// The computed values that layout reads, as typed fields. Each field is read
+// from one property of the computed value map, so a property name is spelled
+// once here instead of at every place layout needs it.
+
+use crate::css::{Calc, Color, Unit, Value};
+use crate::css::Value::{Keyword, Length, Number, ArrayValue, StringLiteral, ColorValue, HexColor, FunCall};
+use crate::render::{BLACK, FontCache};
+use crate::style::computed::INITIAL_FONT_SIZE;
+use crate::style::named_color;
+use std::collections::HashMap;
+
+type PropertyMap = HashMap<String, Value>;
+
+macro_rules! computed_style {
+    ($($field:ident : $ty:ty = $name:literal => $convert:expr,)*) => {
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct ComputedStyle {
+            $(pub $field: $ty,)*
+        }
+
+        impl ComputedStyle {
+            /// The properties the fields are read from.
+            pub const PROPERTIES: &'static [&'static str] = &[$($name,)*];
+
+            pub fn from_values(values:&PropertyMap) -> ComputedStyle {
+                ComputedStyle {
+                    $($field: $convert(values.get($name)),)*
+                }
+            }
+        }
+    };
+}
+
+computed_style! {
+    display: Display = "display" => Display::from_value,
+    position: Position = "position" => Position::from_value,
+    white_space: WhiteSpace = "white-space" => WhiteSpace::from_value,
+    text_align: TextAlign = "text-align" => TextAlign::from_value,
+    vertical_align: VerticalAlign = "vertical-align" => VerticalAlign::from_value,
+    width: Size = "width" => Size::from_value,
+    height: Size = "height" => Size::from_value,
+    max_width: Size = "max-width" => Size::from_value,
+    margin_top: Size = "margin-top" => Size::from_value,
+    margin_right: Size = "margin-right" => Size::from_value,
+    margin_bottom: Size = "margin-bottom" => Size::from_value,
+    margin_left: Size = "margin-left" => Size::from_value,
+    padding_top: Size = "padding-top" => Size::from_value,
+    padding_right: Size = "padding-right" => Size::from_value,
+    padding_bottom: Size = "padding-bottom" => Size::from_value,
+    padding_left: Size = "padding-left" => Size::from_value,
+    border_top_width: f32 = "border-top-width" => border_width,
+    border_right_width: f32 = "border-right-width" => border_width,
+    border_bottom_width: f32 = "border-bottom-width" => border_width,
+    border_left_width: f32 = "border-left-width" => border_width,
+    border_top_color: Option<Color> = "border-top-color" => to_color,
+    border_right_color: Option<Color> = "border-right-color" => to_color,
+    border_bottom_color: Option<Color> = "border-bottom-color" => to_color,
+    border_left_color: Option<Color> = "border-left-color" => to_color,
+    background_color: Option<Color> = "background-color" => to_color,
+    color: Color = "color" => |value| to_color(value).unwrap_or(BLACK),
+    font_family: Vec<String> = "font-family" => font_family,
+    font_size: f32 = "font-size" => font_size,
+    font_weight: i32 = "font-weight" => font_weight,
+    font_style: FontStyle = "font-style" => FontStyle::from_value,
+    text_decoration_line: TextDecorationLine = "text-decoration-line" => TextDecorationLine::from_value,
+    list_style_type: String = "list-style-type" => |value| keyword(value).unwrap_or_else(|| String::from("none")),
+}
+
+impl Default for ComputedStyle {
+    fn default() -> Self {
+        ComputedStyle::from_values(&PropertyMap::new())
+    }
+}
+
+impl ComputedStyle {
+    /// The first family in the list that the font cache has, or sans-serif.
+    pub fn find_font_family(&self, font_cache:&FontCache) -> String {
+        match self.font_family.iter().find(|family| font_cache.has_font_family(family)) {
+            Some(family) => family.clone(),
+            None => String::from("sans-serif"),
+        }
+    }
+}
+
+// keywords are case-insensitive, so they are matched in lowercase
+fn keyword(value:Option<&Value>) -> Option<String> {
+    match value {
+        Some(Keyword(keyword)) => Some(keyword.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Display {
+    Block,
+    Inline,
+    InlineBlock,
+    Table,
+    TableRowGroup,
+    TableRow,
+    TableCell,
+    ListItem,
+    None,
+}
+
+impl Display {
+    fn from_value(value:Option<&Value>) -> Display {
+        match keyword(value).as_deref() {
+            Some("block") => Display::Block,
+            Some("none") => Display::None,
+            Some("inline-block") => Display::InlineBlock,
+            Some("table") => Display::Table,
+            Some("table-row-group") => Display::TableRowGroup,
+            Some("table-row") => Display::TableRow,
+            Some("table-cell") => Display::TableCell,
+            Some("list-item") => Display::ListItem,
+            _ => Display::Inline,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+impl Position {
+    fn from_value(value:Option<&Value>) -> Position {
+        match keyword(value).as_deref() {
+            Some("relative") => Position::Relative,
+            Some("absolute") => Position::Absolute,
+            Some("fixed") => Position::Fixed,
+            Some("sticky") => Position::Sticky,
+            _ => Position::Static,
+        }
+    }
+}
+
+// https://drafts.csswg.org/css-text-3/#white-space-property
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WhiteSpace {
+    Normal,
+    NoWrap,
+    Pre,
+    PreWrap,
+    PreLine,
+    BreakSpaces,
+}
+
+impl WhiteSpace {
+    fn from_value(value:Option<&Value>) -> WhiteSpace {
+        match keyword(value).as_deref() {
+            Some("nowrap") => WhiteSpace::NoWrap,
+            Some("pre") => WhiteSpace::Pre,
+            Some("pre-wrap") => WhiteSpace::PreWrap,
+            Some("pre-line") => WhiteSpace::PreLine,
+            Some("break-spaces") => WhiteSpace::BreakSpaces,
+            _ => WhiteSpace::Normal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+
+impl TextAlign {
+    fn from_value(value:Option<&Value>) -> TextAlign {
+        match keyword(value).as_deref() {
+            Some("right") | Some("end") => TextAlign::Right,
+            Some("center") => TextAlign::Center,
+            Some("justify") => TextAlign::Justify,
+            _ => TextAlign::Left,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VerticalAlign {
+    Baseline,
+    Sub,
+    Super,
+    Top,
+    Middle,
+    Bottom,
+}
+
+impl VerticalAlign {
+    fn from_value(value:Option<&Value>) -> VerticalAlign {
+        match keyword(value).as_deref() {
+            Some("sub") => VerticalAlign::Sub,
+            Some("super") => VerticalAlign::Super,
+            Some("top") | Some("text-top") => VerticalAlign::Top,
+            Some("middle") => VerticalAlign::Middle,
+            Some("bottom") | Some("text-bottom") => VerticalAlign::Bottom,
+            _ => VerticalAlign::Baseline,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+impl FontStyle {
+    fn from_value(value:Option<&Value>) -> FontStyle {
+        match keyword(value).as_deref() {
+            Some("italic") => FontStyle::Italic,
+            Some("oblique") => FontStyle::Oblique,
+            _ => FontStyle::Normal,
+        }
+    }
+    /// The name fonts are installed under in the font cache.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FontStyle::Normal => "normal",
+            FontStyle::Italic => "italic",
+            FontStyle::Oblique => "oblique",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextDecorationLine {
+    None,
+    Underline,
+    Overline,
+    LineThrough,
+}
+
+impl TextDecorationLine {
+    fn from_value(value:Option<&Value>) -> TextDecorationLine {
+        match keyword(value).as_deref() {
+            Some("underline") => TextDecorationLine::Underline,
+            Some("overline") => TextDecorationLine::Overline,
+            Some("line-through") => TextDecorationLine::LineThrough,
+            _ => TextDecorationLine::None,
+        }
+    }
+}
+
+/// A length that may be a percentage of the containing block, or auto. A math
+/// function with percentages in it is resolved along with them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Size {
+    Auto,
+    Px(f32),
+    Percent(f32),
+    Calc(Box<Calc>),
+}
+
+impl Size {
+    fn from_value(value:Option<&Value>) -> Size {
+        match value {
+            Some(Length(v, Unit::Px)) => Size::Px(*v),
+            Some(Length(v, Unit::Per)) => Size::Percent(*v),
+            Some(Number(v)) if *v == 0.0 => Size::Px(0.0),
+            Some(Value::Calc(calc)) if calc.is_length() == Some(true) => Size::Calc(Box::new(calc.clone())),
+            _ => Size::Auto,
+        }
+    }
+    /// In pixels, for a containing block of the width. Auto is zero.
+    pub fn to_px(&self, containing:f32) -> f32 {
+        match self {
+            Size::Auto => 0.0,
+            Size::Px(v) => *v,
+            Size::Percent(p) => containing * p / 100.0,
+            Size::Calc(calc) => calc.eval(&|v, unit| match unit {
+                Unit::Px => Some(v),
+                Unit::Per => Some(containing * v / 100.0),
+                _ => None,
+            }).unwrap_or(0.0),
+        }
+    }
+}
+
+// https://drafts.csswg.org/css-backgrounds-3/#border-width
+fn border_width(value:Option<&Value>) -> f32 {
+    match value {
+        Some(Length(v, Unit::Px)) => *v,
+        Some(Number(v)) => *v,
+        Some(Keyword(keyword)) => match keyword.to_ascii_lowercase().as_str() {
+            "thin" => 1.0,
+            "medium" => 3.0,
+            "thick" => 5.0,
+            _ => 0.0,
+        },
+        _ => 0.0,
+    }
+}
+
+pub(crate) fn to_color(value:Option<&Value>) -> Option<Color> {
+    match value {
+        Some(ColorValue(c)) => Some(c.clone()),
+        Some(HexColor(str)) => Color::from_hex(str),
+        Some(FunCall(f)) => Color::from_function(&f.name, &f.arguments),
+        Some(Keyword(name)) if name.eq_ignore_ascii_case("transparent") => Some(Color::transparent()),
+        Some(Keyword(name)) => named_color(name),
+        _ => None,
+    }
+}
+
+fn font_family(value:Option<&Value>) -> Vec<String> {
+    let items = match value {
+        Some(ArrayValue(items)) => items.iter().collect(),
+        Some(value) => vec![value],
+        None => vec![],
+    };
+    let families:Vec<String> = items.into_iter().filter_map(|item| match item {
+        Keyword(name) | StringLiteral(name) => Some(name.clone()),
+        _ => None,
+    }).collect();
+    if families.is_empty() {
+        vec![String::from("sans-serif")]
+    } else {
+        families
+    }
+}
+
+fn font_size(value:Option<&Value>) -> f32 {
+    match value {
+        Some(Length(v, Unit::Px)) => *v,
+        _ => INITIAL_FONT_SIZE,
+    }
+}
+
+// https://drafts.csswg.org/css-fonts-4/#font-weight-prop
+fn font_weight(value:Option<&Value>) -> i32 {
+    match value {
+        Some(Number(v)) => *v as i32,
+        Some(Keyword(keyword)) if keyword.eq_ignore_ascii_case("bold") => 700,
+        _ => 400,
+    }
+}
+
+#[test]
+fn test_computed_style() {
+    let values:PropertyMap = vec![
+        ("display", Keyword(String::from("list-item"))),
+        ("width", Length(50.0, Unit::Per)),
+        ("margin-left", Keyword(String::from("auto"))),
+        ("padding-top", Number(0.0)),
+        ("padding-left", Value::Calc(Calc::Difference(Box::new(Calc::Length(50.0, Unit::Per)), Box::new(Calc::Length(10.0, Unit::Px))))),
+        ("border-left-width", Keyword(String::from("thick"))),
+        ("border-right-width", Length(2.0, Unit::Px)),
+        ("color", Keyword(String::from("red"))),
+        ("font-family", ArrayValue(vec![StringLiteral(String::from("Open Sans")), Keyword(String::from("serif"))])),
+        ("font-weight", Keyword(String::from("bold"))),
+        ("font-style", Keyword(String::from("italic"))),
+        ("text-align", Keyword(String::from("center"))),
+        ("white-space", Keyword(String::from("pre-wrap"))),
+    ].into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+    let style = ComputedStyle::from_values(&values);
+    assert_eq!(style.display, Display::ListItem);
+    assert_eq!(style.width, Size::Percent(50.0));
+    assert_eq!(style.width.to_px(300.0), 150.0);
+    assert_eq!(style.margin_left, Size::Auto);
+    assert_eq!(style.padding_top, Size::Px(0.0));
+    assert_eq!(style.padding_left.to_px(300.0), 140.0);
+    assert_eq!(style.border_left_width, 5.0);
+    assert_eq!(style.border_right_width, 2.0);
+    assert_eq!(style.border_top_width, 0.0);
+    assert_eq!(Some(style.color), Color::from_hex("#ff0000"));
+    assert_eq!(style.background_color, None);
+    assert_eq!(style.font_family, vec!["Open Sans", "serif"]);
+    assert_eq!(style.font_weight, 700);
+    assert_eq!(style.font_style, FontStyle::Italic);
+    assert_eq!(style.text_align, TextAlign::Center);
+    assert_eq!(style.white_space, WhiteSpace::PreWrap);
+    assert_eq!(style.position, Position::Static);
+    assert_eq!(style.vertical_align, VerticalAlign::Baseline);
+
+    // keywords in any case
+    let values:PropertyMap = vec![
+        ("display", Keyword(String::from("BLOCK"))),
+        ("border-top-width", Keyword(String::from("Thin"))),
+        ("font-weight", Keyword(String::from("Bold"))),
+        ("font-style", Keyword(String::from("ITALIC"))),
+        ("text-decoration-line", Keyword(String::from("Underline"))),
+    ].into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+    let style = ComputedStyle::from_values(&values);
+    assert_eq!(style.display, Display::Block);
+    assert_eq!(style.border_top_width, 1.0);
+    assert_eq!(style.font_weight, 700);
+    assert_eq!(style.font_style, FontStyle::Italic);
+    assert_eq!(style.text_decoration_line, TextDecorationLine::Underline);
+}
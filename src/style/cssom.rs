@@ -0,0 +1,232 @@
+// https://drafts.csswg.org/cssom-1/
+//
+// The CSSOM methods for reading and changing stylesheets from code, named as
+// in the spec. Sheets hold their declarations with the shorthands expanded,
+// so rules and properties added here are expanded the same way. A sheet taken
+// with `StylesheetSet::edit` marks the set changed and the shell styles the
+// document again.
+
+use crate::css::{Stylesheet, RuleType, Rule, Declaration, Value, PseudoElement, parse_declarations, parse_selector_list,
+    parse_stylesheet_with_diagnostics};
+use crate::dom::NodeId;
+use crate::net::{BrowserError, Diagnostic};
+use crate::style::{StyledTree, StyledNode, expand_styles};
+use crate::style::shorthand::expand_shorthand;
+use crate::style::supports::supports_declaration;
+use std::rc::Rc;
+
+#[allow(non_snake_case)]
+impl Stylesheet {
+    /// The rules at the top level of the sheet. The rules inside `@media` and
+    /// the other block rules are in their `rules`.
+    pub fn cssRules(&self) -> &[RuleType] {
+        &self.rules
+    }
+    /// A rule to change, like through `setProperty`.
+    pub fn rule_mut(&mut self, index:usize) -> Option<&mut RuleType> {
+        self.rules.get_mut(index)
+    }
+    /// Parses a single rule and puts it at `index`, which is returned. An
+    /// `@import` added this way isn't loaded, and an `@font-face` doesn't add
+    /// its font to the font cache.
+    pub fn insertRule(&mut self, rule:&str, index:usize) -> Result<usize, BrowserError> {
+        if index > self.rules.len() {
+            return Err(BrowserError::IndexError(index));
+        }
+        let (mut parsed, skipped) = parse_stylesheet_with_diagnostics(rule);
+        if let Some(span) = skipped.into_iter().next() {
+            return Err(BrowserError::ParseError(span.diagnostic));
+        }
+        parsed.rules.retain(|rule| !matches!(rule, RuleType::Comment(_)));
+        if parsed.rules.len() != 1 {
+            return Err(BrowserError::ParseError(Diagnostic::new(rule.as_bytes(), 0, "a single rule")));
+        }
+        expand_styles(&mut parsed);
+        self.rules.insert(index, parsed.rules.remove(0));
+        Ok(index)
+    }
+    pub fn deleteRule(&mut self, index:usize) -> Result<(), BrowserError> {
+        if index >= self.rules.len() {
+            return Err(BrowserError::IndexError(index));
+        }
+        self.rules.remove(index);
+        Ok(())
+    }
+}
+
+#[allow(non_snake_case)]
+impl Rule {
+    pub fn selectorText(&self) -> String {
+        self.selectors.iter().map(|selector| selector.to_string()).collect::<Vec<String>>().join(", ")
+    }
+    /// Replaces the selectors. The rule is left alone if they don't parse.
+    pub fn setSelectorText(&mut self, text:&str) -> Result<(), BrowserError> {
+        self.selectors = parse_selector_list(text)?;
+        Ok(())
+    }
+    /// The value of a property as css text, or an empty string if the rule
+    /// doesn't set it. Shorthands only have one when they were set with var(),
+    /// and then their longhands don't.
+    pub fn getPropertyValue(&self, name:&str) -> String {
+        match self.declaration(name) {
+            Some(dec) => match &dec.value {
+                Value::Unparsed(unparsed) if unparsed.shorthand.is_some() => String::new(),
+                _ => dec.value_css(),
+            },
+            None => match self.shorthand_with_var(name) {
+                Some(dec) => dec.value_css(),
+                None => String::new(),
+            },
+        }
+    }
+    /// `important` if the property is set with `!important`, and empty otherwise.
+    pub fn getPropertyPriority(&self, name:&str) -> String {
+        let important = match self.declaration(name).or_else(|| self.shorthand_with_var(name)) {
+            Some(dec) => dec.important,
+            None => false,
+        };
+        String::from(if important { "important" } else { "" })
+    }
+    /// Sets a property, or the longhands of a shorthand, where the rule
+    /// already has them or else at the end. `priority` is `important` or
+    /// empty. A value the property doesn't take is an error and an empty one
+    /// removes the property.
+    pub fn setProperty(&mut self, name:&str, value:&str, priority:&str) -> Result<(), BrowserError> {
+        let important = match priority {
+            "" => false,
+            "important" => true,
+            _ => return Err(BrowserError::ParseError(Diagnostic::new(priority.as_bytes(), 0, "important or nothing"))),
+        };
+        if value.trim().is_empty() {
+            self.removeProperty(name);
+            return Ok(());
+        }
+        let text = format!("{}: {}{}", name, value, if important { " !important" } else { "" });
+        let (mut declarations, skipped) = parse_declarations(&text);
+        if let Some(span) = skipped.into_iter().next() {
+            return Err(BrowserError::ParseError(span.diagnostic));
+        }
+        let dec = match declarations.pop() {
+            Some(dec) if declarations.is_empty() && supports_declaration(&dec) => dec,
+            _ => return Err(BrowserError::ParseError(Diagnostic::new(text.as_bytes(), name.len() + 2, "a value the property takes"))),
+        };
+        let mut longhands = vec![];
+        expand_shorthand(&dec, &mut longhands);
+        for longhand in longhands {
+            match self.declarations.iter().position(|dec| dec.name == longhand.name) {
+                Some(first) => {
+                    // later ones would win over the one changed in place
+                    let mut i = 0;
+                    self.declarations.retain(|dec| {
+                        i += 1;
+                        i - 1 == first || dec.name != longhand.name
+                    });
+                    self.declarations[first] = longhand;
+                }
+                None => self.declarations.push(longhand),
+            }
+        }
+        Ok(())
+    }
+    /// Removes a property, or the longhands of a shorthand, and returns the
+    /// value it had.
+    pub fn removeProperty(&mut self, name:&str) -> String {
+        let value = self.getPropertyValue(name);
+        let mut longhands = vec![];
+        expand_shorthand(&Declaration { name: name.to_string(), value: Value::Keyword(String::from("initial")), important: false }, &mut longhands);
+        self.declarations.retain(|dec| !longhands.iter().any(|longhand| longhand.name == dec.name));
+        value
+    }
+
+    // the declaration that wins for a property, as the last one in the rule
+    // wins unless an earlier one is important
+    fn declaration(&self, name:&str) -> Option<&Declaration> {
+        let last = self.declarations.iter().rfind(|dec| dec.name == name);
+        self.declarations.iter().rfind(|dec| dec.name == name && dec.important).or(last)
+    }
+    // a longhand holding a shorthand with var() in it
+    fn shorthand_with_var(&self, name:&str) -> Option<&Declaration> {
+        self.declarations.iter().rfind(|dec| matches!(&dec.value, Value::Unparsed(unparsed) if unparsed.shorthand.as_deref() == Some(name)))
+    }
+}
+
+#[allow(non_snake_case)]
+impl StyledTree {
+    /// The styled node of an element, or of its `::before` or `::after`, with
+    /// the computed values. It is None if the element has no box of that kind.
+    pub fn getComputedStyle(&self, node:NodeId, pseudo_element:Option<PseudoElement>) -> Option<Rc<StyledNode>> {
+        find_styled_node(&self.root.borrow(), node, pseudo_element)
+    }
+}
+
+fn find_styled_node(styled:&Rc<StyledNode>, node:NodeId, pseudo_element:Option<PseudoElement>) -> Option<Rc<StyledNode>> {
+    // the text inside a pseudo-element's box has its node and pseudo-element too
+    if styled.node == node && styled.pseudo_element == pseudo_element && styled.text.is_none() {
+        return Some(Rc::clone(styled));
+    }
+    styled.children.borrow().iter().find_map(|child| find_styled_node(child, node, pseudo_element))
+}
+
+#[test]
+fn test_cssom() {
+    use crate::css::{parse_stylesheet, MediaContext, Unit};
+    use crate::dom::load_doc_from_bytestring;
+    use crate::net::StylesheetSet;
+    use crate::style::dom_tree_to_stylednodes;
+    let doc = load_doc_from_bytestring(b"<div id=a><p class=x>text</p></div>").unwrap();
+    let p = doc.querySelector("p").unwrap().unwrap();
+    let mut styles = StylesheetSet::new();
+    let mut sheet = parse_stylesheet("p { color: red; margin: 1px 2px } @media print { p { color: blue } }").unwrap();
+    expand_styles(&mut sheet);
+    styles.append(sheet);
+    let color = |styles:&StylesheetSet| dom_tree_to_stylednodes(&doc, styles, &MediaContext::default())
+        .getComputedStyle(p, None).and_then(|node| node.value("color"));
+    assert_eq!(color(&styles), Some(Value::Keyword(String::from("red"))));
+    assert!(!styles.take_changed());
+
+    // inserting restyles
+    let sheet = styles.edit(0).unwrap();
+    assert_eq!(sheet.insertRule("div > .x { color: green; border: 2px solid }", 1).unwrap(), 1);
+    assert_eq!(sheet.cssRules().len(), 3);
+    assert!(styles.take_changed());
+    assert!(!styles.take_changed());
+    assert_eq!(color(&styles), Some(Value::Keyword(String::from("green"))));
+
+    let sheet = styles.edit(0).unwrap();
+    let rule = match sheet.rule_mut(1) {
+        Some(RuleType::Rule(rule)) => rule,
+        rule => panic!("not a style rule {:?}", rule),
+    };
+    assert_eq!(rule.selectorText(), "div > .x");
+    assert_eq!(rule.getPropertyValue("border-top-style"), "solid");
+    assert_eq!(rule.getPropertyValue("border"), "");
+    rule.setProperty("color", "rgb(0 0 255)", "important").unwrap();
+    assert_eq!(rule.getPropertyValue("color"), "rgb(0, 0, 255)");
+    assert_eq!(rule.getPropertyPriority("color"), "important");
+    rule.setProperty("margin", "var(--gap) 4px", "").unwrap();
+    assert_eq!(rule.getPropertyValue("margin"), "var(--gap) 4px");
+    assert_eq!(rule.getPropertyValue("margin-top"), "");
+    assert_eq!(rule.removeProperty("border"), "");
+    assert_eq!(rule.getPropertyValue("border-top-width"), "");
+    assert!(rule.setProperty("margin", "red", "").is_err());
+    assert!(rule.setProperty("colour", "red", "").is_err());
+    assert!(rule.setSelectorText("p >").is_err());
+    rule.setSelectorText("#a p").unwrap();
+    assert_eq!(rule.to_string(), "#a p { color: rgb(0, 0, 255) !important; margin: var(--gap) 4px; }");
+
+    // width in px after the edit, which the rule now sets with calc()
+    rule.setProperty("width", "calc(10px + 1em)", "").unwrap();
+    let tree = dom_tree_to_stylednodes(&doc, &styles, &MediaContext::default());
+    assert_eq!(tree.getComputedStyle(p, None).unwrap().value("width"), Some(Value::Length(28.0, Unit::Px)));
+    assert!(tree.getComputedStyle(p, Some(PseudoElement::Before)).is_none());
+
+    let sheet = styles.edit(0).unwrap();
+    assert!(matches!(sheet.insertRule("p { color: red }", 4), Err(BrowserError::IndexError(4))));
+    assert!(matches!(sheet.insertRule("p { color: red } a { }", 0), Err(BrowserError::ParseError(_))));
+    assert!(matches!(sheet.insertRule("p > { color: red }", 0), Err(BrowserError::ParseError(_))));
+    assert!(matches!(sheet.deleteRule(3), Err(BrowserError::IndexError(3))));
+    sheet.deleteRule(1).unwrap();
+    assert_eq!(sheet.to_string(), "p { color: red; margin-top: 1px; margin-right: 2px; margin-bottom: 1px; margin-left: 2px; }\n\
+        @media print {\n  p { color: blue; }\n}\n");
+    assert_eq!(color(&styles), Some(Value::Keyword(String::from("red"))));
+}
@@ -0,0 +1,228 @@
+// https://drafts.csswg.org/css-content-3/#content-property
+// https://drafts.csswg.org/css-lists-3/#auto-numbering
+//
+// Evaluates the `content` of ::before and ::after. Counters and the quote
+// depth depend on everything before them in the document, so they are tracked
+// while the styled tree is built in document order.
+
+use crate::css::Value;
+use crate::css::Value::{Keyword, StringLiteral, ArrayValue, FunCall, Number};
+use crate::dom::{Document, NodeId, NodeType};
+use std::collections::HashMap;
+
+type PropertyMap = HashMap<String, Value>;
+
+#[derive(Debug, Default)]
+pub(crate) struct GeneratedContent {
+    // every counter in scope, outermost first
+    counters: Vec<(String, i32)>,
+    // where the counters created by the current set of siblings start
+    level: usize,
+    quote_depth: usize,
+}
+
+impl GeneratedContent {
+    /// Starts the children of an element. Counters they create go out of
+    /// scope at the matching `leave`.
+    pub fn enter(&mut self) -> usize {
+        std::mem::replace(&mut self.level, self.counters.len())
+    }
+
+    pub fn leave(&mut self, level:usize) {
+        self.counters.truncate(self.level);
+        self.level = level;
+    }
+
+    /// Applies `counter-reset`, `counter-increment` and `counter-set`, in that order.
+    pub fn update_counters(&mut self, values:&PropertyMap) {
+        for (name, value) in counter_list(values.get("counter-reset"), 0) {
+            let level = self.level;
+            match self.counters[level..].iter_mut().find(|(n, _)| *n == name) {
+                Some(counter) => counter.1 = value,
+                None => self.counters.push((name, value)),
+            }
+        }
+        for (name, by) in counter_list(values.get("counter-increment"), 1) {
+            *self.counter(&name) += by;
+        }
+        for (name, value) in counter_list(values.get("counter-set"), 0) {
+            *self.counter(&name) = value;
+        }
+    }
+
+    // the innermost counter with the name. a new one starts at zero if there isn't one.
+    fn counter(&mut self, name:&str) -> &mut i32 {
+        let index = match self.counters.iter().rposition(|(n, _)| n == name) {
+            Some(index) => index,
+            None => {
+                self.counters.push((name.to_string(), 0));
+                self.counters.len() - 1
+            }
+        };
+        &mut self.counters[index].1
+    }
+
+    /// The text of a pseudo-element, or None if its `content` doesn't generate a box.
+    pub fn content_text(&mut self, doc:&Document, id:NodeId, values:&PropertyMap) -> Option<String> {
+        let items = match values.get("content")? {
+            ArrayValue(items) => items.clone(),
+            value => vec![value.clone()],
+        };
+        let mut text = String::new();
+        for item in items {
+            match item {
+                StringLiteral(str) => text.push_str(&str),
+                Keyword(keyword) => match keyword.as_str() {
+                    "none" | "normal" => return None,
+                    "open-quote" => {
+                        text.push_str(&quote(values, self.quote_depth, 0));
+                        self.quote_depth += 1;
+                    }
+                    "close-quote" if self.quote_depth > 0 => {
+                        self.quote_depth -= 1;
+                        text.push_str(&quote(values, self.quote_depth, 1));
+                    }
+                    "no-open-quote" => self.quote_depth += 1,
+                    "no-close-quote" => self.quote_depth = self.quote_depth.saturating_sub(1),
+                    _ => {}
+                },
+                FunCall(call) => {
+                    let arg = |i:usize| match call.arguments.get(i) {
+                        Some(Keyword(str)) | Some(StringLiteral(str)) => str.clone(),
+                        _ => String::new(),
+                    };
+                    match call.name.as_str() {
+                        "attr" => if let NodeType::Element(ed) = &doc.node(id).node_type {
+                            text.push_str(ed.attributes.get(&arg(0).to_ascii_lowercase()).map(|v| v.as_str()).unwrap_or(""));
+                        },
+                        "counter" => {
+                            let value = *self.counter(&arg(0));
+                            text.push_str(&format_counter(value, &arg(1)));
+                        }
+                        "counters" => {
+                            let name = arg(0);
+                            self.counter(&name);
+                            let style = arg(2);
+                            let parts:Vec<String> = self.counters.iter()
+                                .filter(|(n, _)| *n == name)
+                                .map(|(_, value)| format_counter(*value, &style))
+                                .collect();
+                            text.push_str(&parts.join(&arg(1)));
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(text)
+    }
+}
+
+// names with an optional integer after each, like `counter-reset: a 2 b`
+fn counter_list(value:Option<&Value>, default:i32) -> Vec<(String, i32)> {
+    let items = match value {
+        Some(ArrayValue(items)) => items.clone(),
+        Some(value) => vec![value.clone()],
+        None => vec![],
+    };
+    let mut list:Vec<(String, i32)> = vec![];
+    for item in items {
+        match item {
+            Keyword(name) if name != "none" => list.push((name, default)),
+            Number(n) => if let Some(last) = list.last_mut() {
+                last.1 = n as i32;
+            },
+            _ => {}
+        }
+    }
+    list
+}
+
+// the opening (0) or closing (1) quote for a nesting depth
+fn quote(values:&PropertyMap, depth:usize, side:usize) -> String {
+    let pairs:Vec<String> = match values.get("quotes") {
+        Some(Keyword(none)) if none == "none" => return String::new(),
+        Some(ArrayValue(items)) => items.iter().filter_map(|item| match item {
+            StringLiteral(str) => Some(str.clone()),
+            _ => None,
+        }).collect(),
+        _ => vec![],
+    };
+    let pairs = if pairs.len() >= 2 {
+        pairs
+    } else {
+        vec!["\u{201C}", "\u{201D}", "\u{2018}", "\u{2019}"].into_iter().map(String::from).collect()
+    };
+    let index = depth.min(pairs.len() / 2 - 1) * 2 + side;
+    pairs[index].clone()
+}
+
+// https://drafts.csswg.org/css-counter-styles-3/#predefined-counters
+pub fn format_counter(value:i32, style:&str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => String::from("\u{2022}"),
+        "circle" => String::from("\u{25E6}"),
+        "square" => String::from("\u{25AA}"),
+        "decimal-leading-zero" if (0..10).contains(&value) => format!("0{}", value),
+        "lower-roman" => roman(value).unwrap_or_else(|| value.to_string()).to_lowercase(),
+        "upper-roman" => roman(value).unwrap_or_else(|| value.to_string()),
+        "lower-alpha" | "lower-latin" => alphabetic(value, "abcdefghijklmnopqrstuvwxyz").unwrap_or_else(|| value.to_string()),
+        "upper-alpha" | "upper-latin" => alphabetic(value, "ABCDEFGHIJKLMNOPQRSTUVWXYZ").unwrap_or_else(|| value.to_string()),
+        "lower-greek" => alphabetic(value, "αβγδεζηθικλμνξοπρστυφχψω").unwrap_or_else(|| value.to_string()),
+        _ => value.to_string(),
+    }
+}
+
+fn roman(value:i32) -> Option<String> {
+    if !(1..4000).contains(&value) {
+        return None;
+    }
+    let numerals = [(1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I")];
+    let mut value = value;
+    let mut out = String::new();
+    for (n, numeral) in numerals.iter() {
+        while value >= *n {
+            out.push_str(numeral);
+            value -= n;
+        }
+    }
+    Some(out)
+}
+
+// a, b, ... z, aa, ab, ... for the letters given, which for greek skip the
+// final sigma between rho and sigma
+fn alphabetic(value:i32, letters:&str) -> Option<String> {
+    if value < 1 {
+        return None;
+    }
+    let letters:Vec<char> = letters.chars().collect();
+    let count = letters.len() as u32;
+    let mut value = value as u32;
+    let mut out = vec![];
+    while value > 0 {
+        value -= 1;
+        out.push(letters[(value % count) as usize]);
+        value /= count;
+    }
+    Some(out.into_iter().rev().collect())
+}
+
+#[test]
+fn test_format_counter() {
+    assert_eq!(format_counter(4, "decimal"), "4");
+    assert_eq!(format_counter(4, ""), "4");
+    assert_eq!(format_counter(4, "decimal-leading-zero"), "04");
+    assert_eq!(format_counter(1994, "upper-roman"), "MCMXCIV");
+    assert_eq!(format_counter(14, "lower-roman"), "xiv");
+    assert_eq!(format_counter(0, "lower-roman"), "0");
+    assert_eq!(format_counter(28, "lower-alpha"), "ab");
+    assert_eq!(format_counter(26, "upper-latin"), "Z");
+    assert_eq!(format_counter(2, "lower-greek"), "\u{3B2}");
+    assert_eq!(format_counter(18, "lower-greek"), "\u{3C3}");
+    assert_eq!(format_counter(24, "lower-greek"), "\u{3C9}");
+    assert_eq!(format_counter(25, "lower-greek"), "\u{3B1}\u{3B1}");
+    assert_eq!(format_counter(2, "none"), "");
+}
@@ -0,0 +1,111 @@
+// https://drafts.csswg.org/css-cascade-5/#layering
+//
+// Flattens the stylesheets into the style rules that apply, in the order they
+// appear. The conditions of @media, @supports and @import are evaluated on the
+// way, and the rules of imported sheets go where the @import is. Each rule gets
+// the rank of its cascade layer: layers rank in the order they are first
+// named, the sub-layers of a layer rank below the layer's own rules, and rules
+// outside of any layer rank above all of them.
+
+use crate::css::{MediaContext, Origin, Rule, RuleType};
+use crate::net::StylesheetSet;
+use crate::style::supports::supports;
+use std::collections::HashMap;
+
+/// A style rule, its origin and the rank of its layer.
+pub type ActiveRule<'a> = (Origin, usize, &'a Rule);
+
+type LayerPath = Vec<String>;
+
+pub fn active_rules<'a>(styles:&'a StylesheetSet, media:&MediaContext) -> Vec<ActiveRule<'a>> {
+    let mut collector = Collector { media, positions: HashMap::new(), children: HashMap::new(), anonymous: 0, rules: vec![] };
+    for sheet in styles.stylesheets.iter() {
+        collector.collect(&sheet.rules, sheet.origin, &[]);
+    }
+    let keys:Vec<Vec<usize>> = collector.rules.iter().map(|(_, path, _)| collector.sort_key(path)).collect();
+    let mut ranks = keys.clone();
+    ranks.sort();
+    ranks.dedup();
+    collector.rules.iter().zip(keys)
+        .map(|((origin, _, rule), key)| (*origin, ranks.binary_search(&key).unwrap(), *rule))
+        .collect()
+}
+
+struct Collector<'a, 'm> {
+    media: &'m MediaContext,
+    // where each layer is among its siblings, in the order they were named
+    positions: HashMap<LayerPath, usize>,
+    children: HashMap<LayerPath, usize>,
+    anonymous: usize,
+    rules: Vec<(Origin, LayerPath, &'a Rule)>,
+}
+
+impl<'a, 'm> Collector<'a, 'm> {
+    fn collect(&mut self, rules:&'a [RuleType], origin:Origin, layer:&[String]) {
+        for rule in rules {
+            match rule {
+                RuleType::Rule(rule) => self.rules.push((origin, layer.to_vec(), rule)),
+                RuleType::Media(media_rule) if media_rule.queries.matches(self.media) => {
+                    self.collect(&media_rule.rules, origin, layer);
+                }
+                RuleType::Supports(supports_rule) if supports(&supports_rule.condition) => {
+                    self.collect(&supports_rule.rules, origin, layer);
+                }
+                RuleType::LayerStatement(names) => {
+                    for name in names {
+                        self.sublayer(layer, Some(name));
+                    }
+                }
+                RuleType::Layer(layer_rule) => {
+                    let path = self.sublayer(layer, layer_rule.name.as_ref());
+                    self.collect(&layer_rule.rules, origin, &path);
+                }
+                RuleType::Import(import) => {
+                    let applies = import.media.matches(self.media) && match &import.supports {
+                        Some(condition) => supports(condition),
+                        None => true,
+                    };
+                    if let (true, Some(sheet)) = (applies, &import.stylesheet) {
+                        let path = match &import.layer {
+                            Some(name) if name.is_empty() => self.sublayer(layer, None),
+                            Some(name) => self.sublayer(layer, Some(name)),
+                            None => layer.to_vec(),
+                        };
+                        self.collect(&sheet.rules, origin, &path);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // the path of a layer inside another, named the first time it is seen. a
+    // dotted name is a layer inside a layer, and no name is a new anonymous one.
+    fn sublayer(&mut self, parent:&[String], name:Option<&String>) -> LayerPath {
+        let mut path = parent.to_vec();
+        match name {
+            Some(name) => path.extend(name.split('.').map(String::from)),
+            None => {
+                self.anonymous += 1;
+                // a name no stylesheet can write
+                path.push(format!(" anonymous {}", self.anonymous));
+            }
+        }
+        for len in parent.len() + 1..=path.len() {
+            if !self.positions.contains_key(&path[..len]) {
+                let count = self.children.entry(path[..len - 1].to_vec()).or_insert(0);
+                self.positions.insert(path[..len].to_vec(), *count);
+                *count += 1;
+            }
+        }
+        path
+    }
+
+    // sorts layers by the positions along their path, with a layer's own rules
+    // after all of its sub-layers
+    fn sort_key(&self, path:&[String]) -> Vec<usize> {
+        let mut key:Vec<usize> = (1..=path.len()).map(|len| self.positions[&path[..len]]).collect();
+        key.push(usize::MAX);
+        key
+    }
+}
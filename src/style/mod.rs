@@ -1,63 +1,59 @@
-use crate::dom::{Node, ElementData, NodeType, load_doc_from_bytestring, strip_empty_nodes};
-use crate::css::{Selector, SimpleSelector, Rule, Stylesheet, Specificity, Value, Color, parse_stylesheet_from_bytestring, Unit, RuleType, Declaration};
+use crate::dom::{Document, NodeId, NodeType, ElementData, ElementState, load_doc_from_bytestring};
+use crate::css::{Selector, SimpleSelector, AttributeSelector, AttributeOp, Combinator, PseudoClass, PseudoElement, Rule, Stylesheet, Specificity, Origin, Value, Color, MediaContext, parse_stylesheet_from_bytestring, Unit, RuleType, Declaration, Token, parse_declarations};
 use std::collections::HashMap;
 use crate::css::Selector::{Simple, Ancestor};
 use crate::dom::NodeType::{Element, Text, Meta};
-use crate::css::Value::{Keyword, ColorValue, Length, HexColor,};
+use crate::css::Value::{Keyword, Length};
 use crate::net::{load_stylesheet_from_net, relative_filepath_to_url, load_doc_from_net, StylesheetSet, load_stylesheets_new};
-use std::fs::File;
-use std::io::BufReader;
 use crate::render::FontCache;
 use std::cell::RefCell;
 use std::rc::{Rc, Weak};
 use crate::layout::{Brush, standard_test_run, standard_test_run_no_default};
+use crate::style::computed::{compute_values, font_size, ComputeContext};
+use crate::style::computed_style::to_color;
+use crate::style::generated::GeneratedContent;
+use crate::style::layers::{ActiveRule, active_rules};
+use crate::style::shorthand::expand_shorthand;
 
-type PropertyMap = HashMap<String, Value>;
+mod colors;
+mod computed;
+mod computed_style;
+mod cssom;
+mod generated;
+mod layers;
+mod shorthand;
+mod supports;
+mod variables;
 
+pub use self::colors::{NAMED_COLORS, named_color};
+pub use self::supports::{supports, supports_declaration};
+pub use self::computed::INITIAL_FONT_SIZE;
+pub(crate) use self::shorthand::accepts_declaration;
+pub use self::computed_style::{ComputedStyle, Display, Position, WhiteSpace, TextAlign, VerticalAlign, FontStyle, TextDecorationLine, Size};
 
-fn load_css_json() -> HashMap<String, Color>{
-    println!("loading css-color-names.json");
-    let file = File::open("res/css-color-names.json").unwrap();
-    let reader = BufReader::new(file);
-    let json:serde_json::Value = serde_json::from_reader(reader).unwrap();
+type PropertyMap = HashMap<String, Value>;
 
-    let mut map:HashMap<String,Color> = HashMap::new();
-    if let serde_json::Value::Object(obj) = json {
-        for (key, value) in obj.iter() {
-            if let serde_json::Value::String(val) = value {
-                map.insert(key.to_string(),Color::from_hex(&*val));
-            }
-        }
-    }
-    map
-}
 
-lazy_static! {
-    pub static ref COLORS_MAP: HashMap<String, Color> = { load_css_json() };
-}
+/// The same as `named_color`, kept for existing callers.
 pub fn find_color_lazy_static(name: &str) -> Option<Color> {
-    COLORS_MAP.get(&name.to_lowercase()).cloned()
-}
-
-#[derive(Debug)]
-pub enum Display {
-    Block,
-    Inline,
-    InlineBlock,
-    Table,
-    TableRowGroup,
-    TableRow,
-    TableCell,
-    ListItem,
-    None,
+    named_color(name)
 }
 
 #[derive(Debug)]
 pub struct StyledNode {
-    pub node: Node,
+    /// for the box of a pseudo-element this is the element it belongs to
+    pub node: NodeId,
     pub children: RefCell<Vec<Rc<StyledNode>>>,
     parent: RefCell<Weak<StyledNode>>,
+    /// the cascaded values, as the stylesheets give them
     pub specified_values: PropertyMap,
+    /// every property that applies, with inheritance and relative lengths resolved
+    pub computed_values: PropertyMap,
+    /// the computed values layout reads
+    pub style: ComputedStyle,
+    pub pseudo_element: Option<PseudoElement>,
+    /// generated content, laid out in place of a dom text node
+    pub text: Option<String>,
 }
 
 #[derive(Debug)]
@@ -65,39 +61,74 @@ pub struct StyledTree {
     pub root: RefCell<Rc<StyledNode>>,
 }
 impl StyledTree {
-    pub fn new() -> Self {
+    pub fn new(node:NodeId) -> Self {
         StyledTree {
             root: RefCell::new(Rc::new(StyledNode {
-                node: Node { node_type: NodeType::Comment(String::from("comment")), children: vec![] },
+                node,
                 children: RefCell::new(vec![]),
                 parent: RefCell::new(Default::default()),
-                specified_values: Default::default()
+                specified_values: Default::default(),
+                computed_values: Default::default(),
+                style: Default::default(),
+                pseudo_element: None,
+                text: None,
             }))
         }
     }
-    pub fn make(&self) -> Rc<StyledNode> {
+    pub fn make(&self, node:NodeId) -> Rc<StyledNode> {
         Rc::new(StyledNode{
-            node: Node {
-                node_type: NodeType::Comment(String::from("comment")),
-                children: vec![]
-            },
+            node,
             children: RefCell::new(vec![]),
             parent: RefCell::new(Weak::new()),
-            specified_values: Default::default()
+            specified_values: Default::default(),
+            computed_values: Default::default(),
+            style: Default::default(),
+            pseudo_element: None,
+            text: None,
         })
     }
-    pub fn make_with(&self, node:Node, specified_values:PropertyMap, children:RefCell<Vec<Rc<StyledNode>>>) -> Rc<StyledNode> {
+    pub fn make_with(&self, node:NodeId, specified_values:PropertyMap, computed_values:PropertyMap, children:RefCell<Vec<Rc<StyledNode>>>) -> Rc<StyledNode> {
         let rc =  Rc::new(StyledNode {
             node,
             children,
             parent: RefCell::new(Default::default()),
             specified_values,
+            style: ComputedStyle::from_values(&computed_values),
+            computed_values,
+            pseudo_element: None,
+            text: None,
         });
         for ch in rc.children.borrow().iter() {
             *ch.parent.borrow_mut() = Rc::downgrade(&rc);
         }
         return rc;
     }
+    /// A ::before or ::after box holding its generated text.
+    pub fn make_pseudo(&self, node:NodeId, pseudo_element:PseudoElement, specified_values:PropertyMap, computed_values:PropertyMap, text:String) -> Rc<StyledNode> {
+        let text_values = compute_values(&PropertyMap::new(), Some(&computed_values), &ComputeContext::default());
+        let text = Rc::new(StyledNode {
+            node,
+            children: RefCell::new(vec![]),
+            parent: RefCell::new(Weak::new()),
+            specified_values: Default::default(),
+            style: ComputedStyle::from_values(&text_values),
+            computed_values: text_values,
+            pseudo_element: Some(pseudo_element),
+            text: Some(text),
+        });
+        let rc = Rc::new(StyledNode {
+            node,
+            children: RefCell::new(vec![Rc::clone(&text)]),
+            parent: RefCell::new(Weak::new()),
+            specified_values,
+            style: ComputedStyle::from_values(&computed_values),
+            computed_values,
+            pseudo_element: Some(pseudo_element),
+            text: None,
+        });
+        *text.parent.borrow_mut() = Rc::downgrade(&rc);
+        rc
+    }
     pub fn set_root(&self, node:Rc<StyledNode>) {
         *self.root.borrow_mut() = node;
     }
@@ -111,7 +142,7 @@ impl StyledTree {
 
 impl StyledNode {
     pub fn value(&self, name: &str) -> Option<Value> {
-        self.specified_values.get(name).cloned()
+        self.computed_values.get(name).cloned()
     }
     pub fn lookup(&self, name:&str, fallback_name: &str, default: &Value) -> Value {
         self.value(name).unwrap_or_else(||self.value(fallback_name)
@@ -136,130 +167,19 @@ impl StyledNode {
             _ => default.clone(),
         }
     }
-    pub fn lookup_text_decoration_line(&self) -> String {
-        let val = self.lookup_keyword("text-decoration-line", &Value::Keyword(String::from("none")));
-        if let Keyword(str) = val { str } else { "none".to_string() }
-    }
-    pub fn lookup_font_weight(&self, default:i32) -> i32{
-        match self.lookup("font-weight", "font-weight",&Keyword(String::from("normal"))) {
-            Keyword(str) => match str.as_str() {
-                "normal" => 400,
-                "bold" => 700,
-                "inherit" => {
-                    println!("!!!inherited font weight. this should already be taken care of!!!");
-                    default
-                }
-                _ => default,
-            },
-            Value::Number(v) => v as i32,
-            _ => default,
-        }
-    }
-    pub fn lookup_font_family(&self, font_cache:&mut FontCache) -> String {
-        let font_family_values = self.lookup(
-            "font-family",
-            "font-family",
-            &Value::Keyword(String::from("sans-serif")));
-        // println!("font family values: {:#?} {:#?}",font_family_values, self);
-        match font_family_values {
-            Value::ArrayValue(vals ) => {
-                for val in vals.iter() {
-                    match val {
-                        Value::StringLiteral(str) => {
-                            if font_cache.has_font_family(str) {
-                                return String::from(str);
-                            }
-                        }
-                        Value::Keyword(str) => {
-                            if font_cache.has_font_family(str) {
-                                return String::from(str);
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                println!("no valid font found in stack: {:#?}",vals);
-                String::from("sans-serif")
-            }
-            Value::Keyword(str) => str,
-            _ => String::from("sans-serif"),
-        }
-    }
-
     pub fn lookup_length_px(&self, name:&str, default:f32) -> f32 {
         match self.value(name) {
             Some(Length(v,_unit)) => v,
             _ => default,
         }
     }
+    // the computed font-size is always in pixels
     pub fn lookup_font_size(&self) -> f32 {
-        match self.value("font-size") {
-            Some(Length(v, unit)) => {
-                match unit {
-                    Unit::Px  => v,
-                    Unit::Per => v/100.0 * self.parent.borrow().upgrade().unwrap().lookup_font_size(),
-                    Unit::Em  => v * self.parent.borrow().upgrade().unwrap().lookup_font_size(),
-                    Unit::Rem => v*18.0, //TODO: use the real document font-size for REMs
-                }
-            }
-            _ => {
-                println!("unrecognized font-size type {:#?}",self.value("font-size"));
-                return 10.0;
-            }
-        }
-
-    }
-
-    pub fn lookup_length_as_px(&self, name:&str, default:f32) -> f32{
-        if let Some(value) = self.value(name) {
-            match value {
-                Length(v, Unit::Px) =>  v,
-                Length(v, Unit::Em) =>  v*self.lookup_font_size(),
-                Length(v, Unit::Rem) => v*self.lookup_font_size(),
-                // TODO: use real document font size
-                Length(_v, Unit::Per) => {
-                    println!("WARNING: percentage in length_to_px. should have be converted to pixels already");
-                    default
-                }
-                _ => default,
-            }
-        } else {
-            default
-        }
-    }
-
-    pub fn display(&self) -> Display {
-        if let Text(_) = self.node.node_type {
-            return Display::Inline
-        }
-        match self.value("display") {
-            Some(Keyword(s)) => match &*s {
-                "block" => Display::Block,
-                "none" => Display::None,
-                "inline-block" => Display::InlineBlock,
-                "table" => Display::Table,
-                "table-row-group" => Display::TableRowGroup,
-                "table-row" => Display::TableRow,
-                "table-cell" => Display::TableCell,
-                "list-item" => Display::ListItem,
-                _ => {
-                    println!("WARNING: unsupported display keyword {}",s);
-                    Display::Inline
-                },
-            },
-            _ => Display::Inline,
-        }
+        self.style.font_size
     }
 
     pub fn color(&self, name: &str) -> Option<Color> {
-        match self.value(name) {
-            Some(ColorValue(c)) => Some(c),
-            Some(HexColor(str)) => Some(Color::from_hex(&str)),
-            Some(Keyword(name)) => find_color_lazy_static(&name),
-            Some(Length(_,_)) => None,
-            None => None,
-            _ => None,
-        }
+        to_color(self.computed_values.get(name))
     }
     pub fn insets(&self, name: &str) -> f32 {
         match self.value(name) {
@@ -269,285 +189,605 @@ impl StyledNode {
     }
 }
 
-fn matches(elem: &ElementData, selector: &Selector, ancestors:&mut Vec::<(&Node,&PropertyMap)>) -> bool {
+/// Whether the element matches the selector. Non-element nodes never match.
+pub fn matches(doc:&Document, id:NodeId, selector: &Selector) -> bool {
+    selector.pseudo_element().is_none() && matches_in_scope(doc, id, selector, None)
+}
+
+// whether the selector styles the ::before or ::after of the element, or the element itself
+fn matches_for(doc:&Document, id:NodeId, selector: &Selector, pseudo:Option<PseudoElement>) -> bool {
+    selector.pseudo_element() == pseudo && matches_in_scope(doc, id, selector, None)
+}
+
+// the scope is the element a :has() is being checked for
+fn matches_in_scope(doc:&Document, id:NodeId, selector: &Selector, scope:Option<NodeId>) -> bool {
+    let elem = match &doc.node(id).node_type {
+        Element(ed) => ed,
+        _ => return false,
+    };
     match *selector {
-        Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
+        Simple(ref simple_selector) => matches_simple_selector(doc, id, elem, simple_selector, scope),
         Ancestor(ref sel) => {
-            println!("ANCESTOR NOT SUPPORTED YET");
-
-            let child_match = matches(elem, &*sel.child, ancestors);
-            let mut parent_match = false;
-            if !ancestors.is_empty() {
-                let (parent_node,_) = &ancestors[0];
-                if let NodeType::Element(ed) = &parent_node.node_type {
-                    parent_match = matches(ed, &*sel.ancestor, ancestors);
+            if !matches_in_scope(doc, id, &sel.child, scope) {
+                return false;
+            }
+            let matches_ancestor = |other:NodeId| matches_in_scope(doc, other, &sel.ancestor, scope);
+            match sel.combinator {
+                Combinator::Child => doc.parent(id).map(matches_ancestor).unwrap_or(false),
+                Combinator::Descendant => {
+                    let mut parent = doc.parent(id);
+                    while let Some(p) = parent {
+                        if matches_ancestor(p) {
+                            return true;
+                        }
+                        parent = doc.parent(p);
+                    }
+                    false
+                }
+                Combinator::NextSibling => previous_element_sibling(doc, id).map(matches_ancestor).unwrap_or(false),
+                Combinator::SubsequentSibling => {
+                    let mut sibling = previous_element_sibling(doc, id);
+                    while let Some(sib) = sibling {
+                        if matches_ancestor(sib) {
+                            return true;
+                        }
+                        sibling = previous_element_sibling(doc, sib);
+                    }
+                    false
                 }
             }
-            child_match && parent_match
         }
     }
 }
 
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+fn is_element(doc:&Document, id:NodeId) -> bool {
+    matches!(doc.node(id).node_type, Element(_) | Meta(_))
+}
+
+fn tag_name(doc:&Document, id:NodeId) -> &str {
+    match &doc.node(id).node_type {
+        Element(ed) => &ed.tag_name,
+        Meta(_) => "meta",
+        _ => "",
+    }
+}
+
+// text and comments between elements don't count as siblings
+fn previous_element_sibling(doc:&Document, id:NodeId) -> Option<NodeId> {
+    let mut sibling = doc.previous_sibling(id);
+    while let Some(sib) = sibling {
+        if is_element(doc, sib) {
+            return Some(sib);
+        }
+        sibling = doc.previous_sibling(sib);
+    }
+    None
+}
+
+// the element siblings before and after the element, including itself
+fn element_siblings(doc:&Document, id:NodeId) -> Vec<NodeId> {
+    match doc.parent(id) {
+        Some(parent) => doc.children(parent).iter().cloned().filter(|ch| is_element(doc, *ch)).collect(),
+        None => vec![id],
+    }
+}
+
+// the 1 based position of the element among the siblings that pass the filter,
+// counting from the start or the end
+fn nth_index(doc:&Document, id:NodeId, from_end:bool, filter:&dyn Fn(NodeId) -> bool) -> i32 {
+    let mut siblings:Vec<NodeId> = element_siblings(doc, id).into_iter().filter(|sib| filter(*sib)).collect();
+    if from_end {
+        siblings.reverse();
+    }
+    siblings.iter().position(|sib| *sib == id).map(|i| i as i32 + 1).unwrap_or(0)
+}
+
+fn is_form_control(tag_name:&str) -> bool {
+    matches!(tag_name, "button" | "input" | "select" | "textarea" | "optgroup" | "option" | "fieldset")
+}
+
+fn matches_pseudo_class(doc:&Document, id:NodeId, elem: &ElementData, pc:&PseudoClass, scope:Option<NodeId>) -> bool {
+    let any = |list:&Vec<Selector>| list.iter().any(|sel| matches_in_scope(doc, id, sel, scope));
+    let same_type = |sib:NodeId| tag_name(doc, sib) == elem.tag_name;
+    let of = |list:&Vec<Selector>, sib:NodeId| list.is_empty() || list.iter().any(|sel| matches_in_scope(doc, sib, sel, scope));
+    let is_link = matches!(elem.tag_name.as_str(), "a" | "area" | "link") && elem.attributes.contains_key("href");
+    let disabled = doc.has_element_state(id, ElementState::Disabled)
+        || (is_form_control(&elem.tag_name) && elem.attributes.contains_key("disabled"));
+    match pc {
+        PseudoClass::Root => id == doc.root(),
+        PseudoClass::Empty => doc.children(id).iter().all(|ch| match &doc.node(*ch).node_type {
+            Text(text) => text.is_empty(),
+            NodeType::Comment(_) => true,
+            _ => false,
+        }),
+        PseudoClass::FirstChild => nth_index(doc, id, false, &|_| true) == 1,
+        PseudoClass::LastChild => nth_index(doc, id, true, &|_| true) == 1,
+        PseudoClass::OnlyChild => element_siblings(doc, id).len() == 1,
+        PseudoClass::FirstOfType => nth_index(doc, id, false, &same_type) == 1,
+        PseudoClass::LastOfType => nth_index(doc, id, true, &same_type) == 1,
+        PseudoClass::OnlyOfType => element_siblings(doc, id).into_iter().filter(|sib| same_type(*sib)).count() == 1,
+        PseudoClass::NthChild(nth, list) => of(list, id) && nth.matches(nth_index(doc, id, false, &|sib| of(list, sib))),
+        PseudoClass::NthLastChild(nth, list) => of(list, id) && nth.matches(nth_index(doc, id, true, &|sib| of(list, sib))),
+        PseudoClass::NthOfType(nth) => nth.matches(nth_index(doc, id, false, &same_type)),
+        PseudoClass::NthLastOfType(nth) => nth.matches(nth_index(doc, id, true, &same_type)),
+        PseudoClass::Not(list) => !any(list),
+        PseudoClass::Is(list) | PseudoClass::Where(list) => any(list),
+        // anything matched relative to this element is inside its parent
+        PseudoClass::Has(list) => {
+            let within = doc.parent(id).unwrap_or(id);
+            doc.descendants(within).into_iter()
+                .any(|other| list.iter().any(|sel| matches_in_scope(doc, other, sel, Some(id))))
+        }
+        PseudoClass::Scope => id == scope.unwrap_or_else(|| doc.root()),
+        PseudoClass::Hover => doc.has_element_state(id, ElementState::Hover),
+        PseudoClass::Active => doc.has_element_state(id, ElementState::Active),
+        PseudoClass::Focus => doc.has_element_state(id, ElementState::Focus),
+        PseudoClass::Visited => is_link && doc.has_element_state(id, ElementState::Visited),
+        PseudoClass::Link => is_link && !doc.has_element_state(id, ElementState::Visited),
+        PseudoClass::Checked => doc.has_element_state(id, ElementState::Checked),
+        PseudoClass::Disabled => disabled,
+        PseudoClass::Enabled => is_form_control(&elem.tag_name) && !disabled,
+    }
+}
+
+// in quirks mode ids and class names match without regard to case
+fn matches_simple_selector(doc:&Document, id:NodeId, elem: &ElementData, selector: &SimpleSelector, scope:Option<NodeId>) -> bool {
+    let quirks = doc.in_quirks_mode();
+    let same = |a:&str, b:&str| if quirks { a.eq_ignore_ascii_case(b) } else { a == b };
     //return false for mis-matches
     if selector.tag_name.iter().any(|name|  "*" != *name)
         && selector.tag_name.iter().any(|name| elem.tag_name != *name) {
             return false;
     }
-    if selector.id.iter().any(|id| elem.id() != Some(id)) {
+    if selector.id.iter().any(|id| !elem.id().map(|v| same(v, id)).unwrap_or(false)) {
         return false;
     }
     let elem_classes = elem.classes();
-    if selector.class.iter().any(|class| !elem_classes.contains(&**class)) {
+    if selector.class.iter().any(|class| !elem_classes.iter().any(|c| same(c, class))) {
+        return false
+    }
+    if selector.attributes.iter().any(|attr| !matches_attribute(elem, attr)) {
+        return false
+    }
+    if selector.pseudo_class.iter().any(|pc| !matches_pseudo_class(doc, id, elem, pc, scope)) {
         return false
     }
     //no non-matching selectors found, so it must be true
     true
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+fn matches_attribute(elem: &ElementData, selector: &AttributeSelector) -> bool {
+    let value = match elem.attributes.get(&selector.name) {
+        Some(value) => value,
+        None => return false,
+    };
+    let (value, expected) = if selector.case_insensitive {
+        (value.to_lowercase(), selector.value.to_lowercase())
+    } else {
+        (value.clone(), selector.value.clone())
+    };
+    match selector.op {
+        AttributeOp::Exists => true,
+        AttributeOp::Equals => value == expected,
+        AttributeOp::Includes => !expected.is_empty()
+            && !expected.contains(char::is_whitespace)
+            && value.split_whitespace().any(|v| v == expected),
+        AttributeOp::DashMatch => value == expected || value.starts_with(&format!("{}-", expected)),
+        AttributeOp::Prefix => !expected.is_empty() && value.starts_with(&expected),
+        AttributeOp::Suffix => !expected.is_empty() && value.ends_with(&expected),
+        AttributeOp::Substring => !expected.is_empty() && value.contains(&expected),
+    }
+}
+
+type MatchedRule<'a> = (Origin, usize, Specificity, &'a Rule);
 
 // return rule that matches, if any.
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule, ancestors:&mut Vec::<(&Node,&PropertyMap)>) -> Option<MatchedRule<'a>> {
+fn match_rule<'a>(doc:&Document, id:NodeId, rule: &'a Rule, origin:Origin, layer:usize, pseudo:Option<PseudoElement>) -> Option<MatchedRule<'a>> {
     rule.selectors.iter()
-        .find(|selector| matches(elem, selector, ancestors))
-        .map(|selector| (selector.specificity(), rule))
+        .filter(|selector| matches_for(doc, id, selector, pseudo))
+        .map(|selector| (origin, layer, selector.specificity(), rule))
+        .max_by_key(|(_, _, specificity, _)| *specificity)
 }
 
-fn only_real_rules(rtype:&RuleType) -> Option<&Rule> {
-    match rtype {
-        RuleType::Rule(rule) => Some(&rule),
-        _ => None,
-    }
-}
 //find all matching rules for an element
-fn matching_rules<'a>(elem: &ElementData, styles: &'a StylesheetSet, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> Vec<MatchedRule<'a>> {
-    let mut rules2:Vec<MatchedRule> = vec![];
-    for sheet in styles.stylesheets.iter() {
-        let mut rules:Vec<MatchedRule> = sheet.rules.iter()
-            .filter_map(only_real_rules)
-            .filter_map(|rule|match_rule(elem, &rule, ancestors)).collect();
-        rules2.append(&mut rules);
-    }
-    rules2
+fn matching_rules<'a>(doc:&Document, id:NodeId, rules:&[ActiveRule<'a>], pseudo:Option<PseudoElement>) -> Vec<MatchedRule<'a>> {
+    rules.iter()
+        .filter_map(|(origin, layer, rule)| match_rule(doc, id, rule, *origin, *layer, pseudo))
+        .collect()
 }
 
 // get all values set by all rules
-fn specified_values(elem: &ElementData, styles: &StylesheetSet, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> PropertyMap {
-    // println!("styling with ancestors {:#?}", ancestors.len());
-    // for an in ancestors.iter() {
-    //     println!("   ancestor {:#?} {:#?}", an.0.node_type, an.1);
-    // }
+fn specified_values(doc:&Document, id:NodeId, rules:&[ActiveRule], pseudo:Option<PseudoElement>) -> PropertyMap {
     let mut values:HashMap<String,Value> = HashMap::new();
-    let mut rules = matching_rules(elem, styles, ancestors);
+    let rules = matching_rules(doc, id, rules, pseudo);
+    let inline = match pseudo {
+        None => style_attribute(doc, id),
+        Some(_) => vec![],
+    };
 
-    //sort rules by specificity
-    rules.sort_by(|&(a,_),&(b,_)| a.cmp(&b));
-    for (_,rule) in rules {
+    // sort by cascade level, then the style attribute over rules, then layer,
+    // then specificity, then the order they appear in. important declarations
+    // reverse the order of the layers.
+    let mut cascade:Vec<(CascadeKey, Origin, &Declaration)> = vec![];
+    for (origin, layer, specificity, rule) in rules {
         for declaration in &rule.declarations {
-            // println!("checking {} {:#?}", declaration.name, declaration.value);
-            let vv = calculate_inherited_property_value(declaration, ancestors);
-            values.insert(declaration.name.clone(), vv);
+            let layer = if declaration.important { usize::MAX - layer } else { layer };
+            let key = (cascade_level(origin, declaration.important), false, layer, specificity, cascade.len());
+            cascade.push((key, origin, declaration));
         }
     }
+    for declaration in &inline {
+        let key = (cascade_level(Origin::Author, declaration.important), true, 0, (0, 0, 0), cascade.len());
+        cascade.push((key, Origin::Author, declaration));
+    }
+    cascade.sort_by_key(|(key, _, _)| *key);
+    for (i, (_, origin, declaration)) in cascade.iter().enumerate() {
+        // println!("checking {} {:#?}", declaration.name, declaration.value);
+        if !is_keyword(&declaration.value, "revert") {
+            values.insert(declaration.name.clone(), declaration.value.clone());
+            continue;
+        }
+        // roll back to what the earlier origins gave it, or to nothing
+        let earlier = cascade[..i].iter().rev()
+            .find(|(_, o, d)| o < origin && d.name == declaration.name && !is_keyword(&d.value, "revert"));
+        match earlier {
+            Some((_, _, d)) => values.insert(d.name.clone(), d.value.clone()),
+            None => values.remove(&declaration.name),
+        };
+    }
     values
 }
 
-//returns inherited value if inherit is set and prop name is found, or just returns the original value
-fn calculate_inherited_property_value(dec:&Declaration, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> Value {
-    if dec.value == Keyword(String::from("inherit")) {
-        for (_node, props) in ancestors.iter() {
-            if props.contains_key(&*dec.name) {
-                let newval = props.get(&*dec.name).unwrap();
-                if newval == &Keyword(String::from("inherit")) {
-                    continue;
-                }
-                return newval.clone();
-            }
-        }
+fn is_keyword(value:&Value, keyword:&str) -> bool {
+    match value {
+        Keyword(k) => k == keyword,
+        // a custom property set to just the keyword
+        Value::Unparsed(unparsed) => matches!(unparsed.tokens.as_slice(), [Token::Ident(k)] if k.eq_ignore_ascii_case(keyword)),
+        _ => false,
     }
-    dec.value.clone()
 }
 
-pub fn dom_tree_to_stylednodes<'a>(root: &'a Node, styles: &'a StylesheetSet) -> StyledTree {
-    let tree = StyledTree::new();
-    let mut ansc:Vec<(&Node, &PropertyMap)> = vec![];
-    tree.set_root(real_style_tree(&tree, root, styles, &mut ansc));
+type CascadeKey = (usize, bool, usize, Specificity, usize);
+
+// https://drafts.csswg.org/css-cascade-4/#cascade-origin
+// important declarations reverse the order of the origins
+fn cascade_level(origin:Origin, important:bool) -> usize {
+    match (important, origin) {
+        (false, Origin::UserAgent) => 0,
+        (false, Origin::User) => 1,
+        (false, Origin::Author) => 2,
+        (true, Origin::Author) => 3,
+        (true, Origin::User) => 4,
+        (true, Origin::UserAgent) => 5,
+    }
+}
+
+// the declarations in the element's style="" attribute
+fn style_attribute(doc:&Document, id:NodeId) -> Vec<Declaration> {
+    match &doc.node(id).node_type {
+        Element(ed) => match ed.attributes.get("style") {
+            Some(text) => expand_declarations(&parse_declarations(text).0),
+            None => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+pub fn dom_tree_to_stylednodes(doc: &Document, styles: &StylesheetSet, media:&MediaContext) -> StyledTree {
+    let tree = StyledTree::new(doc.root());
+    let mut generated = GeneratedContent::default();
+    let rules = active_rules(styles, media);
+    let context = ComputeContext::new(media.width, media.height);
+    tree.set_root(real_style_tree(&tree, doc, doc.root(), &rules, None, &context, &mut generated));
     return tree;
 }
 
-fn real_style_tree<'a>(tree:&StyledTree, root: &'a Node, styles: &'a StylesheetSet, ancestors:&mut Vec::<(&Node, &PropertyMap)>) -> Rc<StyledNode> {
+// the tree is walked in document order so counters and quotes come out right
+fn real_style_tree(tree:&StyledTree, doc: &Document, id: NodeId, rules:&[ActiveRule], parent:Option<&PropertyMap>, context:&ComputeContext, generated:&mut GeneratedContent) -> Rc<StyledNode> {
+    let root = doc.node(id);
     let specified = match root.node_type {
-        Element(ref elem) => specified_values(elem, styles, ancestors),
+        Element(_) => specified_values(doc, id, rules, None),
         Text(_) => HashMap::new(),
         Meta(_) => HashMap::new(),
         _ => HashMap::new(),
     };
-    let mut a2:Vec<(&Node, &PropertyMap)> = vec![];
-    a2.push((root, &specified));
-    let ch2:Vec<Rc<StyledNode>> = root.children.iter()
-        .map(|child| {
-            real_style_tree(tree, child, styles, &mut a2)
-        }).collect();
-    return tree.make_with((*root).clone(),specified,RefCell::new(ch2));
-}
-
-fn expand_array_decl(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
-    match &dec.value {
-        Value::ArrayValue(arr) => {
-            if arr.len() == 2 {
-                new_decs.push(Declaration {
-                    name: format!("{}-top",dec.name),
-                    value: arr[0].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-right",dec.name),
-                    value: arr[1].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-bottom",dec.name),
-                    value: arr[0].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-left",dec.name),
-                    value: arr[1].clone()
-                });
-            }
-            if arr.len() == 4 {
-                new_decs.push(Declaration {
-                    name: format!("{}-top",dec.name),
-                    value: arr[0].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-right",dec.name),
-                    value: arr[1].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-bottom",dec.name),
-                    value: arr[2].clone()
-                });
-                new_decs.push(Declaration {
-                    name: format!("{}-left",dec.name),
-                    value: arr[3].clone()
-                });
-            }
-        }
-        Value::Length(_, _) => {
-            new_decs.push(Declaration {
-                name: format!("{}-top",dec.name),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration {
-                name: format!("{}-right",dec.name),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration {
-                name: format!("{}-bottom",dec.name),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration {
-                name: format!("{}-left",dec.name),
-                value: dec.value.clone()
-            });
-        }
-        _ => {
-            new_decs.push(dec.clone());
-        }
+    let computed = compute_values(&specified, parent, context);
+    // rem lengths are relative to the root element
+    let context = &match parent {
+        None => ComputeContext { root_font_size: font_size(&computed), ..*context },
+        Some(_) => *context,
+    };
+    generated.update_counters(&computed);
+    let level = generated.enter();
+    let mut ch2:Vec<Rc<StyledNode>> = vec![];
+    ch2.extend(pseudo_element_values(doc, id, PseudoElement::Before, rules, &computed, context, generated)
+        .map(|(specified, computed, text)| tree.make_pseudo(id, PseudoElement::Before, specified, computed, text)));
+    for child in root.children().iter() {
+        ch2.push(real_style_tree(tree, doc, *child, rules, Some(&computed), context, generated));
+    }
+    ch2.extend(pseudo_element_values(doc, id, PseudoElement::After, rules, &computed, context, generated)
+        .map(|(specified, computed, text)| tree.make_pseudo(id, PseudoElement::After, specified, computed, text)));
+    generated.leave(level);
+    return tree.make_with(id,specified,computed,RefCell::new(ch2));
+}
+
+// elements that can't hold content don't get ::before and ::after boxes
+fn is_void_element(tag_name:&str) -> bool {
+    matches!(tag_name, "area" | "base" | "br" | "col" | "embed" | "hr" | "img" | "input" | "link"
+        | "meta" | "source" | "track" | "wbr")
+}
+
+// the specified and computed values and the text of a pseudo-element, if it
+// generates a box. the element is its parent.
+fn pseudo_element_values(doc:&Document, id:NodeId, pseudo:PseudoElement, rules:&[ActiveRule], element:&PropertyMap, context:&ComputeContext, generated:&mut GeneratedContent) -> Option<(PropertyMap, PropertyMap, String)> {
+    match &doc.node(id).node_type {
+        Element(ed) if !is_void_element(&ed.tag_name) => {}
+        _ => return None,
+    }
+    let specified = specified_values(doc, id, rules, Some(pseudo));
+    match specified.get("content") {
+        None => return None,
+        Some(Keyword(content)) if content == "none" || content == "normal" => return None,
+        _ => {}
+    }
+    let computed = compute_values(&specified, Some(element), context);
+    if is_keyword(computed.get("display")?, "none") {
+        return None;
     }
+    generated.update_counters(&computed);
+    let text = generated.content_text(doc, id, &computed)?;
+    Some((specified, computed, text))
 }
 
 pub fn expand_styles(ss:&mut Stylesheet) {
-    for rule in ss.rules.iter_mut() {
-        if let RuleType::Rule(rule) = rule {
-            let mut new_decs = vec![];
-            for dec in rule.declarations.iter_mut() {
-                // println!("decl = {:#?}",dec);
-                match dec.name.as_str() {
-                    "margin" => expand_array_decl(&mut new_decs, dec),
-                    "padding" => expand_array_decl(&mut new_decs, dec),
-                    "border-width" => expand_array_decl(&mut new_decs, dec),
-                    "border" => expand_border_shorthand(&mut new_decs, dec),
-                    _ => new_decs.push(dec.clone()),
-                }
-            }
-            rule.declarations = new_decs;
+    expand_rules(&mut ss.rules);
+}
+
+fn expand_rules(rules:&mut [RuleType]) {
+    for rule in rules.iter_mut() {
+        match rule {
+            RuleType::Rule(rule) => rule.declarations = expand_declarations(&rule.declarations),
+            RuleType::Media(media_rule) => expand_rules(&mut media_rule.rules),
+            RuleType::Supports(supports_rule) => expand_rules(&mut supports_rule.rules),
+            RuleType::Layer(layer_rule) => expand_rules(&mut layer_rule.rules),
+            _ => {}
         }
     }
 }
 
-fn expand_border_shorthand(new_decs:&mut Vec::<Declaration>, dec:&Declaration) {
-    // println!("expanding border shorthand: {:#?}",dec);
-    match &dec.value {
-        Value::ArrayValue(vec) => {
-            if vec.len() != 3 {
-                panic!("border shorthand must have three values");
-            }
-            new_decs.push(Declaration{
-                name: String::from("border-width-top"),
-                value: vec[0].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-left"),
-                value: vec[0].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-right"),
-                value: vec[0].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-bottom"),
-                value: vec[0].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-style"),
-                value: vec[1].clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-color"),
-                value: vec[2].clone()
-            });
-        }
-        Value::Number(v) => {
-            new_decs.push(Declaration{
-                name: String::from("border-width-top"),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-left"),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-right"),
-                value: dec.value.clone()
-            });
-            new_decs.push(Declaration{
-                name: String::from("border-width-bottom"),
-                value: dec.value.clone()
-            });
-        }
-        _ => {
-            panic!("border shorthand must be an array value {:#?}",dec);
-        }
+fn expand_declarations(declarations:&[Declaration]) -> Vec<Declaration> {
+    let mut new_decs = vec![];
+    for dec in declarations.iter() {
+        expand_shorthand(dec, &mut new_decs);
     }
+    new_decs
 }
 
 #[test]
 fn test_multifile_cascade() {
     let stylesheet_parent = load_stylesheet_from_net(&relative_filepath_to_url("tests/default.css").unwrap()).unwrap();
     let mut stylesheet = load_stylesheet_from_net(&relative_filepath_to_url("tests/child.css").unwrap()).unwrap();
-    let elem = ElementData {
-        tag_name: String::from("div"),
-        attributes: Default::default()
-    };
-    let mut a2:Vec<(&Node, &PropertyMap)> = vec![];
+    let doc = load_doc_from_bytestring(b"<div></div>").unwrap();
+    let div = doc.getElementsByTagName("div")[0];
     let mut styles = StylesheetSet::new();
     styles.append(stylesheet_parent);
     styles.append(stylesheet);
-    let values = specified_values(&elem, &styles, &mut a2);
+    let values = specified_values(&doc, div, &active_rules(&styles, &MediaContext::default()), None);
     println!("got the values {:#?}", values);
     assert_eq!(values.get("background-color").unwrap(),&Value::Keyword(String::from("blue")));
 }
 
+#[test]
+fn test_cascade_order() {
+    let doc = load_doc_from_bytestring(br#"<div>
+        <p id=a class=x style="color: green; margin: 3px 4px">a</p>
+        <p id=b class=x style="color: green !important">b</p>
+    </div>"#).unwrap();
+    let sheet = |text:&[u8], origin:Origin| {
+        let mut ss = parse_stylesheet_from_bytestring(text).unwrap();
+        expand_styles(&mut ss);
+        ss.origin = origin;
+        ss
+    };
+    let mut styles = StylesheetSet::new();
+    styles.append(sheet(b"p { display: block !important; font-style: italic; width: 1px }", Origin::UserAgent));
+    styles.append(sheet(br#"
+        p { display: inline !important; color: blue !important; }
+        #a, p { margin-left: 1px; font-style: oblique; }
+        .x { font-style: normal; width: 2px }
+        .x { width: 3px }
+        #b { width: revert; font-variant: revert }
+    "#, Origin::Author));
+    let rules = active_rules(&styles, &MediaContext::default());
+    let values = |id:&str| specified_values(&doc, doc.getElementById(id).unwrap(), &rules, None);
+    let a = values("a");
+    // important user agent declarations beat important author ones
+    assert_eq!(a.get("display").unwrap(), &Keyword(String::from("block")));
+    // important beats the style attribute, which beats every selector
+    assert_eq!(a.get("color").unwrap(), &Keyword(String::from("blue")));
+    assert_eq!(a.get("margin-left").unwrap(), &Length(4.0, Unit::Px));
+    // a list counts its most specific matching selector
+    assert_eq!(a.get("font-style").unwrap(), &Keyword(String::from("oblique")));
+    assert_eq!(a.get("width").unwrap(), &Length(3.0, Unit::Px));
+    let b = values("b");
+    assert_eq!(b.get("color").unwrap(), &Keyword(String::from("green")));
+    assert_eq!(b.get("font-style").unwrap(), &Keyword(String::from("normal")));
+    assert_eq!(b.get("margin-left").unwrap(), &Length(1.0, Unit::Px));
+    // revert rolls back to the user agent's value
+    assert_eq!(b.get("width").unwrap(), &Length(1.0, Unit::Px));
+    assert_eq!(b.get("font-variant"), None);
+}
+
+#[test]
+fn test_media_rules() {
+    let doc = load_doc_from_bytestring(b"<div></div>").unwrap();
+    let div = doc.getElementsByTagName("div")[0];
+    let mut ss = parse_stylesheet_from_bytestring(br#"
+        div { width: 1px; margin: 0 }
+        @media (max-width: 600px) {
+            div { width: 2px; margin: 5px }
+            @media (orientation: landscape) { div { width: 3px } }
+        }
+        @media print, (min-width: 1000px) { div { width: 4px } }
+        @media not all and (monochrome) { div { color: red } }
+    "#).unwrap();
+    expand_styles(&mut ss);
+    let mut styles = StylesheetSet::new();
+    styles.append(ss);
+    let values = |media:MediaContext| specified_values(&doc, div, &active_rules(&styles, &media), None);
+    let width = |media:MediaContext| values(media).get("width").cloned().unwrap();
+    assert_eq!(width(MediaContext::new(400.0, 800.0)), Length(2.0, Unit::Px));
+    assert_eq!(width(MediaContext::new(500.0, 300.0)), Length(3.0, Unit::Px));
+    assert_eq!(width(MediaContext::new(800.0, 600.0)), Length(1.0, Unit::Px));
+    assert_eq!(width(MediaContext::new(1200.0, 600.0)), Length(4.0, Unit::Px));
+    // the shorthands in @media blocks are expanded too
+    assert_eq!(values(MediaContext::new(400.0, 800.0)).get("margin-left"), Some(&Length(5.0, Unit::Px)));
+    assert_eq!(values(MediaContext::default()).get("color"), Some(&Keyword(String::from("red"))));
+}
+
+#[test]
+fn test_layer_rules() {
+    let doc = load_doc_from_bytestring(b"<div id=main></div>").unwrap();
+    let div = doc.getElementsByTagName("div")[0];
+    let mut ss = parse_stylesheet_from_bytestring(br#"
+        @layer base, theme;
+        div { width: 1px }
+        @layer theme {
+            #main { width: 2px; height: 2px; color: red !important }
+            @layer dark { #main { height: 3px } }
+        }
+        @layer base {
+            #main { width: 4px; height: 4px; color: blue !important }
+        }
+        @layer theme.dark { div { color: green } }
+        @layer { div { margin-top: 5px } }
+        @supports (display: block) { div { margin-left: 6px } }
+        @supports (display: grid) { div { margin-left: 7px } }
+        div { color: black }
+    "#).unwrap();
+    expand_styles(&mut ss);
+    let mut styles = StylesheetSet::new();
+    styles.append(ss);
+    let values = specified_values(&doc, div, &active_rules(&styles, &MediaContext::default()), None);
+    let length = |name:&str| values.get(name).cloned().unwrap();
+    // unlayered rules win over layers, whatever the specificity
+    assert_eq!(length("width"), Length(1.0, Unit::Px));
+    // the layer's own rules win over its sub-layers, and later layers over earlier ones
+    assert_eq!(length("height"), Length(2.0, Unit::Px));
+    // important declarations reverse the order of the layers
+    assert_eq!(length("color"), Keyword(String::from("blue")));
+    assert_eq!(length("margin-top"), Length(5.0, Unit::Px));
+    assert_eq!(length("margin-left"), Length(6.0, Unit::Px));
+}
+
+#[test]
+fn test_quirks_mode_class_match() {
+    let sel = Selector::Simple(SimpleSelector{
+        tag_name: None, id: Some(String::from("Main")), class: vec![String::from("note")], pseudo_class: vec![], attributes: vec![], pseudo_element: None });
+    let quirky = load_doc_from_bytestring(b"<p id=main class=NOTE>a</p>").unwrap();
+    assert!(matches(&quirky, quirky.getElementsByTagName("p")[0], &sel));
+    let standard = load_doc_from_bytestring(b"<!DOCTYPE html><p id=main class=NOTE>a</p>").unwrap();
+    assert!(!matches(&standard, standard.getElementsByTagName("p")[0], &sel));
+}
+
+// the ids of the elements matching the selectors, in document order
+#[cfg(test)]
+fn ids(doc:&Document, selectors:&str) -> Vec<String> {
+    doc.querySelectorAll(selectors).unwrap().into_iter().map(|id| match &doc.node(id).node_type {
+        Element(ed) => ed.id().cloned().unwrap_or_default(),
+        _ => String::new(),
+    }).collect()
+}
+#[cfg(test)]
+fn spec(text:&str) -> crate::css::Specificity {
+    crate::css::parse_selector_list(text).unwrap()[0].specificity()
+}
+
+#[test]
+fn test_combinator_and_attribute_match() {
+    let doc = load_doc_from_bytestring(br#"<div id=outer><section><ul>
+        <li id=one lang=en-US>a</li> text <!-- note -->
+        <li id=two class="x y" title="Hello World">b</li>
+        <li id=three data-kind="">c</li>
+    </ul></section></div>"#).unwrap();
+    assert_eq!(ids(&doc, "div li:not(.z)").len(), 3);
+    assert_eq!(ids(&doc, "#outer > section ul > li").len(), 3);
+    assert!(ids(&doc, "#outer > ul li").is_empty());
+    assert_eq!(ids(&doc, "#one + li"), vec!["two"]);
+    assert_eq!(ids(&doc, "#one ~ li"), vec!["two", "three"]);
+    assert!(ids(&doc, "#three ~ li").is_empty());
+    assert_eq!(ids(&doc, "[lang|=en]"), vec!["one"]);
+    assert_eq!(ids(&doc, "li[class~=y]"), vec!["two"]);
+    assert!(ids(&doc, "li[class~='x y']").is_empty());
+    assert_eq!(ids(&doc, "[title^=Hello][title$=World][title*='o W']"), vec!["two"]);
+    assert!(ids(&doc, "[title='hello world']").is_empty());
+    assert_eq!(ids(&doc, "[title='hello world' i]"), vec!["two"]);
+    assert_eq!(ids(&doc, "li[data-kind]"), vec!["three"]);
+    assert!(ids(&doc, "[data-kind^='']").is_empty());
+}
+
+#[test]
+fn test_compound_specificity() {
+    assert_eq!(spec("*"), (0, 0, 0));
+    assert_eq!(spec("li"), (0, 0, 1));
+    assert_eq!(spec("ul li"), (0, 0, 2));
+    assert_eq!(spec("ul ol+li"), (0, 0, 3));
+    assert_eq!(spec("h1 + *[rel=up]"), (0, 1, 1));
+    assert_eq!(spec("ul ol li.red"), (0, 1, 3));
+    assert_eq!(spec("li.red.level"), (0, 2, 1));
+    assert_eq!(spec("#x34y"), (1, 0, 0));
+    assert_eq!(spec("div#main > p.note:hover a"), (1, 2, 3));
+}
+
+#[test]
+fn test_pseudo_class_match() {
+    let mut doc = load_doc_from_bytestring(br#"<!DOCTYPE html><ul>
+        <li id=a class=x>a</li> <!-- note -->
+        <li id=b>b<img></li>
+        <p id=c></p>
+        <li id=d class=x>d</li>
+        <li id=e><a id=link href="/">e</a></li>
+    </ul><form><input id=f type=checkbox checked><input id=g disabled><button id=h>h</button></form>"#).unwrap();
+    assert_eq!(ids(&doc, "li:first-child"), vec!["a"]);
+    assert_eq!(ids(&doc, "li:last-child"), vec!["e"]);
+    assert_eq!(ids(&doc, "ul > :nth-child(2n+1)"), vec!["a", "c", "e"]);
+    assert_eq!(ids(&doc, "li:nth-child(even of li)"), vec!["b", "e"]);
+    assert_eq!(ids(&doc, "ul > :nth-last-child(-n+2)"), vec!["d", "e"]);
+    assert_eq!(ids(&doc, "li:nth-of-type(3)"), vec!["d"]);
+    assert_eq!(ids(&doc, "li:first-of-type, li:last-of-type"), vec!["a", "e"]);
+    assert_eq!(ids(&doc, "ul > :only-of-type"), vec!["c"]);
+    assert_eq!(ids(&doc, "#e > :only-child"), vec!["link"]);
+    assert_eq!(ids(&doc, "p:empty, li:empty"), vec!["c"]);
+    assert_eq!(doc.querySelectorAll(":root").unwrap(), vec![doc.root()]);
+    assert_eq!(ids(&doc, "li:not(.x, :has(img))"), vec!["e"]);
+    assert_eq!(ids(&doc, "li:has(> img)"), vec!["b"]);
+    assert_eq!(ids(&doc, "li:has(+ p)"), vec!["b"]);
+    assert_eq!(ids(&doc, "li:has(~ li a)"), vec!["a", "b", "d"]);
+    assert_eq!(ids(&doc, ":is(p, #link):not(:where(p))"), vec!["link"]);
+    assert_eq!(ids(&doc, ":checked, :disabled"), vec!["f", "g"]);
+    assert_eq!(ids(&doc, ":enabled"), vec!["f", "h"]);
+    assert_eq!(ids(&doc, "a:link"), vec!["link"]);
+    assert!(ids(&doc, ":hover, :focus, :active, a:visited").is_empty());
+
+    let link = doc.getElementById("link").unwrap();
+    doc.set_hovered(Some(link));
+    doc.set_focused(Some(link));
+    doc.set_element_state(link, ElementState::Visited, true);
+    assert_eq!(ids(&doc, "li:hover, a:focus, li:focus"), vec!["e", "link"]);
+    assert_eq!(ids(&doc, "a:visited"), vec!["link"]);
+    assert!(ids(&doc, "a:link").is_empty());
+}
+
+#[test]
+fn test_pseudo_class_specificity() {
+    assert_eq!(spec("a:hover"), (0, 1, 1));
+    assert_eq!(spec(":is(#a, p)"), (1, 0, 0));
+    assert_eq!(spec("li:not(.x, p)"), (0, 1, 1));
+    assert_eq!(spec(":where(#a) p"), (0, 0, 1));
+    assert_eq!(spec("li:nth-child(2n of .x)"), (0, 2, 1));
+    assert_eq!(spec("div:has(> img.big)"), (0, 1, 2));
+}
+
 #[test]
 fn test_inherited_match() {
     let doc_text = br#"
@@ -576,18 +816,20 @@ fn test_inherited_match() {
     //println!("doc is {:#?} {:#?} {:#?}",doc,stylesheet,snode);
 
     //check html element
-    assert_eq!(snode.specified_values.get("color").unwrap(),
+    assert_eq!(snode.computed_values.get("color").unwrap(),
                &Keyword(String::from("black")));
 
-    // check html b element
-    assert_eq!(snode.children.borrow()[0].specified_values.get("color").unwrap(),
+    // check html body b element
+    let body = &snode.children.borrow()[1];
+    let b = &body.children.borrow()[0];
+    assert_eq!(b.computed_values.get("color").unwrap(),
                &Keyword(String::from("black")));
-    assert_eq!(snode.children.borrow()[0].specified_values.get("font-weight").unwrap(),
+    assert_eq!(b.computed_values.get("font-weight").unwrap(),
                &Keyword(String::from("bold")));
-    // check html b a element
-    assert_eq!(snode.children.borrow()[0].children.borrow()[1].specified_values.get("color").unwrap(),
+    // check html body b a element
+    assert_eq!(b.children.borrow()[1].computed_values.get("color").unwrap(),
                &Keyword(String::from("blue")));
-    assert_eq!(snode.children.borrow()[0].children.borrow()[1].specified_values.get("font-weight").unwrap(),
+    assert_eq!(b.children.borrow()[1].computed_values.get("font-weight").unwrap(),
                &Keyword(String::from("bold")));
 }
 
@@ -613,6 +855,7 @@ fn test_em_to_px() {
 
     //check html element
     assert_eq!(snode.specified_values.get("margin-left").unwrap(), &Length(1.0,Unit::Em));
+    assert_eq!(snode.computed_values.get("margin-left").unwrap(), &Length(18.0,Unit::Px));
 }
 
 #[test]
@@ -627,7 +870,8 @@ fn test_vertical_align() {
     </html>"#;
     let (doc,sss,stree,lbox, rbox) = standard_test_run(doc_text, br"").unwrap();
     let root = &stree.root.borrow();
-    let div = &root.children.borrow()[1];
+    let body = &root.children.borrow()[1];
+    let div = &body.children.borrow()[0];
     // println!("the styles are {:#?}", sss);
     assert_eq!(div.lookup_string("vertical-align","foo"),"top".to_string());
 }
@@ -656,15 +900,28 @@ fn test_multi_selector_match() {
     assert_eq!(snode.specified_values.get("color").unwrap(),
                &Keyword(String::from("black")));
 
-    // check html b element
-    assert_eq!(snode.children.borrow()[0].specified_values.get("color").unwrap(),
+    // check html body b element
+    let body = &snode.children.borrow()[1];
+    assert_eq!(body.children.borrow()[0].specified_values.get("color").unwrap(),
                &Keyword(String::from("red")));
-    // check html a element
-    assert_eq!(snode.children.borrow()[1].specified_values.get("color").unwrap(),
+    // check html body a element
+    assert_eq!(body.children.borrow()[1].specified_values.get("color").unwrap(),
                &Keyword(String::from("red")));
 
 }
 
+#[test]
+fn test_every_style_element() {
+    let doc_text = br#"<head><style>p { color: red; }</style></head><body><p>cool</p><style>p { color: blue; }</style></body>"#;
+    let (_doc, stylesheets, stree, _lbox, _rbox) = standard_test_run(doc_text, b"").unwrap();
+    //the default stylesheet, both style elements, and the empty test css
+    assert_eq!(stylesheets.stylesheets.len(), 4);
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let p = &body.children.borrow()[0];
+    assert_eq!(p.specified_values.get("color").unwrap(), &Keyword(String::from("blue")));
+}
+
 #[test]
 fn test_ancestor_match() {
     let doc_text = br#"
@@ -679,7 +936,9 @@ fn test_ancestor_match() {
         }
     "#;
     let (doc, stylesheet,stree,lbox, rbox) = standard_test_run(doc_text, css_text).unwrap();
-    let snode = stree.root.borrow();
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let snode = &body.children.borrow()[0];
     println!("doc is {:#?} {:#?} {:#?}",doc,stylesheet,snode);
 
     //check b
@@ -702,20 +961,23 @@ fn test_property_expansion_1() {
         div {
             margin: 1px;
             border-width: 1px;
+            border-style: solid;
         }
     "#;
 
     let (doc, stylesheet,stree,lbox, rbox) = standard_test_run(doc_text, css_text).unwrap();
-    let snode = stree.root.borrow();
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let snode = &body.children.borrow()[0];
     println!("stylesheet is {:#?}",stylesheet);
     assert_eq!(snode.lookup_length_px("margin-top",5.0),1.0);
     assert_eq!(snode.lookup_length_px("margin-right",5.0),1.0);
     assert_eq!(snode.lookup_length_px("margin-bottom",5.0),1.0);
     assert_eq!(snode.lookup_length_px("margin-left",5.0),1.0);
-    assert_eq!(snode.lookup_length_px("border-width-top",5.0),1.0);
-    assert_eq!(snode.lookup_length_px("border-width-right",5.0),1.0);
-    assert_eq!(snode.lookup_length_px("border-width-bottom",5.0),1.0);
-    assert_eq!(snode.lookup_length_px("border-width-left",5.0),1.0);
+    assert_eq!(snode.lookup_length_px("border-top-width",5.0),1.0);
+    assert_eq!(snode.lookup_length_px("border-right-width",5.0),1.0);
+    assert_eq!(snode.lookup_length_px("border-bottom-width",5.0),1.0);
+    assert_eq!(snode.lookup_length_px("border-left-width",5.0),1.0);
 }
 
 #[test]
@@ -728,7 +990,9 @@ fn test_property_expansion_2() {
     "#;
 
     let (doc, stylesheet,stree,lbox, rbox) = standard_test_run(doc_text, css_text).unwrap();
-    let snode = stree.root.borrow();
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let snode = &body.children.borrow()[0];
     println!("doc is {:#?} {:#?} {:#?}",doc,stylesheet,snode);
     assert_eq!(snode.lookup_length_px("margin-top",5.0),1.0);
     assert_eq!(snode.lookup_length_px("margin-right",5.0),2.0);
@@ -746,7 +1010,9 @@ fn test_property_expansion_4() {
     "#;
 
     let (doc, stylesheet,stree,lbox, rbox) = standard_test_run(doc_text, css_text).unwrap();
-    let snode = stree.root.borrow();
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let snode = &body.children.borrow()[0];
     println!("doc is {:#?} {:#?} {:#?}",doc,stylesheet,snode);
     assert_eq!(snode.lookup_length_px("margin-top",5.0),1.0);
     assert_eq!(snode.lookup_length_px("margin-right",5.0),2.0);
@@ -754,6 +1020,104 @@ fn test_property_expansion_4() {
     assert_eq!(snode.lookup_length_px("margin-left",5.0),4.0);
 }
 
+#[test]
+fn test_property_expansion_3() {
+    let doc_text = br#"<div></div>"#;
+    let css_text = br#"
+        div {
+            margin: 1px auto 3px;
+            padding: 1px 2px 3px;
+        }
+    "#;
+
+    let (doc, stylesheet,stree,lbox, rbox) = standard_test_run(doc_text, css_text).unwrap();
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let snode = &body.children.borrow()[0];
+    println!("doc is {:#?} {:#?} {:#?}",doc,stylesheet,snode);
+    assert_eq!(snode.lookup_length_px("margin-top",5.0),1.0);
+    assert_eq!(snode.lookup_keyword("margin-right",&Keyword(String::from("foo"))),Keyword(String::from("auto")));
+    assert_eq!(snode.lookup_length_px("margin-bottom",5.0),3.0);
+    assert_eq!(snode.lookup_keyword("margin-left",&Keyword(String::from("foo"))),Keyword(String::from("auto")));
+    assert_eq!(snode.lookup_length_px("padding-top",5.0),1.0);
+    assert_eq!(snode.lookup_length_px("padding-right",5.0),2.0);
+    assert_eq!(snode.lookup_length_px("padding-bottom",5.0),3.0);
+    assert_eq!(snode.lookup_length_px("padding-left",5.0),2.0);
+}
+
+#[test]
+fn test_property_expansion_rejected() {
+    // declarations made without the parser are checked too
+    let dec = |name:&str, value:Value| Declaration { name: String::from(name), value, important: false };
+    let expanded = expand_declarations(&[
+        dec("border-top-style", Value::Length(3.0, Unit::Px)),
+        dec("margin", Keyword(String::from("bogus"))),
+        dec("display", Keyword(String::from("BLOCK"))),
+        dec("border-top", Keyword(String::from("inherit"))),
+    ]);
+    assert_eq!(expanded.iter().map(|dec| dec.name.as_str()).collect::<Vec<&str>>(),
+               vec!["display", "border-top-width", "border-top-style", "border-top-color"]);
+}
+
+#[test]
+fn test_property_expansion_shorthands() {
+    use crate::css::Value::{Number, ArrayValue, StringLiteral, HexColor};
+    let expand = |text:&str| -> Vec<(String, Value)> {
+        expand_declarations(&parse_declarations(text).0).into_iter().map(|dec| (dec.name, dec.value)).collect()
+    };
+    let kw = |k:&str| Keyword(String::from(k));
+    let px = |v:f32| Value::Length(v, Unit::Px);
+    let pairs = |list:&[(&str, Value)]| -> Vec<(String, Value)> {
+        list.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    };
+
+    assert_eq!(expand("border-top: 2px dashed red"), pairs(&[
+        ("border-top-width", px(2.0)), ("border-top-style", kw("dashed")), ("border-top-color", kw("red")),
+    ]));
+    assert_eq!(expand("border-left: thick"), pairs(&[
+        ("border-left-width", kw("thick")), ("border-left-style", kw("initial")), ("border-left-color", kw("initial")),
+    ]));
+    assert_eq!(expand("border-style: solid none"), pairs(&[
+        ("border-top-style", kw("solid")), ("border-right-style", kw("none")),
+        ("border-bottom-style", kw("solid")), ("border-left-style", kw("none")),
+    ]));
+    assert_eq!(expand("border-radius: 1px 2px / 3px"), pairs(&[
+        ("border-top-left-radius", ArrayValue(vec![px(1.0), px(3.0)])),
+        ("border-top-right-radius", ArrayValue(vec![px(2.0), px(3.0)])),
+        ("border-bottom-right-radius", ArrayValue(vec![px(1.0), px(3.0)])),
+        ("border-bottom-left-radius", ArrayValue(vec![px(2.0), px(3.0)])),
+    ]));
+    assert_eq!(expand("list-style: square inside"), pairs(&[
+        ("list-style-position", kw("inside")), ("list-style-type", kw("square")), ("list-style-image", kw("initial")),
+    ]));
+    assert_eq!(expand("text-decoration: underline wavy blue"), pairs(&[
+        ("text-decoration-line", kw("underline")), ("text-decoration-style", kw("wavy")),
+        ("text-decoration-color", kw("blue")), ("text-decoration-thickness", kw("initial")),
+    ]));
+    assert_eq!(expand("background: #eee no-repeat left top"), pairs(&[
+        ("background-color", HexColor(String::from("#eee"))), ("background-image", kw("initial")),
+        ("background-repeat", kw("no-repeat")), ("background-attachment", kw("initial")),
+        ("background-position", ArrayValue(vec![kw("left"), kw("top")])),
+    ]));
+    assert_eq!(expand("font: italic bold 12px/1.5 Times New Roman, serif"), pairs(&[
+        ("font-style", kw("italic")), ("font-variant", kw("initial")), ("font-weight", kw("bold")),
+        ("font-size", px(12.0)), ("line-height", Number(1.5)),
+        ("font-family", ArrayValue(vec![StringLiteral(String::from("Times New Roman")), kw("serif")])),
+    ]));
+    assert_eq!(expand("flex: 2"), pairs(&[
+        ("flex-grow", Number(2.0)), ("flex-shrink", Number(1.0)), ("flex-basis", Value::Length(0.0, Unit::Per)),
+    ]));
+    assert_eq!(expand("flex: none"), pairs(&[
+        ("flex-grow", Number(0.0)), ("flex-shrink", Number(0.0)), ("flex-basis", kw("auto")),
+    ]));
+    assert_eq!(expand("outline: inherit"), pairs(&[
+        ("outline-color", kw("inherit")), ("outline-style", kw("inherit")), ("outline-width", kw("inherit")),
+    ]));
+    // a value none of the longhands takes drops the whole declaration
+    assert_eq!(expand("border: 1px solid 2px; font: bold serif; margin: 1px 2px 3px 4px 5px"), vec![]);
+    assert_eq!(expand("color: red"), pairs(&[("color", kw("red"))]));
+}
+
 #[test]
 fn test_border_shorthand() {
     let doc_text = br#"<div></div>"#;
@@ -764,13 +1128,40 @@ fn test_border_shorthand() {
     "#;
 
     let (doc, stylesheet,stree,lbox, rbox) = standard_test_run(doc_text, css_text).unwrap();
-    let snode = stree.root.borrow();
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let snode = &body.children.borrow()[0];
     println!("doc is {:#?} {:#?} {:#?}", doc, stylesheet, snode);
-    assert_eq!(snode.lookup_length_px("border-width-top",5.0),1.0);
-    assert_eq!(snode.lookup_length_px("border-width-right",5.0),1.0);
-    assert_eq!(snode.lookup_length_px("border-width-bottom",5.0),1.0);
-    assert_eq!(snode.lookup_length_px("border-width-left",5.0),1.0);
-    assert_eq!(snode.lookup_keyword("border-color", &Keyword(String::from("white"))), Keyword(String::from("black")));
+    assert_eq!(snode.lookup_length_px("border-top-width",5.0),1.0);
+    assert_eq!(snode.lookup_length_px("border-right-width",5.0),1.0);
+    assert_eq!(snode.lookup_length_px("border-bottom-width",5.0),1.0);
+    assert_eq!(snode.lookup_length_px("border-left-width",5.0),1.0);
+    assert_eq!(snode.lookup_keyword("border-left-style", &Keyword(String::from("none"))), Keyword(String::from("solid")));
+    assert_eq!(snode.lookup_keyword("border-top-color", &Keyword(String::from("white"))), Keyword(String::from("black")));
+    assert_eq!(snode.lookup_keyword("border-left-color", &Keyword(String::from("white"))), Keyword(String::from("black")));
+}
+
+#[test]
+fn test_color_functions() {
+    let doc_text = br#"<div></div>"#;
+    let css_text = br#"
+        div {
+            color: hsl(120deg 100% 25%);
+            background-color: rgba(0, 0, 255, 50%);
+            border: 1px solid currentColor;
+            outline-color: #0000ff80;
+        }
+    "#;
+
+    let (doc, stylesheet,stree,lbox, rbox) = standard_test_run(doc_text, css_text).unwrap();
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let snode = &body.children.borrow()[0];
+    let green = Color { r:0, g:128, b:0, a:255 };
+    assert_eq!(snode.style.color, green);
+    assert_eq!(snode.style.background_color, Some(Color { r:0, g:0, b:255, a:128 }));
+    assert_eq!(snode.style.border_left_color, Some(green));
+    assert_eq!(snode.color("outline-color"), snode.style.background_color);
 }
 
 #[test]
@@ -779,13 +1170,35 @@ fn test_relative_font_sizes() {
     let css_text = br#"body { font-size: 10px; } p { font-size: 200%; }"#;
     let (doc, stylesheet,stree,lbox, rbox) = standard_test_run(doc_text, css_text).unwrap();
     println!("doc is {:#?} {:#?} {:#?}", doc, stylesheet, stree);
-    let style_root = stree.root.borrow();
+    let root = stree.root.borrow();
+    let style_root = &root.children.borrow()[1];
     assert_eq!(style_root.lookup_font_size(), 10.0);
     let style_child = &style_root.children.borrow()[0];
     assert_eq!(style_child.lookup_font_size(), 20.0);
 
 }
 
+#[test]
+fn test_inherited_properties() {
+    let doc_text = br#"<html><body><p>a <span>b</span></p></body></html>"#;
+    let css_text = br#"html { font-size: 20px; } body { font-family: serif; font-size: 10px; }
+        p { font-size: 2em; margin-left: 1rem; color: red; } span { color: unset; margin-left: unset; }"#;
+    let (_doc, _stylesheet, stree, _lbox, _rbox) = standard_test_run(doc_text, css_text).unwrap();
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let p = &body.children.borrow()[0];
+    assert_eq!(p.lookup_font_size(), 20.0);
+    assert_eq!(p.value("font-family"), Some(Keyword(String::from("serif"))));
+    assert_eq!(p.value("margin-left"), Some(Length(20.0, Unit::Px)));
+    let span = &p.children.borrow()[1];
+    assert_eq!(span.value("color"), Some(Keyword(String::from("red"))));
+    assert_eq!(span.value("margin-left"), Some(Length(0.0, Unit::Px)));
+    // text takes everything inherited from its element
+    let text = &span.children.borrow()[0];
+    assert_eq!(text.lookup_font_size(), 20.0);
+    assert_eq!(text.value("font-family"), Some(Keyword(String::from("serif"))));
+}
+
 #[test]
 fn test_default_styles() {
     let (doc, stylesheet,stree,lbox, rbox) = standard_test_run(
@@ -793,8 +1206,44 @@ fn test_default_styles() {
     br#"html { color: red; }"#).unwrap();
 
     let html_style = stree.root.borrow();
-    assert_eq!(html_style.lookup_color("color",&Color::from_hex("#000000")), Color::from_hex("#ff0000"));
+    assert_eq!(html_style.lookup_color("color",&crate::render::BLACK), crate::render::RED);
     assert_eq!(
         html_style.lookup_keyword("display",&Value::Keyword(String::from("foo"))),
                                     Value::Keyword("block".to_string()));
 }
+
+#[test]
+fn test_generated_content() {
+    let doc_text = br#"<body><ol><li>a<ol><li>b</li><li>c</li></ol></li><li>d</li></ol>
+        <p title="note"><q>x<q>y</q></q></p><br><p class=gone>z</p></body>"#;
+    let css_text = br#"
+        ol { counter-reset: item; }
+        li { counter-increment: item; }
+        li::before { content: counters(item, ".") " "; }
+        p::after { content: "[" attr(title) "]"; display: block; }
+        q::before { content: open-quote; }
+        q::after { content: close-quote; }
+        br::before { content: "no"; }
+        p.gone::before { content: none; }
+        p.gone::after { display: none; }
+    "#;
+    let (_doc, _stylesheet, stree, _lbox, _rbox) = standard_test_run(doc_text, css_text).unwrap();
+    fn generated(node:&Rc<StyledNode>, out:&mut Vec<String>) {
+        if let Some(text) = &node.text {
+            out.push(text.clone());
+        }
+        for ch in node.children.borrow().iter() {
+            generated(ch, out);
+        }
+    }
+    let mut texts = vec![];
+    generated(&stree.root.borrow(), &mut texts);
+    assert_eq!(texts, vec!["1 ", "1.1 ", "1.2 ", "2 ", "\u{201C}", "\u{2018}", "\u{2019}", "\u{201D}", "[note]"]);
+
+    let root = stree.root.borrow();
+    let body = &root.children.borrow()[1];
+    let p = &body.children.borrow()[2];
+    let after = p.children.borrow().last().cloned().unwrap();
+    assert_eq!(after.pseudo_element, Some(PseudoElement::After));
+    assert_eq!(after.style.display, Display::Block);
+}
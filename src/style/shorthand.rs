@@ -0,0 +1,452 @@
+// https://drafts.csswg.org/css-cascade-4/#shorthand-property
+//
+// Shorthands are replaced by their longhands before the cascade. Each one has
+// a row in SHORTHANDS with its longhands and how its values are spread over
+// them. A value that no longhand can take makes the whole declaration invalid,
+// and longhands the shorthand leaves out are reset to their initial values.
+
+use crate::css::{Color as CssColor, Declaration, Value, UnparsedValue};
+use crate::css::Value::{Keyword, Number, ArrayValue, StringLiteral, ColorValue, HexColor, FunCall};
+use crate::css::Unit;
+use crate::style::named_color;
+
+#[derive(Clone, Copy)]
+enum Syntax {
+    /// one to four values for the top, right, bottom and left
+    Sides,
+    /// one to four values for the corners, clockwise from the top left. values
+    /// after a '/' are the vertical radii.
+    Corners,
+    /// values in any order, each going to the first longhand that takes it
+    AnyOrder,
+    /// like AnyOrder, or comma separated layers of one value each
+    Layers,
+    Font,
+    Flex,
+}
+
+// what a longhand accepts. numbers count as lengths, as they always have here.
+#[derive(Clone, Copy)]
+enum Kind {
+    Length,
+    LengthOrAuto,
+    LengthOrNone,
+    LengthOrNormal,
+    LineWidth,
+    Color,
+    Image,
+    /// up to two keywords or lengths
+    Position,
+    /// a number, as for flex-grow
+    Factor,
+    /// a counter style name or a string
+    CounterStyle,
+    FontWeight,
+    FontSize,
+    LineHeight,
+    FontFamily,
+    /// strings, quotes and the functions of `content`
+    Content,
+    /// names each with an optional integer, as for counter-reset
+    Counters,
+    /// pairs of strings
+    Quotes,
+    Keywords(&'static [&'static str]),
+}
+
+const LINE_STYLES:&[&str] = &["none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset"];
+const OUTLINE_STYLES:&[&str] = &["auto", "none", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset"];
+const DECORATION_LINES:&[&str] = &["none", "underline", "overline", "line-through", "blink"];
+const DECORATION_STYLES:&[&str] = &["solid", "double", "dotted", "dashed", "wavy"];
+const REPEATS:&[&str] = &["repeat", "repeat-x", "repeat-y", "no-repeat", "space", "round"];
+const ATTACHMENTS:&[&str] = &["scroll", "fixed", "local"];
+const FONT_STYLES:&[&str] = &["normal", "italic", "oblique"];
+const FONT_VARIANTS:&[&str] = &["normal", "small-caps"];
+const FONT_SIZES:&[&str] = &["xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large", "xxx-large", "larger", "smaller"];
+const CONTENT_KEYWORDS:&[&str] = &["open-quote", "close-quote", "no-open-quote", "no-close-quote"];
+// the background longhands that take a value for each layer
+const LAYERED:&[&str] = &["background-image", "background-repeat", "background-attachment", "background-position"];
+pub(crate) const CSS_WIDE:&[&str] = &["inherit", "initial", "unset", "revert"];
+
+use self::Kind::*;
+use self::Syntax::*;
+
+// the shorthand, how its values map to the longhands, and the longhands.
+// a longhand may itself be a shorthand, as with border.
+type Longhands = &'static [(&'static str, Kind)];
+
+const SHORTHANDS:&[(&str, Syntax, Longhands)] = &[
+    ("margin", Sides, &[("margin-top", LengthOrAuto), ("margin-right", LengthOrAuto), ("margin-bottom", LengthOrAuto), ("margin-left", LengthOrAuto)]),
+    ("padding", Sides, &[("padding-top", Length), ("padding-right", Length), ("padding-bottom", Length), ("padding-left", Length)]),
+    ("border-width", Sides, &[("border-top-width", LineWidth), ("border-right-width", LineWidth), ("border-bottom-width", LineWidth), ("border-left-width", LineWidth)]),
+    ("border-style", Sides, &[("border-top-style", Keywords(LINE_STYLES)), ("border-right-style", Keywords(LINE_STYLES)), ("border-bottom-style", Keywords(LINE_STYLES)), ("border-left-style", Keywords(LINE_STYLES))]),
+    ("border-color", Sides, &[("border-top-color", Color), ("border-right-color", Color), ("border-bottom-color", Color), ("border-left-color", Color)]),
+    ("border-radius", Corners, &[("border-top-left-radius", Length), ("border-top-right-radius", Length), ("border-bottom-right-radius", Length), ("border-bottom-left-radius", Length)]),
+    ("border", AnyOrder, &[("border-width", LineWidth), ("border-style", Keywords(LINE_STYLES)), ("border-color", Color)]),
+    ("border-top", AnyOrder, &[("border-top-width", LineWidth), ("border-top-style", Keywords(LINE_STYLES)), ("border-top-color", Color)]),
+    ("border-right", AnyOrder, &[("border-right-width", LineWidth), ("border-right-style", Keywords(LINE_STYLES)), ("border-right-color", Color)]),
+    ("border-bottom", AnyOrder, &[("border-bottom-width", LineWidth), ("border-bottom-style", Keywords(LINE_STYLES)), ("border-bottom-color", Color)]),
+    ("border-left", AnyOrder, &[("border-left-width", LineWidth), ("border-left-style", Keywords(LINE_STYLES)), ("border-left-color", Color)]),
+    ("outline", AnyOrder, &[("outline-color", Color), ("outline-style", Keywords(OUTLINE_STYLES)), ("outline-width", LineWidth)]),
+    // position comes first so inside and outside aren't taken as counter style names
+    ("list-style", AnyOrder, &[("list-style-position", Keywords(&["inside", "outside"])), ("list-style-type", CounterStyle), ("list-style-image", Image)]),
+    ("text-decoration", AnyOrder, &[("text-decoration-line", Keywords(DECORATION_LINES)), ("text-decoration-style", Keywords(DECORATION_STYLES)), ("text-decoration-color", Color), ("text-decoration-thickness", LengthOrAuto)]),
+    ("background", Layers, &[("background-color", Color), ("background-image", Image), ("background-repeat", Keywords(REPEATS)), ("background-attachment", Keywords(ATTACHMENTS)), ("background-position", Position)]),
+    ("font", Font, &[("font-style", Keywords(FONT_STYLES)), ("font-variant", Keywords(FONT_VARIANTS)), ("font-weight", FontWeight), ("font-size", FontSize), ("line-height", LineHeight), ("font-family", FontFamily)]),
+    ("flex", Flex, &[("flex-grow", Factor), ("flex-shrink", Factor), ("flex-basis", LengthOrAuto)]),
+];
+
+// what the properties that aren't longhands of a shorthand accept
+const PROPERTIES:&[(&str, Kind)] = &[
+    ("width", LengthOrAuto),
+    ("height", LengthOrAuto),
+    ("max-width", LengthOrNone),
+    ("color", Color),
+    ("letter-spacing", LengthOrNormal),
+    ("word-spacing", LengthOrNormal),
+    ("display", Keywords(&["block", "inline", "inline-block", "table", "table-row-group", "table-row", "table-cell", "list-item", "none"])),
+    ("position", Keywords(&["static", "relative", "absolute", "fixed", "sticky"])),
+    ("white-space", Keywords(&["normal", "nowrap", "pre", "pre-wrap", "pre-line", "break-spaces"])),
+    ("text-align", Keywords(&["left", "right", "center", "justify", "start", "end"])),
+    ("vertical-align", Keywords(&["baseline", "sub", "super", "top", "text-top", "middle", "bottom", "text-bottom"])),
+    ("text-transform", Keywords(&["none", "capitalize", "uppercase", "lowercase", "full-width"])),
+    ("visibility", Keywords(&["visible", "hidden", "collapse"])),
+    ("quotes", Quotes),
+    ("content", Content),
+    ("counter-reset", Counters),
+    ("counter-increment", Counters),
+    ("counter-set", Counters),
+];
+
+impl Kind {
+    fn accepts(self, value:&Value) -> bool {
+        match (self, value) {
+            (Length, Value::Length(_, _)) | (Length, Number(_)) | (Length, Value::Calc(_)) => true,
+            (LengthOrAuto, Keyword(k)) => k.eq_ignore_ascii_case("auto"),
+            (LengthOrAuto, value) => Length.accepts(value),
+            (LengthOrNone, Keyword(k)) => k.eq_ignore_ascii_case("none"),
+            (LengthOrNone, value) => Length.accepts(value),
+            (LengthOrNormal, Keyword(k)) => k.eq_ignore_ascii_case("normal"),
+            (LengthOrNormal, value) => Length.accepts(value),
+            (LineWidth, Keyword(k)) => one_of(k, &["thin", "medium", "thick"]),
+            (LineWidth, value) => Length.accepts(value),
+            (Color, ColorValue(_)) | (Color, HexColor(_)) => true,
+            (Color, Keyword(k)) => k.eq_ignore_ascii_case("transparent") || k.eq_ignore_ascii_case("currentcolor") || named_color(k).is_some(),
+            (Color, FunCall(f)) => CssColor::from_function(&f.name, &f.arguments).is_some(),
+            (Image, Keyword(k)) => k.eq_ignore_ascii_case("none"),
+            (Image, FunCall(f)) => f.name == "url" || f.name.ends_with("gradient"),
+            (Position, Keyword(k)) => one_of(k, &["left", "center", "right", "top", "bottom"]),
+            (Position, value) => Length.accepts(value),
+            (Factor, Number(_)) => true,
+            (CounterStyle, Keyword(k)) => k != "/" && !one_of(k, CSS_WIDE),
+            (CounterStyle, StringLiteral(_)) => true,
+            (FontWeight, Number(n)) => *n >= 1.0 && *n <= 1000.0,
+            (FontWeight, Keyword(k)) => one_of(k, &["normal", "bold", "bolder", "lighter"]),
+            (FontSize, Keyword(k)) => one_of(k, FONT_SIZES),
+            (FontSize, value) => Length.accepts(value),
+            (LineHeight, Keyword(k)) => k.eq_ignore_ascii_case("normal"),
+            (LineHeight, value) => Length.accepts(value),
+            (FontFamily, Keyword(k)) => k != "/" && !one_of(k, CSS_WIDE),
+            (FontFamily, StringLiteral(_)) => true,
+            (FontFamily, ArrayValue(items)) => items.iter().all(|item| FontFamily.accepts(item)),
+            (Content, Keyword(k)) => one_of(k, &["normal", "none"]) || one_of(k, CONTENT_KEYWORDS),
+            (Content, StringLiteral(_)) => true,
+            (Content, FunCall(f)) => matches!(f.name.as_str(), "attr" | "counter" | "counters" | "url"),
+            (Content, ArrayValue(items)) => items.iter().all(|item| match item {
+                Keyword(k) => one_of(k, CONTENT_KEYWORDS),
+                item => Content.accepts(item),
+            }),
+            (Counters, Keyword(k)) => !one_of(k, CSS_WIDE),
+            (Counters, ArrayValue(items)) => matches!(items.first(), Some(Keyword(_))) && items.iter().all(|item| match item {
+                Keyword(k) => !k.eq_ignore_ascii_case("none") && !one_of(k, CSS_WIDE),
+                item => matches!(item, Number(_)),
+            }),
+            (Quotes, Keyword(k)) => one_of(k, &["auto", "none"]),
+            (Quotes, ArrayValue(items)) => items.len() % 2 == 0 && items.iter().all(|item| matches!(item, StringLiteral(_))),
+            (Keywords(keywords), Keyword(k)) => one_of(k, keywords),
+            _ => false,
+        }
+    }
+    fn max_values(self) -> usize {
+        match self {
+            Position => 2,
+            _ => 1,
+        }
+    }
+}
+
+// keywords are case-insensitive
+fn one_of(keyword:&str, words:&[&str]) -> bool {
+    words.iter().any(|word| word.eq_ignore_ascii_case(keyword))
+}
+
+fn is_shorthand(name:&str) -> bool {
+    SHORTHANDS.iter().any(|(n, _, _)| *n == name)
+}
+
+/// Whether the value is valid for the shorthand, or None if it isn't one.
+pub(crate) fn accepts_shorthand(dec:&Declaration) -> Option<bool> {
+    let (_, syntax, longhands) = SHORTHANDS.iter().find(|(name, _, _)| *name == dec.name)?;
+    Some(longhand_values(dec, *syntax, longhands).is_some())
+}
+
+/// Whether the value is valid for a longhand in the tables, or None if it
+/// isn't one.
+pub(crate) fn accepts_longhand(name:&str, value:&Value) -> Option<bool> {
+    let kind = SHORTHANDS.iter()
+        .flat_map(|(_, _, longhands)| longhands.iter())
+        .chain(PROPERTIES.iter())
+        .find(|(longhand, _)| *longhand == name && !is_shorthand(longhand))
+        .map(|(_, kind)| *kind)?;
+    let fits = |value:&Value| kind.accepts(value) || matches!(value, ArrayValue(items)
+        if items.len() <= kind.max_values() && items.iter().all(|item| kind.accepts(item)));
+    Some(fits(value) || matches!(value, ArrayValue(items) if LAYERED.contains(&name) && items.iter().all(fits)))
+}
+
+/// Whether the tables take the value of a declaration. Properties they don't
+/// have take anything, and so does a value with var() until it's substituted.
+pub(crate) fn accepts_declaration(dec:&Declaration) -> bool {
+    match &dec.value {
+        Value::Unparsed(_) => true,
+        Keyword(k) if CSS_WIDE.contains(&k.as_str()) => true,
+        value => accepts_shorthand(dec).or_else(|| accepts_longhand(&dec.name, value)).unwrap_or(true),
+    }
+}
+
+// the values of the longhands, in order, or None if the value is invalid
+fn longhand_values(dec:&Declaration, syntax:Syntax, longhands:Longhands) -> Option<Vec<Value>> {
+    match &dec.value {
+        Keyword(k) if CSS_WIDE.contains(&k.as_str()) => Some(longhands.iter().map(|_| dec.value.clone()).collect()),
+        value => {
+            let items = match value {
+                ArrayValue(items) => items.clone(),
+                value => vec![value.clone()],
+            };
+            match syntax {
+                Sides => sides(&items, longhands),
+                Corners => corners(&items, longhands),
+                AnyOrder => any_order(&items, longhands),
+                Layers => any_order(&items, longhands).or_else(|| layers(&items, longhands)),
+                Font => font(&items, longhands),
+                Flex => flex(&items),
+            }
+        }
+    }
+}
+
+/// Adds the longhands of the declaration to the list, or the declaration
+/// itself if it isn't a shorthand. A value the tables don't take adds nothing.
+pub fn expand_shorthand(dec:&Declaration, out:&mut Vec<Declaration>) {
+    if !accepts_declaration(dec) {
+        return;
+    }
+    let (_, syntax, longhands) = match SHORTHANDS.iter().find(|(name, _, _)| *name == dec.name) {
+        Some(row) => row,
+        None => {
+            out.push(dec.clone());
+            return;
+        }
+    };
+    // with var() in it, the longhands wait for the variables and then take
+    // their part of the shorthand
+    if let Value::Unparsed(unparsed) = &dec.value {
+        let shorthand = unparsed.shorthand.clone().unwrap_or_else(|| dec.name.clone());
+        for (name, _) in longhands.iter() {
+            let longhand = Declaration {
+                name: name.to_string(),
+                value: Value::Unparsed(UnparsedValue { tokens: unparsed.tokens.clone(), shorthand: Some(shorthand.clone()) }),
+                important: dec.important,
+            };
+            if is_shorthand(name) {
+                expand_shorthand(&longhand, out);
+            } else {
+                out.push(longhand);
+            }
+        }
+        return;
+    }
+    let values = match longhand_values(dec, *syntax, longhands) {
+        Some(values) => values,
+        None => return,
+    };
+    for ((name, _), value) in longhands.iter().zip(values) {
+        let longhand = Declaration {
+            name: name.to_string(),
+            value,
+            important: dec.important,
+        };
+        if is_shorthand(name) {
+            expand_shorthand(&longhand, out);
+        } else {
+            out.push(longhand);
+        }
+    }
+}
+
+// comma separated layers, of which only the last may have a color. one layer
+// is drawn, so the longhands get the last one.
+fn layers(items:&[Value], longhands:&[(&str, Kind)]) -> Option<Vec<Value>> {
+    let (last, rest) = items.split_last()?;
+    let color = longhands[0].1;
+    if rest.iter().any(|item| color.accepts(item) || any_order(std::slice::from_ref(item), longhands).is_none()) {
+        return None;
+    }
+    any_order(std::slice::from_ref(last), longhands)
+}
+
+fn initial() -> Value {
+    Keyword(String::from("initial"))
+}
+
+// which of the one to four values goes to each side or corner
+fn spread(count:usize) -> Option<[usize; 4]> {
+    match count {
+        1 => Some([0, 0, 0, 0]),
+        2 => Some([0, 1, 0, 1]),
+        3 => Some([0, 1, 2, 1]),
+        4 => Some([0, 1, 2, 3]),
+        _ => None,
+    }
+}
+
+fn sides(items:&[Value], longhands:&[(&str, Kind)]) -> Option<Vec<Value>> {
+    let order = spread(items.len())?;
+    longhands.iter().zip(order.iter()).map(|((_, kind), i)| {
+        if kind.accepts(&items[*i]) { Some(items[*i].clone()) } else { None }
+    }).collect()
+}
+
+fn corners(items:&[Value], longhands:&[(&str, Kind)]) -> Option<Vec<Value>> {
+    let slash = Keyword(String::from("/"));
+    let (horizontal, vertical) = match items.iter().position(|item| *item == slash) {
+        Some(i) => (&items[..i], Some(&items[i + 1..])),
+        None => (items, None),
+    };
+    let horizontal = sides(horizontal, longhands)?;
+    match vertical {
+        None => Some(horizontal),
+        Some(vertical) => {
+            let vertical = sides(vertical, longhands)?;
+            Some(horizontal.into_iter().zip(vertical).map(|(h, v)| ArrayValue(vec![h, v])).collect())
+        }
+    }
+}
+
+fn any_order(items:&[Value], longhands:&[(&str, Kind)]) -> Option<Vec<Value>> {
+    let mut assigned:Vec<Vec<Value>> = vec![vec![]; longhands.len()];
+    for item in items {
+        let slot = longhands.iter().enumerate().position(|(i, (_, kind))| {
+            assigned[i].len() < kind.max_values() && kind.accepts(item)
+        })?;
+        assigned[slot].push(item.clone());
+    }
+    Some(assigned.into_iter().map(|mut values| match values.len() {
+        0 => initial(),
+        1 => values.remove(0),
+        _ => ArrayValue(values),
+    }).collect())
+}
+
+// https://drafts.csswg.org/css-fonts-4/#font-prop
+// [ <font-style> || <font-variant> || <font-weight> ]? <font-size> [ / <line-height> ]? <font-family>
+fn font(items:&[Value], longhands:&[(&str, Kind)]) -> Option<Vec<Value>> {
+    let mut values = vec![initial(); longhands.len()];
+    let mut rest = items.iter().peekable();
+    // normal is allowed for any of the first three, so it just leaves them alone
+    while let Some(item) = rest.peek() {
+        if *item == &Keyword(String::from("normal")) {
+            rest.next();
+            continue;
+        }
+        let slot = (0..3).find(|i| values[*i] == initial() && longhands[*i].1.accepts(item));
+        match slot {
+            Some(i) => values[i] = rest.next()?.clone(),
+            None => break,
+        }
+    }
+    let size = rest.next()?;
+    if !longhands[3].1.accepts(size) {
+        return None;
+    }
+    values[3] = size.clone();
+    if rest.peek() == Some(&&Keyword(String::from("/"))) {
+        rest.next();
+        let line_height = rest.next()?;
+        if !longhands[4].1.accepts(line_height) {
+            return None;
+        }
+        values[4] = line_height.clone();
+    }
+    values[5] = font_family(rest.cloned().collect())?;
+    Some(values)
+}
+
+// unquoted family names can be several words, as in `Times New Roman, serif`
+fn font_family(items:Vec<Value>) -> Option<Value> {
+    let mut families = vec![];
+    let mut words:Vec<String> = vec![];
+    fn finish(words:&mut Vec<String>, families:&mut Vec<Value>) {
+        if !words.is_empty() {
+            families.push(if words.len() == 1 { Keyword(words.remove(0)) } else { StringLiteral(words.join(" ")) });
+            words.clear();
+        }
+    }
+    for item in items {
+        if !FontFamily.accepts(&item) {
+            return None;
+        }
+        match item {
+            Keyword(word) => words.push(word),
+            StringLiteral(_) => {
+                finish(&mut words, &mut families);
+                families.push(item);
+            }
+            ArrayValue(list) => {
+                for (i, family) in list.into_iter().enumerate() {
+                    match family {
+                        Keyword(word) if i == 0 => words.push(word),
+                        family => {
+                            finish(&mut words, &mut families);
+                            match family {
+                                Keyword(word) => words.push(word),
+                                family => families.push(family),
+                            }
+                        }
+                    }
+                }
+            }
+            _ => return None,
+        }
+    }
+    finish(&mut words, &mut families);
+    match families.len() {
+        0 => None,
+        1 => families.pop(),
+        _ => Some(ArrayValue(families)),
+    }
+}
+
+// https://drafts.csswg.org/css-flexbox-1/#flex-property
+// none | auto | <flex-grow> <flex-shrink>? || <flex-basis>
+fn flex(items:&[Value]) -> Option<Vec<Value>> {
+    let auto = Keyword(String::from("auto"));
+    match items {
+        [Keyword(k)] if k == "none" => return Some(vec![Number(0.0), Number(0.0), auto]),
+        [Keyword(k)] if k == "auto" => return Some(vec![Number(1.0), Number(1.0), auto]),
+        _ => {}
+    }
+    let mut factors = vec![];
+    let mut basis = None;
+    for item in items {
+        match item {
+            Number(n) if factors.len() < 2 && (basis.is_none() || factors.is_empty()) => factors.push(Number(*n)),
+            item if basis.is_none() && LengthOrAuto.accepts(item) => basis = Some(item.clone()),
+            _ => return None,
+        }
+    }
+    let grow = factors.first().cloned().unwrap_or(Number(1.0));
+    let shrink = factors.get(1).cloned().unwrap_or(Number(1.0));
+    // a flex with only the factors starts from nothing
+    let basis = basis.unwrap_or(Value::Length(0.0, Unit::Per));
+    Some(vec![grow, shrink, basis])
+}
@@ -0,0 +1,91 @@
+// https://drafts.csswg.org/css-conditional-3/#support-definition
+//
+// A declaration is supported when the engine implements the property and the
+// value is one it understands. Implemented means that it is computed, that
+// layout or generated content reads it, or that it is a shorthand of those.
+// Values are checked by the same rules shorthand expansion uses, and a
+// property without a rule for its values isn't supported.
+
+use crate::css::{Declaration, SupportsCondition, Value, parse_selector_list};
+use crate::style::ComputedStyle;
+use crate::style::computed::initial_value;
+use crate::style::shorthand::{accepts_shorthand, accepts_longhand, CSS_WIDE};
+
+// read by the generated content of ::before and ::after
+const GENERATED_PROPERTIES:&[&str] = &["content", "counter-reset", "counter-increment", "counter-set"];
+
+pub fn supports(condition:&SupportsCondition) -> bool {
+    match condition {
+        SupportsCondition::Declaration(dec) => supports_declaration(dec),
+        SupportsCondition::Selector(text) => parse_selector_list(text).is_ok(),
+        SupportsCondition::Not(condition) => !supports(condition),
+        SupportsCondition::And(conditions) => conditions.iter().all(supports),
+        SupportsCondition::Or(conditions) => conditions.iter().any(supports),
+        SupportsCondition::Unknown => false,
+    }
+}
+
+pub fn supports_declaration(dec:&Declaration) -> bool {
+    // custom properties take anything
+    if dec.name.starts_with("--") {
+        return true;
+    }
+    // var() could be anything, so only the property counts
+    if let Value::Unparsed(_) = dec.value {
+        return accepts_shorthand(dec).is_some() || is_implemented(&dec.name);
+    }
+    if let Some(valid) = accepts_shorthand(dec) {
+        return valid;
+    }
+    if !is_implemented(&dec.name) {
+        return false;
+    }
+    match &dec.value {
+        Value::Keyword(k) if CSS_WIDE.contains(&k.as_str()) => true,
+        value => accepts_longhand(&dec.name, value) == Some(true),
+    }
+}
+
+fn is_implemented(name:&str) -> bool {
+    initial_value(name).is_some()
+        || ComputedStyle::PROPERTIES.contains(&name)
+        || GENERATED_PROPERTIES.contains(&name)
+}
+
+#[test]
+fn test_supports() {
+    use crate::css::parse_supports_condition;
+    let check = |text:&str| supports(&parse_supports_condition(text));
+    assert!(check("(display: block)"));
+    assert!(check("(display: inherit)"));
+    assert!(!check("(display: grid)"));
+    assert!(!check("(display: 12px)"));
+    assert!(check("(width: 50%)"));
+    assert!(check("(color: rgb(0 0 0 / 50%))"));
+    assert!(check("(border: 1px solid red)"));
+    assert!(!check("(border: 1px solid 2px)"));
+    assert!(check("(border-left-style: dashed)"));
+    assert!(!check("(border-left-style: wavy)"));
+    assert!(!check("(width: red)"));
+    assert!(!check("(color: 12px)"));
+    assert!(!check("(margin-top: solid)"));
+    assert!(!check("(max-width: auto)"));
+    assert!(check("(max-width: none)"));
+    assert!(!check("(font-size: bold)"));
+    assert!(!check("(background-color: 1px)"));
+    assert!(!check("(quotes: \"a\")"));
+    assert!(check("(content: open-quote counter(a) \"b\")"));
+    assert!(!check("(content: 12px)"));
+    assert!(check("(counter-reset: a 2 b)"));
+    assert!(!check("(counter-increment: 2 a)"));
+    assert!(!check("(grid-template-columns: 1px)"));
+    assert!(check("(--anything: at all)"));
+    assert!(check("(color: var(--anything))"));
+    assert!(!check("(grid-area: var(--anything))"));
+    assert!(check("not (display: grid)"));
+    assert!(check("(display: grid) or (display: table)"));
+    assert!(!check("(display: grid) and (display: table)"));
+    assert!(check("selector(p > a:hover)"));
+    assert!(!check("selector(p >)"));
+    assert!(!check("(unknown syntax)"));
+}
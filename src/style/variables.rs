@@ -0,0 +1,241 @@
+// https://drafts.csswg.org/css-variables-1/#using-variables
+//
+// Custom properties are kept as tokens and always inherit. At computed-value
+// time the var() functions in them are replaced by the tokens of the variables
+// they name, or by their fallbacks. Variables that depend on each other in a
+// cycle are all invalid. A normal property with var() in it is then parsed
+// from the substituted tokens with the grammar of the property, and if that
+// doesn't work it is invalid at computed-value time, which acts as `unset`.
+
+use crate::css::{Token, UnparsedValue, Value, parse_declarations};
+use crate::style::shorthand::{expand_shorthand, CSS_WIDE};
+use crate::style::supports::supports_declaration;
+use std::collections::{HashMap, HashSet};
+
+type PropertyMap = HashMap<String, Value>;
+
+/// The computed custom properties of a node, with the inherited ones. Invalid
+/// custom properties are left out, which is their initial value.
+pub fn custom_properties(specified:&PropertyMap, parent:Option<&PropertyMap>) -> PropertyMap {
+    let mut values:PropertyMap = match parent {
+        Some(parent) => parent.iter()
+            .filter(|(name, _)| name.starts_with("--"))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+        None => PropertyMap::new(),
+    };
+    let mut declared:HashMap<&str, &[Token]> = HashMap::new();
+    for (name, value) in specified.iter().filter(|(name, _)| name.starts_with("--")) {
+        let tokens = match value {
+            Value::Unparsed(unparsed) => &unparsed.tokens,
+            _ => continue,
+        };
+        match css_wide_keyword(tokens) {
+            Some("initial") => {
+                values.remove(name);
+            }
+            // the rest keep what the parent has
+            Some(_) => {}
+            None => {
+                declared.insert(name, tokens);
+            }
+        }
+    }
+    let resolved = {
+        let mut resolver = Resolver { declared: &declared, inherited: &values, resolved: HashMap::new(), visiting: vec![], cycles: HashSet::new() };
+        for name in declared.keys() {
+            resolver.lookup(name);
+        }
+        resolver.resolved.into_iter()
+            .map(|(name, tokens)| (name.to_string(), tokens))
+            .collect::<Vec<_>>()
+    };
+    for (name, tokens) in resolved {
+        match tokens {
+            Some(tokens) => values.insert(name, Value::Unparsed(UnparsedValue { tokens, shorthand: None })),
+            None => values.remove(&name),
+        };
+    }
+    values
+}
+
+/// The value of a property with var() in it, given the custom properties of
+/// the node. It is `unset` when it turns out to be invalid.
+pub fn substitute(name:&str, value:&UnparsedValue, custom:&PropertyMap) -> Value {
+    parse_substituted(name, value, custom).unwrap_or_else(|| Value::Keyword(String::from("unset")))
+}
+
+fn parse_substituted(name:&str, value:&UnparsedValue, custom:&PropertyMap) -> Option<Value> {
+    let tokens = substitute_tokens(&value.tokens, &mut |variable| match custom.get(variable) {
+        Some(Value::Unparsed(unparsed)) => Some(unparsed.tokens.clone()),
+        _ => None,
+    })?;
+    let text:String = tokens.iter().map(|token| token.to_string()).collect();
+    // the longhands of a shorthand parse the whole shorthand and take their part
+    let property = value.shorthand.as_deref().unwrap_or(name);
+    let declaration = parse_declarations(&format!("{}: {}", property, text)).0.into_iter().next()?;
+    if let Value::Unparsed(_) = declaration.value {
+        return None;
+    }
+    let mut longhands = vec![];
+    expand_shorthand(&declaration, &mut longhands);
+    let longhand = longhands.into_iter().find(|dec| dec.name == name)?;
+    if !supports_declaration(&longhand) {
+        return None;
+    }
+    Some(longhand.value)
+}
+
+fn css_wide_keyword(tokens:&[Token]) -> Option<&'static str> {
+    match tokens {
+        [Token::Ident(word)] => CSS_WIDE.iter().find(|keyword| keyword.eq_ignore_ascii_case(word)).copied(),
+        _ => None,
+    }
+}
+
+// resolves the custom properties declared on a node, in whatever order they
+// are asked for
+struct Resolver<'a> {
+    declared: &'a HashMap<&'a str, &'a [Token]>,
+    inherited: &'a PropertyMap,
+    resolved: HashMap<&'a str, Option<Vec<Token>>>,
+    // the chain of variables being resolved, to find cycles
+    visiting: Vec<&'a str>,
+    cycles: HashSet<&'a str>,
+}
+
+impl<'a> Resolver<'a> {
+    // the tokens of a variable, or None if it's invalid
+    fn lookup(&mut self, name:&str) -> Option<Vec<Token>> {
+        let declared:&'a HashMap<&'a str, &'a [Token]> = self.declared;
+        let (name, tokens) = match declared.get_key_value(name) {
+            Some((name, tokens)) => (*name, *tokens),
+            None => return match self.inherited.get(name) {
+                Some(Value::Unparsed(unparsed)) => Some(unparsed.tokens.clone()),
+                _ => None,
+            },
+        };
+        if let Some(tokens) = self.resolved.get(name) {
+            return tokens.clone();
+        }
+        if let Some(start) = self.visiting.iter().position(|visiting| *visiting == name) {
+            self.cycles.extend(self.visiting[start..].iter().copied());
+            return None;
+        }
+        self.visiting.push(name);
+        let substituted = substitute_tokens(tokens, &mut |variable| self.lookup(variable));
+        self.visiting.pop();
+        let substituted = if self.cycles.contains(name) { None } else { substituted };
+        self.resolved.insert(name, substituted.clone());
+        substituted
+    }
+}
+
+// the tokens with each var() replaced, or None if one has no value
+fn substitute_tokens(tokens:&[Token], lookup:&mut dyn FnMut(&str) -> Option<Vec<Token>>) -> Option<Vec<Token>> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Function(function) if function.eq_ignore_ascii_case("var") => {
+                let close = closing_paren(tokens, i);
+                out.extend(var_function(&tokens[i + 1..close], lookup)?);
+                i = close + 1;
+            }
+            token => {
+                out.push(token.clone());
+                i += 1;
+            }
+        }
+    }
+    Some(out)
+}
+
+// the arguments are a custom property name and an optional fallback after a comma
+fn var_function(arguments:&[Token], lookup:&mut dyn FnMut(&str) -> Option<Vec<Token>>) -> Option<Vec<Token>> {
+    let (name, rest) = match trim(arguments).split_first() {
+        Some((Token::Ident(name), rest)) if name.starts_with("--") => (name, trim(rest)),
+        _ => return None,
+    };
+    match rest.split_first() {
+        None => lookup(name),
+        Some((Token::Comma, fallback)) => match lookup(name) {
+            Some(tokens) => Some(tokens),
+            None => substitute_tokens(trim(fallback), lookup),
+        },
+        Some(_) => None,
+    }
+}
+
+// the index of the paren that closes the function at open, or the end
+fn closing_paren(tokens:&[Token], open:usize) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Function(_) | Token::OpenParen | Token::OpenSquare | Token::OpenCurly => depth += 1,
+            Token::CloseParen | Token::CloseSquare | Token::CloseCurly => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return i;
+        }
+    }
+    tokens.len()
+}
+
+fn trim(mut tokens:&[Token]) -> &[Token] {
+    while let [Token::Whitespace, rest @ ..] = tokens {
+        tokens = rest;
+    }
+    while let [rest @ .., Token::Whitespace] = tokens {
+        tokens = rest;
+    }
+    tokens
+}
+
+#[test]
+fn test_custom_properties() {
+    use crate::css::Unit;
+    use crate::css::Value::{Keyword, Length};
+    use crate::style::computed::{compute_values, ComputeContext};
+    let declarations = |text:&str| -> PropertyMap {
+        let mut out = vec![];
+        for dec in parse_declarations(text).0 {
+            expand_shorthand(&dec, &mut out);
+        }
+        out.into_iter().map(|dec| (dec.name, dec.value)).collect()
+    };
+    let compute = |specified:&PropertyMap, parent:Option<&PropertyMap>| compute_values(specified, parent, &ComputeContext::default());
+    let root = compute(&declarations("--gap: 4px; --brand: red; --pair: var(--gap) 8px; color: var(--brand)"), None);
+    assert_eq!(root.get("color"), Some(&Keyword(String::from("red"))));
+    assert_eq!(root.get("--pair").map(|v| match v { Value::Unparsed(u) => u.to_css(), _ => String::new() }), Some(String::from("4px 8px")));
+
+    // inherited, with fallbacks, and through shorthands
+    let child = compute(&declarations("--brand: initial; margin: var(--pair); \
+        color: var(--brand, var(--missing, blue)); border-top-color: var(--missing)"), Some(&root));
+    assert_eq!(child.get("--gap"), Some(&Value::Unparsed(UnparsedValue { tokens: vec![Token::Dimension { value: 4.0, integer: true, unit: String::from("px") }], shorthand: None })));
+    assert_eq!(child.get("--brand"), None);
+    assert_eq!(child.get("margin-top"), Some(&Length(4.0, Unit::Px)));
+    assert_eq!(child.get("margin-right"), Some(&Length(8.0, Unit::Px)));
+    assert_eq!(child.get("margin-left"), Some(&Length(8.0, Unit::Px)));
+    assert_eq!(child.get("color"), Some(&Keyword(String::from("blue"))));
+    // unset, which is currentcolor
+    assert_eq!(child.get("border-top-color"), Some(&Keyword(String::from("blue"))));
+
+    // cycles make every variable in them invalid, but not the ones that use them with a fallback
+    let cycle = compute(&declarations("--a: var(--b); --b: var(--a); --c: var(--c); --d: var(--a, 2px) 3px; \
+        width: var(--d); color: var(--a, green)"), None);
+    assert_eq!(cycle.get("--a"), None);
+    assert_eq!(cycle.get("--b"), None);
+    assert_eq!(cycle.get("--c"), None);
+    assert_eq!(cycle.get("color"), Some(&Keyword(String::from("green"))));
+    // two lengths aren't a width, so it's unset
+    assert_eq!(cycle.get("width"), Some(&Keyword(String::from("auto"))));
+    // parsed with the grammar of the property
+    let invalid = compute(&declarations("--color: 12px; color: var(--color); border-top-style: var(--color); \
+        margin-top: var(--brand); width: var(--color)"), Some(&root));
+    assert_eq!(invalid.get("color"), Some(&Keyword(String::from("red"))));
+    assert_eq!(invalid.get("border-top-style"), Some(&Keyword(String::from("none"))));
+    assert_eq!(invalid.get("margin-top"), Some(&Length(0.0, Unit::Px)));
+    assert_eq!(invalid.get("width"), Some(&Length(12.0, Unit::Px)));
+}